@@ -3,6 +3,8 @@ use std::fs;
 use std::io;
 use std::path::{Path, PathBuf};
 
+use syn::{Attribute, Expr, ExprLit, Item, Lit, Meta, Token, punctuated::Punctuated};
+
 pub struct PacketScannerConfig {
     /// Source directories to scan
     pub src_dirs: Vec<PathBuf>,
@@ -52,18 +54,14 @@ impl PacketScanner {
             self.collect_rust_files(dir, &mut rust_files)?;
         }
 
+        // Scan in a fixed order so the same packet wins when names clash
+        rust_files.sort();
+
         // Find packet types
         let packet_types = self.find_packet_types(&rust_files)?;
 
-        let cache_path = std::path::Path::new("target").join(".tnet_packet_cache.json");
-        if let Ok(cache_json) = serde_json::to_string(&packet_types) {
-            // Try to save, but don't fail if we can't
-            let _ = std::fs::create_dir_all("target");
-            let _ = std::fs::write(&cache_path, cache_json);
-        }
-
         // Generate the TnetPacket implementation
-        let output_content = self.generate_tnet_packet_code(&packet_types);
+        let output_content = self.generate_tnet_packet_code(&packet_types)?;
 
         // Get output directory from environment or config
         let out_dir = match std::env::var("OUT_DIR") {
@@ -110,206 +108,31 @@ impl PacketScanner {
 
     fn find_packet_types(&self, files: &[PathBuf]) -> io::Result<Vec<(String, String)>> {
         let mut packet_types = Vec::new();
-        let mut active_packet_fields = std::collections::HashSet::new();
 
         println!(
             "cargo:warning=Scanning {} files for packet types",
             files.len()
         );
 
-        // First, scan all files to build a set of active packet field names
         for file in files {
-            println!("cargo:warning=Looking at file: {}", file.display());
-
-            if let Ok(content) = fs::read_to_string(file) {
-                if content.contains("#[tpacket") {
-                    println!(
-                        "cargo:warning=Found tpacket attribute in file: {}",
-                        file.display()
-                    );
+            let Ok(content) = fs::read_to_string(file) else {
+                continue;
+            };
 
-                    // Extract struct names and custom names following #[tpacket]
-                    let lines = content.lines().collect::<Vec<_>>();
-                    for (i, line) in lines.iter().enumerate() {
-                        if line.contains("#[tpacket") {
-                            // Check for custom name in the attribute
-                            let mut custom_name = None;
-                            if line.contains("name =") {
-                                if let Some(name_start) = line.find("name = \"") {
-                                    if let Some(name_end) = line[name_start + 7..].find('\"') {
-                                        custom_name = Some(
-                                            line[name_start + 7..name_start + 7 + name_end]
-                                                .to_string(),
-                                        );
-                                    }
-                                }
-                            }
-
-                            // Now check the next line for struct definition
-                            if i + 1 < lines.len() {
-                                let next_line = lines[i + 1];
-                                if next_line.contains("struct ") {
-                                    let parts: Vec<&str> = next_line.split("struct ").collect();
-                                    if parts.len() > 1 {
-                                        let struct_name_parts =
-                                            parts[1].split_whitespace().collect::<Vec<_>>();
-                                        if !struct_name_parts.is_empty() {
-                                            let struct_name =
-                                                struct_name_parts[0].trim_end_matches('{').trim();
-
-                                            // Use custom name if provided, otherwise convert struct name to snake case
-                                            let field_name = match custom_name {
-                                                Some(name) => name,
-                                                None => to_snake_case(struct_name),
-                                            };
-
-                                            // Mark this as an active #[tpacket] struct
-                                            active_packet_fields.insert(field_name.clone());
-
-                                            // Try to construct the full type path based on file location
-                                            let file_path = file.to_string_lossy();
-                                            let module_path =
-                                                if let Some(src_idx) = file_path.find("src/") {
-                                                    let module_part = &file_path[src_idx + 4..];
-                                                    let module_part = module_part
-                                                        .trim_end_matches(".rs")
-                                                        .replace('/', "::");
-                                                    format!("crate::{}", module_part)
-                                                } else {
-                                                    "crate".to_string()
-                                                };
-
-                                            // If it's a mod.rs file, adjust the path
-                                            let adjusted_path = if module_path.ends_with("::mod") {
-                                                module_path.trim_end_matches("::mod").to_string()
-                                            } else {
-                                                module_path
-                                            };
-
-                                            let full_type =
-                                                format!("{}::{}", adjusted_path, struct_name);
-
-                                            println!(
-                                                "cargo:warning=Found active packet in source: {} at {}",
-                                                field_name, full_type
-                                            );
-
-                                            // Add to packet types directly from source scanning
-                                            packet_types.push((field_name, full_type));
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
+            // Cheap pre-check so we only parse files that could contain packets
+            if !content.contains("tpacket") {
+                continue;
             }
-        }
 
-        // Now scan temp directory for registrations
-        // But only use ones that are still active
-        let temp_dir = std::env::temp_dir().join("tnet_registry");
-        if let Ok(entries) = std::fs::read_dir(temp_dir) {
-            for entry in entries.flatten() {
-                let path = entry.path();
-                if path.is_file() && path.extension().is_some_and(|ext| ext == "packet") {
-                    if let Some(stem) = path.file_stem() {
-                        if let Some(field_name) = stem.to_str() {
-                            // Check if this is still an active #[tpacket] struct
-                            if active_packet_fields.contains(field_name) {
-                                if let Ok(content) = std::fs::read_to_string(&path) {
-                                    // Check if the content has a custom field name marker
-                                    let parts: Vec<&str> = content.split('|').collect();
-
-                                    let type_path = parts[0].trim();
-                                    let actual_field_name = if parts.len() > 1 {
-                                        parts[1].trim()
-                                    } else {
-                                        field_name
-                                    };
-
-                                    // Only add if not already in the list
-                                    if !packet_types.iter().any(|(f, _)| f == actual_field_name) {
-                                        packet_types.push((
-                                            actual_field_name.to_string(),
-                                            type_path.to_string(),
-                                        ));
-                                        println!(
-                                            "cargo:warning=Found packet from temp file: {} ({})",
-                                            actual_field_name, type_path
-                                        );
-                                    }
-                                }
-                            } else {
-                                println!(
-                                    "cargo:warning=Skipping inactive packet marker: {}",
-                                    field_name
-                                );
-                                // Clean up the marker file for inactive packets
-                                let _ = std::fs::remove_file(&path);
-                            }
-                        }
-                    }
+            let syntax = match syn::parse_file(&content) {
+                Ok(syntax) => syntax,
+                Err(e) => {
+                    println!("cargo:warning=Failed to parse {}: {}", file.display(), e);
+                    continue;
                 }
-            }
-        }
+            };
 
-        // Also check target directory markers (but these are secondary to source scanning)
-        let target_dirs = [
-            std::path::Path::new("target/.tpacket_markers"),
-            std::path::Path::new("../../target/.tpacket_markers"),
-        ];
-
-        for dir in &target_dirs {
-            if dir.exists() {
-                if let Ok(entries) = std::fs::read_dir(dir) {
-                    for entry in entries.flatten() {
-                        let path = entry.path();
-                        if path.is_file() && path.extension().is_some_and(|ext| ext == "marker") {
-                            if let Some(stem) = path.file_stem() {
-                                if let Some(field_name) = stem.to_str() {
-                                    // Check if this is still an active #[tpacket] struct
-                                    if active_packet_fields.contains(field_name) {
-                                        if let Ok(content) = std::fs::read_to_string(&path) {
-                                            // Check if the content has a custom field name marker
-                                            let parts: Vec<&str> = content.split('|').collect();
-
-                                            let type_path = parts[0].trim();
-                                            let actual_field_name = if parts.len() > 1 {
-                                                parts[1].trim()
-                                            } else {
-                                                field_name
-                                            };
-
-                                            // Only add if not already in the list
-                                            if !packet_types
-                                                .iter()
-                                                .any(|(f, _)| f == actual_field_name)
-                                            {
-                                                packet_types.push((
-                                                    actual_field_name.to_string(),
-                                                    type_path.to_string(),
-                                                ));
-                                                println!(
-                                                    "cargo:warning=Found packet from target marker: {} ({})",
-                                                    actual_field_name, type_path
-                                                );
-                                            }
-                                        }
-                                    } else {
-                                        println!(
-                                            "cargo:warning=Skipping inactive packet marker in target: {}",
-                                            field_name
-                                        );
-                                        // Clean up the marker file for inactive packets
-                                        let _ = std::fs::remove_file(&path);
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-            }
+            collect_packet_types(&syntax.items, &module_path_for(file), &mut packet_types);
         }
 
         // Make the list of packet types unique by field name, keeping the first entry
@@ -332,7 +155,32 @@ impl PacketScanner {
         Ok(unique_packet_types)
     }
 
-    fn generate_tnet_packet_code(&self, packet_types: &[(String, String)]) -> String {
+    /// Generate the TnetPacket source for the given (field name, type path) pairs.
+    ///
+    /// Fails with `InvalidInput` when two packet types end up with the same field,
+    /// such as `#[tpacket(name = "type")]`, which becomes `type_value`, next to a
+    /// `TypeValue` struct.
+    fn generate_tnet_packet_code(&self, packet_types: &[(String, String)]) -> io::Result<String> {
+        // Generate in field name order so the output doesn't depend on discovery order
+        let mut packet_types = packet_types.to_vec();
+        packet_types.sort();
+        let packet_types = packet_types.as_slice();
+
+        let mut fields = std::collections::HashMap::new();
+        for (field_name, type_path) in packet_types {
+            let field_ident = sanitize_identifier(field_name);
+            if let Some(other) = fields.insert(field_ident.clone(), type_path) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "packet types {} and {} both map to the TnetPacket field `{}`, \
+                         give one of them a different #[tpacket(name = \"...\")]",
+                        other, type_path, field_ident
+                    ),
+                ));
+            }
+        }
+
         let mut struct_fields = String::new();
         let mut default_fields = String::new();
         // Remove these variables since we won't be generating getters and setters
@@ -366,7 +214,7 @@ impl PacketScanner {
 
         // Generate the TnetPacket implementation with fully qualified paths
         // And remove references to getter and setter methods
-        format!(
+        Ok(format!(
             r#"// This file is auto-generated. Do not edit manually.
 
             /// Dynamic packet type that can contain registered packet types.
@@ -433,7 +281,200 @@ impl PacketScanner {
             }}
             "#,
             struct_fields, default_fields, default_fields
+        ) + &generate_header_enum(packet_types))
+    }
+}
+
+/// Generate the `TnetHeader` enum with a variant per packet type next to the builtin headers.
+///
+/// A packet type's header is its field name in upper case, e.g. `login_packet` is sent
+/// as `LOGIN_PACKET` and matched by `TnetHeader::LoginPacket`.
+fn generate_header_enum(packet_types: &[(String, String)]) -> String {
+    let mut headers = vec![
+        ("Ok".to_string(), "OK".to_string()),
+        ("Error".to_string(), "ERROR".to_string()),
+        ("KeepAlive".to_string(), "KEEPALIVE".to_string()),
+    ];
+    for (field_name, _) in packet_types {
+        let variant = to_pascal_case(field_name);
+        let header = field_name.to_uppercase();
+        // Skip packets that would clash with a builtin or an earlier packet
+        if variant == "Other"
+            || headers.iter().any(|(existing, existing_header)| {
+                *existing == variant || *existing_header == header
+            })
+        {
+            println!(
+                "cargo:warning=Skipping header variant for {} which clashes with another header",
+                field_name
+            );
+            continue;
+        }
+        headers.push((variant, header));
+    }
+
+    let mut variants = String::new();
+    let mut to_str_arms = String::new();
+    let mut from_str_arms = String::new();
+    for (variant, header) in &headers {
+        writeln!(
+            &mut variants,
+            "    /// The `{}` header\n    {},",
+            header, variant
+        )
+        .unwrap();
+        writeln!(
+            &mut to_str_arms,
+            "            Self::{} => \"{}\",",
+            variant, header
         )
+        .unwrap();
+        writeln!(
+            &mut from_str_arms,
+            "            \"{}\" => Self::{},",
+            header, variant
+        )
+        .unwrap();
+    }
+
+    format!(
+        r#"
+/// Packet headers known to `TnetPacket`.
+///
+/// This enum is automatically generated with a variant per type marked with `#[tpacket]`,
+/// next to the builtin `OK`, `ERROR` and `KEEPALIVE` headers.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum TnetHeader {{
+{}    /// A header no packet type was generated for
+    Other(String),
+}}
+
+impl TnetHeader {{
+    /// The header string sent on the wire.
+    pub fn as_str(&self) -> &str {{
+        match self {{
+{}            Self::Other(header) => header.as_str(),
+        }}
+    }}
+}}
+
+impl ::std::convert::From<&str> for TnetHeader {{
+    fn from(header: &str) -> Self {{
+        match header {{
+{}            other => Self::Other(other.to_string()),
+        }}
+    }}
+}}
+
+impl ::std::fmt::Display for TnetHeader {{
+    fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {{
+        f.write_str(self.as_str())
+    }}
+}}
+
+impl TnetPacket {{
+    /// The packet's header as a `TnetHeader`.
+    pub fn header_kind(&self) -> TnetHeader {{
+        TnetHeader::from(self.header.as_str())
+    }}
+}}
+"#,
+        variants, to_str_arms, from_str_arms
+    )
+}
+
+/// Walk parsed items, collecting every `#[tpacket]` struct as a (field name, type path) pair.
+///
+/// Inline `mod` blocks are descended into with their name appended to `module_path`.
+fn collect_packet_types(
+    items: &[Item],
+    module_path: &str,
+    packet_types: &mut Vec<(String, String)>,
+) {
+    for item in items {
+        match item {
+            Item::Struct(item) => {
+                let Some(attr) = item.attrs.iter().find(|attr| is_tpacket_attr(attr)) else {
+                    continue;
+                };
+
+                let struct_name = item.ident.to_string();
+
+                // Use custom name if provided, otherwise convert struct name to snake case
+                let field_name = tpacket_name(attr).unwrap_or_else(|| to_snake_case(&struct_name));
+                let full_type = format!("{}::{}", module_path, struct_name);
+
+                println!(
+                    "cargo:warning=Found active packet in source: {} at {}",
+                    field_name, full_type
+                );
+
+                packet_types.push((field_name, full_type));
+            }
+            Item::Mod(item) => {
+                if let Some((_, items)) = &item.content {
+                    let module_path = format!("{}::{}", module_path, item.ident);
+                    collect_packet_types(items, &module_path, packet_types);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Check if an attribute is `#[tpacket]`, including paths such as `#[tnet::tpacket]`
+fn is_tpacket_attr(attr: &Attribute) -> bool {
+    attr.path()
+        .segments
+        .last()
+        .is_some_and(|segment| segment.ident == "tpacket")
+}
+
+/// Read the custom field name from `#[tpacket(name = "...")]` or `#[tpacket("...")]`
+///
+/// Other options such as `derive(...)` or `no_derive` may appear alongside the name.
+fn tpacket_name(attr: &Attribute) -> Option<String> {
+    let Meta::List(list) = &attr.meta else {
+        return None;
+    };
+
+    let args = list
+        .parse_args_with(Punctuated::<Expr, Token![,]>::parse_terminated)
+        .ok()?;
+
+    args.into_iter().find_map(|arg| match arg {
+        Expr::Lit(ExprLit {
+            lit: Lit::Str(lit), ..
+        }) => Some(lit.value()),
+        Expr::Assign(assign) => match (*assign.left, *assign.right) {
+            (
+                Expr::Path(path),
+                Expr::Lit(ExprLit {
+                    lit: Lit::Str(lit), ..
+                }),
+            ) if path.path.is_ident("name") => Some(lit.value()),
+            _ => None,
+        },
+        _ => None,
+    })
+}
+
+/// Build the module path of a source file, relative to its `src` directory
+fn module_path_for(file: &Path) -> String {
+    let file_path = file.to_string_lossy().replace('\\', "/");
+    let Some(src_idx) = file_path.rfind("src/") else {
+        return "crate".to_string();
+    };
+
+    let module_part = file_path[src_idx + 4..].trim_end_matches(".rs");
+    let module_part = module_part
+        .strip_suffix("/mod")
+        .unwrap_or(module_part)
+        .replace('/', "::");
+
+    match module_part.as_str() {
+        "lib" | "main" | "mod" => "crate".to_string(),
+        _ => format!("crate::{}", module_part),
     }
 }
 
@@ -546,6 +587,48 @@ macro_rules! scan_packets {
                         Self::new("KEEPALIVE")
                     }
                 }
+
+                #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+                pub enum TnetHeader {
+                    Ok,
+                    Error,
+                    KeepAlive,
+                    Other(String),
+                }
+
+                impl TnetHeader {
+                    pub fn as_str(&self) -> &str {
+                        match self {
+                            Self::Ok => "OK",
+                            Self::Error => "ERROR",
+                            Self::KeepAlive => "KEEPALIVE",
+                            Self::Other(header) => header.as_str(),
+                        }
+                    }
+                }
+
+                impl ::std::convert::From<&str> for TnetHeader {
+                    fn from(header: &str) -> Self {
+                        match header {
+                            "OK" => Self::Ok,
+                            "ERROR" => Self::Error,
+                            "KEEPALIVE" => Self::KeepAlive,
+                            other => Self::Other(other.to_string()),
+                        }
+                    }
+                }
+
+                impl ::std::fmt::Display for TnetHeader {
+                    fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                        f.write_str(self.as_str())
+                    }
+                }
+
+                impl TnetPacket {
+                    pub fn header_kind(&self) -> TnetHeader {
+                        TnetHeader::from(self.header.as_str())
+                    }
+                }
                 "#;
 
                 if let Err(write_err) = std::fs::write(&fallback_path, fallback_content) {
@@ -695,6 +778,19 @@ macro_rules! configure_scanner {
     };
 }
 
+fn to_pascal_case(s: &str) -> String {
+    s.split('_')
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            chars
+                .next()
+                .map(|first| first.to_uppercase().chain(chars).collect::<String>())
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
 fn to_snake_case(s: &str) -> String {
     let mut result = String::new();
     let mut chars = s.chars().peekable();
@@ -716,3 +812,6 @@ fn to_snake_case(s: &str) -> String {
 
     result
 }
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1 @@
+mod scanner_tests;
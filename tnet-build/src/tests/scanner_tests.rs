@@ -0,0 +1,170 @@
+use std::path::{Path, PathBuf};
+
+use crate::{PacketScanner, PacketScannerConfig, module_path_for};
+
+fn fixtures_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/src")
+}
+
+fn scan_fixtures() -> Vec<(String, String)> {
+    let scanner = PacketScanner::new(PacketScannerConfig {
+        src_dirs: vec![fixtures_dir()],
+        ..Default::default()
+    });
+
+    let mut files = Vec::new();
+    scanner
+        .collect_rust_files(&fixtures_dir(), &mut files)
+        .unwrap();
+    files.sort();
+
+    let mut packet_types = scanner.find_packet_types(&files).unwrap();
+    packet_types.sort();
+    packet_types
+}
+
+#[test]
+fn test_finds_packets_with_non_adjacent_attributes() {
+    let packet_types = scan_fixtures();
+
+    let expected = [
+        ("custom_chat", "crate::packets::ChatMessage"),
+        ("custom_derives", "crate::packets::CustomDerives"),
+        ("documented", "crate::packets::Documented"),
+        (
+            "inline_module_packet",
+            "crate::packets::inner::InlineModulePacket",
+        ),
+        ("nested_packet", "crate::nested::NestedPacket"),
+        ("positional", "crate::packets::PathAttr"),
+        ("spread_out", "crate::packets::MultiLineArgs"),
+    ]
+    .map(|(field, path)| (field.to_string(), path.to_string()));
+
+    assert_eq!(packet_types, expected);
+}
+
+#[test]
+fn test_module_path_for_files() {
+    assert_eq!(module_path_for(Path::new("src/lib.rs")), "crate");
+    assert_eq!(module_path_for(Path::new("src/main.rs")), "crate");
+    assert_eq!(module_path_for(Path::new("src/net/mod.rs")), "crate::net");
+    assert_eq!(
+        module_path_for(Path::new("src/net/packets.rs")),
+        "crate::net::packets"
+    );
+    assert_eq!(module_path_for(Path::new("build.rs")), "crate");
+}
+
+#[test]
+fn test_generates_header_enum_for_packet_types() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/headers/src");
+    let scanner = PacketScanner::new(PacketScannerConfig {
+        src_dirs: vec![dir.clone()],
+        ..Default::default()
+    });
+
+    let mut files = Vec::new();
+    scanner.collect_rust_files(&dir, &mut files).unwrap();
+    let packet_types = scanner.find_packet_types(&files).unwrap();
+    let code = scanner.generate_tnet_packet_code(&packet_types).unwrap();
+
+    let syntax = syn::parse_file(&code).expect("Generated code does not parse");
+    let header_enum = syntax
+        .items
+        .iter()
+        .find_map(|item| match item {
+            syn::Item::Enum(item) if item.ident == "TnetHeader" => Some(item),
+            _ => None,
+        })
+        .expect("No TnetHeader enum generated");
+    let variants: Vec<String> = header_enum
+        .variants
+        .iter()
+        .map(|variant| variant.ident.to_string())
+        .collect();
+    assert_eq!(
+        variants,
+        ["Ok", "Error", "KeepAlive", "Chat", "LoginPacket", "Other"]
+    );
+
+    assert!(code.contains(r#""LOGIN_PACKET" => Self::LoginPacket"#));
+    assert!(code.contains(r#"Self::Chat => "CHAT""#));
+}
+
+#[test]
+fn test_generated_code_ignores_discovery_order() {
+    let scanner = PacketScanner::new(PacketScannerConfig {
+        src_dirs: vec![fixtures_dir()],
+        ..Default::default()
+    });
+    let packet_types = scan_fixtures();
+
+    let mut shuffled = packet_types.clone();
+    shuffled.reverse();
+    shuffled.rotate_left(2);
+    assert_ne!(shuffled, packet_types);
+
+    let first = scanner.generate_tnet_packet_code(&packet_types).unwrap();
+    let second = scanner.generate_tnet_packet_code(&shuffled).unwrap();
+    assert_eq!(first.as_bytes(), second.as_bytes());
+}
+
+#[test]
+fn test_concurrent_scans_do_not_share_packets() {
+    let out_root = std::env::temp_dir().join(format!("tnet-build-scan-{}", std::process::id()));
+    let crates = [
+        ("fixtures", fixtures_dir()),
+        (
+            "headers",
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/headers/src"),
+        ),
+    ];
+
+    let handles: Vec<_> = crates
+        .iter()
+        .map(|(name, src)| {
+            let config = PacketScannerConfig {
+                src_dirs: vec![src.clone()],
+                out_dir: out_root.join(name),
+                out_file: "tnet_packet.rs".to_string(),
+                rerun_if_changed: false,
+            };
+            std::thread::spawn(move || PacketScanner::new(config).run().unwrap())
+        })
+        .collect();
+    let outputs: Vec<String> = handles
+        .into_iter()
+        .map(|handle| std::fs::read_to_string(handle.join().unwrap()).unwrap())
+        .collect();
+    let _ = std::fs::remove_dir_all(&out_root);
+
+    assert!(outputs[0].contains("pub custom_chat: Option<crate::packets::ChatMessage>"));
+    assert!(!outputs[0].contains("LoginPacket"));
+    assert!(outputs[1].contains("pub login_packet: Option<crate::packets::LoginPacket>"));
+    assert!(!outputs[1].contains("custom_chat"));
+    assert!(!outputs[1].contains("NestedPacket"));
+}
+
+#[test]
+fn test_reports_field_name_collisions() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/collisions/src");
+    let scanner = PacketScanner::new(PacketScannerConfig {
+        src_dirs: vec![dir.clone()],
+        ..Default::default()
+    });
+
+    let mut files = Vec::new();
+    scanner.collect_rust_files(&dir, &mut files).unwrap();
+    let packet_types = scanner.find_packet_types(&files).unwrap();
+    assert_eq!(packet_types.len(), 2);
+
+    let error = scanner
+        .generate_tnet_packet_code(&packet_types)
+        .unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    let message = error.to_string();
+    assert!(message.contains("crate::packets::TypeName"), "{message}");
+    assert!(message.contains("crate::packets::TypeValue"), "{message}");
+    assert!(message.contains("`type_value`"), "{message}");
+}
@@ -0,0 +1,10 @@
+//! Scanner fixture: two packet types that end up with the same TnetPacket field.
+
+use tnet::prelude::*;
+
+// `type` is a keyword, so its field becomes `type_value`
+#[tpacket(name = "type")]
+pub struct TypeName;
+
+#[tpacket]
+pub struct TypeValue;
@@ -0,0 +1,13 @@
+//! Scanner fixture: two packet types for the generated `TnetHeader` enum.
+
+use tnet::prelude::*;
+
+#[tpacket]
+pub struct LoginPacket {
+    pub username: String,
+}
+
+#[tpacket(name = "chat")]
+pub struct ChatMessage {
+    pub text: String,
+}
@@ -0,0 +1,4 @@
+//! Scanner fixture: a file that does not parse, and must be skipped.
+
+#[tpacket]
+pub struct Broken {
@@ -0,0 +1,7 @@
+//! Scanner fixture: packets declared in a `mod.rs` file.
+
+#[derive(Default)]
+#[tpacket]
+pub struct NestedPacket {
+    pub value: u32,
+}
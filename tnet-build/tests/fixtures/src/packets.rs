@@ -0,0 +1,41 @@
+//! Scanner fixture: `#[tpacket]` attributes in positions a line-based scan misses.
+
+use tnet::prelude::*;
+
+/// A documented packet, the doc comment sits between the attribute and the struct.
+#[tpacket]
+/// More docs after the attribute.
+pub struct Documented {
+    pub value: u32,
+}
+
+#[tpacket(name = "custom_chat")]
+#[allow(dead_code)]
+#[cfg_attr(test, allow(unused))]
+pub struct ChatMessage {
+    pub text: String,
+}
+
+#[tpacket(
+    name = "spread_out"
+)]
+pub struct MultiLineArgs {
+    pub value: u32,
+}
+
+#[tnet::tpacket("positional")]
+pub struct PathAttr;
+
+#[tpacket(derive(Debug), name = "custom_derives")]
+pub struct CustomDerives;
+
+// #[tpacket]
+// struct CommentedOut;
+
+#[derive(Debug)]
+pub struct NotAPacket;
+
+pub mod inner {
+    #[tpacket]
+    pub struct InlineModulePacket;
+}
@@ -75,11 +75,40 @@ pub fn register_scan_dir(_input: TokenStream) -> TokenStream {
 ///
 /// When using `From::from()` on invalid strings, it will panic with an error message.
 ///
+/// ## Variants With Data
+///
+/// Tuple and struct variants are written as the variant name followed by each
+/// field in declaration order, separated by `:`. Fields are converted using
+/// their own `Display` and `FromStr` implementations. When parsing, the last
+/// field receives the rest of the string, so it may itself contain the separator.
+///
+/// ```
+/// # use tnet_macros::ParseEnumString;
+/// #[derive(Debug, PartialEq, ParseEnumString)]
+/// pub enum Header {
+///     Ping,
+///     Error(String),
+///     Chat { room: String, text: String },
+/// }
+///
+/// assert_eq!(Header::Error("oops".to_string()).to_string(), "Error:oops");
+/// assert_eq!(
+///     "Chat:lobby:hi: there".parse::<Header>().unwrap(),
+///     Header::Chat { room: "lobby".to_string(), text: "hi: there".to_string() }
+/// );
+/// ```
+///
+/// ## Attributes
+///
+/// - `#[enum_str(separator = "...")]` on the enum changes the field separator
+/// - `#[enum_str(name = "...")]` on a variant changes its string name
+///
+/// Any other key, or one of these in the wrong position, is a compile error.
+///
 /// # Limitations
 ///
-/// - This derive macro only works on enums with unit variants (no fields)
 /// - The string representation is case-sensitive
-/// - Variant names must be valid Rust identifiers
+/// - Only the last field of a variant may contain the separator
 ///
 /// # Example
 ///
@@ -117,49 +146,136 @@ pub fn register_scan_dir(_input: TokenStream) -> TokenStream {
 ///     assert_eq!(result.unwrap_err(), "Unknown variant: Unknown");
 /// }
 /// ```
-#[proc_macro_derive(ParseEnumString)]
+#[proc_macro_derive(ParseEnumString, attributes(enum_str))]
 pub fn parse_enum_string(input: TokenStream) -> TokenStream {
     // Parse the input tokens into a syntax tree
     let input = parse_macro_input!(input as DeriveInput);
     let name = &input.ident;
 
+    // Separator between the variant name and its fields
+    let separator = match enum_str_value(&input.attrs, "separator") {
+        Ok(separator) => separator.unwrap_or_else(|| ":".to_string()),
+        Err(e) => return e.to_compile_error().into(),
+    };
+
     // Extract enum variants
     let variants = match &input.data {
         Data::Enum(DataEnum { variants, .. }) => variants,
         _ => panic!("ParseEnumString can only be derived for enums"),
     };
 
-    // Generate match arms for to_string
-    let to_string_arms = variants.iter().map(|variant| {
+    let mut to_string_arms = Vec::new();
+    let mut unit_arms = Vec::new();
+    let mut data_arms = Vec::new();
+
+    for variant in variants {
         let variant_name = &variant.ident;
-        // Ensure variant has no fields
+        let variant_str = match enum_str_value(&variant.attrs, "name") {
+            Ok(variant_str) => variant_str.unwrap_or_else(|| variant_name.to_string()),
+            Err(e) => return e.to_compile_error().into(),
+        };
+
         match &variant.fields {
-            Fields::Unit => {}
-            _ => panic!("ParseEnumString only supports unit variants"),
+            Fields::Unit => {
+                to_string_arms.push(quote! {
+                    #name::#variant_name => write!(f, "{}", #variant_str)
+                });
+                unit_arms.push(quote! {
+                    #variant_str => Ok(#name::#variant_name)
+                });
+            }
+            Fields::Unnamed(fields) => {
+                let count = fields.unnamed.len();
+                let bindings = (0..count)
+                    .map(|i| format_ident!("field_{}", i))
+                    .collect::<Vec<_>>();
+                let parsers = fields
+                    .unnamed
+                    .iter()
+                    .map(|field| parse_enum_field(&field.ty, &variant_str));
+
+                to_string_arms.push(quote! {
+                    #name::#variant_name(#(#bindings),*) => {
+                        write!(f, "{}", #variant_str)?;
+                        #(write!(f, "{}{}", #separator, #bindings)?;)*
+                        Ok(())
+                    }
+                });
+                // `V()` is written without a separator, so it parses like a unit variant
+                if count == 0 {
+                    unit_arms.push(quote! {
+                        #variant_str => Ok(#name::#variant_name())
+                    });
+                } else {
+                    data_arms.push(quote! {
+                        #variant_str => {
+                            let mut parts = rest.splitn(#count, #separator);
+                            Ok(#name::#variant_name(#(#parsers),*))
+                        }
+                    });
+                }
+            }
+            Fields::Named(fields) => {
+                let count = fields.named.len();
+                let idents = fields
+                    .named
+                    .iter()
+                    .map(|field| field.ident.as_ref().unwrap())
+                    .collect::<Vec<_>>();
+                let parsers = fields
+                    .named
+                    .iter()
+                    .map(|field| parse_enum_field(&field.ty, &variant_str));
+
+                to_string_arms.push(quote! {
+                    #name::#variant_name { #(#idents),* } => {
+                        write!(f, "{}", #variant_str)?;
+                        #(write!(f, "{}{}", #separator, #idents)?;)*
+                        Ok(())
+                    }
+                });
+                if count == 0 {
+                    unit_arms.push(quote! {
+                        #variant_str => Ok(#name::#variant_name {})
+                    });
+                } else {
+                    data_arms.push(quote! {
+                        #variant_str => {
+                            let mut parts = rest.splitn(#count, #separator);
+                            Ok(#name::#variant_name { #(#idents: #parsers),* })
+                        }
+                    });
+                }
+            }
         }
-        let variant_str = variant_name.to_string();
+    }
+
+    // Only split off the fields when there are variants that carry any
+    let fallback_arm = if data_arms.is_empty() {
         quote! {
-            #name::#variant_name => #variant_str.to_string()
+            _ => Err(format!("Unknown variant: {}", s))
         }
-    });
-
-    // Generate match arms for from_str
-    let from_str_arms = variants.iter().map(|variant| {
-        let variant_name = &variant.ident;
-        let variant_str = variant_name.to_string();
+    } else {
         quote! {
-            #variant_str => Ok(#name::#variant_name)
+            _ => {
+                let (variant, rest) = s
+                    .split_once(#separator)
+                    .ok_or_else(|| format!("Unknown variant: {}", s))?;
+                match variant {
+                    #(#data_arms,)*
+                    _ => Err(format!("Unknown variant: {}", s)),
+                }
+            }
         }
-    });
+    };
 
     // Generate the implementation
     let expanded = quote! {
         impl std::fmt::Display for #name {
             fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-                let s = match self {
+                match self {
                     #(#to_string_arms),*
-                };
-                write!(f, "{}", s)
+                }
             }
         }
 
@@ -168,8 +284,8 @@ pub fn parse_enum_string(input: TokenStream) -> TokenStream {
 
             fn from_str(s: &str) -> Result<Self, Self::Err> {
                 match s {
-                    #(#from_str_arms),*,
-                    _ => Err(format!("Unknown variant: {}", s))
+                    #(#unit_arms,)*
+                    #fallback_arm
                 }
             }
         }
@@ -191,6 +307,43 @@ pub fn parse_enum_string(input: TokenStream) -> TokenStream {
     expanded.into()
 }
 
+/// Reads a string argument such as `name = "..."` from `#[enum_str(...)]` attributes.
+///
+/// Any other key is an error, so a typo or a key in the wrong position can't
+/// silently change the string format.
+fn enum_str_value(attrs: &[Attribute], key: &str) -> Result<Option<String>> {
+    let mut value = None;
+
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("enum_str")) {
+        attr.parse_nested_meta(|meta| {
+            if !meta.path.is_ident(key) {
+                let path = &meta.path;
+                return Err(meta.error(format!(
+                    "unsupported enum_str key `{}` here, expected `{}`",
+                    quote!(#path),
+                    key
+                )));
+            }
+            let lit: LitStr = meta.value()?.parse()?;
+            value = Some(lit.value());
+            Ok(())
+        })?;
+    }
+
+    Ok(value)
+}
+
+/// Generates the expression parsing the next field of a variant from the `parts` iterator.
+fn parse_enum_field(ty: &syn::Type, variant_str: &str) -> proc_macro2::TokenStream {
+    quote! {
+        parts
+            .next()
+            .ok_or_else(|| format!("Missing field for variant: {}", #variant_str))?
+            .parse::<#ty>()
+            .map_err(|e| format!("Invalid field for variant {}: {}", #variant_str, e))?
+    }
+}
+
 /// Registers a function as a packet handler for a specific packet type.
 ///
 /// This attribute macro allows you to define handler functions for specific packet types
@@ -306,7 +459,10 @@ pub fn parse_enum_string(input: TokenStream) -> TokenStream {
 ///
 /// # Combining with Packet Header Enums
 ///
-/// For better type safety, you can use this macro with the `PacketHeader` derive macro:
+/// For better type safety, the attribute also accepts a path to an enum variant instead of
+/// a string literal. The variant is converted with its `Display` implementation when the
+/// handler is registered, so a misspelled variant is a compile error rather than a handler
+/// that never fires:
 ///
 /// ```rust
 /// #[derive(Debug, Clone, PacketHeader)]
@@ -316,12 +472,12 @@ pub fn parse_enum_string(input: TokenStream) -> TokenStream {
 ///     Logout,
 /// }
 ///
-/// #[tlisten_for("Login")]
+/// #[tlisten_for(MyHeaders::Login)]
 /// async fn handle_login(sources: HandlerSources<MySession, MyResource>, packet: MyPacket) {
 ///     // Login handling logic
 /// }
 ///
-/// #[tlisten_for("Chat")]
+/// #[tlisten_for(MyHeaders::Chat)]
 /// async fn handle_chat(sources: HandlerSources<MySession, MyResource>, packet: MyPacket) {
 ///     // Chat handling logic
 /// }
@@ -340,11 +496,15 @@ pub fn parse_enum_string(input: TokenStream) -> TokenStream {
 /// - The handler function must be `async`
 /// - The handler function must be accessible where it's used (public or in the same module)
 /// - The handler must accept exactly two parameters: `HandlerSources` and a packet type
+///   (a non-async function or a different parameter count is rejected with a compile error)
 /// - The packet header string is case-sensitive and must match exactly what's returned by `Packet::header()`
 #[proc_macro_attribute]
 pub fn tlisten_for(attr: TokenStream, item: TokenStream) -> TokenStream {
-    let packet_type = parse_macro_input!(attr as LitStr).value();
+    let header = parse_macro_input!(attr as ListenHeader);
     let input_fn = parse_macro_input!(item as ItemFn);
+    if let Err(e) = check_handler_signature(&input_fn) {
+        return e.to_compile_error().into();
+    }
     let fn_name = &input_fn.sig.ident;
 
     // Generate a unique registration function name
@@ -353,10 +513,33 @@ pub fn tlisten_for(attr: TokenStream, item: TokenStream) -> TokenStream {
     // Extract the function's path for clarity in logs
     let fn_path = format!("{}::{}", module_path!(), fn_name);
 
+    // Variant paths are resolved next to the handler, where the user's imports apply
+    let (header_fn, packet_type) = match header {
+        ListenHeader::Literal(literal) => {
+            let packet_type = literal.value();
+            (quote! {}, quote! { #packet_type })
+        }
+        ListenHeader::Variant(path) => {
+            let header_fn_name = format_ident!("__tnet_header_{}", fn_name);
+            (
+                quote! {
+                    #[doc(hidden)]
+                    #[allow(non_snake_case)]
+                    fn #header_fn_name() -> std::string::String {
+                        std::string::ToString::to_string(&#path)
+                    }
+                },
+                quote! { super::#header_fn_name() },
+            )
+        }
+    };
+
     let expanded = quote! {
         // Keep the original function
         #input_fn
 
+        #header_fn
+
         // Create a unique module to avoid name conflicts
         #[doc(hidden)]
         #[allow(non_snake_case)]
@@ -370,15 +553,17 @@ pub fn tlisten_for(attr: TokenStream, item: TokenStream) -> TokenStream {
             #[ctor::ctor]
             fn register() {
                 let _ = REGISTER.get_or_init(|| {
+                    let packet_type = #packet_type;
+
                     // Only register once
                     tnet::handler_registry::register_handler(
-                        #packet_type,
+                        &packet_type,
                         |sources, packet| Box::pin(super::#fn_name(sources, packet))
                     );
 
                     // Optional: Log registration for debugging
                     #[cfg(debug_assertions)]
-                    println!("Registered handler for {} at {}", #packet_type, #fn_path);
+                    println!("Registered handler for {} at {}", packet_type, #fn_path);
                 });
             }
         }
@@ -387,43 +572,145 @@ pub fn tlisten_for(attr: TokenStream, item: TokenStream) -> TokenStream {
     TokenStream::from(expanded)
 }
 
+// Handlers are called as `handler(sources, packet)` and boxed as futures
+fn check_handler_signature(input_fn: &ItemFn) -> Result<()> {
+    let sig = &input_fn.sig;
+    if sig.asyncness.is_none() {
+        return Err(syn::Error::new_spanned(
+            sig.fn_token,
+            "tlisten_for handlers must be `async fn`",
+        ));
+    }
+    if sig.inputs.len() != 2 {
+        return Err(syn::Error::new(
+            sig.paren_token.span.join(),
+            format!(
+                "tlisten_for handlers take exactly two parameters, `(HandlerSources, Packet)`, found {}",
+                sig.inputs.len()
+            ),
+        ));
+    }
+    Ok(())
+}
+
+// The header given to `tlisten_for`: a string literal or a path to an enum variant
+enum ListenHeader {
+    Literal(LitStr),
+    Variant(syn::Path),
+}
+
+impl Parse for ListenHeader {
+    fn parse(input: ParseStream) -> Result<Self> {
+        if input.peek(LitStr) {
+            Ok(ListenHeader::Literal(input.parse()?))
+        } else {
+            Ok(ListenHeader::Variant(input.parse()?))
+        }
+    }
+}
+
 struct TPacketArgs {
     name: Option<String>,
+    // `None` keeps the default derives, `Some` replaces them (empty for `no_derive`)
+    derives: Option<Vec<syn::Path>>,
 }
 
 impl Parse for TPacketArgs {
     fn parse(input: ParseStream) -> Result<Self> {
-        // If input is empty, return default
-        if input.is_empty() {
-            return Ok(TPacketArgs { name: None });
-        }
+        let mut args = TPacketArgs {
+            name: None,
+            derives: None,
+        };
+
+        while !input.is_empty() {
+            // A bare literal string is shorthand for `name = "..."`
+            if input.peek(LitStr) {
+                let lit: LitStr = input.parse()?;
+                args.name = Some(lit.value());
+            } else {
+                let lookahead = input.lookahead1();
+                if !lookahead.peek(Ident) {
+                    return Err(lookahead.error());
+                }
 
-        // Parse a literal string if that's all that's provided
-        if input.peek(LitStr) {
-            let lit: LitStr = input.parse()?;
-            return Ok(TPacketArgs {
-                name: Some(lit.value()),
-            });
-        }
+                let ident: Ident = input.parse()?;
+                if ident == "name" {
+                    let _: Token![=] = input.parse()?;
+                    let lit: LitStr = input.parse()?;
+                    args.name = Some(lit.value());
+                } else if ident == "no_derive" {
+                    args.derives = Some(Vec::new());
+                } else if ident == "derive" {
+                    let content;
+                    syn::parenthesized!(content in input);
+                    let paths = Punctuated::<syn::Path, Token![,]>::parse_terminated(&content)?;
+                    args.derives = Some(paths.into_iter().collect());
+                } else {
+                    return Err(syn::Error::new(
+                        ident.span(),
+                        "Expected `name`, `derive` or `no_derive`",
+                    ));
+                }
+            }
 
-        // Try to parse name = "value" format
-        let lookahead = input.lookahead1();
-        if lookahead.peek(Ident) {
-            let ident: Ident = input.parse()?;
-            if ident == "name" {
-                let _: Token![=] = input.parse()?;
-                let lit: LitStr = input.parse()?;
-                return Ok(TPacketArgs {
-                    name: Some(lit.value()),
-                });
+            if input.is_empty() {
+                break;
             }
-            return Err(syn::Error::new(ident.span(), "Expected `name`"));
+            let _: Token![,] = input.parse()?;
         }
 
-        Err(lookahead.error())
+        Ok(args)
     }
 }
 
+// Bare `Serialize`/`Deserialize` refer to serde's derives, as in the default set
+fn resolve_derive(path: syn::Path) -> syn::Path {
+    if path.is_ident("Serialize") || path.is_ident("Deserialize") {
+        let ident = &path.segments[0].ident;
+        syn::parse_quote!(::serde::#ident)
+    } else {
+        path
+    }
+}
+
+// Names of the derives already present on the struct, compared by their last segment
+fn existing_derives(attrs: &[Attribute]) -> Vec<Ident> {
+    attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("derive"))
+        .filter_map(|attr| {
+            attr.parse_args_with(Punctuated::<syn::Path, Token![,]>::parse_terminated)
+                .ok()
+        })
+        .flatten()
+        .filter_map(|path| path.segments.last().map(|segment| segment.ident.clone()))
+        .collect()
+}
+
+/// Marks a struct as a packet type for the `TnetPacket` generated by `tnet-build`.
+///
+/// The struct is given `#[derive(Debug, Clone, Serialize, Deserialize)]`, skipping any of
+/// these it already derives. The attribute accepts, comma separated:
+///
+/// * `name = "..."` (or just `"..."`): the field name used in `TnetPacket`
+/// * `derive(...)`: derive these instead of the default set
+/// * `no_derive`: add no derives at all
+///
+/// # Example
+///
+/// ```rust
+/// #[tpacket(name = "chat")]
+/// #[derive(PartialEq)]
+/// struct ChatMessage {
+///     text: String,
+/// }
+///
+/// // `Connection` is neither `Clone` nor `Serialize`
+/// #[tpacket(derive(Debug))]
+/// struct LocalOnly {
+///     connection: Connection,
+/// }
+/// ```
 #[proc_macro_attribute]
 pub fn tpacket(args: TokenStream, item: TokenStream) -> TokenStream {
     // Parse the struct
@@ -450,52 +737,38 @@ pub fn tpacket(args: TokenStream, item: TokenStream) -> TokenStream {
     // Create a string value for the registration
     let marker_value = format!("{}={}", field_name, struct_name);
 
-    // Create a unique function name for registration
-    let register_fn_name = format_ident!(
-        "__tnet_register_{}",
-        to_snake_case(&struct_name.to_string())
-    );
-
-    let field_name_str = field_name.clone();
-    let struct_name_str = struct_name.to_string();
-
     // Create the registration code
     let registration = quote! {
         #[doc(hidden)]
         #[allow(dead_code)]
         pub static #marker_name: &'static str = #marker_value;
-
-        // Run at compile time to create marker files
-        #[doc(hidden)]
-        #[ctor::ctor]
-        fn #register_fn_name() {
-            // This function will be called when the program starts
-            // Get the full module path at runtime
-            let module_path = module_path!();
-
-            // Create the full type path by combining module path with struct name
-            let full_path = format!("{}::{}", module_path, #struct_name_str);
-
-            // Create a marker file in the temporary directory
-            let temp_dir = ::std::env::temp_dir().join("tnet_registry");
-            let _ = ::std::fs::create_dir_all(&temp_dir);
-            let temp_file = temp_dir.join(format!("{}.packet", #field_name_str));
-
-            // Store both the full path to the type and the custom field name
-            let data = format!("{}|{}", full_path, #field_name_str);
-            let _ = ::std::fs::write(&temp_file, &data);
-
-            // Also write to target directory for persistence
-            let target_dir = ::std::path::Path::new("target/.tpacket_markers");
-            let _ = ::std::fs::create_dir_all(target_dir);
-            let target_file = target_dir.join(format!("{}.marker", #field_name_str));
-            let _ = ::std::fs::write(&target_file, &data);
-        }
     };
 
-    // Always add the necessary derives
-    let derive_tokens = quote! {
-        #[derive(Debug, Clone, ::serde::Serialize, ::serde::Deserialize)]
+    // Add the requested derives, leaving out any the struct already has
+    let derives = args.derives.unwrap_or_else(|| {
+        vec![
+            syn::parse_quote!(Debug),
+            syn::parse_quote!(Clone),
+            syn::parse_quote!(::serde::Serialize),
+            syn::parse_quote!(::serde::Deserialize),
+        ]
+    });
+    let existing = existing_derives(&input.attrs);
+    let derives: Vec<syn::Path> = derives
+        .into_iter()
+        .map(resolve_derive)
+        .filter(|path| {
+            path.segments
+                .last()
+                .is_none_or(|segment| !existing.contains(&segment.ident))
+        })
+        .collect();
+    let derive_tokens = if derives.is_empty() {
+        quote! {}
+    } else {
+        quote! {
+            #[derive(#(#derives),*)]
+        }
     };
 
     // Combine everything and return
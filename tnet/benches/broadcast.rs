@@ -0,0 +1,150 @@
+//! Measures how long `TSockets::broadcast` takes to reach many connections.
+//!
+//! Run with `cargo bench -p tnet --bench broadcast`. The socket count and
+//! payload size can be changed with `TNET_BENCH_SOCKETS` and
+//! `TNET_BENCH_PAYLOAD`. Setting `TNET_BENCH_ENCRYPTION` to `per-socket`
+//! gives every socket its own key, and `shared` gives them all one encryptor.
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use tnet::{asynch::socket::TSockets, prelude::*};
+use tokio::{io::AsyncReadExt, net::TcpListener, sync::RwLock};
+
+const ROUNDS: usize = 50;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct BenchPacket {
+    header: String,
+    body: PacketBody,
+}
+
+impl ImplPacket for BenchPacket {
+    fn header(&self) -> String {
+        self.header.clone()
+    }
+
+    fn body(&self) -> PacketBody {
+        self.body.clone()
+    }
+
+    fn body_mut(&mut self) -> &mut PacketBody {
+        &mut self.body
+    }
+
+    fn ok() -> Self {
+        Self {
+            header: "OK".to_string(),
+            body: PacketBody::default(),
+        }
+    }
+
+    fn error(error: Error) -> Self {
+        Self {
+            header: "ERROR".to_string(),
+            body: PacketBody::with_error_string(error.to_string()),
+        }
+    }
+
+    fn keep_alive() -> Self {
+        Self {
+            header: "KEEPALIVE".to_string(),
+            body: PacketBody::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct BenchSession {
+    id: String,
+    created_at: u64,
+}
+
+impl ImplSession for BenchSession {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn created_at(&self) -> u64 {
+        self.created_at
+    }
+
+    fn lifespan(&self) -> Duration {
+        Duration::from_secs(3600)
+    }
+
+    fn empty(id: String) -> Self {
+        Self {
+            id,
+            created_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+        }
+    }
+}
+
+fn env_or(name: &str, default: usize) -> usize {
+    std::env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+#[tokio::main]
+async fn main() {
+    let socket_count = env_or("TNET_BENCH_SOCKETS", 1000);
+    let payload_len = env_or("TNET_BENCH_PAYLOAD", 1024);
+    let encryption = std::env::var("TNET_BENCH_ENCRYPTION").unwrap_or_default();
+    let shared_encryptor = Encryptor::new(&Encryptor::generate_key()).unwrap();
+
+    let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let sessions = Arc::new(RwLock::new(Sessions::<BenchSession>::new()));
+    let mut sockets = TSockets::new();
+
+    for i in 0..socket_count {
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        // Drain everything the broadcasts write so the send buffers never fill up
+        tokio::spawn(async move {
+            let mut buf = vec![0u8; 64 * 1024];
+            while matches!(client.read(&mut buf).await, Ok(n) if n > 0) {}
+        });
+
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut socket =
+            TSocket::new(stream, sessions.clone()).with_session_id(format!("bench-{i}"));
+        match encryption.as_str() {
+            "per-socket" => {
+                socket = socket.with_encryptor(Encryptor::new(&Encryptor::generate_key()).unwrap());
+            }
+            "shared" => socket = socket.with_encryptor(shared_encryptor.clone()),
+            _ => {}
+        }
+        sockets.add(socket).await;
+    }
+
+    let mut packet = BenchPacket::ok();
+    packet.body_mut().error_string = Some("x".repeat(payload_len));
+
+    // Warm up the connections before timing
+    sockets.broadcast(packet.clone()).await.unwrap();
+
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        sockets.broadcast(packet.clone()).await.unwrap();
+    }
+    let elapsed = start.elapsed();
+
+    println!(
+        "broadcast of {payload_len} bytes to {socket_count} sockets ({}): {:?} per round ({ROUNDS} rounds)",
+        if encryption.is_empty() {
+            "plain"
+        } else {
+            &encryption
+        },
+        elapsed / ROUNDS as u32
+    );
+}
@@ -1,7 +1,17 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
 use serde::{Deserialize, Serialize};
 
 use crate::errors::Error;
-use std::{future::Future, pin::Pin};
+use std::{
+    future::Future,
+    net::IpAddr,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use super::rate_limit::AuthLockout;
 
 /// Defines the authentication methods supported by the system.
 ///
@@ -12,6 +22,8 @@ use std::{future::Future, pin::Pin};
 ///
 /// * `RootPassword` - Single password authentication for root access
 /// * `UserPassword` - Individual username/password pairs for each user
+/// * `PreSharedKey` - A token or API key presented by the client
+/// * `Challenge` - The client answers a nonce issued by the server
 /// * `None` - No authentication required
 ///
 /// # Example
@@ -23,6 +35,8 @@ use std::{future::Future, pin::Pin};
 /// match auth_type {
 ///     AuthType::RootPassword => println!("Using root password authentication"),
 ///     AuthType::UserPassword => println!("Using per-user authentication"),
+///     AuthType::PreSharedKey => println!("Using key authentication"),
+///     AuthType::Challenge => println!("Using challenge-response authentication"),
 ///     AuthType::None => println!("No authentication required"),
 /// }
 /// ```
@@ -32,6 +46,10 @@ pub enum AuthType {
     RootPassword,
     /// Each user has their own password.
     UserPassword,
+    /// The client presents a token or API key.
+    PreSharedKey,
+    /// The server sends a nonce and the client answers it, for example by signing it.
+    Challenge,
     /// There is no authentication
     None,
 }
@@ -66,6 +84,107 @@ pub type AuthFunction = fn(
     password: String,
 ) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send>>;
 
+/// Type alias for key validation function.
+///
+/// Represents a function that takes the key presented by a client and returns
+/// a future that resolves to a Result indicating whether the key is accepted.
+///
+/// # Type Parameters
+///
+/// * Input: `String` - The presented key
+/// * Output: `Result<(), Error>` - Authentication result
+///
+/// # Example
+///
+/// ```rust
+/// use tnet::asynch::authenticator::KeyFunction;
+///
+/// let key_fn: KeyFunction = |key: String| {
+///     Box::pin(async move {
+///         if key == "api-key-123" {
+///             Ok(())
+///         } else {
+///             Err(Error::InvalidCredentials)
+///         }
+///     })
+/// };
+/// ```
+pub type KeyFunction = fn(key: String) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send>>;
+
+/// Type alias for challenge validation function.
+///
+/// Represents a function that takes the challenge issued by the server and the
+/// client's answer, and returns a future that resolves to a Result indicating
+/// whether the answer is accepted.
+///
+/// # Type Parameters
+///
+/// * Input: (`String`, `String`) - The issued challenge and the client's response
+/// * Output: `Result<(), Error>` - Authentication result
+///
+/// # Example
+///
+/// ```rust
+/// use tnet::asynch::authenticator::ChallengeFunction;
+///
+/// let challenge_fn: ChallengeFunction = |challenge: String, response: String| {
+///     Box::pin(async move {
+///         if response == format!("signed:{challenge}") {
+///             Ok(())
+///         } else {
+///             Err(Error::InvalidCredentials)
+///         }
+///     })
+/// };
+/// ```
+pub type ChallengeFunction = fn(
+    challenge: String,
+    response: String,
+) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send>>;
+
+/// Type alias for the client side of challenge-response authentication.
+///
+/// Takes the challenge sent by the server and returns the answer to send back.
+///
+/// # Example
+///
+/// ```rust
+/// use tnet::asynch::authenticator::ChallengeResponder;
+///
+/// let responder: ChallengeResponder = |challenge| format!("signed:{challenge}");
+/// ```
+pub type ChallengeResponder = fn(challenge: String) -> String;
+
+/// What is known about a client when a session is created for it.
+///
+/// # Fields
+///
+/// * `auth_type` - How the client authenticated
+/// * `username` - The username the client logged in with, for password authentication
+/// * `peer_ip` - The client's IP address, or None for Unix domain socket and in-memory connections
+#[derive(Debug, Clone, Copy)]
+pub struct SessionIdContext<'a> {
+    pub auth_type: &'a AuthType,
+    pub username: Option<&'a str>,
+    pub peer_ip: Option<IpAddr>,
+}
+
+/// Type alias for session id generation functions.
+///
+/// Takes what is known about the client and returns the id of its new session.
+/// Ids must be unique among the sessions in the store.
+///
+/// # Example
+///
+/// ```rust
+/// use tnet::asynch::authenticator::SessionIdFunction;
+///
+/// let session_id_fn: SessionIdFunction = |context| {
+///     format!("{}-{}", context.username.unwrap_or("guest"), uuid::Uuid::new_v4())
+/// };
+/// ```
+pub type SessionIdFunction = fn(context: &SessionIdContext<'_>) -> String;
+
 /**
 Main authenticator structure that handles all authentication operations.
 
@@ -77,6 +196,10 @@ different authentication methods and maintain authentication state.
 * `auth_type` - The type of authentication being used
 * `root_password` - Optional root password for `RootPassword` authentication
 * `auth_fn` - Optional function for custom authentication logic
+* `key_fn` - Optional function for validating pre-shared keys
+* `challenge_fn` - Optional function for validating challenge responses
+* `lockout` - Optional per-IP lockout after repeated failed attempts, shared by clones
+* `session_id_fn` - Optional function generating the ids of new sessions
 
 # Example
 
@@ -92,6 +215,10 @@ pub struct Authenticator {
     pub auth_type: AuthType,
     pub root_password: Option<String>,
     pub auth_fn: Option<AuthFunction>,
+    pub key_fn: Option<KeyFunction>,
+    pub challenge_fn: Option<ChallengeFunction>,
+    pub(crate) lockout: Option<Arc<Mutex<AuthLockout<IpAddr>>>>,
+    pub session_id_fn: Option<SessionIdFunction>,
 }
 
 impl Authenticator {
@@ -130,6 +257,9 @@ impl Authenticator {
     - Root password is not set for `RootPassword` authentication
     - Username/password combination is invalid
     - Authentication function is not set for `UserPassword` authentication
+    - The authenticator uses `PreSharedKey` or `Challenge` authentication, which go
+      through [`Authenticator::authenticate_key`] and
+      [`Authenticator::authenticate_challenge`] instead
     */
     pub async fn authenticate(&mut self, username: String, password: String) -> Result<(), Error> {
         match self.auth_type {
@@ -148,11 +278,104 @@ impl Authenticator {
                 let auth_fn = self.auth_fn.as_ref().unwrap();
                 auth_fn(username, password).await?;
             }
+            AuthType::PreSharedKey | AuthType::Challenge => return Err(Error::InvalidCredentials),
             AuthType::None => {}
         }
         Ok(())
     }
 
+    /// Authenticates a client by the key it presented.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The token or API key to validate
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), Error>` - Ok(()) if the key is accepted, Error otherwise
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::InvalidCredentials` if:
+    /// - The authenticator does not use `PreSharedKey` authentication
+    /// - No key function is set
+    /// - The key function rejects the key
+    pub async fn authenticate_key(&mut self, key: String) -> Result<(), Error> {
+        if self.auth_type != AuthType::PreSharedKey {
+            return Err(Error::InvalidCredentials);
+        }
+        match self.key_fn.as_ref() {
+            Some(key_fn) => key_fn(key).await,
+            None => Err(Error::InvalidCredentials),
+        }
+    }
+
+    /// Authenticates a client by its answer to a challenge.
+    ///
+    /// # Arguments
+    ///
+    /// * `challenge` - The challenge that was sent to the client
+    /// * `response` - The client's answer
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), Error>` - Ok(()) if the answer is accepted, Error otherwise
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::InvalidCredentials` if:
+    /// - The authenticator does not use `Challenge` authentication
+    /// - No challenge function is set
+    /// - The challenge function rejects the answer
+    pub async fn authenticate_challenge(
+        &mut self,
+        challenge: String,
+        response: String,
+    ) -> Result<(), Error> {
+        if self.auth_type != AuthType::Challenge {
+            return Err(Error::InvalidCredentials);
+        }
+        match self.challenge_fn.as_ref() {
+            Some(challenge_fn) => challenge_fn(challenge, response).await,
+            None => Err(Error::InvalidCredentials),
+        }
+    }
+
+    /// Generates a fresh random challenge to send to a client.
+    ///
+    /// # Returns
+    ///
+    /// * `String` - 32 random bytes encoded as base64
+    #[must_use]
+    pub fn issue_challenge() -> String {
+        BASE64.encode(rand::random::<[u8; 32]>())
+    }
+
+    /// Checks whether authentication attempts from `ip` are currently locked out.
+    ///
+    /// Always `false` when no lockout is configured or the peer has no IP address.
+    pub(crate) fn is_locked_out(&self, ip: Option<IpAddr>) -> bool {
+        match (ip, &self.lockout) {
+            (Some(ip), Some(lockout)) => lockout
+                .lock()
+                .expect("Auth lockout lock poisoned")
+                .is_locked(&ip),
+            _ => false,
+        }
+    }
+
+    /// Records the outcome of an authentication attempt from `ip` for the lockout.
+    pub(crate) fn record_attempt(&self, ip: Option<IpAddr>, succeeded: bool) {
+        if let (Some(ip), Some(lockout)) = (ip, &self.lockout) {
+            let mut lockout = lockout.lock().expect("Auth lockout lock poisoned");
+            if succeeded {
+                lockout.record_success(&ip);
+            } else {
+                lockout.record_failure(ip);
+            }
+        }
+    }
+
     /// Creates a new Authenticator instance with the specified authentication type.
     ///
     /// # Arguments
@@ -174,6 +397,10 @@ impl Authenticator {
             auth_type: type_,
             root_password: None,
             auth_fn: None,
+            key_fn: None,
+            challenge_fn: None,
+            lockout: None,
+            session_id_fn: None,
         }
     }
 
@@ -227,4 +454,139 @@ impl Authenticator {
         self.auth_fn = Some(auth_fn);
         self
     }
+
+    /// Sets the key validation function for `PreSharedKey` authentication.
+    ///
+    /// # Arguments
+    ///
+    /// * `key_fn` - The function used to validate presented keys
+    ///
+    /// # Returns
+    ///
+    /// * The modified Authenticator instance
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let auth = Authenticator::new(AuthType::PreSharedKey)
+    ///     .with_key_fn(|key| Box::pin(async move {
+    ///         if key == "api-key-123" {
+    ///             Ok(())
+    ///         } else {
+    ///             Err(Error::InvalidCredentials)
+    ///         }
+    ///     }));
+    /// ```
+    #[must_use]
+    pub fn with_key_fn(mut self, key_fn: KeyFunction) -> Self {
+        self.key_fn = Some(key_fn);
+        self
+    }
+
+    /// Sets the challenge validation function for `Challenge` authentication.
+    ///
+    /// # Arguments
+    ///
+    /// * `challenge_fn` - The function used to check a client's answer to a challenge
+    ///
+    /// # Returns
+    ///
+    /// * The modified Authenticator instance
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let auth = Authenticator::new(AuthType::Challenge)
+    ///     .with_challenge_fn(|challenge, response| Box::pin(async move {
+    ///         if response == format!("signed:{challenge}") {
+    ///             Ok(())
+    ///         } else {
+    ///             Err(Error::InvalidCredentials)
+    ///         }
+    ///     }));
+    /// ```
+    #[must_use]
+    pub fn with_challenge_fn(mut self, challenge_fn: ChallengeFunction) -> Self {
+        self.challenge_fn = Some(challenge_fn);
+        self
+    }
+
+    /// Sets the function generating the ids of new sessions.
+    ///
+    /// Without one, sessions get random UUIDs. Prefixed or user derived ids are
+    /// easier to follow in logs, but must still be unique and hard to guess
+    /// when clients resume sessions by id.
+    ///
+    /// # Arguments
+    ///
+    /// * `session_id_fn` - The function returning the id of each new session
+    ///
+    /// # Returns
+    ///
+    /// * The modified Authenticator instance
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let auth = Authenticator::new(AuthType::UserPassword)
+    ///     .with_auth_fn(auth_fn)
+    ///     .with_session_id_fn(|context| {
+    ///         format!("{}-{}", context.username.unwrap_or("guest"), uuid::Uuid::new_v4())
+    ///     });
+    /// ```
+    #[must_use]
+    pub fn with_session_id_fn(mut self, session_id_fn: SessionIdFunction) -> Self {
+        self.session_id_fn = Some(session_id_fn);
+        self
+    }
+
+    /// Generates the id of a new session.
+    ///
+    /// # Arguments
+    ///
+    /// * `context` - What is known about the client the session is for
+    ///
+    /// # Returns
+    ///
+    /// * The id from the configured session id function, or a random UUID
+    #[must_use]
+    pub fn new_session_id(&self, context: &SessionIdContext<'_>) -> String {
+        self.session_id_fn.map_or_else(
+            || uuid::Uuid::new_v4().to_string(),
+            |session_id_fn| session_id_fn(context),
+        )
+    }
+
+    /// Locks out peers that fail authentication too often.
+    ///
+    /// Once an IP fails `max_attempts` times within `window`, further attempts from
+    /// it are rejected with `Error::AuthRateLimited` for `cooldown`, without calling
+    /// the configured authentication function.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_attempts` - Failures allowed within the window before locking out
+    /// * `window` - Period over which failures are counted
+    /// * `cooldown` - How long a locked out IP is rejected for
+    ///
+    /// # Returns
+    ///
+    /// * The modified Authenticator instance
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let auth = Authenticator::new(AuthType::UserPassword)
+    ///     .with_auth_fn(auth_fn)
+    ///     .with_lockout(5, Duration::from_secs(60), Duration::from_secs(300));
+    /// ```
+    #[must_use]
+    pub fn with_lockout(mut self, max_attempts: u32, window: Duration, cooldown: Duration) -> Self {
+        self.lockout = Some(Arc::new(Mutex::new(AuthLockout::new(
+            max_attempts,
+            window,
+            cooldown,
+        ))));
+        self
+    }
 }
@@ -0,0 +1,113 @@
+//! Reassembly of packets split up by `AsyncClient::send_chunked`.
+//!
+//! A chunked transfer is a series of packets marked with a [`PacketChunk`] that
+//! share a transfer id, each carrying a slice of the attachment. The listener feeds
+//! every received packet through a [`ChunkedTransfers`] buffer and only dispatches
+//! the last chunk, with the whole attachment restored.
+
+use std::collections::HashMap;
+
+use crate::{
+    errors::Error,
+    packet::{Packet, PacketChunk},
+};
+
+/// Most attachment bytes a connection may buffer for chunked transfers unless a
+/// different limit is configured.
+pub const DEFAULT_MAX_TRANSFER_LEN: usize = 64 * 1024 * 1024;
+
+// A transfer whose last chunk hasn't arrived yet
+struct PendingTransfer {
+    next_index: u32,
+    data: Vec<u8>,
+}
+
+/// The chunked transfers in progress on one connection.
+pub struct ChunkedTransfers {
+    pending: HashMap<u64, PendingTransfer>,
+    buffered: usize,
+    max_len: usize,
+}
+
+impl ChunkedTransfers {
+    /// Creates an empty buffer.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_len` - Most attachment bytes buffered across all pending transfers
+    pub fn new(max_len: usize) -> Self {
+        Self {
+            pending: HashMap::new(),
+            buffered: 0,
+            max_len,
+        }
+    }
+
+    /// Passes a received packet through the buffer.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - The packet read from the connection
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(packet))` - A packet to dispatch: either it wasn't chunked, or it is
+    ///   the last chunk of a transfer and now carries the whole attachment
+    /// * `Ok(None)` - The chunk was buffered until the rest of its transfer arrives
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::InvalidPacket` if a chunk arrives out of sequence and
+    /// `Error::PacketTooLarge` if buffering it would exceed the limit. The chunk's
+    /// transfer is dropped in both cases.
+    pub fn accept<P: Packet>(&mut self, mut packet: P) -> Result<Option<P>, Error> {
+        let chunk = match packet.body_mut().chunk.take() {
+            Some(chunk) => chunk,
+            None => return Ok(Some(packet)),
+        };
+
+        // A first chunk replaces any earlier transfer that reused its id
+        let transfer = self.pending.remove(&chunk.transfer_id);
+        if let Some(transfer) = &transfer {
+            self.buffered -= transfer.data.len();
+        }
+        let mut transfer = match transfer {
+            _ if chunk.index == 0 => PendingTransfer {
+                next_index: 0,
+                data: Vec::new(),
+            },
+            Some(transfer) => transfer,
+            None => return Err(out_of_sequence(chunk)),
+        };
+        let expected = transfer.next_index;
+        if chunk.index != expected || expected >= chunk.count {
+            return Err(out_of_sequence(chunk));
+        }
+
+        let data = packet.body_mut().attachment.take().unwrap_or_default();
+        let buffered = self.buffered + transfer.data.len() + data.len();
+        if buffered > self.max_len {
+            return Err(Error::PacketTooLarge(buffered));
+        }
+        transfer.data.extend_from_slice(&data);
+        transfer.next_index += 1;
+
+        if transfer.next_index < chunk.count {
+            self.buffered = buffered;
+            self.pending.insert(chunk.transfer_id, transfer);
+            return Ok(None);
+        }
+
+        packet.body_mut().attachment = Some(transfer.data);
+        Ok(Some(packet))
+    }
+}
+
+fn out_of_sequence(chunk: PacketChunk) -> Error {
+    Error::InvalidPacket(format!(
+        "Chunk {} of {} for transfer {} arrived out of sequence",
+        chunk.index + 1,
+        chunk.count,
+        chunk.transfer_id
+    ))
+}
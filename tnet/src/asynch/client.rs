@@ -1,26 +1,36 @@
 use std::{
     marker::PhantomData,
+    net::SocketAddr,
     sync::{
         Arc,
-        atomic::{AtomicBool, Ordering},
+        atomic::{AtomicBool, AtomicUsize, Ordering},
     },
-    time::Duration,
+    time::{Duration, Instant},
 };
 
+use bytes::Bytes;
+use futures::Stream;
+use rand::Rng;
 use serde::{Deserialize, Serialize};
+#[cfg(unix)]
+use tokio::net::UnixStream;
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
-    sync::{Mutex, mpsc},
+    io::{AsyncRead, AsyncWrite, DuplexStream},
+    net::TcpStream,
+    sync::{Mutex, broadcast, mpsc},
 };
+use tracing::{debug, info, warn};
 
 use crate::{
-    encrypt::{Encryptor, KeyExchange},
+    compression::CompressionConfig,
+    encrypt::{CipherSuite, Encryptor, HandshakeHello, KeyExchange, Role},
     errors::Error,
-    packet::{self, Packet},
+    metrics::{Counter, Metrics, NoopMetrics, Observation},
+    packet::{self, Packet, PacketChunk},
     phantom::PhantomPacket,
 };
 
-use super::client_ext::AsyncClientRef;
+use super::{authenticator::ChallengeResponder, client_ext::AsyncClientRef, connection, framing};
 
 /// Represents the encryption state of a client connection.
 ///
@@ -30,13 +40,31 @@ use super::client_ext::AsyncClientRef;
 /// # Variants
 ///
 /// * `None` - No encryption is being used
-/// * `Encrypted` - Connection is encrypted using the provided encryptor
+/// * `Encrypted` - Connection is encrypted using the provided encryptor, which also
+///   records the cipher suite agreed during the key exchange
 #[derive(Clone)]
 pub enum ClientEncryption {
     None,
     Encrypted(Box<Encryptor>),
 }
 
+impl ClientEncryption {
+    /// Returns the encryptor if the connection is encrypted.
+    #[must_use]
+    pub fn encryptor(&self) -> Option<&Encryptor> {
+        match self {
+            Self::None => None,
+            Self::Encrypted(encryptor) => Some(encryptor),
+        }
+    }
+
+    /// Returns the cipher suite agreed for the connection, if it is encrypted.
+    #[must_use]
+    pub fn suite(&self) -> Option<CipherSuite> {
+        self.encryptor().map(Encryptor::suite)
+    }
+}
+
 /// Configuration settings for client encryption.
 ///
 /// Defines how the client should handle encryption, including whether it's enabled,
@@ -45,8 +73,11 @@ pub enum ClientEncryption {
 /// # Fields
 ///
 /// * `enabled` - Whether encryption is enabled
-/// * `key` - Optional encryption key (32 bytes)
+/// * `key` - Optional encryption key (32 bytes). The client encrypts with
+///   `Role::Client`, so the peer must use `Role::Server`
 /// * `auto_key_exchange` - Whether to automatically perform key exchange
+/// * `cipher_suites` - Supported cipher suites, most preferred first. Empty means
+///   every suite in `CipherSuite::ALL`
 ///
 /// # Example
 ///
@@ -57,6 +88,7 @@ pub enum ClientEncryption {
 ///     enabled: true,
 ///     key: Some([0u8; 32]),
 ///     auto_key_exchange: true,
+///     cipher_suites: Vec::new(),
 /// };
 /// ```
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -64,6 +96,8 @@ pub struct EncryptionConfig {
     pub enabled: bool,
     pub key: Option<[u8; 32]>,
     pub auto_key_exchange: bool,
+    #[serde(default)]
+    pub cipher_suites: Vec<CipherSuite>,
 }
 
 impl EncryptionConfig {
@@ -74,6 +108,7 @@ impl EncryptionConfig {
             enabled: true,
             key: None,
             auto_key_exchange: true,
+            cipher_suites: Vec::new(),
         }
     }
 
@@ -84,6 +119,36 @@ impl EncryptionConfig {
             enabled: false,
             key: None,
             auto_key_exchange: true,
+            cipher_suites: Vec::new(),
+        }
+    }
+
+    /// Restricts the cipher suites offered or accepted during the key exchange.
+    ///
+    /// # Arguments
+    ///
+    /// * `suites` - The supported suites, most preferred first
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The modified configuration
+    #[must_use]
+    pub fn with_cipher_suites(mut self, suites: impl Into<Vec<CipherSuite>>) -> Self {
+        self.cipher_suites = suites.into();
+        self
+    }
+
+    /// Returns the supported cipher suites, most preferred first.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<CipherSuite>` - `cipher_suites`, or every suite if none were listed
+    #[must_use]
+    pub fn supported_suites(&self) -> Vec<CipherSuite> {
+        if self.cipher_suites.is_empty() {
+            CipherSuite::ALL.to_vec()
+        } else {
+            self.cipher_suites.clone()
         }
     }
 }
@@ -94,6 +159,7 @@ impl Default for EncryptionConfig {
             enabled: false,
             key: None,
             auto_key_exchange: true,
+            cipher_suites: Vec::new(),
         }
     }
 }
@@ -101,16 +167,32 @@ impl Default for EncryptionConfig {
 /// Configuration settings for keep-alive functionality.
 ///
 /// Defines whether and how often keep-alive messages should be sent
-/// to maintain the connection.
+/// to maintain the connection, and when a silent connection is given up on.
 ///
 /// # Fields
 ///
 /// * `enabled` - Whether keep-alive is enabled
 /// * `interval` - Time in seconds between keep-alive messages
+/// * `max_failures` - Consecutive failed keep-alives before the connection is declared
+///   dead, at least one
+/// * `probe_every` - Check that the writer is still responsive every this many
+///   keep-alives, or never if zero
+///
+/// # Example
+///
+/// ```rust
+/// use tnet::asynch::client::KeepAliveConfig;
+///
+/// let keep_alive = KeepAliveConfig::default_on()
+///     .with_max_failures(1)
+///     .with_probe_every(2);
+/// ```
 #[derive(Debug, Clone)]
 pub struct KeepAliveConfig {
     pub enabled: bool,
     pub interval: u64,
+    pub max_failures: u32,
+    pub probe_every: u32,
 }
 
 impl KeepAliveConfig {
@@ -119,48 +201,226 @@ impl KeepAliveConfig {
     pub const fn default_on() -> Self {
         Self {
             enabled: true,
+            ..Self::disabled()
+        }
+    }
+
+    /// Sets how many consecutive keep-alive failures close the connection.
+    #[must_use]
+    pub const fn with_max_failures(mut self, max_failures: u32) -> Self {
+        self.max_failures = max_failures;
+        self
+    }
+
+    /// Sets how many keep-alives are sent between writer probes.
+    #[must_use]
+    pub const fn with_probe_every(mut self, probe_every: u32) -> Self {
+        self.probe_every = probe_every;
+        self
+    }
+
+    const fn disabled() -> Self {
+        Self {
+            enabled: false,
             interval: 30,
+            max_failures: 3,
+            probe_every: 5,
         }
     }
 }
 
 impl Default for KeepAliveConfig {
+    fn default() -> Self {
+        Self::disabled()
+    }
+}
+
+/// Configuration settings for network timeouts.
+///
+/// # Fields
+///
+/// * `send` - How long `AsyncClient::send` and keep-alive sends wait for room in the send queue
+/// * `recv` - How long `AsyncClient::recv` waits for a packet
+/// * `read` - How long `TSocket::recv` waits for data before returning `Error::ReadTimeout`
+/// * `keepalive_ping` - How long the keep-alive task waits for the writer to answer a ping
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+/// use tnet::asynch::client::TimeoutConfig;
+///
+/// let timeouts = TimeoutConfig {
+///     recv: Duration::from_secs(30),
+///     ..TimeoutConfig::default()
+/// };
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeoutConfig {
+    pub send: Duration,
+    pub recv: Duration,
+    pub read: Duration,
+    pub keepalive_ping: Duration,
+}
+
+impl Default for TimeoutConfig {
     fn default() -> Self {
         Self {
-            enabled: false,
-            interval: 30,
+            send: Duration::from_secs(5),
+            recv: Duration::from_secs(10),
+            read: Duration::from_secs(1),
+            keepalive_ping: Duration::from_secs(2),
+        }
+    }
+}
+
+/// Per-call settings for `AsyncClient::send_recv_with`.
+///
+/// # Fields
+///
+/// * `timeout` - How long to wait for the response, or `None` to use `TimeoutConfig::recv`
+/// * `retries` - How many times to resend after the connection fails or the wait times out
+/// * `reconnect_on_fail` - Whether to reconnect before resending after the connection closes
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+/// use tnet::asynch::client::SendRecvOptions;
+///
+/// let options = SendRecvOptions::default()
+///     .with_timeout(Duration::from_secs(2))
+///     .with_retries(0);
+/// let response = client.send_recv_with(packet, options).await?;
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SendRecvOptions {
+    pub timeout: Option<Duration>,
+    pub retries: usize,
+    pub reconnect_on_fail: bool,
+}
+
+impl SendRecvOptions {
+    /// Sets how long to wait for the response.
+    #[must_use]
+    pub const fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Sets how many times the request is resent after a failure.
+    #[must_use]
+    pub const fn with_retries(mut self, retries: usize) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Sets whether a closed connection is re-established before resending.
+    #[must_use]
+    pub const fn with_reconnect_on_fail(mut self, reconnect_on_fail: bool) -> Self {
+        self.reconnect_on_fail = reconnect_on_fail;
+        self
+    }
+}
+
+impl Default for SendRecvOptions {
+    fn default() -> Self {
+        Self {
+            timeout: None,
+            retries: 5,
+            reconnect_on_fail: true,
         }
     }
 }
 
+/// The state of a client's connection, as reported by `AsyncClient::status`.
+///
+/// # Variants
+///
+/// * `Connecting` - Connected to the server but nothing has been received from it yet
+/// * `Connected` - The connection is up and healthy
+/// * `Unstable` - The connection is up but keep-alives or sends have been failing
+/// * `Reconnecting` - The client is trying to re-establish a lost connection
+/// * `Closed` - The connection was closed by either side
+///
+/// # Example
+///
+/// ```rust
+/// use tnet::asynch::client::ConnectionStatus;
+///
+/// match client.status() {
+///     ConnectionStatus::Connected => println!("Online"),
+///     ConnectionStatus::Unstable | ConnectionStatus::Reconnecting => println!("Degraded"),
+///     ConnectionStatus::Connecting => println!("Connecting..."),
+///     ConnectionStatus::Closed => println!("Offline"),
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionStatus {
+    Connecting,
+    Connected,
+    Unstable,
+    Reconnecting,
+    Closed,
+}
+
 /// Messages that can be sent through the client's internal channels.
 ///
 /// Used for internal communication between different parts of the client.
 ///
 /// # Variants
 ///
-/// * `Data` - Regular data packet
-/// * `Keepalive` - Keep-alive message
+/// * `Data` - Regular data packet, encrypted by the writer if an encryptor is given
+/// * `Keepalive` - Keep-alive message, encrypted like `Data`
 /// * `Ping` - Connection test with response channel
-#[derive(Debug)]
 pub enum ClientMessage {
-    Data(Vec<u8>),
-    Keepalive(Vec<u8>),
+    Data(Bytes, Option<Encryptor>),
+    Keepalive(Bytes, Option<Encryptor>),
     Ping(tokio::sync::oneshot::Sender<bool>),
 }
 
 /// Handles the connection's I/O channels.
 ///
-/// Provides channels for sending and receiving data through the connection.
+/// Provides the channel for sending data through the connection. Only the
+/// reader task holds the sending side of the response channel, so receivers
+/// see it close as soon as the connection goes away.
 ///
 /// # Fields
 ///
 /// * `writer_tx` - Channel for sending data
-/// * `reader_tx` - Channel for receiving data
 #[derive(Debug)]
 pub struct ConnectionHandler {
     pub writer_tx: mpsc::Sender<ClientMessage>,
-    pub reader_tx: mpsc::Sender<Vec<u8>>,
+}
+
+/// Number of outgoing messages that can be queued for the writer task.
+pub const WRITE_QUEUE_CAPACITY: usize = 32;
+
+/// The address a client connected to, kept so it can reconnect.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Endpoint {
+    Tcp(String, u16),
+    #[cfg(unix)]
+    Unix(std::path::PathBuf),
+    /// A WebSocket URL, such as `ws://127.0.0.1:8080`
+    #[cfg(feature = "websocket")]
+    WebSocket(String),
+    /// An in-memory stream, which can't be reconnected
+    Memory,
+}
+
+impl std::fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Tcp(host, port) if host.contains(':') => write!(f, "[{host}]:{port}"),
+            Self::Tcp(host, port) => write!(f, "{host}:{port}"),
+            #[cfg(unix)]
+            Self::Unix(path) => write!(f, "{}", path.display()),
+            #[cfg(feature = "websocket")]
+            Self::WebSocket(url) => write!(f, "{url}"),
+            Self::Memory => write!(f, "memory"),
+        }
+    }
 }
 
 /// Type alias for message handling functions.
@@ -169,6 +429,13 @@ pub type MessageHandler<P> = Box<dyn Fn(&P) -> bool + Send + Sync>;
 /// Type alias for broadcast handling functions.
 pub type BroadcastHandler<P> = Box<dyn Fn(P) + Send + Sync>;
 
+/// Type alias for callbacks run when the client connects or loses its connection.
+pub type LifecycleHandler = Arc<dyn Fn() + Send + Sync>;
+
+/// Type alias for callbacks run after a reconnect, given the attempt that succeeded
+/// (starting at 1) and the endpoint the client reconnected to.
+pub type ReconnectHandler = Arc<dyn Fn(usize, &Endpoint) + Send + Sync>;
+
 /// Configuration for reconnection behavior with exponential backoff.
 #[derive(Debug, Clone)]
 pub struct ReconnectionConfig {
@@ -184,10 +451,13 @@ pub struct ReconnectionConfig {
     pub max_retry_delay: f64,
     /// Multiplier for exponential backoff (e.g., 1.5 means each retry is 1.5x longer than previous)
     pub backoff_factor: f64,
-    /// Random jitter factor (0.0-1.0) to add to delay to prevent thundering herd
+    /// Random jitter factor (0.0-1.0) spreading each delay by up to this fraction
+    /// of it in either direction, to prevent thundering herd
     pub jitter: f64,
     /// Whether to send initialization packets after successful reconnection
     pub reinitialize: bool,
+    /// Maximum time to spend reconnecting, across all attempts (None for unlimited)
+    pub max_total_retry_duration: Option<Duration>,
 }
 
 impl ReconnectionConfig {
@@ -201,8 +471,31 @@ impl ReconnectionConfig {
             backoff_factor: 1.5,
             jitter: 0.1,
             reinitialize: true,
+            max_total_retry_duration: None,
         }
     }
+
+    /// Calculates how long to wait before a reconnection attempt.
+    ///
+    /// The delay grows by `backoff_factor` with every attempt and is moved up
+    /// or down by a random amount of at most `jitter` times the delay. The
+    /// result never drops below `initial_retry_delay` or exceeds `max_retry_delay`.
+    ///
+    /// # Arguments
+    ///
+    /// * `attempt` - The number of attempts already made, starting at 0
+    ///
+    /// # Returns
+    ///
+    /// * `f64` - The delay in seconds
+    #[must_use]
+    pub fn backoff_delay(&self, attempt: usize) -> f64 {
+        let backoff = self.initial_retry_delay * self.backoff_factor.powi(attempt as i32);
+        let jitter = rand::thread_rng().gen_range(-1.0..=1.0) * self.jitter * backoff;
+        (backoff + jitter)
+            .max(self.initial_retry_delay)
+            .min(self.max_retry_delay)
+    }
 }
 
 impl Default for ReconnectionConfig {
@@ -216,6 +509,7 @@ impl Default for ReconnectionConfig {
             backoff_factor: 1.5,
             jitter: 0.1,
             reinitialize: true,
+            max_total_retry_duration: None,
         }
     }
 }
@@ -237,6 +531,8 @@ impl Default for ReconnectionConfig {
 ///
 /// * `connection` - Handles the underlying network connection
 /// * `encryption` - Manages encryption state
+/// * `compression` - Payload compression settings
+/// * `max_packet_size` - Largest packet accepted from the server, shared with the reader task
 /// * `session_id` - Current session identifier
 /// * `user` - Username for authentication
 /// * `pass` - Password for authentication
@@ -244,28 +540,55 @@ impl Default for ReconnectionConfig {
 /// * `keep_alive_cold_start` - Indicates first keep-alive cycle
 /// * `keep_alive_running` - Keep-alive active status
 /// * `response_rx` - Channel for receiving responses
+/// * `responses_decrypted` - Whether the broadcast processor already decrypted `response_rx`
 /// * `broadcast_handler` - Optional handler for broadcast messages
+/// * `restart_broadcast_processor` - Starts the broadcast processor on a new connection, once it has run
+/// * `push_tx` - Fans pushed packets out to `subscribe` streams
+/// * `stream_end_header` - Header that also ends a `send_recv_stream`, besides the stream end flag
+/// * `resubscribe` - Packets sent again after every reconnection, such as pool joins
 pub struct AsyncClient<P>
 where
     P: packet::Packet,
 {
-    connection: ConnectionHandler,
+    pub(crate) connection: ConnectionHandler,
     pub(crate) encryption: ClientEncryption,
+    compression: CompressionConfig,
+    timeouts: TimeoutConfig,
+    max_packet_size: Arc<AtomicUsize>,
     session_id: Option<String>,
     user: Option<String>,
     pass: Option<String>,
+    token: Option<String>,
+    challenge_responder: Option<ChallengeResponder>,
     keep_alive: KeepAliveConfig,
     keep_alive_cold_start: Arc<Mutex<bool>>,
     keep_alive_running: Arc<AtomicBool>,
     keepalive_reconnect_needed: Arc<AtomicBool>,
     pub(crate) keepalive_reconnect_tx: Option<mpsc::Sender<()>>,
-    response_rx: mpsc::Receiver<Vec<u8>>,
+    response_rx: mpsc::Receiver<Bytes>,
+    responses_decrypted: bool,
     broadcast_handler: Option<Arc<BroadcastHandler<P>>>,
+    on_connect: Option<LifecycleHandler>,
+    on_disconnect: Option<LifecycleHandler>,
+    on_reconnect: Option<ReconnectHandler>,
+    disconnect_reported: Arc<AtomicBool>,
+    metrics: Arc<dyn Metrics>,
     broadcast_processor_running: Arc<AtomicBool>,
+    restart_broadcast_processor: Option<fn(&mut Self)>,
+    push_tx: broadcast::Sender<Result<P, Error>>,
+    stream_end_header: Option<String>,
+    resubscribe: Vec<P>,
     reconnection_config: ReconnectionConfig,
-    current_endpoint: Option<(String, u16)>,
+    primary_endpoint: Endpoint,
+    current_endpoint: Endpoint,
+    // A second handle on a TCP connection, for changing its socket options
+    tcp: Option<std::net::TcpStream>,
+    nodelay: Option<bool>,
     connection_closed: Arc<AtomicBool>,
     connection_stable: Arc<AtomicBool>,
+    server_responded: Arc<AtomicBool>,
+    reconnecting: Arc<AtomicBool>,
+    next_request_id: u64,
     _packet: PhantomData<P>,
 }
 
@@ -278,9 +601,13 @@ where
     /// Establishes a connection to the specified server and initializes all necessary
     /// components for network communication.
     ///
+    /// The host may be an IPv4 or IPv6 literal or a hostname. Every address it
+    /// resolves to is tried in order, and the one that connects is reused when
+    /// reconnecting.
+    ///
     /// # Arguments
     ///
-    /// * `ip` - Server IP address
+    /// * `ip` - Server IP address or hostname
     /// * `port` - Server port number
     ///
     /// # Returns
@@ -290,129 +617,223 @@ where
     /// # Errors
     ///
     /// Returns an error if:
-    /// - Unable to establish TCP connection
+    /// - The host cannot be resolved (`Error::ResolutionFailed`)
+    /// - Unable to establish TCP connection to any resolved address
     /// - IO error occurs during connection setup
     ///
     /// # Example
     ///
     /// ```rust
     /// async fn connect() -> Result<AsyncClient<MyPacket>, Error> {
-    ///     let client = AsyncClient::new("127.0.0.1", 8080).await?;
+    ///     let client = AsyncClient::new("localhost", 8080).await?;
     ///     Ok(client)
     /// }
     /// ```
     pub async fn new(ip: &str, port: u16) -> Result<Self, Error> {
-        let server = tokio::net::TcpStream::connect((ip, port))
-            .await
-            .map_err(|e| Error::IoError(e.to_string()))?;
+        let (server, addr) = Self::connect_to(ip, port).await?;
+        let (server, tcp) = Self::clone_option_handle(server)?;
+        let (read_half, write_half) = server.into_split();
+
+        let mut client = Self::from_stream(
+            read_half,
+            write_half,
+            Endpoint::Tcp(addr.ip().to_string(), addr.port()),
+        );
+        client.tcp = Some(tcp);
+        Ok(client)
+    }
 
-        let (writer_tx, mut writer_rx) = mpsc::channel::<ClientMessage>(32);
-        let (reader_tx, reader_rx) = mpsc::channel::<Vec<u8>>(32); // Keep as Vec<u8>
+    /// Clones the handle of a connected stream, so its socket options can
+    /// still be changed once the stream is split between the I/O tasks.
+    fn clone_option_handle(stream: TcpStream) -> Result<(TcpStream, std::net::TcpStream), Error> {
+        let stream = stream
+            .into_std()
+            .map_err(|e| Error::IoError(e.to_string()))?;
+        let handle = stream
+            .try_clone()
+            .map_err(|e| Error::IoError(e.to_string()))?;
+        let stream = TcpStream::from_std(stream).map_err(|e| Error::IoError(e.to_string()))?;
+        Ok((stream, handle))
+    }
 
-        let connection_closed = Arc::new(AtomicBool::new(false));
-        let connection_closed_writer = connection_closed.clone();
-        let connection_closed_reader = connection_closed.clone();
+    /// Creates a new `AsyncClient` connected to a Unix domain socket.
+    ///
+    /// The client behaves exactly as one created with `new`, including
+    /// reconnecting to the same path.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Filesystem path of the listener's socket
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Self, Error>` - The initialized client or an error
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::IoError` if unable to connect to the socket
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let client = AsyncClient::<MyPacket>::new_uds("/tmp/my-daemon.sock").await?;
+    /// ```
+    #[cfg(unix)]
+    pub async fn new_uds(path: impl AsRef<std::path::Path>) -> Result<Self, Error> {
+        let path = path.as_ref().to_path_buf();
+        let server = UnixStream::connect(&path)
+            .await
+            .map_err(|e| Error::IoError(e.to_string()))?;
+        let (read_half, write_half) = server.into_split();
 
-        // Split the connection
-        let (mut read_half, mut write_half) = server.into_split();
+        Ok(Self::from_stream(
+            read_half,
+            write_half,
+            Endpoint::Unix(path),
+        ))
+    }
 
-        // Spawn writer task
-        tokio::spawn({
-            async move {
-                while let Some(msg) = writer_rx.recv().await {
-                    if connection_closed_writer.load(Ordering::SeqCst) {
-                        // Don't try to write if connection is known to be closed
-                        continue;
-                    }
+    /// Creates a new `AsyncClient` connected to a WebSocket listener.
+    ///
+    /// Performs the HTTP upgrade, then behaves exactly as a client created with
+    /// `new`, including reconnecting to the same URL. Pairs with
+    /// `AsyncListener::new_ws`.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The listener's URL, such as `ws://127.0.0.1:8080`
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Self, Error>` - The initialized client or an error
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::IoError` if unable to connect or the upgrade fails
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let mut client = AsyncClient::<MyPacket>::new_ws("ws://127.0.0.1:8080").await?;
+    /// let response = client.send_recv(MyPacket::ok()).await?;
+    /// ```
+    #[cfg(feature = "websocket")]
+    pub async fn new_ws(url: &str) -> Result<Self, Error> {
+        let (stream, _) = tokio_tungstenite::connect_async(url)
+            .await
+            .map_err(|e| Error::IoError(e.to_string()))?;
+        let (read_half, write_half) = tokio::io::split(super::websocket::WsStream::new(stream));
 
-                    match msg {
-                        ClientMessage::Data(data) | ClientMessage::Keepalive(data) => {
-                            if let Err(e) = write_half.write_all(&data).await {
-                                eprintln!("Write error: {e}");
-                                connection_closed_writer.store(true, Ordering::SeqCst);
-                                break;
-                            }
-                            if let Err(e) = write_half.flush().await {
-                                eprintln!("Flush error: {e}");
-                                connection_closed_writer.store(true, Ordering::SeqCst);
-                                break;
-                            }
-                        }
-                        ClientMessage::Ping(response) => {
-                            let _ = response.send(true);
-                        }
-                    }
-                }
-                println!("Writer task ended");
-            }
-        });
+        Ok(Self::from_stream(
+            read_half,
+            write_half,
+            Endpoint::WebSocket(url.to_string()),
+        ))
+    }
 
-        // Clone reader_tx before moving it
-        let reader_tx_clone = reader_tx.clone();
+    /// Creates a new client over an in-memory stream.
+    ///
+    /// Pairs with `AsyncListener::accept_in_memory` on the other end of a
+    /// `tokio::io::duplex` pipe, so a client and listener can talk without
+    /// binding a port. The client can't reconnect once the pipe is closed.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream` - The client's end of the duplex pipe
+    ///
+    /// # Returns
+    ///
+    /// * The initialized client
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let (client_end, server_end) = tokio::io::duplex(64 * 1024);
+    /// let mut client = AsyncClient::<MyPacket>::from_duplex(client_end);
+    /// let (_, greeting) = tokio::join!(listener.accept_in_memory(server_end), client.recv());
+    /// ```
+    #[must_use]
+    pub fn from_duplex(stream: DuplexStream) -> Self {
+        let (read_half, write_half) = tokio::io::split(stream);
 
-        tokio::spawn({
-            async move {
-                let mut buf = vec![0; 4096];
-                loop {
-                    if connection_closed_reader.load(Ordering::SeqCst) {
-                        // Don't try to read if connection is known to be closed
-                        break;
-                    }
+        Self::from_stream(read_half, write_half, Endpoint::Memory)
+    }
 
-                    match read_half.read(&mut buf).await {
-                        Ok(n) if n > 0 => {
-                            let data = buf[..n].to_vec();
-                            if let Err(e) = reader_tx_clone.send(data).await {
-                                eprintln!("Reader send error: {e}");
-                                connection_closed_reader.store(true, Ordering::SeqCst);
-                                break;
-                            }
-                        }
-                        Ok(n) => {
-                            if n == 0 {
-                                println!("Connection closed by peer");
-                                connection_closed_reader.store(true, Ordering::SeqCst);
-                            }
-                            break;
-                        }
-                        Err(e) => {
-                            eprintln!("Read error: {e}");
-                            connection_closed_reader.store(true, Ordering::SeqCst);
-                            break;
-                        }
-                    }
-                }
-                println!("Reader task ended");
-            }
-        });
+    /// Spawns the reader and writer tasks for a connected stream and builds the client.
+    fn from_stream<RH, WH>(read_half: RH, write_half: WH, endpoint: Endpoint) -> Self
+    where
+        RH: AsyncRead + Send + Unpin + 'static,
+        WH: AsyncWrite + Send + Unpin + 'static,
+    {
+        let max_packet_size = Arc::new(AtomicUsize::new(framing::DEFAULT_MAX_FRAME_LEN));
+        let io = connection::spawn_io(
+            read_half,
+            write_half,
+            endpoint.to_string(),
+            max_packet_size.clone(),
+        );
 
         let broadcast_processor_running = Arc::new(AtomicBool::new(false));
 
-        let client = Self {
-            connection: ConnectionHandler {
-                writer_tx,
-                reader_tx,
-            },
+        Self {
+            connection: io.handler,
             encryption: ClientEncryption::None,
+            compression: CompressionConfig::default(),
+            timeouts: TimeoutConfig::default(),
+            max_packet_size,
             session_id: None,
             user: None,
             pass: None,
+            token: None,
+            challenge_responder: None,
             keep_alive: KeepAliveConfig::default(),
             keep_alive_cold_start: Arc::new(Mutex::new(true)),
             keep_alive_running: Arc::new(AtomicBool::new(false)),
-            response_rx: reader_rx,
+            response_rx: io.response_rx,
+            responses_decrypted: false,
             broadcast_handler: None,
+            on_connect: None,
+            on_disconnect: None,
+            on_reconnect: None,
+            disconnect_reported: Arc::new(AtomicBool::new(false)),
+            metrics: Arc::new(NoopMetrics),
             broadcast_processor_running,
+            restart_broadcast_processor: None,
+            push_tx: broadcast::channel(64).0,
+            stream_end_header: None,
+            resubscribe: Vec::new(),
             reconnection_config: ReconnectionConfig::default(),
-            current_endpoint: Some((ip.to_string(), port)),
-            connection_closed,
+            primary_endpoint: endpoint.clone(),
+            current_endpoint: endpoint,
+            tcp: None,
+            nodelay: None,
+            connection_closed: io.connection_closed,
             connection_stable: Arc::new(AtomicBool::new(true)),
+            server_responded: io.server_responded,
+            reconnecting: Arc::new(AtomicBool::new(false)),
             keepalive_reconnect_tx: None,
             keepalive_reconnect_needed: Arc::new(AtomicBool::new(false)),
+            next_request_id: 1,
             _packet: PhantomData,
-        };
+        }
+    }
 
-        Ok(client)
+    /// Resolves `host` and connects to the first address that accepts the connection.
+    async fn connect_to(host: &str, port: u16) -> Result<(TcpStream, SocketAddr), Error> {
+        let addrs = tokio::net::lookup_host((host, port))
+            .await
+            .map_err(|e| Error::ResolutionFailed(format!("{host}: {e}")))?;
+
+        let mut last_error = None;
+        for addr in addrs {
+            match TcpStream::connect(addr).await {
+                Ok(stream) => return Ok((stream, addr)),
+                Err(e) => last_error = Some(Error::IoError(e.to_string())),
+            }
+        }
+
+        Err(last_error
+            .unwrap_or_else(|| Error::ResolutionFailed(format!("{host}: no addresses found"))))
     }
 
     async fn try_reconnect(&mut self) -> Result<(), Error> {
@@ -420,47 +841,109 @@ where
             return Err(Error::ConnectionClosed);
         }
 
+        warn!(peer = %self.current_endpoint, "Connection lost, reconnecting");
+        Self::report_disconnect(
+            self.on_disconnect.as_ref(),
+            &self.disconnect_reported,
+            self.metrics.as_ref(),
+        );
+
+        let reconnecting = self.reconnecting.clone();
+        reconnecting.store(true, Ordering::SeqCst);
+        let _reconnecting = scopeguard::guard((), move |()| {
+            reconnecting.store(false, Ordering::SeqCst);
+        });
+
         let mut attempt = 0;
         let max_attempts = self.reconnection_config.max_attempts.unwrap_or(usize::MAX);
+        let deadline = self
+            .reconnection_config
+            .max_total_retry_duration
+            .map(|limit| Instant::now() + limit);
 
         while attempt < max_attempts {
-            let delay = self.calculate_backoff_delay(attempt);
-            tokio::time::sleep(Duration::from_secs_f64(delay)).await;
+            let delay = Duration::from_secs_f64(self.reconnection_config.backoff_delay(attempt));
+            // Give up rather than start an attempt past the time limit
+            if deadline.is_some_and(|deadline| Instant::now() + delay > deadline) {
+                warn!(peer = %self.current_endpoint, attempt, "Reconnection time limit reached");
+                return Err(Error::ReconnectTimedOut);
+            }
+            tokio::time::sleep(delay).await;
+            self.metrics.increment(Counter::ReconnectAttempts);
+
+            match self.connect_any_endpoint().await {
+                Ok((mut new_client, endpoint)) => {
+                    self.current_endpoint = endpoint;
 
-            match Self::new(
-                &self.current_endpoint.as_ref().unwrap().0,
-                self.current_endpoint.as_ref().unwrap().1,
-            )
-            .await
-            {
-                Ok(mut new_client) => {
                     // Transfer state
                     new_client.encryption = self.encryption.clone();
+                    new_client.compression = self.compression;
+                    new_client.timeouts = self.timeouts;
+                    new_client.max_packet_size.store(
+                        self.max_packet_size.load(Ordering::SeqCst),
+                        Ordering::SeqCst,
+                    );
+                    new_client.session_id = self.session_id.clone();
                     new_client.user = self.user.clone();
                     new_client.pass = self.pass.clone();
+                    new_client.token = self.token.clone();
+                    new_client.challenge_responder = self.challenge_responder;
                     new_client.keep_alive = self.keep_alive.clone();
                     new_client.broadcast_handler = self.broadcast_handler.clone();
                     new_client.reconnection_config = self.reconnection_config.clone();
-
-                    // Replace connection
+                    new_client.nodelay = self.nodelay;
+                    new_client.apply_socket_options();
+
+                    // Replace connection, leaving the old background tasks behind
+                    self.detach_keepalive();
+                    self.broadcast_processor_running
+                        .store(false, Ordering::SeqCst);
+                    self.broadcast_processor_running = Arc::new(AtomicBool::new(false));
+                    self.keep_alive_cold_start = new_client.keep_alive_cold_start;
                     self.connection = new_client.connection;
                     self.response_rx = new_client.response_rx;
-                    self.connection_closed.store(false, Ordering::SeqCst);
-
-                    // Initialize the connection
-                    if self.reconnection_config.reinitialize {
-                        match self.initialize_connection().await {
-                            Ok(_) => return Ok(()),
-                            Err(_) => {
-                                attempt += 1;
-                                continue;
-                            }
-                        }
+                    self.server_responded = new_client.server_responded;
+                    self.responses_decrypted = false;
+                    self.connection_closed = new_client.connection_closed;
+                    self.tcp = new_client.tcp;
+                    self.connection_stable.store(true, Ordering::SeqCst);
+
+                    // Keep feeding the broadcast handler and existing subscriptions
+                    if let Some(restart) = self.restart_broadcast_processor {
+                        restart(self);
+                    }
+
+                    // Initialize the connection, or pick the session back up
+                    let ready = if self.reconnection_config.reinitialize {
+                        self.initialize_connection().await
+                    } else if self.session_id.is_some() {
+                        self.resume_session().await
                     } else {
-                        return Ok(());
+                        Ok(())
+                    };
+                    let ready = match ready {
+                        Ok(()) => self.replay_subscriptions().await,
+                        Err(e) => Err(e),
+                    };
+                    if ready.is_err() {
+                        attempt += 1;
+                        continue;
+                    }
+
+                    if self.keep_alive.enabled {
+                        let _ = self.start_keepalive();
+                    }
+
+                    info!(peer = %self.current_endpoint, attempt = attempt + 1, "Reconnected");
+                    self.disconnect_reported.store(false, Ordering::SeqCst);
+                    self.metrics.increment(Counter::ConnectionsOpened);
+                    if let Some(on_reconnect) = &self.on_reconnect {
+                        on_reconnect(attempt + 1, &self.current_endpoint);
                     }
+                    return Ok(());
                 }
-                Err(_) => {
+                Err(e) => {
+                    debug!(attempt = attempt + 1, error = %e, "Reconnection attempt failed");
                     attempt += 1;
                     continue;
                 }
@@ -472,12 +955,55 @@ where
         ))
     }
 
-    fn calculate_backoff_delay(&self, attempt: usize) -> f64 {
-        let base_delay = self.reconnection_config.initial_retry_delay;
-        let max_delay = self.reconnection_config.max_retry_delay;
-        let backoff = base_delay * self.reconnection_config.backoff_factor.powi(attempt as i32);
-        let jitter = rand::random::<f64>() * self.reconnection_config.jitter * backoff;
-        (backoff + jitter).min(max_delay)
+    /// Connects to the primary endpoint, falling back to each of the configured
+    /// `endpoints` in order.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(Self, Endpoint), Error>` - A client for the first endpoint that
+    ///   accepted the connection and that endpoint, or the last connection error
+    async fn connect_any_endpoint(&self) -> Result<(Self, Endpoint), Error> {
+        let fallbacks = self
+            .reconnection_config
+            .endpoints
+            .iter()
+            .map(|(ip, port)| Endpoint::Tcp(ip.clone(), *port));
+
+        let mut last_error = Error::ConnectionClosed;
+        for endpoint in std::iter::once(self.primary_endpoint.clone()).chain(fallbacks) {
+            let connected = match &endpoint {
+                Endpoint::Tcp(ip, port) => Self::new(ip, *port).await,
+                #[cfg(unix)]
+                Endpoint::Unix(path) => Self::new_uds(path).await,
+                #[cfg(feature = "websocket")]
+                Endpoint::WebSocket(url) => Self::new_ws(url).await,
+                Endpoint::Memory => Err(Error::ConnectionClosed),
+            };
+
+            match connected {
+                Ok(client) => return Ok((client, endpoint)),
+                Err(e) => {
+                    debug!(peer = %endpoint, error = %e, "Endpoint unavailable");
+                    last_error = e;
+                }
+            }
+        }
+
+        Err(last_error)
+    }
+
+    /// Runs the disconnect callback and counts the closed connection, once per lost connection.
+    fn report_disconnect(
+        on_disconnect: Option<&LifecycleHandler>,
+        reported: &AtomicBool,
+        metrics: &dyn Metrics,
+    ) {
+        if !reported.swap(true, Ordering::SeqCst) {
+            metrics.increment(Counter::ConnectionsClosed);
+            if let Some(on_disconnect) = on_disconnect {
+                on_disconnect();
+            }
+        }
     }
 
     async fn initialize_connection(&mut self) -> Result<(), Error> {
@@ -486,8 +1012,9 @@ where
             init_packet.body_mut().username = Some(user.clone());
             init_packet.body_mut().password = Some(pass.clone());
         }
+        init_packet.body_mut().token = self.token.clone();
 
-        match self.send_recv(init_packet).await {
+        match self.send_recv_presenting_session(init_packet).await {
             Ok(mut response) => {
                 if response.header() == P::ok().header() {
                     self.session_id = response.session_id(None);
@@ -499,16 +1026,67 @@ where
 
                     Ok(())
                 } else {
-                    Err(Error::Error("Initialization failed".to_string()))
+                    Err(Self::auth_rejection(&response))
                 }
             }
             Err(e) => Err(e),
         }
     }
 
-    /// Configures reconnection behavior for the client.
+    /// Sends the first packet of a fresh connection and returns the reply to it.
     ///
-    /// # Arguments
+    /// Servers without authentication greet every connection with an OK before
+    /// reading anything, then answer a packet presenting a session id with the
+    /// session the connection ended up on. The greeting is skipped in that case.
+    async fn send_recv_presenting_session(&mut self, packet: P) -> Result<P, Error> {
+        let presenting = self.session_id.is_some();
+        let request_id = self.next_request_id;
+        let response = self.send_recv(packet).await?;
+
+        if presenting
+            && response.header() == P::ok().header()
+            && response.body().request_id.is_none()
+        {
+            return self.recv_response(request_id, self.timeouts.recv).await;
+        }
+        Ok(response)
+    }
+
+    /// Sends the `resubscribe` packets on a fresh connection.
+    async fn replay_subscriptions(&mut self) -> Result<(), Error> {
+        for packet in self.resubscribe.clone() {
+            self.send_recv(packet).await?;
+        }
+        Ok(())
+    }
+
+    /// Resumes the current session on a fresh connection.
+    ///
+    /// The server authenticates the first packet of every connection, so the
+    /// session id is presented on its own before anything else is sent. If the
+    /// server no longer knows the session, the client authenticates from scratch.
+    async fn resume_session(&mut self) -> Result<(), Error> {
+        let mut response = self.send_recv_presenting_session(P::ok()).await?;
+
+        if response.header() == P::ok().header() {
+            // Servers without authentication may hand out a new session instead
+            if let Some(id) = response.session_id(None) {
+                self.session_id = Some(id);
+            }
+            return Ok(());
+        }
+
+        debug!(
+            session_id = ?self.session_id,
+            "Session was not resumed, authenticating again"
+        );
+        self.session_id = None;
+        self.initialize_connection().await
+    }
+
+    /// Configures reconnection behavior for the client.
+    ///
+    /// # Arguments
     ///
     /// * `config` - Reconnection configuration settings
     ///
@@ -538,6 +1116,40 @@ where
         self
     }
 
+    /// Adds a pre-shared key or API token used to authenticate with the server.
+    ///
+    /// # Arguments
+    ///
+    /// * `token` - The key to present to the server
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The configured client instance
+    #[must_use]
+    pub fn with_token(mut self, token: &str) -> Self {
+        self.token = Some(token.to_string());
+        self
+    }
+
+    /// Sets the function used to answer a server's authentication challenge.
+    ///
+    /// When the server uses challenge-response authentication it replies to the
+    /// first request with a challenge. The client answers it with `responder` and
+    /// `send_recv` returns the server's verdict on the answer.
+    ///
+    /// # Arguments
+    ///
+    /// * `responder` - Computes the answer for a challenge
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The configured client instance
+    #[must_use]
+    pub fn with_challenge_responder(mut self, responder: ChallengeResponder) -> Self {
+        self.challenge_responder = Some(responder);
+        self
+    }
+
     /// Sets up root authentication credentials.
     ///
     /// # Arguments
@@ -569,10 +1181,103 @@ where
         self
     }
 
+    /// Configures payload compression.
+    ///
+    /// The server must have compression enabled as well.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - Compression configuration settings
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The configured client instance
+    #[must_use]
+    pub const fn with_compression(mut self, config: CompressionConfig) -> Self {
+        self.compression = config;
+        self
+    }
+
+    /// Configures the send, receive and keep-alive timeouts.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeouts` - Timeout configuration settings
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The configured client instance
+    #[must_use]
+    pub const fn with_timeouts(mut self, timeouts: TimeoutConfig) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    /// Limits the size of the packets accepted from the server.
+    ///
+    /// A server announcing a larger packet is treated as broken: the connection
+    /// is closed before any of the payload is buffered. Defaults to 16 MB.
+    ///
+    /// # Arguments
+    ///
+    /// * `max` - Maximum packet size in bytes, as sent on the wire
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The configured client instance
+    #[must_use]
+    pub fn with_max_packet_size(self, max: usize) -> Self {
+        self.max_packet_size.store(max, Ordering::SeqCst);
+        self
+    }
+
+    /// Enables or disables `TCP_NODELAY` on the connection.
+    ///
+    /// With it set, small packets are sent right away instead of being held
+    /// back by Nagle's algorithm, which favours latency over throughput. The
+    /// setting is applied to the current connection and to every reconnection.
+    /// It has no effect on Unix domain sockets and in-memory connections.
+    ///
+    /// # Arguments
+    ///
+    /// * `nodelay` - Whether to disable Nagle's algorithm
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The configured client instance
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let client = AsyncClient::<MyPacket>::new("127.0.0.1", 8080)
+    ///     .await?
+    ///     .with_nodelay(true);
+    /// ```
+    #[must_use]
+    pub fn with_nodelay(mut self, nodelay: bool) -> Self {
+        self.nodelay = Some(nodelay);
+        self.apply_socket_options();
+        self
+    }
+
+    // Applies the configured socket options to the current TCP connection
+    fn apply_socket_options(&self) {
+        let applied = match (&self.tcp, self.nodelay) {
+            (Some(tcp), Some(nodelay)) => tcp.set_nodelay(nodelay),
+            _ => Ok(()),
+        };
+        if let Err(e) = applied {
+            warn!(peer = %self.current_endpoint, error = %e, "Failed to set TCP_NODELAY");
+        }
+    }
+
     /// Sets a broadcast handler and starts the broadcast processor.
     ///
-    /// This method takes a function that will be called whenever a broadcast
-    /// packet is received.
+    /// This method takes a function that will be called with every packet the
+    /// server marks with `set_broadcasting`. Once `finalize` has started the
+    /// broadcast processor, broadcasts are handled as soon as they arrive;
+    /// before that, they are handled while `recv` or `send_recv` wait for a
+    /// packet. Either way they are never returned as replies.
     ///
     /// # Arguments
     ///
@@ -587,32 +1292,229 @@ where
         self
     }
 
+    /// Sets a callback run once `finalize` has established the connection.
+    ///
+    /// # Arguments
+    ///
+    /// * `handler` - Function to be called after connecting
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The configured client instance
+    #[must_use]
+    pub fn with_on_connect<F>(mut self, handler: F) -> Self
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        self.on_connect = Some(Arc::new(handler));
+        self
+    }
+
+    /// Sets a callback run when the client notices its connection is gone.
+    ///
+    /// It is called when keep-alives keep failing or when the client starts
+    /// reconnecting, at most once for each lost connection.
+    ///
+    /// # Arguments
+    ///
+    /// * `handler` - Function to be called after disconnecting
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The configured client instance
+    #[must_use]
+    pub fn with_on_disconnect<F>(mut self, handler: F) -> Self
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        self.on_disconnect = Some(Arc::new(handler));
+        self
+    }
+
+    /// Sets a callback run after the client reconnects.
+    ///
+    /// The callback receives the attempt that succeeded, starting at 1, and the
+    /// endpoint the client is now connected to.
+    ///
+    /// # Arguments
+    ///
+    /// * `handler` - Function to be called after reconnecting
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The configured client instance
+    #[must_use]
+    pub fn with_on_reconnect<F>(mut self, handler: F) -> Self
+    where
+        F: Fn(usize, &Endpoint) + Send + Sync + 'static,
+    {
+        self.on_reconnect = Some(Arc::new(handler));
+        self
+    }
+
+    /// Reports the client's activity to a metrics collector.
+    ///
+    /// The client counts the packets and bytes it sends and receives, opened
+    /// and closed connections, and reconnection attempts.
+    ///
+    /// # Arguments
+    ///
+    /// * `metrics` - The collector to report to
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The configured client instance
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let metrics = Arc::new(AtomicMetrics::new());
+    /// let client = AsyncClient::<MyPacket>::new("127.0.0.1", 8080)
+    ///     .await?
+    ///     .with_metrics(metrics.clone());
+    /// ```
+    #[must_use]
+    pub fn with_metrics(mut self, metrics: Arc<dyn Metrics>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Sets a header that ends a `send_recv_stream`.
+    ///
+    /// Packets flagged with `Packet::set_stream_end` always end the stream;
+    /// this lets servers that can't set the flag end it with a dedicated header.
+    ///
+    /// # Arguments
+    ///
+    /// * `header` - The header of the packet marking the end of a stream
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The configured client instance
+    #[must_use]
+    pub fn with_stream_end_header(mut self, header: &str) -> Self {
+        self.stream_end_header = Some(header.to_string());
+        self
+    }
+
+    /// Sets packets to send again after every reconnection.
+    ///
+    /// The server sees a reconnected client as a new connection, so pools and
+    /// other subscriptions tied to the old connection are lost. Each packet is
+    /// sent with `send_recv` once the connection is re-established, in order,
+    /// and the reconnection attempt fails if any of them is not answered.
+    ///
+    /// # Arguments
+    ///
+    /// * `packets` - The packets that re-establish the client's subscriptions
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The configured client instance
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let mut client = AsyncClient::<MyPacket>::new("127.0.0.1", 8080)
+    ///     .await?
+    ///     .with_reconnection(ReconnectionConfig::default_on())
+    ///     .with_resubscribe(vec![MyPacket::join("chat")]);
+    /// ```
+    #[must_use]
+    pub fn with_resubscribe(mut self, packets: Vec<P>) -> Self {
+        self.resubscribe = packets;
+        self
+    }
+
+    /// Adds a packet to send again after every reconnection.
+    ///
+    /// Use this for subscriptions made after the client was built. See
+    /// `with_resubscribe`.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - The packet that re-establishes a subscription
+    pub fn add_resubscribe(&mut self, packet: P) {
+        self.resubscribe.push(packet);
+    }
+
+    /// Subscribes to packets pushed by the server.
+    ///
+    /// Every broadcast packet received after this call is yielded by the
+    /// returned stream, while replies keep flowing to `recv` and `send_recv`.
+    /// Any number of subscriptions can be active at once, each receiving every
+    /// pushed packet. The broadcast handler, if any, keeps being called too.
+    ///
+    /// # Returns
+    ///
+    /// * A stream of pushed packets. It yields `Error::ConnectionClosed` once the
+    ///   connection goes away, and an error if the subscriber falls too far behind.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use futures::StreamExt;
+    ///
+    /// async fn listen(client: &mut AsyncClient<MyPacket>) {
+    ///     let mut stream = Box::pin(client.subscribe());
+    ///     while let Some(Ok(packet)) = stream.next().await {
+    ///         println!("Pushed: {:?}", packet);
+    ///     }
+    /// }
+    /// ```
+    pub fn subscribe(&mut self) -> impl Stream<Item = Result<P, Error>> + Send + 'static
+    where
+        P: 'static,
+    {
+        let rx = self.push_tx.subscribe();
+        self.start_broadcast_processor();
+
+        futures::stream::unfold(rx, |mut rx| async move {
+            match rx.recv().await {
+                Ok(item) => Some((item, rx)),
+                Err(broadcast::error::RecvError::Lagged(skipped)) => Some((
+                    Err(Error::Error(format!(
+                        "Subscriber lagged behind, {skipped} packets dropped"
+                    ))),
+                    rx,
+                )),
+                Err(broadcast::error::RecvError::Closed) => None,
+            }
+        })
+    }
+
     /// Starts the broadcast packet processor.
     ///
     /// This creates a new channel for regular responses and spawns a task that:
     /// 1. Reads from the original response channel
     /// 2. Determines if packets are broadcasts or regular responses
-    /// 3. Routes broadcasts to the handler and regular responses to the new channel
-    fn start_broadcast_processor(&mut self) -> Result<(), Error>
+    /// 3. Routes broadcasts to the handler and subscribers, and regular responses to the new channel
+    fn start_broadcast_processor(&mut self)
     where
         P: 'static,
     {
-        // Only start if we have a broadcast handler and it's not already running
-        if self.broadcast_handler.is_none()
-            || self.broadcast_processor_running.load(Ordering::SeqCst)
-        {
-            return Ok(());
+        // Only start if it's not already running
+        if self.broadcast_processor_running.load(Ordering::SeqCst) {
+            return;
         }
 
+        self.restart_broadcast_processor = Some(Self::start_broadcast_processor);
+
         // Create a new channel for filtered responses
-        let (filtered_tx, filtered_rx) = mpsc::channel::<Vec<u8>>(32);
+        let (filtered_tx, filtered_rx) = mpsc::channel::<Bytes>(32);
 
         // Take ownership of the original response channel
         let mut original_rx = std::mem::replace(&mut self.response_rx, filtered_rx);
 
+        // Each frame may only be decrypted once, so responses are forwarded as plaintext
+        self.responses_decrypted = true;
+
         // Get references to needed data
-        let broadcast_handler = self.broadcast_handler.clone().unwrap();
+        let broadcast_handler = self.broadcast_handler.clone();
+        let push_tx = self.push_tx.clone();
         let encryption = self.encryption.clone();
+        let compression = self.compression;
+        let max_packet_size = self.max_packet_size.clone();
         let broadcast_running = self.broadcast_processor_running.clone();
         let connection_closed = self.connection_closed.clone();
 
@@ -621,12 +1523,12 @@ where
 
         // Spawn the processor task
         tokio::spawn(async move {
-            println!("Broadcast processor started");
+            debug!("Broadcast processor started");
 
             while broadcast_running.load(Ordering::SeqCst) {
                 // Exit if connection is closed
                 if connection_closed.load(Ordering::SeqCst) {
-                    println!("Connection closed, stopping broadcast processor");
+                    debug!("Connection closed, stopping broadcast processor");
                     break;
                 }
 
@@ -635,8 +1537,11 @@ where
                     match tokio::time::timeout(Duration::from_secs(1), original_rx.recv()).await {
                         Ok(Some(bytes)) => bytes,
                         Ok(None) => {
-                            println!("Response channel closed, stopping broadcast processor");
-                            connection_closed.store(true, Ordering::SeqCst);
+                            debug!("Response channel closed, stopping broadcast processor");
+                            // A detached processor's channel closes when its connection is replaced
+                            if broadcast_running.load(Ordering::SeqCst) {
+                                connection_closed.store(true, Ordering::SeqCst);
+                            }
                             break;
                         }
                         Err(_) => {
@@ -645,26 +1550,50 @@ where
                         }
                     };
 
-                let packet = match &encryption {
-                    ClientEncryption::None => P::de(&bytes),
-                    ClientEncryption::Encrypted(encryptor) => P::encrypted_de(&bytes, encryptor),
+                let bytes = match encryption.encryptor() {
+                    Some(enc) => match enc.decrypt(&String::from_utf8_lossy(&bytes)) {
+                        Ok(plaintext) => Bytes::from(plaintext),
+                        Err(e) => {
+                            warn!(error = %e, "Failed to decrypt packet");
+                            continue;
+                        }
+                    },
+                    None => bytes,
+                };
+
+                let max_len = max_packet_size.load(Ordering::SeqCst);
+                let packet = match compression.decode_limited::<P>(&bytes, None, max_len) {
+                    Ok(packet) => packet,
+                    Err(e) => {
+                        warn!(error = %e, "Failed to decode packet");
+                        continue;
+                    }
                 };
 
-                if packet.is_broadcasting() {
-                    broadcast_handler(packet);
+                if packet.is_broadcast() {
+                    if let Some(handler) = &broadcast_handler {
+                        handler(packet.clone());
+                    }
+                    // Nobody subscribed is not an error
+                    let _ = push_tx.send(Ok(packet));
                 } else if packet.header() == P::keep_alive().header() {
                 } else if let Err(e) = filtered_tx.send(bytes).await {
-                    eprintln!("Failed to forward response: {}", e);
-                    connection_closed.store(true, Ordering::SeqCst);
+                    if broadcast_running.load(Ordering::SeqCst) {
+                        warn!(error = %e, "Failed to forward response");
+                        connection_closed.store(true, Ordering::SeqCst);
+                    }
                     break;
                 }
             }
 
+            if broadcast_running.load(Ordering::SeqCst) && connection_closed.load(Ordering::SeqCst)
+            {
+                let _ = push_tx.send(Err(Error::ConnectionClosed));
+            }
+
             broadcast_running.store(false, Ordering::SeqCst);
-            println!("Broadcast processor stopped");
+            debug!("Broadcast processor stopped");
         });
-
-        Ok(())
     }
 
     /// Finalizes the client setup and establishes the connection.
@@ -679,32 +1608,39 @@ where
     where
         P: 'static,
     {
-        println!("Finalizing client connection...");
+        info!(peer = %self.current_endpoint, "Finalizing client connection");
 
         self.connection_closed.store(false, Ordering::SeqCst);
 
         match self.send_recv(P::ok()).await {
-            Ok(_) => println!("Successfully initialized connection"),
+            Ok(mut response) => {
+                info!(peer = %self.current_endpoint, "Successfully initialized connection");
+                if let Some(id) = response.session_id(None) {
+                    self.session_id = Some(id);
+                }
+                self.metrics.increment(Counter::ConnectionsOpened);
+                if let Some(on_connect) = &self.on_connect {
+                    on_connect();
+                }
+            }
             Err(e) => {
-                println!("Error during initialization: {}", e);
+                warn!(peer = %self.current_endpoint, error = %e, "Error during initialization");
                 // Try to reconnect if initialization fails
                 if let Err(reconnect_err) = self.try_reconnect().await {
-                    eprintln!("Reconnection failed: {}", reconnect_err);
+                    warn!(error = %reconnect_err, "Reconnection failed");
                 }
             }
         }
 
         if self.keep_alive.enabled {
             match self.start_keepalive() {
-                Ok(_) => println!("Keepalive initialized successfully"),
-                Err(e) => println!("Failed to start keepalive: {}", e),
+                Ok(_) => debug!("Keepalive initialized successfully"),
+                Err(e) => warn!(error = %e, "Failed to start keepalive"),
             }
         }
 
         if self.broadcast_handler.is_some() {
-            self.start_broadcast_processor()
-                .map_err(|e| panic!("Failed to start broadcast processor \n\n{e}"))
-                .unwrap();
+            self.start_broadcast_processor();
         }
     }
 
@@ -718,6 +1654,7 @@ where
 
         packet.body.username = self.user.clone();
         packet.body.password = self.pass.clone();
+        packet.body.token = self.token.clone();
 
         self.send_phantom_packet(packet).await.unwrap();
 
@@ -732,7 +1669,10 @@ where
     ///
     /// * `AsyncClientRef<P>` - A reference-counted version of the client
     #[must_use]
-    pub fn convert_to_ref(self) -> AsyncClientRef<P> {
+    pub fn convert_to_ref(self) -> AsyncClientRef<P>
+    where
+        P: 'static,
+    {
         AsyncClientRef::new(self)
     }
 
@@ -744,113 +1684,126 @@ where
     ///
     /// # Returns
     ///
-    /// * `std::io::Result<Self>` - The configured client or an error
+    /// * `Result<Self, Error>` - The configured client or an error
     ///
     /// # Errors
     ///
     /// Returns an error if:
-    /// - Key exchange fails
-    /// - Authentication fails
-    /// - No session ID is received
-    pub async fn with_encryption_config(
-        mut self,
-        config: EncryptionConfig,
-    ) -> std::io::Result<Self> {
+    /// - Key exchange fails (`Error::KeyExchangeFailed`)
+    /// - The credentials are wrong (`Error::InvalidCredentials`)
+    /// - The server locked the client out after failed attempts (`Error::AuthRateLimited`)
+    /// - The server rejects the authentication for another reason (`Error::AuthResponseInvalid`)
+    /// - No session ID is received (`Error::NoSessionId`)
+    /// - Sending or receiving the authentication packets fails
+    pub async fn with_encryption_config(mut self, config: EncryptionConfig) -> Result<Self, Error> {
         if !config.enabled {
             return Ok(self);
         }
 
         if let Some(key) = config.key {
+            // A pre-shared key skips the handshake, so use the first preference
+            let suite = config.supported_suites()[0];
             self.encryption = ClientEncryption::Encrypted(Box::new(
-                Encryptor::new(&key).expect("Failed to create encryptor"),
+                Encryptor::new_with_suite(&key, suite)
+                    .expect("Failed to create encryptor")
+                    .with_role(Role::Client),
             ));
             return Ok(self);
         }
 
         if config.auto_key_exchange {
-            self.establish_encrypted_connection().await?;
+            self.establish_encrypted_connection(&config.supported_suites())
+                .await?;
         }
 
         // After encryption setup, handle authentication response
-        if let (Some(user), Some(pass)) = (&self.user, &self.pass) {
+        if (self.user.is_some() && self.pass.is_some())
+            || self.token.is_some()
+            || self.challenge_responder.is_some()
+        {
             let mut auth_packet = P::ok();
-            auth_packet.body_mut().username = Some(user.clone());
-            auth_packet.body_mut().password = Some(pass.clone());
+            if let (Some(user), Some(pass)) = (&self.user, &self.pass) {
+                auth_packet.body_mut().username = Some(user.clone());
+                auth_packet.body_mut().password = Some(pass.clone());
+            }
+            auth_packet.body_mut().token = self.token.clone();
 
-            match self.send_recv(auth_packet).await {
-                Ok(mut response) => {
-                    if let Some(id) = response.session_id(None) {
-                        self.session_id = Some(id);
-                    } else {
-                        return Err(std::io::Error::new(
-                            std::io::ErrorKind::Other,
-                            "No session ID received".to_string(),
-                        ));
-                    }
-                }
-                Err(e) => {
-                    return Err(std::io::Error::new(
-                        std::io::ErrorKind::Other,
-                        e.to_string(),
-                    ));
-                }
+            let mut response = self.send_recv(auth_packet).await?;
+            if response.header() != P::ok().header() {
+                return Err(Self::auth_rejection(&response));
             }
+            self.session_id = Some(response.session_id(None).ok_or(Error::NoSessionId)?);
         }
 
         Ok(self)
     }
 
+    /// Builds the error for a server's reply rejecting authentication.
+    ///
+    /// Error packets reporting wrong credentials or too many failed attempts map
+    /// back to `Error::InvalidCredentials` and `Error::AuthRateLimited`, so callers
+    /// can tell a rejected login apart from a broken connection.
+    fn auth_rejection(response: &P) -> Error {
+        let reason = response
+            .body()
+            .error_string
+            .unwrap_or_else(|| response.header());
+
+        if response.header() == P::error(Error::InvalidCredentials).header() {
+            for known in [Error::InvalidCredentials, Error::AuthRateLimited] {
+                if reason == known.to_string() {
+                    return known;
+                }
+            }
+        }
+        Error::AuthResponseInvalid(reason)
+    }
+
     /// Establishes an encrypted connection with the server.
     ///
     /// Performs key exchange and sets up encryption for secure communication.
-    async fn establish_encrypted_connection(&mut self) -> std::io::Result<()> {
+    async fn establish_encrypted_connection(
+        &mut self,
+        suites: &[CipherSuite],
+    ) -> Result<(), Error> {
         let key_exchange = KeyExchange::new();
-        let public_key = key_exchange.get_public_key();
-
-        // Send length-prefixed public key
-        let mut data = Vec::new();
-        data.extend_from_slice(&(public_key.len() as u32).to_be_bytes());
-        data.extend_from_slice(&public_key);
+        let hello = HandshakeHello {
+            public_key: key_exchange.get_public_key(),
+            suites: suites.to_vec(),
+        };
 
+        // Send our public key along with the cipher suites we support
         self.connection
             .writer_tx
-            .send(ClientMessage::Data(data))
+            .send(ClientMessage::Data(hello.encode().into(), None))
             .await
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.to_string()))?;
-
-        // Receive server's length prefix
-        let mut server_response = Vec::new();
-        while server_response.len() < 4 {
-            if let Some(data) = self.response_rx.recv().await {
-                server_response.extend(data);
-            } else {
-                return Err(std::io::Error::new(
-                    std::io::ErrorKind::ConnectionReset,
-                    "Connection closed while reading length prefix",
-                ));
-            }
-        }
+            .map_err(|e| Error::FailedPacketSend(e.to_string()))?;
 
-        let length = u32::from_be_bytes(server_response[0..4].try_into().unwrap()) as usize;
-
-        // Continue receiving until we have the full key
-        while server_response.len() < 4 + length {
-            if let Some(data) = self.response_rx.recv().await {
-                server_response.extend(data);
-            } else {
-                return Err(std::io::Error::new(
-                    std::io::ErrorKind::ConnectionReset,
-                    "Connection closed while reading public key",
-                ));
-            }
+        // Receive server's public key and the suite it picked
+        let server_hello = self.response_rx.recv().await.ok_or_else(|| {
+            Error::KeyExchangeFailed(
+                "Connection closed while waiting for server's public key".to_string(),
+            )
+        })?;
+
+        if server_hello.is_empty() {
+            return Err(Error::KeyExchangeFailed(
+                "Server does not support any offered cipher suite".to_string(),
+            ));
         }
 
-        let mut server_public_key = [0u8; 32];
-        server_public_key.copy_from_slice(&server_response[4..4 + length]);
+        let server_hello = HandshakeHello::decode(&server_hello).ok_or_else(|| {
+            Error::KeyExchangeFailed("Invalid server public key length".to_string())
+        })?;
+        let suite = server_hello.chosen_suite(suites).ok_or_else(|| {
+            Error::KeyExchangeFailed("Server picked a cipher suite we did not offer".to_string())
+        })?;
 
-        let shared_secret = key_exchange.compute_shared_secret(&server_public_key);
+        let shared_secret = key_exchange.compute_shared_secret(&server_hello.public_key);
         self.encryption = ClientEncryption::Encrypted(Box::new(
-            Encryptor::new(&shared_secret).expect("Failed to create encryptor"),
+            Encryptor::new_with_suite(&shared_secret, suite)
+                .expect("Failed to create encryptor")
+                .with_role(Role::Client),
         ));
 
         Ok(())
@@ -869,44 +1822,36 @@ where
     /// # Errors
     ///
     /// Returns an error if sending the packet fails
-    pub async fn send(&mut self, mut packet: P) -> Result<(), Error> {
+    pub async fn send(&mut self, packet: P) -> Result<(), Error> {
         // Check if connection is already known to be closed
         if self.connection_closed.load(Ordering::SeqCst) {
             return Err(Error::ConnectionClosed);
         }
 
-        // Add session ID if available
-        if let Some(id) = self.session_id.clone() {
-            packet.session_id(Some(id));
-        } else if let Some(user) = &self.user {
-            if let Some(pass) = &self.pass {
-                packet.body_mut().username = Some(user.to_owned());
-                packet.body_mut().password = Some(pass.to_owned());
-            }
-        }
-
-        let data = match &self.encryption {
-            ClientEncryption::None => packet.ser(),
-            ClientEncryption::Encrypted(encryptor) => packet.encrypted_ser(encryptor),
-        };
-
-        let timeout_duration = Duration::from_secs(5); // 5 second timeout
+        let data = self.encode_outgoing(packet);
+        let len = data.len() as u64;
+        let encryptor = self.encryption.encryptor().cloned();
 
         match tokio::time::timeout(
-            timeout_duration,
-            self.connection.writer_tx.send(ClientMessage::Data(data)),
+            self.timeouts.send,
+            self.connection
+                .writer_tx
+                .send(ClientMessage::Data(data, encryptor)),
         )
         .await
         {
-            Ok(Ok(())) => Ok(()),
+            Ok(Ok(())) => {
+                self.record_sent(len);
+                Ok(())
+            }
             Ok(Err(e)) => {
-                println!("Send error: {}", e);
+                warn!(error = %e, "Send error");
                 self.connection_closed.store(true, Ordering::SeqCst);
                 self.connection_stable.store(false, Ordering::SeqCst);
                 Err(Error::IoError(format!("Send error: {}", e)))
             }
             Err(_) => {
-                println!("Send operation timed out");
+                warn!("Send operation timed out");
                 self.connection_closed.store(true, Ordering::SeqCst);
                 self.connection_stable.store(false, Ordering::SeqCst);
                 Err(Error::IoError("Send operation timed out".to_string()))
@@ -914,6 +1859,135 @@ where
         }
     }
 
+    /// Sends a packet to the server without waiting for room in the send queue.
+    ///
+    /// Unlike `send`, a full queue is reported straight away instead of timing
+    /// out and marking the connection as closed, so applications can apply
+    /// their own backpressure. See `pending_writes`.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - The packet to send
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), Error>` - Success or failure of queueing the packet
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::WouldBlock` if the send queue is full and
+    /// `Error::ConnectionClosed` if the connection is closed
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// match client.try_send_now(packet) {
+    ///     Err(Error::WouldBlock) => {
+    ///         // Slow down and retry later
+    ///     }
+    ///     result => result?,
+    /// }
+    /// ```
+    pub fn try_send_now(&mut self, packet: P) -> Result<(), Error> {
+        if self.connection_closed.load(Ordering::SeqCst) {
+            return Err(Error::ConnectionClosed);
+        }
+
+        let data = self.encode_outgoing(packet);
+        let len = data.len() as u64;
+        let encryptor = self.encryption.encryptor().cloned();
+
+        match self
+            .connection
+            .writer_tx
+            .try_send(ClientMessage::Data(data, encryptor))
+        {
+            Ok(()) => {
+                self.record_sent(len);
+                Ok(())
+            }
+            Err(mpsc::error::TrySendError::Full(_)) => Err(Error::WouldBlock),
+            Err(mpsc::error::TrySendError::Closed(_)) => {
+                self.connection_closed.store(true, Ordering::SeqCst);
+                self.connection_stable.store(false, Ordering::SeqCst);
+                Err(Error::ConnectionClosed)
+            }
+        }
+    }
+
+    /// Gets the number of messages queued for the writer but not yet written.
+    ///
+    /// # Returns
+    ///
+    /// * `usize` - The number of pending writes, at most `WRITE_QUEUE_CAPACITY`
+    pub fn pending_writes(&self) -> usize {
+        self.connection.writer_tx.max_capacity() - self.connection.writer_tx.capacity()
+    }
+
+    /// Waits until every packet queued so far has been written to the connection.
+    ///
+    /// `send` returns once a packet is queued, so call this before exiting or
+    /// going idle to make sure fire-and-forget packets actually went out.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), Error>` - Success once the queue ahead of the call is written
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ConnectionClosed` if the writer stopped before writing
+    /// everything and `Error::IoError` if it doesn't catch up in time
+    pub async fn flush(&mut self) -> Result<(), Error> {
+        if self.connection_closed.load(Ordering::SeqCst) {
+            return Err(Error::ConnectionClosed);
+        }
+
+        // The writer answers pings in queue order, after the frames ahead of it
+        let (written_tx, written_rx) = tokio::sync::oneshot::channel();
+        let flushed = async {
+            self.connection
+                .writer_tx
+                .send(ClientMessage::Ping(written_tx))
+                .await
+                .map_err(|_| Error::ConnectionClosed)?;
+            written_rx.await.map_err(|_| Error::ConnectionClosed)
+        };
+
+        match tokio::time::timeout(self.timeouts.send, flushed).await {
+            Ok(Ok(_)) => Ok(()),
+            Ok(Err(e)) => {
+                self.connection_closed.store(true, Ordering::SeqCst);
+                self.connection_stable.store(false, Ordering::SeqCst);
+                Err(e)
+            }
+            Err(_) => Err(Error::IoError("Flush operation timed out".to_string())),
+        }
+    }
+
+    /// Counts a packet of `len` bytes queued for the writer.
+    fn record_sent(&self, len: u64) {
+        self.metrics.increment(Counter::PacketsSent);
+        self.metrics.observe(Observation::BytesSent, len);
+    }
+
+    /// Attaches the session or credentials to a packet and encodes it.
+    ///
+    /// The writer task encrypts the result, see `connection::spawn_io`.
+    fn encode_outgoing(&self, mut packet: P) -> Bytes {
+        // Add session ID if available
+        if let Some(id) = self.session_id.clone() {
+            packet.session_id(Some(id));
+        } else {
+            if let (Some(user), Some(pass)) = (&self.user, &self.pass) {
+                packet.body_mut().username = Some(user.to_owned());
+                packet.body_mut().password = Some(pass.to_owned());
+            }
+            packet.body_mut().token.clone_from(&self.token);
+        }
+
+        self.compression.encode(&packet, None).into()
+    }
+
     /// Sends a phantom packet to the server.
     ///
     /// # Arguments
@@ -933,30 +2007,23 @@ where
         &mut self,
         mut packet: PhantomPacket,
     ) -> Result<PhantomPacket, Error> {
-        tokio::time::sleep(Duration::from_nanos(500_000)).await;
-
         if let Some(id) = self.session_id.clone() {
             packet.session_id(Some(id));
-        } else if let Some(user) = &self.user {
-            if let Some(pass) = &self.pass {
+        } else {
+            if let (Some(user), Some(pass)) = (&self.user, &self.pass) {
                 packet.body_mut().username = Some(user.to_owned());
                 packet.body_mut().password = Some(pass.to_owned());
             }
+            packet.body_mut().token.clone_from(&self.token);
         }
 
-        let data = match &self.encryption {
-            ClientEncryption::None => packet.ser(),
-            ClientEncryption::Encrypted(encryptor) => packet.encrypted_ser(encryptor),
-        };
-
+        let encryptor = self.encryption.encryptor().cloned();
         self.connection
             .writer_tx
-            .send(ClientMessage::Data(data))
+            .send(ClientMessage::Data(packet.ser().into(), encryptor))
             .await
             .map_err(|e| Error::FailedPacketSend(e.to_string()))?;
 
-        tokio::time::sleep(Duration::from_nanos(750)).await;
-
         let data = self
             .response_rx
             .recv()
@@ -973,28 +2040,53 @@ where
 
     /// Receives a packet from the server.
     ///
+    /// Broadcasts are handed to the broadcast handler, if one is set, instead
+    /// of being returned.
+    ///
     /// # Returns
     ///
     /// * `Result<P, Error>` - The received packet or an error
     ///
     /// # Errors
     ///
-    /// Returns an error if the connection is closed
+    /// Returns an error if the connection is closed or the packet cannot be decompressed
     pub async fn recv(&mut self) -> Result<P, Error> {
+        self.recv_within(self.timeouts.recv).await
+    }
+
+    /// Receives a packet from the server, waiting at most `timeout`.
+    async fn recv_within(&mut self, timeout: Duration) -> Result<P, Error> {
         if self.connection_closed.load(Ordering::SeqCst) {
             return Err(Error::ConnectionClosed);
         }
 
-        match tokio::time::timeout(Duration::from_secs(10), self.response_rx.recv()).await {
+        match tokio::time::timeout(timeout, self.response_rx.recv()).await {
             Ok(Some(data)) => {
-                let packet = match &self.encryption {
-                    ClientEncryption::None => P::de(&data),
-                    ClientEncryption::Encrypted(encryptor) => P::encrypted_de(&data, encryptor),
-                };
+                self.metrics.increment(Counter::PacketsReceived);
+                self.metrics
+                    .observe(Observation::BytesReceived, data.len() as u64);
+
+                let encryptor = self
+                    .encryption
+                    .encryptor()
+                    .filter(|_| !self.responses_decrypted);
+                let max_len = self.max_packet_size.load(Ordering::SeqCst);
+                let packet = self
+                    .compression
+                    .decode_limited::<P>(&data, encryptor, max_len)?;
 
                 if packet.header() == P::keep_alive().header() {
-                    println!("Skipping keep-alive packet during recv");
-                    return Box::pin(self.recv()).await;
+                    debug!("Skipping keep-alive packet during recv");
+                    return Box::pin(self.recv_within(timeout)).await;
+                }
+
+                let handler = self
+                    .broadcast_handler
+                    .as_ref()
+                    .filter(|_| packet.is_broadcast());
+                if let Some(handler) = handler {
+                    handler(packet);
+                    return Box::pin(self.recv_within(timeout)).await;
                 }
 
                 Ok(packet)
@@ -1003,14 +2095,44 @@ where
                 self.connection_closed.store(true, Ordering::SeqCst);
                 Err(Error::ConnectionClosed)
             }
-            Err(_) => {
-                Err(Error::IoError("Receive operation timed out".to_string()))
+            Err(_) => Err(Error::IoError("Receive operation timed out".to_string())),
+        }
+    }
+
+    /// Receives the response to the request stamped with `request_id`.
+    ///
+    /// Broadcasts arriving in the meantime are handed to the broadcast handler
+    /// or dropped, and responses carrying a different request id are discarded.
+    /// Responses without any request id are accepted, for servers that don't
+    /// echo it.
+    async fn recv_response(&mut self, request_id: u64, timeout: Duration) -> Result<P, Error> {
+        loop {
+            let packet = Box::pin(self.recv_within(timeout)).await?;
+
+            // Only reaches here without a broadcast handler
+            if packet.is_broadcast() {
+                continue;
+            }
+
+            match packet.body().request_id {
+                Some(id) if id != request_id => {
+                    debug!(
+                        discarded = id,
+                        request_id, "Discarding response to another request"
+                    );
+                }
+                _ => return Ok(packet),
             }
         }
     }
 
     /// Sends a packet and waits for a response.
     ///
+    /// The packet is stamped with a fresh request id and only the response
+    /// echoing that id is returned. Failed requests are retried up to
+    /// `ReconnectionConfig::max_attempts` times (5 if unlimited), reconnecting
+    /// first; use `send_recv_with` for different per-call behavior.
+    ///
     /// # Arguments
     ///
     /// * `packet` - The packet to send
@@ -1025,52 +2147,351 @@ where
     /// - Sending the packet fails
     /// - Receiving the response fails
     pub async fn send_recv(&mut self, packet: P) -> Result<P, Error> {
+        let options = SendRecvOptions::default()
+            .with_retries(self.reconnection_config.max_attempts.unwrap_or(5));
+        self.send_recv_with(packet, options).await
+    }
+
+    /// Sends a packet and waits for a response, using per-call settings.
+    ///
+    /// Works like `send_recv`, but the response timeout, the number of retries
+    /// and whether the client reconnects between them come from `options`.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - The packet to send
+    /// * `options` - Timeout and retry settings for this request
+    ///
+    /// # Returns
+    ///
+    /// * `Result<P, Error>` - The response packet or an error
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - Sending the packet fails
+    /// - Receiving the response fails after every retry
+    /// - The connection closes and `reconnect_on_fail` is off
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let options = SendRecvOptions::default().with_retries(0);
+    /// let response = client.send_recv_with(packet, options).await?;
+    /// ```
+    pub async fn send_recv_with(
+        &mut self,
+        mut packet: P,
+        options: SendRecvOptions,
+    ) -> Result<P, Error> {
         let mut attempt_count = 0;
-        let max_attempts = self.reconnection_config.max_attempts.unwrap_or(5);
+        let timeout = options.timeout.unwrap_or(self.timeouts.recv);
+
+        let request_id = self.next_request_id;
+        self.next_request_id = self.next_request_id.wrapping_add(1);
+        packet.body_mut().request_id = Some(request_id);
 
         loop {
-            match Box::pin(self.send(packet.clone())).await {
-                Ok(_) => match Box::pin(self.recv()).await {
-                    Ok(response) => return Ok(response),
-                    Err(e) => {
-                        if matches!(e, Error::ConnectionClosed | Error::IoError(_))
-                            && attempt_count < max_attempts
-                        {
-                            attempt_count += 1;
-                            match Box::pin(self.try_reconnect()).await {
-                                Ok(_) => continue,
-                                Err(_) if attempt_count < max_attempts => {
-                                    tokio::time::sleep(Duration::from_secs(1)).await;
-                                    continue;
-                                }
-                                Err(e) => return Err(e),
-                            }
-                        } else {
-                            return Err(e);
-                        }
-                    }
-                },
-                Err(e) => {
-                    if matches!(e, Error::ConnectionClosed | Error::IoError(_))
-                        && attempt_count < max_attempts
-                    {
-                        attempt_count += 1;
-                        match Box::pin(self.try_reconnect()).await {
-                            Ok(_) => continue,
-                            Err(_) if attempt_count < max_attempts => {
-                                tokio::time::sleep(Duration::from_secs(1)).await;
-                                continue;
-                            }
-                            Err(e) => return Err(e),
-                        }
-                    } else {
-                        return Err(e);
-                    }
+            let result = match Box::pin(self.send(packet.clone())).await {
+                Ok(()) => Box::pin(self.recv_response(request_id, timeout)).await,
+                Err(e) => Err(e),
+            };
+
+            let e = match result {
+                Ok(response) => {
+                    return Box::pin(self.answer_challenge(response, request_id, timeout)).await;
                 }
+                Err(e) => e,
+            };
+
+            if !matches!(e, Error::ConnectionClosed | Error::IoError(_))
+                || attempt_count >= options.retries
+            {
+                return Err(e);
+            }
+            attempt_count += 1;
+
+            if !options.reconnect_on_fail {
+                if self.connection_closed.load(Ordering::SeqCst) {
+                    return Err(e);
+                }
+                continue;
+            }
+
+            match Box::pin(self.try_reconnect()).await {
+                Ok(_) => {}
+                Err(Error::ReconnectTimedOut) => return Err(Error::ReconnectTimedOut),
+                Err(_) if attempt_count < options.retries => {
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+                Err(e) => return Err(e),
             }
         }
     }
 
+    /// Measures the round-trip time to the server.
+    ///
+    /// Sends a ping, which the listener answers itself without running any
+    /// handler, and times how long the answer takes. The ping is not retried,
+    /// so a dropped connection shows up as an error rather than a slow reading.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Duration, Error>` - The time between sending the ping and receiving its answer
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - Sending the ping fails
+    /// - No answer arrives within the receive timeout
+    /// - The answer is not an "OK" packet marked as a pong
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let latency = client.ping().await?;
+    /// println!("Round trip: {latency:?}");
+    /// ```
+    pub async fn ping(&mut self) -> Result<Duration, Error> {
+        let options = SendRecvOptions::default()
+            .with_retries(0)
+            .with_reconnect_on_fail(false);
+        let started = std::time::Instant::now();
+        let response = self.send_recv_with(P::ping(), options).await?;
+        let elapsed = started.elapsed();
+        response.expected_ok()?;
+        if !response.is_pong() {
+            return Err(Error::InvalidPacket(
+                "Expected an answer to the ping".to_string(),
+            ));
+        }
+        Ok(elapsed)
+    }
+
+    /// Sends a packet with a large attachment as a series of smaller packets.
+    ///
+    /// The attachment is split into pieces of at most `chunk_size` bytes and each
+    /// piece is queued on its own, so other traffic on the connection goes out in
+    /// between instead of waiting for the whole attachment. The listener puts the
+    /// attachment back together and dispatches the packet once the last piece
+    /// arrives; the response to it is returned. The transfer is not retried, as
+    /// the listener drops partial transfers along with the connection.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - The packet to send, carrying the attachment
+    /// * `chunk_size` - Largest number of attachment bytes per chunk
+    /// * `progress` - Called after each chunk with the bytes sent so far and the total
+    ///
+    /// # Returns
+    ///
+    /// * `Result<P, Error>` - The response to the reassembled packet or an error
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - `chunk_size` is zero
+    /// - Sending any of the chunks fails
+    /// - No response arrives within the receive timeout
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let mut packet = MyPacket::ok();
+    /// packet.body_mut().attachment = Some(std::fs::read("video.mp4")?);
+    /// let response = client
+    ///     .send_chunked(packet, 64 * 1024, |sent, total| {
+    ///         println!("{sent}/{total} bytes");
+    ///     })
+    ///     .await?;
+    /// ```
+    pub async fn send_chunked<F>(
+        &mut self,
+        mut packet: P,
+        chunk_size: usize,
+        mut progress: F,
+    ) -> Result<P, Error>
+    where
+        F: FnMut(usize, usize) + Send,
+    {
+        if chunk_size == 0 {
+            return Err(Error::InvalidPacket(
+                "Chunk size must be greater than zero".to_string(),
+            ));
+        }
+
+        let data = packet.body_mut().attachment.take().unwrap_or_default();
+        let total = data.len();
+        let mut pieces: Vec<&[u8]> = data.chunks(chunk_size).collect();
+        let last = pieces.pop().unwrap_or_default();
+        let count = u32::try_from(pieces.len() + 1).map_err(|_| Error::PacketTooLarge(total))?;
+
+        let transfer_id = self.next_request_id;
+        self.next_request_id = self.next_request_id.wrapping_add(1);
+
+        let mut sent = 0;
+        for (index, piece) in (0..).zip(pieces) {
+            let mut chunk = P::ok();
+            chunk.body_mut().chunk = Some(PacketChunk {
+                transfer_id,
+                index,
+                count,
+            });
+            chunk.body_mut().attachment = Some(piece.to_vec());
+            self.send(chunk).await?;
+
+            sent += piece.len();
+            progress(sent, total);
+        }
+
+        packet.body_mut().chunk = Some(PacketChunk {
+            transfer_id,
+            index: count - 1,
+            count,
+        });
+        packet.body_mut().attachment = Some(last.to_vec());
+        let options = SendRecvOptions::default()
+            .with_retries(0)
+            .with_reconnect_on_fail(false);
+        let response = self.send_recv_with(packet, options).await?;
+        progress(total, total);
+        Ok(response)
+    }
+
+    /// Sends a request and streams every response to it.
+    ///
+    /// The request is sent when the stream is first polled. Responses echoing
+    /// its request id are yielded until one flagged with `Packet::set_stream_end`,
+    /// or carrying the header set with `with_stream_end_header`, arrives. That
+    /// end marker is not yielded. Broadcasts arriving in the meantime go to the
+    /// broadcast handler, as with `send_recv`.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - The request to send
+    ///
+    /// # Returns
+    ///
+    /// * A stream of responses. It ends after the end marker, or after yielding
+    ///   the error if sending or receiving fails.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use futures::StreamExt;
+    ///
+    /// let mut rows = Box::pin(client.send_recv_stream(query));
+    /// while let Some(row) = rows.next().await {
+    ///     println!("Row: {:?}", row?);
+    /// }
+    /// ```
+    pub fn send_recv_stream(&mut self, mut packet: P) -> impl Stream<Item = Result<P, Error>> + '_ {
+        let request_id = self.next_request_id;
+        self.next_request_id = self.next_request_id.wrapping_add(1);
+        packet.body_mut().request_id = Some(request_id);
+
+        futures::stream::unfold(
+            (self, Some(packet), false),
+            move |(client, request, done)| async move {
+                if done {
+                    return None;
+                }
+
+                if let Some(request) = request
+                    && let Err(e) = client.send(request).await
+                {
+                    return Some((Err(e), (client, None, true)));
+                }
+
+                let timeout = client.timeouts.recv;
+                match client.recv_response(request_id, timeout).await {
+                    Ok(response) if client.ends_stream(&response) => None,
+                    Ok(response) => Some((Ok(response), (client, None, false))),
+                    Err(e) => Some((Err(e), (client, None, true))),
+                }
+            },
+        )
+    }
+
+    /// Checks whether `response` marks the end of a `send_recv_stream`.
+    fn ends_stream(&self, response: &P) -> bool {
+        response.is_stream_end()
+            || self
+                .stream_end_header
+                .as_ref()
+                .is_some_and(|header| *header == response.header())
+    }
+
+    /// Answers an authentication challenge carried by `response`, if there is one.
+    ///
+    /// The answer reuses the request id of the original request so the server's
+    /// verdict is returned in place of the challenge.
+    async fn answer_challenge(
+        &mut self,
+        response: P,
+        request_id: u64,
+        timeout: Duration,
+    ) -> Result<P, Error> {
+        match (response.body().challenge, self.challenge_responder) {
+            (Some(challenge), Some(responder)) => {
+                let mut answer = P::ok();
+                answer.body_mut().challenge_response = Some(responder(challenge));
+                answer.body_mut().request_id = Some(request_id);
+                self.send(answer).await?;
+                self.recv_response(request_id, timeout).await
+            }
+            _ => Ok(response),
+        }
+    }
+
+    /// Closes the connection cleanly.
+    ///
+    /// Sends `P::disconnect()` so the server can run its disconnect handler, waits
+    /// for the writer to put it on the wire, and marks the connection as closed.
+    /// Calling `close` on a connection that is already closed does nothing.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), Error>` - Success or failure of sending the disconnect notice
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the disconnect packet cannot be sent
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// client.close().await?;
+    /// ```
+    pub async fn close(&mut self) -> Result<(), Error> {
+        if self.connection_closed.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+
+        self.stop_keepalive();
+        self.send(P::disconnect()).await?;
+
+        // The writer handles messages in order, so the ping is only answered
+        // once the disconnect packet has been written
+        let (flushed_tx, flushed_rx) = tokio::sync::oneshot::channel();
+        if self
+            .connection
+            .writer_tx
+            .send(ClientMessage::Ping(flushed_tx))
+            .await
+            .is_ok()
+        {
+            let _ = tokio::time::timeout(self.timeouts.send, flushed_rx).await;
+        }
+
+        self.connection_closed.store(true, Ordering::SeqCst);
+        self.connection_stable.store(false, Ordering::SeqCst);
+        if !self.disconnect_reported.swap(true, Ordering::SeqCst) {
+            self.metrics.increment(Counter::ConnectionsClosed);
+        }
+        Ok(())
+    }
+
     /// Starts the keep-alive mechanism.
     ///
     /// # Returns
@@ -1087,7 +2508,11 @@ where
         let session_id = self.session_id.clone().unwrap_or_default();
 
         let interval = self.keep_alive.interval;
+        let max_failures = self.keep_alive.max_failures.max(1);
+        let probe_every = self.keep_alive.probe_every;
         let encryption = self.encryption.clone();
+        let compression = self.compression;
+        let timeouts = self.timeouts;
         let keep_alive_running = self.keep_alive_running.clone();
         let writer_tx = self.connection.writer_tx.clone();
         let cold_start = self.keep_alive_cold_start.clone();
@@ -1095,6 +2520,10 @@ where
         let connection_stable = self.connection_stable.clone();
         let keepalive_reconnect_needed = Arc::new(AtomicBool::new(false));
         self.keepalive_reconnect_needed = keepalive_reconnect_needed.clone();
+        let keepalive_reconnect_tx = self.keepalive_reconnect_tx.clone();
+        let on_disconnect = self.on_disconnect.clone();
+        let disconnect_reported = self.disconnect_reported.clone();
+        let metrics = self.metrics.clone();
 
         keep_alive_running.store(true, Ordering::SeqCst);
 
@@ -1102,14 +2531,21 @@ where
         tokio::spawn(async move {
             let mut interval = tokio::time::interval(Duration::from_secs(interval));
             let mut consecutive_failures = 0;
+            let mut sent: u32 = 0;
 
             while keep_alive_running.load(Ordering::SeqCst) {
                 interval.tick().await;
 
                 // Don't send keepalive if connection is known to be closed
                 if connection_closed.load(Ordering::SeqCst) {
-                    println!("Connection is closed, stopping keepalive");
-                    keep_alive_running.store(false, Ordering::SeqCst);
+                    debug!(session_id = %session_id, "Connection is closed, stopping keepalive");
+                    // Unless the client is closing, the lost connection should come back
+                    if keep_alive_running.swap(false, Ordering::SeqCst) {
+                        keepalive_reconnect_needed.store(true, Ordering::SeqCst);
+                        if let Some(tx) = &keepalive_reconnect_tx {
+                            let _ = tx.try_send(());
+                        }
+                    }
                     break;
                 }
 
@@ -1124,15 +2560,13 @@ where
 
                 packet.session_id(Some(session_id.clone()));
 
-                let data = match &encryption {
-                    ClientEncryption::None => packet.ser(),
-                    ClientEncryption::Encrypted(encryptor) => packet.encrypted_ser(encryptor),
-                };
+                let data = compression.encode(&packet, None);
+                let encryptor = encryption.encryptor().cloned();
 
                 // Use timeout for keepalive send
                 match tokio::time::timeout(
-                    Duration::from_secs(5),
-                    writer_tx.send(ClientMessage::Keepalive(data)),
+                    timeouts.send,
+                    writer_tx.send(ClientMessage::Keepalive(data.into(), encryptor)),
                 )
                 .await
                 {
@@ -1141,49 +2575,65 @@ where
                         consecutive_failures = 0;
                     }
                     Ok(Err(e)) => {
-                        println!("Keepalive send error: {}", e);
+                        warn!(session_id = %session_id, error = %e, "Keepalive send error");
                         consecutive_failures += 1;
                     }
                     Err(_) => {
-                        println!("Keepalive send timeout");
+                        warn!(session_id = %session_id, "Keepalive send timeout");
                         consecutive_failures += 1;
                     }
                 }
 
-                // Verify connection with a ping periodically
-                if consecutive_failures == 0 && rand::random::<u8>() % 5 == 0 {
-                    // 20% chance to check
+                // Verify the writer is still draining the queue every few keep-alives
+                sent = sent.wrapping_add(1);
+                let probe_due = probe_every != 0 && sent.is_multiple_of(probe_every);
+                if consecutive_failures == 0 && probe_due {
                     let (ping_tx, ping_rx) = tokio::sync::oneshot::channel();
 
                     match writer_tx.send(ClientMessage::Ping(ping_tx)).await {
                         Ok(()) => {
-                            match tokio::time::timeout(Duration::from_secs(2), ping_rx).await {
+                            match tokio::time::timeout(timeouts.keepalive_ping, ping_rx).await {
                                 Ok(Ok(true)) => {}
                                 _ => {
-                                    println!("Ping failed, connection may be unstable");
+                                    warn!(
+                                        session_id = %session_id,
+                                        "Ping failed, connection may be unstable"
+                                    );
                                     consecutive_failures += 1;
                                 }
                             }
                         }
                         Err(_) => {
-                            println!("Failed to send ping request");
+                            warn!(session_id = %session_id, "Failed to send ping request");
                             consecutive_failures += 1;
                         }
                     }
                 }
 
-                if consecutive_failures >= 3 {
-                    println!("Keepalive failed 3 times consecutively, triggering reconnection");
+                if consecutive_failures >= max_failures {
+                    warn!(
+                        session_id = %session_id,
+                        failures = consecutive_failures,
+                        "Keepalive failed consecutively, triggering reconnection"
+                    );
                     connection_closed.store(true, Ordering::SeqCst);
                     connection_stable.store(false, Ordering::SeqCst);
                     keepalive_reconnect_needed.store(true, Ordering::SeqCst);
+                    Self::report_disconnect(
+                        on_disconnect.as_ref(),
+                        &disconnect_reported,
+                        metrics.as_ref(),
+                    );
 
                     keep_alive_running.store(false, Ordering::SeqCst);
+                    if let Some(tx) = &keepalive_reconnect_tx {
+                        let _ = tx.try_send(());
+                    }
                     break;
                 }
             }
 
-            println!("Keepalive task stopped");
+            debug!(session_id = %session_id, "Keepalive task stopped");
         });
 
         Ok(())
@@ -1194,6 +2644,107 @@ where
         self.keep_alive_running.store(false, Ordering::SeqCst);
     }
 
+    /// Stops the running keep-alive task and detaches the client from it, so a
+    /// new task can be started even before the old one has noticed.
+    fn detach_keepalive(&mut self) {
+        self.keep_alive_running.store(false, Ordering::SeqCst);
+        self.keep_alive_running = Arc::new(AtomicBool::new(false));
+    }
+
+    /// Routes keep-alive reconnection requests to `tx`, restarting a running
+    /// keep-alive task so it picks the channel up.
+    ///
+    /// # Arguments
+    ///
+    /// * `tx` - Channel signalled when the keep-alive task gives up on the connection
+    pub(crate) fn set_keepalive_reconnect_tx(&mut self, tx: mpsc::Sender<()>) {
+        self.keepalive_reconnect_tx = Some(tx);
+        if self.keep_alive_running.load(Ordering::SeqCst) {
+            self.detach_keepalive();
+            let _ = self.start_keepalive();
+        }
+    }
+
+    /// Reconnects after the keep-alive task gave up on the connection.
+    ///
+    /// Does nothing unless a reconnection was requested since the last call.
+    pub(crate) async fn reconnect_after_keepalive(&mut self) {
+        if !self
+            .keepalive_reconnect_needed
+            .swap(false, Ordering::SeqCst)
+        {
+            return;
+        }
+
+        if let Err(e) = self.try_reconnect().await {
+            warn!(peer = %self.current_endpoint, error = %e, "Keepalive reconnection failed");
+        }
+    }
+
+    /// Gets the id of the session the server assigned to this client.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<&str>` - The session id, or `None` if no session has been established
+    #[must_use]
+    pub fn session_id(&self) -> Option<&str> {
+        self.session_id.as_deref()
+    }
+
+    /// Reports the current state of the connection.
+    ///
+    /// # Returns
+    ///
+    /// * `ConnectionStatus` - The connection state derived from the client's health flags
+    #[must_use]
+    pub fn status(&self) -> ConnectionStatus {
+        if self.reconnecting.load(Ordering::SeqCst) {
+            ConnectionStatus::Reconnecting
+        } else if self.connection_closed.load(Ordering::SeqCst) {
+            ConnectionStatus::Closed
+        } else if !self.server_responded.load(Ordering::SeqCst) {
+            ConnectionStatus::Connecting
+        } else if !self.connection_stable.load(Ordering::SeqCst) {
+            ConnectionStatus::Unstable
+        } else {
+            ConnectionStatus::Connected
+        }
+    }
+
+    /// Checks if the client is connected to the server.
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - True if the status is `Connected` or `Unstable`, false otherwise
+    #[must_use]
+    pub fn is_connected(&self) -> bool {
+        matches!(
+            self.status(),
+            ConnectionStatus::Connected | ConnectionStatus::Unstable
+        )
+    }
+
+    /// Checks if the connection to the server is encrypted.
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - True once an encrypted handshake has completed, false otherwise
+    #[must_use]
+    pub const fn is_encrypted(&self) -> bool {
+        matches!(self.encryption, ClientEncryption::Encrypted(_))
+    }
+
+    /// Checks if Nagle's algorithm is disabled on the connection.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<bool>` - The connection's `TCP_NODELAY` setting, or None if it
+    ///   isn't a TCP connection
+    #[must_use]
+    pub fn nodelay(&self) -> Option<bool> {
+        self.tcp.as_ref().and_then(|tcp| tcp.nodelay().ok())
+    }
+
     /// Checks if keep-alive is currently active.
     ///
     /// # Returns
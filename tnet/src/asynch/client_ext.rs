@@ -1,6 +1,6 @@
 use std::sync::Arc;
 
-use tokio::sync::RwLock;
+use tokio::sync::{RwLock, mpsc};
 
 use crate::{errors::Error, packet};
 
@@ -10,9 +10,43 @@ use super::client::AsyncClient;
 pub struct AsyncClientRef<P: packet::Packet>(Arc<RwLock<AsyncClient<P>>>);
 
 impl<P: packet::Packet> AsyncClientRef<P> {
+    /// Wraps a client for sharing between tasks.
+    ///
+    /// Also spawns a task that reconnects the client whenever its keep-alive
+    /// gives up on the connection, so a shared client recovers without waiting
+    /// for the next `send_recv`. The task ends once every reference is dropped.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - The client to wrap
+    ///
+    /// # Returns
+    ///
+    /// * A new `AsyncClientRef` instance
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a Tokio runtime.
     #[must_use]
-    pub fn new(client: AsyncClient<P>) -> Self {
-        Self(Arc::new(RwLock::new(client)))
+    pub fn new(mut client: AsyncClient<P>) -> Self
+    where
+        P: 'static,
+    {
+        let (reconnect_tx, mut reconnect_rx) = mpsc::channel(1);
+        client.set_keepalive_reconnect_tx(reconnect_tx);
+
+        let shared = Arc::new(RwLock::new(client));
+        let weak = Arc::downgrade(&shared);
+        tokio::spawn(async move {
+            while reconnect_rx.recv().await.is_some() {
+                match weak.upgrade() {
+                    Some(client) => client.write().await.reconnect_after_keepalive().await,
+                    None => break,
+                }
+            }
+        });
+
+        Self(shared)
     }
 
     pub async fn write(&mut self) -> tokio::sync::RwLockWriteGuard<'_, AsyncClient<P>> {
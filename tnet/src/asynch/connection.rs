@@ -0,0 +1,152 @@
+//! Reader and writer tasks shared by the client types.
+//!
+//! Every client talks to its connection through a pair of channels: outgoing
+//! messages are queued for a writer task and incoming frames are forwarded by a
+//! reader task. Both tasks flag the connection as closed when it fails, so the
+//! client can report the failure instead of queueing into a dead connection.
+
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+use bytes::Bytes;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    sync::mpsc,
+};
+use tracing::{debug, info, warn};
+
+use super::{
+    client::{ClientMessage, ConnectionHandler, WRITE_QUEUE_CAPACITY},
+    framing::{self, FrameReader},
+};
+
+/// The channels and flags connecting a client to its I/O tasks.
+///
+/// # Fields
+///
+/// * `handler` - Channels for queueing outgoing messages
+/// * `response_rx` - Frames read from the connection
+/// * `connection_closed` - Set once either task sees the connection fail or close
+/// * `server_responded` - Set once the first frame has been read
+pub struct ConnectionIo {
+    pub handler: ConnectionHandler,
+    pub response_rx: mpsc::Receiver<Bytes>,
+    pub connection_closed: Arc<AtomicBool>,
+    pub server_responded: Arc<AtomicBool>,
+}
+
+/// Spawns the reader and writer tasks for a connected stream.
+///
+/// Messages queued after the connection is known to be closed are dropped
+/// rather than written.
+///
+/// # Arguments
+///
+/// * `read_half` - The read side of the stream
+/// * `write_half` - The write side of the stream
+/// * `peer` - The peer's address, used in log events
+/// * `max_frame_len` - Largest frame the reader accepts, read before every frame
+///
+/// # Returns
+///
+/// * `ConnectionIo` - The channels and flags shared with the spawned tasks
+pub fn spawn_io<RH, WH>(
+    read_half: RH,
+    mut write_half: WH,
+    peer: String,
+    max_frame_len: Arc<AtomicUsize>,
+) -> ConnectionIo
+where
+    RH: AsyncRead + Send + Unpin + 'static,
+    WH: AsyncWrite + Send + Unpin + 'static,
+{
+    let (writer_tx, mut writer_rx) = mpsc::channel::<ClientMessage>(WRITE_QUEUE_CAPACITY);
+    let (reader_tx, reader_rx) = mpsc::channel::<Bytes>(32);
+
+    let connection_closed = Arc::new(AtomicBool::new(false));
+    let connection_closed_writer = connection_closed.clone();
+    let connection_closed_reader = connection_closed.clone();
+    let server_responded = Arc::new(AtomicBool::new(false));
+    let server_responded_reader = server_responded.clone();
+    let writer_peer = peer.clone();
+
+    // Spawn writer task
+    tokio::spawn(async move {
+        while let Some(msg) = writer_rx.recv().await {
+            if connection_closed_writer.load(Ordering::SeqCst) {
+                // Don't try to write if connection is known to be closed
+                continue;
+            }
+
+            match msg {
+                ClientMessage::Data(data, encryptor)
+                | ClientMessage::Keepalive(data, encryptor) => {
+                    // Encrypting here hands out counters in the order frames are
+                    // written, which keeps them inside the server's replay window
+                    let data = match encryptor.map(|enc| enc.encrypt(&data)).transpose() {
+                        Ok(Some(sealed)) => Bytes::from(sealed.into_bytes()),
+                        Ok(None) => data,
+                        Err(e) => {
+                            warn!(peer = %writer_peer, error = %e, "Failed to encrypt frame");
+                            continue;
+                        }
+                    };
+                    debug!(peer = %writer_peer, bytes = data.len(), "Writing frame");
+                    if let Err(e) = framing::write_frame(&mut write_half, &data).await {
+                        warn!(peer = %writer_peer, error = %e, "Write error");
+                        connection_closed_writer.store(true, Ordering::SeqCst);
+                        break;
+                    }
+                }
+                ClientMessage::Ping(response) => {
+                    let _ = response.send(true);
+                }
+            }
+        }
+        debug!(peer = %writer_peer, "Writer task ended");
+    });
+
+    // Spawn reader task
+    tokio::spawn(async move {
+        let mut frames = FrameReader::new(read_half);
+        loop {
+            if connection_closed_reader.load(Ordering::SeqCst) {
+                // Don't try to read if connection is known to be closed
+                break;
+            }
+
+            frames.set_max_frame_len(max_frame_len.load(Ordering::Relaxed));
+            match frames.read_frame().await {
+                Ok(Some(data)) => {
+                    debug!(peer = %peer, bytes = data.len(), "Read frame");
+                    server_responded_reader.store(true, Ordering::SeqCst);
+                    if let Err(e) = reader_tx.send(data).await {
+                        warn!(peer = %peer, error = %e, "Reader send error");
+                        connection_closed_reader.store(true, Ordering::SeqCst);
+                        break;
+                    }
+                }
+                Ok(None) => {
+                    info!(peer = %peer, "Connection closed by peer");
+                    connection_closed_reader.store(true, Ordering::SeqCst);
+                    break;
+                }
+                Err(e) => {
+                    warn!(peer = %peer, error = %e, "Read error");
+                    connection_closed_reader.store(true, Ordering::SeqCst);
+                    break;
+                }
+            }
+        }
+        debug!(peer = %peer, "Reader task ended");
+    });
+
+    ConnectionIo {
+        handler: ConnectionHandler { writer_tx },
+        response_rx: reader_rx,
+        connection_closed,
+        server_responded,
+    }
+}
@@ -0,0 +1,217 @@
+//! Length-prefixed framing shared by every tnet connection.
+//!
+//! Each message on the wire is a 4-byte big-endian length followed by exactly that
+//! many payload bytes. This keeps back-to-back packets from being merged or split
+//! by the transport, so readers never have to rely on timing to find boundaries.
+//!
+//! Frames are handed around as [`Bytes`], so a payload read from or written to
+//! the wire can be shared between tasks and sockets without copying it.
+
+use std::io;
+
+use bytes::{Buf, Bytes, BytesMut};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::errors::Error;
+
+/// Size of the length prefix written in front of every frame.
+pub const FRAME_HEADER_LEN: usize = 4;
+
+/// How much room the reader makes in its buffer before each read.
+const READ_CHUNK_LEN: usize = 4096;
+
+/// Largest frame payload accepted unless a different limit is configured.
+pub const DEFAULT_MAX_FRAME_LEN: usize = 16 * 1024 * 1024;
+
+/// The error carried by `read_frame` when a peer announces a frame larger than
+/// the reader's limit.
+#[derive(Debug, thiserror::Error)]
+#[error("Frame of {len} bytes exceeds the {max} byte limit")]
+pub struct FrameTooLarge {
+    pub len: usize,
+    pub max: usize,
+}
+
+/// Converts an error returned by `read_frame` into a tnet error.
+///
+/// # Arguments
+///
+/// * `error` - The error returned by the frame reader
+///
+/// # Returns
+///
+/// * `Error` - `Error::PacketTooLarge` for oversized frames, `Error::IoError` otherwise
+#[must_use]
+pub fn read_error(error: &io::Error) -> Error {
+    error
+        .get_ref()
+        .and_then(|inner| inner.downcast_ref::<FrameTooLarge>())
+        .map_or_else(
+            || Error::IoError(error.to_string()),
+            |too_large| Error::PacketTooLarge(too_large.len),
+        )
+}
+
+/// Prefixes a payload with its length so it can be written as a single frame.
+///
+/// # Arguments
+///
+/// * `payload` - The bytes to frame
+///
+/// # Returns
+///
+/// * `Vec<u8>` - The length prefix followed by the payload
+#[must_use]
+pub fn encode_frame(payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(FRAME_HEADER_LEN + payload.len());
+    frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// Writes a payload as a single frame and flushes the writer.
+///
+/// The length prefix and the payload are written together as one vectored
+/// write where the stream supports it, so the payload is never copied.
+///
+/// # Arguments
+///
+/// * `writer` - The stream to write to
+/// * `payload` - The bytes to send
+///
+/// # Errors
+///
+/// Returns an error if writing to or flushing the stream fails
+pub async fn write_frame<W>(writer: &mut W, payload: &[u8]) -> io::Result<()>
+where
+    W: AsyncWrite + Unpin + ?Sized,
+{
+    write_frame_unflushed(writer, payload).await?;
+    writer.flush().await
+}
+
+/// Writes a payload as a single frame without flushing the writer.
+///
+/// Over a buffered writer the frame may stay in the buffer until the next
+/// flush, which lets several frames go out together.
+///
+/// # Arguments
+///
+/// * `writer` - The stream to write to
+/// * `payload` - The bytes to send
+///
+/// # Errors
+///
+/// Returns an error if writing to the stream fails
+pub async fn write_frame_unflushed<W>(writer: &mut W, payload: &[u8]) -> io::Result<()>
+where
+    W: AsyncWrite + Unpin + ?Sized,
+{
+    let header = (payload.len() as u32).to_be_bytes();
+    let mut frame = Buf::chain(&header[..], payload);
+    // `write_all_buf` needs a sized writer, which `&mut W` always is
+    let mut sized_writer = &mut *writer;
+    AsyncWriteExt::write_all_buf(&mut sized_writer, &mut frame).await
+}
+
+/// Reads length-prefixed frames from a stream.
+///
+/// Bytes read past the end of a frame are kept for the next call, and a read that
+/// is cancelled part way through (for example by a timeout) loses nothing, so
+/// `read_frame` can safely be raced against other futures.
+///
+/// Frames announcing more than the configured maximum length are refused as
+/// soon as their length prefix arrives, so a peer can't make the reader buffer
+/// an arbitrarily large payload.
+pub struct FrameReader<R> {
+    inner: R,
+    buf: BytesMut,
+    max_frame_len: usize,
+}
+
+impl<R> FrameReader<R>
+where
+    R: AsyncRead + Unpin,
+{
+    /// Wraps a stream in a new frame reader.
+    ///
+    /// # Arguments
+    ///
+    /// * `inner` - The stream to read frames from
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            buf: BytesMut::new(),
+            max_frame_len: DEFAULT_MAX_FRAME_LEN,
+        }
+    }
+
+    /// Sets the largest frame payload the reader accepts.
+    ///
+    /// # Arguments
+    ///
+    /// * `max` - Maximum payload length in bytes
+    pub const fn set_max_frame_len(&mut self, max: usize) {
+        self.max_frame_len = max;
+    }
+
+    /// Reads the next complete frame.
+    ///
+    /// # Returns
+    ///
+    /// * `io::Result<Option<Bytes>>` - The frame payload, or `None` if the peer
+    ///   closed the connection between frames
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading fails, the connection closes in the middle of a
+    /// frame, or the frame is longer than the reader's limit. The latter carries a
+    /// [`FrameTooLarge`], see [`read_error`].
+    pub async fn read_frame(&mut self) -> io::Result<Option<Bytes>> {
+        loop {
+            if let Some(frame) = self.next_frame()? {
+                return Ok(Some(frame));
+            }
+
+            self.buf.reserve(READ_CHUNK_LEN);
+            let n = self.inner.read_buf(&mut self.buf).await?;
+            if n == 0 {
+                if self.buf.is_empty() {
+                    return Ok(None);
+                }
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "Connection closed in the middle of a frame",
+                ));
+            }
+        }
+    }
+
+    /// Splits a complete frame off the front of the buffer, if one has arrived.
+    fn next_frame(&mut self) -> io::Result<Option<Bytes>> {
+        let header = self
+            .buf
+            .get(..FRAME_HEADER_LEN)
+            .and_then(|header| <[u8; FRAME_HEADER_LEN]>::try_from(header).ok());
+        let len = match header {
+            Some(header) => u32::from_be_bytes(header) as usize,
+            None => return Ok(None),
+        };
+        if len > self.max_frame_len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                FrameTooLarge {
+                    len,
+                    max: self.max_frame_len,
+                },
+            ));
+        }
+
+        if self.buf.len() < FRAME_HEADER_LEN + len {
+            return Ok(None);
+        }
+
+        self.buf.advance(FRAME_HEADER_LEN);
+        Ok(Some(self.buf.split_to(len).freeze()))
+    }
+}
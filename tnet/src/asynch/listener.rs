@@ -1,23 +1,45 @@
-use std::{collections::HashMap, marker::PhantomData, sync::Arc};
+use std::{
+    collections::HashMap,
+    marker::PhantomData,
+    net::{IpAddr, SocketAddr},
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
 
-use futures::future::BoxFuture;
+use futures::future::{BoxFuture, join_all};
+#[cfg(unix)]
+use tokio::net::UnixListener;
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
-    net::TcpListener,
-    sync::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+    io::{AsyncWriteExt, DuplexStream},
+    net::{TcpListener, TcpSocket},
+    sync::{Notify, RwLock, RwLockReadGuard, RwLockWriteGuard, Semaphore, oneshot, watch},
+    task::JoinHandle,
+    time::Instant,
 };
+use tracing::{debug, info, warn};
 
 use crate::{
-    encrypt::{Encryptor, KeyExchange},
+    compression::CompressionConfig,
+    encrypt::{CipherSuite, Encryptor, HandshakeHello, KeyExchange, Role},
     errors::Error,
-    handler_registry, packet, resources,
-    session::{self, Sessions},
+    handler_registry,
+    metrics::{Counter, Metrics, NoopMetrics},
+    packet, resources,
+    session::{self, SessionExpiryPolicy, SessionStore, SessionStoreRef, SessionSummary, Sessions},
 };
 
+#[cfg(feature = "websocket")]
+use super::websocket::WsListener;
 use super::{
-    authenticator::{AuthType, Authenticator},
-    client::EncryptionConfig,
-    socket::{TSocket, TSockets},
+    authenticator::{AuthType, Authenticator, SessionIdContext},
+    chunking::{self, ChunkedTransfers},
+    client::{EncryptionConfig, TimeoutConfig},
+    framing,
+    rate_limit::{RateLimitConfig, TokenBucket, TokenBuckets},
+    socket::{BatchConfig, BroadcastReport, PreparedBroadcast, TSocket, TSockets},
 };
 
 /// A collection of resources provided to packet handlers.
@@ -31,6 +53,7 @@ use super::{
 ///
 /// * `S` - The session type implementing the `Session` trait
 /// * `R` - The resource type implementing the `Resource` trait
+/// * `C` - The per-connection resource type, `()` when unused
 ///
 /// # Examples
 ///
@@ -49,14 +72,91 @@ use super::{
 /// }
 /// ```
 #[derive(Clone)]
-pub struct HandlerSources<S, R>
+pub struct HandlerSources<S, R, C = ()>
 where
     S: crate::session::Session,
     R: crate::resources::Resource,
+    C: crate::resources::Resource,
 {
     pub socket: TSocket<S>,
     pub pools: PoolRef<S>,
     pub resources: ResourceRef<R>,
+    /// Every authenticated connection on the listener
+    pub all_connections: TSockets<S>,
+    /// Resources of this connection alone, created with `C::new()` when it is
+    /// accepted. Not locked, so anything mutable inside needs its own interior mutability
+    pub connection_resources: Arc<C>,
+}
+
+impl<S, R, C> HandlerSources<S, R, C>
+where
+    S: crate::session::Session,
+    R: crate::resources::Resource,
+    C: crate::resources::Resource,
+{
+    /// Returns the username the connection's session authenticated with.
+    ///
+    /// # Returns
+    ///
+    /// * An Option containing the username, or None if the session was not
+    ///   created through username and password authentication
+    pub async fn username(&self) -> Option<String> {
+        self.socket.username().await
+    }
+
+    /// Replies to the packet being handled.
+    ///
+    /// The reply carries the connection's session id, unless it already has one,
+    /// and the request id of the packet it answers, so the client can match it
+    /// to its request.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet`: The response to send
+    ///
+    /// # Returns
+    ///
+    /// * A Result indicating success or failure
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::IoError` if writing to the socket fails
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// async fn handle_ping(mut sources: HandlerSources<MySession, MyResource>, _packet: MyPacket) {
+    ///     sources.reply(MyPacket::ok()).await.unwrap();
+    /// }
+    /// ```
+    pub async fn reply<P: packet::Packet>(&mut self, mut packet: P) -> Result<(), Error> {
+        if packet.body().session_id.is_none() {
+            packet.session_id(self.socket.session_id.clone());
+        }
+        self.socket.send(packet).await
+    }
+
+    /// Broadcasts a packet to every authenticated connection on the listener.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet`: The packet to broadcast
+    ///
+    /// # Returns
+    ///
+    /// * `Result<BroadcastReport, Error>` - The delivery and pruning counts
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Broadcast` if sending to a live connection fails
+    pub async fn broadcast_all<P: packet::Packet>(
+        &self,
+        packet: P,
+    ) -> Result<BroadcastReport, Error> {
+        self.all_connections
+            .broadcast(packet.set_broadcasting())
+            .await
+    }
 }
 
 /// Type alias for the success handler function in the async listener.
@@ -69,8 +169,9 @@ where
 /// * `P` - The packet type implementing the `Packet` trait
 /// * `S` - The session type implementing the `Session` trait
 /// * `R` - The resource type implementing the `Resource` trait
-pub type AsyncListenerOkHandler<P, S, R> =
-    Arc<dyn Fn(HandlerSources<S, R>, P) -> BoxFuture<'static, ()> + Send + Sync>;
+/// * `C` - The per-connection resource type, `()` when unused
+pub type AsyncListenerOkHandler<P, S, R, C = ()> =
+    Arc<dyn Fn(HandlerSources<S, R, C>, P) -> BoxFuture<'static, ()> + Send + Sync>;
 
 /// Type alias for the error handler function in the async listener.
 ///
@@ -81,8 +182,79 @@ pub type AsyncListenerOkHandler<P, S, R> =
 ///
 /// * `S` - The session type implementing the `Session` trait
 /// * `R` - The resource type implementing the `Resource` trait
-pub type AsyncListenerErrorHandler<S, R> =
-    Arc<dyn Fn(HandlerSources<S, R>, Error) -> BoxFuture<'static, ()> + Send + Sync>;
+/// * `C` - The per-connection resource type, `()` when unused
+pub type AsyncListenerErrorHandler<S, R, C = ()> =
+    Arc<dyn Fn(HandlerSources<S, R, C>, Error) -> BoxFuture<'static, ()> + Send + Sync>;
+
+/// What a middleware decided about a received packet.
+///
+/// # Variants
+///
+/// * `Pass` - Hand the packet, possibly modified, to the next middleware or the handlers
+/// * `Reject` - Drop the packet and pass the error to the error handler
+#[derive(Debug, Clone)]
+pub enum MiddlewareFlow<P> {
+    Pass(P),
+    Reject(Error),
+}
+
+/// Inspects every packet before it is dispatched to the handlers.
+///
+/// Middleware registered with [`AsyncListener::with_middleware`] runs in the
+/// order it was added, and is the place for concerns shared by every packet
+/// type such as logging, validation or access checks. Keep-alive and
+/// disconnect packets are handled by the listener itself and skip the chain.
+///
+/// # Type Parameters
+///
+/// * `P` - The packet type implementing the `Packet` trait
+/// * `S` - The session type implementing the `Session` trait
+/// * `R` - The resource type implementing the `Resource` trait
+/// * `C` - The per-connection resource type, `()` when unused
+///
+/// # Example
+///
+/// ```rust
+/// struct RequireSession;
+///
+/// impl Middleware<MyPacket, MySession, MyResource> for RequireSession {
+///     fn process<'a>(
+///         &'a self,
+///         _sources: &'a HandlerSources<MySession, MyResource>,
+///         packet: MyPacket,
+///     ) -> BoxFuture<'a, MiddlewareFlow<MyPacket>> {
+///         Box::pin(async move {
+///             if packet.body().session_id.is_some() {
+///                 MiddlewareFlow::Pass(packet)
+///             } else {
+///                 MiddlewareFlow::Reject(Error::InvalidSessionId("missing".to_string()))
+///             }
+///         })
+///     }
+/// }
+/// ```
+pub trait Middleware<P, S, R, C = ()>: Send + Sync
+where
+    S: crate::session::Session,
+    R: crate::resources::Resource,
+    C: crate::resources::Resource,
+{
+    /// Decides whether a packet reaches the handlers.
+    ///
+    /// # Arguments
+    ///
+    /// * `sources` - The socket, pools and resources of the connection
+    /// * `packet` - The received packet
+    ///
+    /// # Returns
+    ///
+    /// * `MiddlewareFlow<P>` - The packet to continue with, or the error to reject it with
+    fn process<'a>(
+        &'a self,
+        sources: &'a HandlerSources<S, R, C>,
+        packet: P,
+    ) -> BoxFuture<'a, MiddlewareFlow<P>>;
+}
 
 /// Thread-safe reference to a pool of socket connections.
 ///
@@ -125,22 +297,76 @@ impl<S: session::Session> PoolRef<S> {
             .await;
     }
 
+    // Remove the socket with the given session id from a pool, returning whether one was removed
+    pub async fn remove_from(&mut self, name: impl ToString, session_id: &str) -> bool {
+        let mut lock = self.0.write().await;
+        match lock.get_mut(name.to_string().as_str()) {
+            Some(pool) => pool.remove_by_session_id(session_id).await,
+            None => false,
+        }
+    }
+
     pub async fn get(&self, name: impl ToString) -> Option<TSockets<S>> {
         let lock = self.0.read().await;
         lock.get(name.to_string().as_str()).cloned()
     }
 
-    pub async fn broadcast<P: packet::Packet>(&self, packet: P) -> Result<(), Error> {
+    // Number of sockets in a pool, or 0 if the pool doesn't exist
+    pub async fn len(&self, pool_name: &str) -> usize {
+        match self.get(pool_name).await {
+            Some(pool) => pool.len().await,
+            None => 0,
+        }
+    }
+
+    // Whether a pool has no sockets; a missing pool counts as empty
+    pub async fn is_empty(&self, pool_name: &str) -> bool {
+        self.len(pool_name).await == 0
+    }
+
+    // Whether a pool holds a socket with the given session id
+    pub async fn contains(&self, pool_name: &str, session_id: &str) -> bool {
+        match self.get(pool_name).await {
+            Some(pool) => pool.contains(session_id).await,
+            None => false,
+        }
+    }
+
+    // Broadcast to every pool, pruning sockets that have disconnected
+    pub async fn broadcast<P: packet::Packet>(&self, packet: P) -> Result<BroadcastReport, Error> {
         let pools_to_broadcast = {
             let pools = self.0.read().await;
             pools.values().cloned().collect::<Vec<_>>()
         };
 
+        let mut report = BroadcastReport::default();
         for pool in pools_to_broadcast {
-            pool.broadcast(packet.clone().set_broadcasting()).await?;
+            report += pool.broadcast(packet.clone().set_broadcasting()).await?;
         }
 
-        Ok(())
+        Ok(report)
+    }
+
+    // Broadcast to every pool, but only to sockets whose session matches `pred`.
+    // Pools are copied out before sessions are loaded so no pool lock is held
+    // across a session store call.
+    pub async fn broadcast_where<P: packet::Packet, F: Fn(&S) -> bool + Sync>(
+        &self,
+        pred: F,
+        packet: P,
+    ) -> Result<BroadcastReport, Error> {
+        let pools_to_broadcast = {
+            let pools = self.0.read().await;
+            pools.values().cloned().collect::<Vec<_>>()
+        };
+
+        let prepared = PreparedBroadcast::new(packet.set_broadcasting());
+        let mut report = BroadcastReport::default();
+        for pool in pools_to_broadcast {
+            report += pool.broadcast_where_preserialized(&pred, &prepared).await?;
+        }
+
+        Ok(report)
     }
 
     // Broadcast to a specific pool
@@ -148,11 +374,47 @@ impl<S: session::Session> PoolRef<S> {
         &self,
         pool_name: &str,
         packet: P,
-    ) -> Result<(), Error> {
+    ) -> Result<BroadcastReport, Error> {
+        let pools = self.0.read().await;
+        if let Some(pool) = pools.get(pool_name) {
+            pool.broadcast(packet.set_broadcasting()).await
+        } else {
+            Err(Error::InvalidPool(pool_name.to_string()))
+        }
+    }
+
+    // Broadcast to every pool, skipping the socket that shares `exclude`'s session id
+    pub async fn broadcast_except<P: packet::Packet>(
+        &self,
+        exclude: &TSocket<S>,
+        packet: P,
+    ) -> Result<BroadcastReport, Error> {
+        let pools_to_broadcast = {
+            let pools = self.0.read().await;
+            pools.values().cloned().collect::<Vec<_>>()
+        };
+
+        let mut report = BroadcastReport::default();
+        for pool in pools_to_broadcast {
+            report += pool
+                .broadcast_except(exclude, packet.clone().set_broadcasting())
+                .await?;
+        }
+
+        Ok(report)
+    }
+
+    // Broadcast to a specific pool, skipping the socket that shares `exclude`'s session id
+    pub async fn broadcast_to_except<P: packet::Packet>(
+        &self,
+        pool_name: &str,
+        exclude: &TSocket<S>,
+        packet: P,
+    ) -> Result<BroadcastReport, Error> {
         let pools = self.0.read().await;
         if let Some(pool) = pools.get(pool_name) {
-            pool.broadcast(packet.set_broadcasting()).await?;
-            Ok(())
+            pool.broadcast_except(exclude, packet.set_broadcasting())
+                .await
         } else {
             Err(Error::InvalidPool(pool_name.to_string()))
         }
@@ -198,6 +460,352 @@ impl<R: resources::Resource + 'static> ResourceRef<R> {
     }
 }
 
+/// Determines what the listener does with new connections once the
+/// maximum number of concurrent connections has been reached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MaxConnPolicy {
+    /// Accept the connection, send `P::error(Error::TooManyConnections)` and close it.
+    #[default]
+    Reject,
+    /// Stop accepting new connections until an existing one disconnects.
+    Wait,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct CleanupSettings {
+    interval: Duration,
+    paused: bool,
+}
+
+/// A handle to a listener's expired session cleanup.
+///
+/// `AsyncListener::run` holds the listener for as long as it runs, so the
+/// cleanup cadence of a running server is changed through this handle. Clones
+/// control the same schedule, and changes apply to the wait in progress.
+///
+/// # Example
+///
+/// ```rust
+/// let schedule = listener.cleanup_schedule();
+/// tokio::spawn(async move { listener.run().await });
+///
+/// // Clean up more often during a traffic spike
+/// schedule.set_interval(5);
+/// ```
+#[derive(Debug, Clone)]
+pub struct CleanupSchedule(Arc<watch::Sender<CleanupSettings>>);
+
+impl CleanupSchedule {
+    fn new(secs: u64) -> Self {
+        Self(Arc::new(watch::Sender::new(CleanupSettings {
+            interval: Self::interval_from_secs(secs),
+            paused: false,
+        })))
+    }
+
+    // A zero interval would clean continuously, so it waits at least a second
+    fn interval_from_secs(secs: u64) -> Duration {
+        Duration::from_secs(secs.max(1))
+    }
+
+    /// Sets how many seconds pass between cleanups.
+    ///
+    /// # Arguments
+    ///
+    /// * `secs` - The new interval in seconds, at least one
+    pub fn set_interval(&self, secs: u64) {
+        self.0
+            .send_modify(|settings| settings.interval = Self::interval_from_secs(secs));
+    }
+
+    /// Returns the number of seconds between cleanups.
+    pub fn interval(&self) -> u64 {
+        self.0.borrow().interval.as_secs()
+    }
+
+    /// Stops cleaning up until `resume` is called. Sessions are still cleared
+    /// as new connections arrive.
+    pub fn pause(&self) {
+        self.0.send_modify(|settings| settings.paused = true);
+    }
+
+    /// Starts cleaning up again after `pause`, a full interval from now.
+    pub fn resume(&self) {
+        self.0.send_modify(|settings| settings.paused = false);
+    }
+
+    /// Returns `true` if cleanup is paused.
+    pub fn is_paused(&self) -> bool {
+        self.0.borrow().paused
+    }
+
+    /// Clears expired sessions on the schedule until every handle is dropped.
+    async fn drive<S: session::Session>(
+        mut settings: watch::Receiver<CleanupSettings>,
+        sessions: SessionStoreRef<S>,
+        expiry_policy: SessionExpiryPolicy,
+    ) {
+        loop {
+            let current = *settings.borrow_and_update();
+            if current.paused {
+                if settings.changed().await.is_err() {
+                    break;
+                }
+                continue;
+            }
+
+            tokio::select! {
+                () = tokio::time::sleep(current.interval) => {
+                    if let Err(e) = sessions.clear_expired(expiry_policy).await {
+                        warn!(error = %e, "Failed to clear expired sessions");
+                    }
+                }
+                changed = settings.changed() => {
+                    if changed.is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Determines how the listener runs the handlers for received packets.
+///
+/// Sequential dispatch keeps every guarantee about ordering: a connection's
+/// packets are handled one at a time in the order they arrived, and the
+/// handlers registered for a header run one after another by priority. Parallel
+/// dispatch trades those guarantees for throughput when packets and handlers
+/// don't depend on each other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DispatchMode {
+    /// Handle one packet per connection at a time, running its handlers in
+    /// priority order. A handler returning `HandlerFlow::Stop` skips the rest.
+    #[default]
+    Sequential,
+    /// Run every handler registered for a header at the same time, so priorities
+    /// no longer order them and `HandlerFlow::Stop` no longer skips any.
+    ///
+    /// Up to `max_in_flight` packets from one connection are handled at once,
+    /// after which the listener stops reading from it until one finishes. With
+    /// more than one in flight, replies can be sent in a different order than
+    /// the requests arrived; clients match them up by request id.
+    Parallel { max_in_flight: usize },
+}
+
+/// A listener running in the background, started with `AsyncListener::spawn`.
+///
+/// Stopping the server, or dropping this handle, stops accepting new
+/// connections. Connections that were already accepted keep being served
+/// until their clients disconnect.
+///
+/// # Example
+///
+/// ```rust
+/// let server = AsyncListener::new(("127.0.0.1", 0), 30, ok_handler, error_handler)
+///     .await
+///     .spawn();
+/// let port = server.local_addr().unwrap().port();
+///
+/// // ...
+///
+/// server.stop().await;
+/// ```
+pub struct RunningServer {
+    local_addr: Option<SocketAddr>,
+    shutdown: oneshot::Sender<()>,
+    task: JoinHandle<()>,
+}
+
+impl RunningServer {
+    /// Returns the address the server is bound to.
+    ///
+    /// For a listener bound to port 0 this holds the port the system picked.
+    ///
+    /// # Returns
+    ///
+    /// * The bound socket address, or None for Unix domain sockets
+    #[must_use]
+    pub const fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_addr
+    }
+
+    /// Stops accepting connections and waits for the listener task to finish.
+    pub async fn stop(self) {
+        let _ = self.shutdown.send(());
+        if let Err(e) = self.task.await {
+            warn!(error = %e, "Listener task failed");
+        }
+    }
+}
+
+/// Socket options applied when an `AsyncListener` binds a TCP port.
+///
+/// # Fields
+///
+/// * `reuseaddr` - Whether to set `SO_REUSEADDR`, so a restarted server can bind
+///   a port its previous connections still hold in `TIME_WAIT`
+/// * `backlog` - How many connections may wait to be accepted before the
+///   system starts refusing them
+///
+/// # Example
+///
+/// ```rust
+/// use tnet::asynch::listener::BindOptions;
+///
+/// let options = BindOptions::default()
+///     .with_reuseaddr(true)
+///     .with_backlog(4096);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BindOptions {
+    pub reuseaddr: bool,
+    pub backlog: u32,
+}
+
+impl Default for BindOptions {
+    // Matches what `TcpListener::bind` does
+    fn default() -> Self {
+        Self {
+            reuseaddr: !cfg!(windows),
+            backlog: 1024,
+        }
+    }
+}
+
+impl BindOptions {
+    /// Sets whether `SO_REUSEADDR` is set on the listening socket.
+    ///
+    /// # Arguments
+    ///
+    /// * `reuseaddr` - Whether to allow binding an address in `TIME_WAIT`
+    ///
+    /// # Returns
+    ///
+    /// * The modified `BindOptions`
+    #[must_use]
+    pub const fn with_reuseaddr(mut self, reuseaddr: bool) -> Self {
+        self.reuseaddr = reuseaddr;
+        self
+    }
+
+    /// Sets the length of the queue of connections waiting to be accepted.
+    ///
+    /// # Arguments
+    ///
+    /// * `backlog` - Maximum number of pending connections
+    ///
+    /// # Returns
+    ///
+    /// * The modified `BindOptions`
+    #[must_use]
+    pub const fn with_backlog(mut self, backlog: u32) -> Self {
+        self.backlog = backlog;
+        self
+    }
+
+    // Binds and listens on the first address that accepts these options
+    async fn bind(self, ip_port: (&str, u16)) -> std::io::Result<TcpListener> {
+        let mut last_error = None;
+        for addr in tokio::net::lookup_host(ip_port).await? {
+            let socket = if addr.is_ipv4() {
+                TcpSocket::new_v4()
+            } else {
+                TcpSocket::new_v6()
+            };
+            let listener = socket.and_then(|socket| {
+                socket.set_reuseaddr(self.reuseaddr)?;
+                socket.bind(addr)?;
+                socket.listen(self.backlog)
+            });
+            match listener {
+                Ok(listener) => return Ok(listener),
+                Err(e) => last_error = Some(e),
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::NotFound, "no addresses found")
+        }))
+    }
+}
+
+/// The socket an `AsyncListener` accepts connections on.
+///
+/// # Variants
+///
+/// * `Tcp` - A TCP listener bound to an IP address and port
+/// * `Unix` - A Unix domain socket listener bound to a filesystem path
+/// * `WebSocket` - A TCP listener that upgrades every connection to a WebSocket
+/// * `Memory` - No socket at all; connections are handed over with
+///   `accept_in_memory`
+pub enum ListenerSocket {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener),
+    #[cfg(feature = "websocket")]
+    WebSocket(WsListener),
+    Memory,
+}
+
+impl ListenerSocket {
+    /// Returns the address the listener is bound to.
+    ///
+    /// # Returns
+    ///
+    /// * The bound socket address, or None for Unix domain sockets and
+    ///   in-memory listeners
+    #[must_use]
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        match self {
+            Self::Tcp(listener) => listener.local_addr().ok(),
+            #[cfg(unix)]
+            Self::Unix(_) => None,
+            #[cfg(feature = "websocket")]
+            Self::WebSocket(listener) => listener.local_addr(),
+            Self::Memory => None,
+        }
+    }
+
+    /// Accepts a new connection and wraps it in a `TSocket`.
+    ///
+    /// WebSocket connections are returned once their upgrade completes, which
+    /// may take up to `timeouts.recv`.
+    ///
+    /// # Returns
+    ///
+    /// * The new socket, along with the peer's IP address for TCP and
+    ///   WebSocket connections
+    async fn accept<S: session::Session>(
+        &self,
+        sessions: SessionStoreRef<S>,
+        nodelay: Option<bool>,
+        #[cfg_attr(not(feature = "websocket"), expect(unused_variables))] timeouts: TimeoutConfig,
+    ) -> std::io::Result<(TSocket<S>, Option<IpAddr>)> {
+        match self {
+            Self::Tcp(listener) => {
+                let (socket, addr) = listener.accept().await?;
+                if let Err(e) = nodelay.map_or(Ok(()), |nodelay| socket.set_nodelay(nodelay)) {
+                    warn!(peer = %addr, error = %e, "Failed to set TCP_NODELAY");
+                }
+                Ok((TSocket::accepted(socket, addr, sessions), Some(addr.ip())))
+            }
+            #[cfg(unix)]
+            Self::Unix(listener) => {
+                let (socket, _) = listener.accept().await?;
+                Ok((TSocket::new_uds(socket, sessions), None))
+            }
+            #[cfg(feature = "websocket")]
+            Self::WebSocket(listener) => {
+                let (stream, addr) = listener.accept(nodelay, timeouts.recv).await?;
+                Ok((TSocket::new_ws(stream, addr, sessions), Some(addr.ip())))
+            }
+            // Nothing ever arrives on its own; `accept_in_memory` feeds these
+            Self::Memory => std::future::pending().await,
+        }
+    }
+}
+
 /// The main server component for handling network connections and packet processing.
 ///
 /// `AsyncListener` provides a robust framework for:
@@ -230,35 +838,61 @@ impl<R: resources::Resource + 'static> ResourceRef<R> {
 ///     // Configure and run the server...
 /// }
 /// ```
-pub struct AsyncListener<P, S, R>
+pub struct AsyncListener<P, S, R, C = ()>
 where
     P: packet::Packet + 'static,
     S: session::Session + 'static,
     R: resources::Resource + 'static,
+    C: resources::Resource + 'static,
 {
-    pub listener: TcpListener,
-    ok_handler: AsyncListenerOkHandler<P, S, R>,
-    error_handler: AsyncListenerErrorHandler<S, R>,
+    pub listener: ListenerSocket,
+    ok_handler: AsyncListenerOkHandler<P, S, R, C>,
+    error_handler: AsyncListenerErrorHandler<S, R, C>,
+    disconnect_handler: Option<AsyncListenerOkHandler<P, S, R, C>>,
+    middleware: Vec<Arc<dyn Middleware<P, S, R, C>>>,
     authenticator: Authenticator,
     encryption: EncryptionConfig,
-    sessions: Arc<RwLock<Sessions<S>>>,
+    compression: CompressionConfig,
+    timeouts: TimeoutConfig,
+    idle_timeout: Option<Duration>,
+    max_packet_size: usize,
+    max_transfer_size: usize,
+    batching: Option<BatchConfig>,
+    nodelay: Option<bool>,
+    sessions: SessionStoreRef<S>,
+    cleanup: CleanupSchedule,
+    expiry_policy: SessionExpiryPolicy,
+    // Every authenticated connection, whether or not it sends keepalives
     pub keep_alive_pool: TSockets<S>,
     pub pools: Arc<RwLock<HashMap<String, TSockets<S>>>>,
     resources: ResourceRef<R>,
+    max_connections: Option<usize>,
+    max_conn_policy: MaxConnPolicy,
+    dispatch_mode: DispatchMode,
+    default_reply: Option<P>,
+    active_connections: Arc<AtomicUsize>,
+    connection_freed: Arc<Notify>,
+    rate_limiter: Option<TokenBuckets<IpAddr>>,
+    // Messages per second and burst allowed on each connection
+    message_rate: Option<(u32, u32)>,
+    report_rate_limited: bool,
+    metrics: Arc<dyn Metrics>,
     _packet: PhantomData<P>,
 }
 
-impl<P, S, R> AsyncListener<P, S, R>
+impl<P, S, R, C> AsyncListener<P, S, R, C>
 where
     P: packet::Packet + 'static,
     S: session::Session + 'static,
     R: resources::Resource + 'static,
+    C: resources::Resource + 'static,
 {
     /// Creates a new `AsyncListener` instance.
     ///
     /// # Arguments
     ///
-    /// * `ip_port` - Tuple of IP address and port to bind to
+    /// * `ip_port` - Tuple of IP address and port to bind to. Port 0 binds a
+    ///   port picked by the system, which `local_addr` reports
     /// * `clean_interval` - Interval in seconds for cleaning expired sessions
     /// * `ok_handler` - Handler for successful packet processing
     /// * `error_handler` - Handler for error conditions
@@ -269,353 +903,1081 @@ where
     ///
     /// # Panics
     ///
-    /// * Panics if unable to bind to the specified IP address and port
+    /// * Panics if unable to bind to the specified IP address and port; use
+    ///   `try_new` to handle that instead
     pub async fn new(
         ip_port: (&str, u16),
         clean_interval: u64,
-        ok_handler: AsyncListenerOkHandler<P, S, R>,
-        error_handler: AsyncListenerErrorHandler<S, R>,
+        ok_handler: AsyncListenerOkHandler<P, S, R, C>,
+        error_handler: AsyncListenerErrorHandler<S, R, C>,
     ) -> Self {
-        let sessions = Arc::new(RwLock::new(Sessions::new()));
-
-        let sessions_clone = sessions.clone();
-        tokio::spawn(async move {
-            let mut interval =
-                tokio::time::interval(tokio::time::Duration::from_secs(clean_interval));
-            loop {
-                interval.tick().await;
-                sessions_clone.write().await.clear_expired();
-            }
-        });
-
-        Self {
-            listener: TcpListener::bind(ip_port).await.unwrap(),
-            ok_handler,
-            error_handler,
-            authenticator: Authenticator::new(AuthType::None),
-            encryption: EncryptionConfig::default(),
-            sessions,
-            keep_alive_pool: TSockets::new(),
-            pools: Arc::new(RwLock::new(HashMap::new())),
-            resources: ResourceRef::new(R::new()),
-            _packet: PhantomData,
-        }
+        Self::try_new(ip_port, clean_interval, ok_handler, error_handler)
+            .await
+            .unwrap()
     }
 
-    /// Registers a handler for a specific packet type.
+    /// Creates a new `AsyncListener` instance, returning an error if the
+    /// address can't be bound.
     ///
     /// # Arguments
     ///
-    /// * `packet_type` - The packet type string that triggers this handler
-    /// * `handler` - The handler function to register
+    /// * `ip_port` - Tuple of IP address and port to bind to. Port 0 binds a
+    ///   port picked by the system, which `local_addr` reports
+    /// * `clean_interval` - Interval in seconds for cleaning expired sessions
+    /// * `ok_handler` - Handler for successful packet processing
+    /// * `error_handler` - Handler for error conditions
     ///
     /// # Returns
     ///
-    /// * `Self` - The configured listener instance
-    #[must_use]
-    pub fn with_handler(self, packet_type: &str, handler: AsyncListenerOkHandler<P, S, R>) -> Self {
-        crate::handler_registry::register_handler(packet_type, move |sources, packet| {
-            handler(sources, packet)
-        });
-
-        self
-    }
-
-    /// Configures encryption settings for the listener.
+    /// * `Result<Self, Error>` - The configured `AsyncListener` instance
     ///
-    /// # Arguments
+    /// # Errors
     ///
-    /// * `config` - Encryption configuration settings
+    /// Returns `Error::BindFailed` if the address is in use or can't be bound
     ///
-    /// # Returns
+    /// # Example
     ///
-    /// * The modified `AsyncListener` instance
-    #[must_use]
-    pub const fn with_encryption_config(mut self, config: EncryptionConfig) -> Self {
-        self.encryption = config;
-        self
-    }
-
-    /// Checks if encryption is enabled for this listener.
-    pub const fn is_encryption_enabled(&self) -> bool {
-        self.encryption.enabled
+    /// ```rust
+    /// match AsyncListener::try_new(("127.0.0.1", 8080), 30, ok_handler, error_handler).await {
+    ///     Ok(listener) => listener.run().await,
+    ///     Err(e) => eprintln!("Could not start the server: {e}"),
+    /// }
+    /// ```
+    pub async fn try_new(
+        ip_port: (&str, u16),
+        clean_interval: u64,
+        ok_handler: AsyncListenerOkHandler<P, S, R, C>,
+        error_handler: AsyncListenerErrorHandler<S, R, C>,
+    ) -> Result<Self, Error> {
+        Self::try_new_with_options(
+            ip_port,
+            BindOptions::default(),
+            clean_interval,
+            ok_handler,
+            error_handler,
+        )
+        .await
     }
 
-    /// Configures authentication settings for the listener.
+    /// Creates a new `AsyncListener` instance, binding with the given socket
+    /// options and returning an error if the address can't be bound.
     ///
     /// # Arguments
     ///
-    /// * `authenticator` - The authenticator instance to use for client authentication
+    /// * `ip_port` - Tuple of IP address and port to bind to
+    /// * `options` - Address reuse and backlog settings for the listening socket
+    /// * `clean_interval` - Interval in seconds for cleaning expired sessions
+    /// * `ok_handler` - Handler for successful packet processing
+    /// * `error_handler` - Handler for error conditions
     ///
     /// # Returns
     ///
-    /// * `Self` - The configured listener instance
+    /// * `Result<Self, Error>` - The configured `AsyncListener` instance
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::BindFailed` if the address is in use or can't be bound
     ///
     /// # Example
     ///
     /// ```rust
-    /// use tnet::{Authenticator, AuthType};
-    ///
-    /// async fn configure_auth(listener: AsyncListener<P, S, R>) {
-    ///     let auth = Authenticator::new(AuthType::UserPassword)
-    ///         .with_auth_fn(|user, pass| Box::pin(async move {
-    ///             // Authentication logic here
-    ///             Ok(())
-    ///         }));
-    ///     let listener = listener.with_authenticator(auth);
-    /// }
+    /// let listener = AsyncListener::try_new_with_options(
+    ///     ("0.0.0.0", 8080),
+    ///     BindOptions::default().with_reuseaddr(true).with_backlog(4096),
+    ///     30,
+    ///     ok_handler,
+    ///     error_handler,
+    /// )
+    /// .await?;
     /// ```
-    #[must_use]
-    pub fn with_authenticator(mut self, authenticator: Authenticator) -> Self {
-        self.authenticator = authenticator;
-        self
+    pub async fn try_new_with_options(
+        ip_port: (&str, u16),
+        options: BindOptions,
+        clean_interval: u64,
+        ok_handler: AsyncListenerOkHandler<P, S, R, C>,
+        error_handler: AsyncListenerErrorHandler<S, R, C>,
+    ) -> Result<Self, Error> {
+        let listener = Self::bind_tcp(ip_port, options).await?;
+        Ok(Self::from_listener(
+            ListenerSocket::Tcp(listener),
+            clean_interval,
+            ok_handler,
+            error_handler,
+        ))
     }
 
-    /// Creates a new connection pool with the specified name.
+    /// Creates a new `AsyncListener` bound to a Unix domain socket.
+    ///
+    /// Useful for communication between processes on the same host, such as a
+    /// daemon and its CLI. Handlers, sessions, authentication and encryption
+    /// work exactly as they do over TCP. Rate limiting does not apply, since
+    /// Unix socket peers have no IP address.
     ///
     /// # Arguments
     ///
-    /// * `pool_name` - Name for the new connection pool
+    /// * `path` - Filesystem path to bind the socket to
+    /// * `clean_interval` - Interval in seconds for cleaning expired sessions
+    /// * `ok_handler` - Handler for successful packet processing
+    /// * `error_handler` - Handler for error conditions
     ///
-    /// # Example
+    /// # Returns
     ///
-    /// ```rust
-    /// async fn setup_pool(listener: &AsyncListener<P, S, R>) {
-    ///     listener.with_pool("main_pool").await;
-    /// }
-    /// ```
-    pub async fn with_pool(self, pool_name: impl ToString) -> Self {
-        self.pools
-            .write()
-            .await
-            .insert(pool_name.to_string(), TSockets::new());
-        self
-    }
-
-    /// Creates multiple connection pools with the specified names.
+    /// * The configured `AsyncListener` instance
     ///
-    /// # Arguments
+    /// # Panics
     ///
-    /// * `pool_names` - Names for the new connection pools
+    /// * Panics if unable to bind to the path, for example if a file already exists
+    ///   there; use `try_new_uds` to handle that instead
     ///
     /// # Example
     ///
     /// ```rust
-    /// async fn setup_pools(listener: &AsyncListener<P, S, R>) {
-    ///     listener.with_pools(vec!["main_pool", "secondary_pool"]).await;
-    /// }
+    /// let listener = AsyncListener::new_uds(
+    ///     "/tmp/my-daemon.sock",
+    ///     30,
+    ///     ok_handler,
+    ///     error_handler
+    /// ).await;
     /// ```
-    pub async fn with_pools(self, pool_names: Vec<impl ToString>) -> Self {
-        for name in pool_names {
-            self.pools
-                .write()
-                .await
-                .insert(name.to_string(), TSockets::new());
-        }
-        self
+    #[cfg(unix)]
+    pub async fn new_uds(
+        path: impl AsRef<std::path::Path>,
+        clean_interval: u64,
+        ok_handler: AsyncListenerOkHandler<P, S, R, C>,
+        error_handler: AsyncListenerErrorHandler<S, R, C>,
+    ) -> Self {
+        Self::try_new_uds(path, clean_interval, ok_handler, error_handler)
+            .await
+            .unwrap()
     }
 
-    /// Configures shared resources for the listener.
+    /// Creates a new `AsyncListener` bound to a Unix domain socket, returning
+    /// an error if the path can't be bound.
     ///
     /// # Arguments
     ///
-    /// * `resource` - The resource instance to share across connections
+    /// * `path` - Filesystem path to bind the socket to
+    /// * `clean_interval` - Interval in seconds for cleaning expired sessions
+    /// * `ok_handler` - Handler for successful packet processing
+    /// * `error_handler` - Handler for error conditions
     ///
     /// # Returns
     ///
-    /// * `Self` - The configured listener instance
-    #[must_use]
-    pub fn with_resource(mut self, resource: R) -> Self {
-        self.resources = ResourceRef::new(resource);
-        self
+    /// * `Result<Self, Error>` - The configured `AsyncListener` instance
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::BindFailed` if the path can't be bound, for example if a
+    /// file already exists there
+    #[cfg(unix)]
+    pub async fn try_new_uds(
+        path: impl AsRef<std::path::Path>,
+        clean_interval: u64,
+        ok_handler: AsyncListenerOkHandler<P, S, R, C>,
+        error_handler: AsyncListenerErrorHandler<S, R, C>,
+    ) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let listener = UnixListener::bind(path)
+            .map_err(|e| Error::BindFailed(format!("{}: {e}", path.display())))?;
+        Ok(Self::from_listener(
+            ListenerSocket::Unix(listener),
+            clean_interval,
+            ok_handler,
+            error_handler,
+        ))
     }
 
-    /// Adds a socket to a specified connection pool.
+    /// Creates a new `AsyncListener` that accepts WebSocket connections.
+    ///
+    /// Lets browser clients connect: each connection goes through the HTTP
+    /// upgrade, then tnet frames travel inside binary messages. Handlers,
+    /// sessions, authentication, encryption and rate limiting work exactly as
+    /// they do over plain TCP.
     ///
     /// # Arguments
     ///
-    /// * `pool_name` - Name of the pool to add the socket to
-    /// * `socket` - The socket to add
+    /// * `ip_port` - Tuple of IP address and port to bind to
+    /// * `clean_interval` - Interval in seconds for cleaning expired sessions
+    /// * `ok_handler` - Handler for successful packet processing
+    /// * `error_handler` - Handler for error conditions
+    ///
+    /// # Returns
+    ///
+    /// * The configured `AsyncListener` instance
     ///
     /// # Panics
     ///
-    /// * Panics if the specified pool doesn't exist
-    pub async fn add_socket_to_pool(&mut self, pool_name: &str, socket: &TSocket<S>) {
-        self.pools
-            .write()
+    /// * Panics if unable to bind to the specified address; use `try_new_ws`
+    ///   to handle that instead
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let listener = AsyncListener::new_ws(
+    ///     ("0.0.0.0", 8080),
+    ///     30,
+    ///     ok_handler,
+    ///     error_handler
+    /// ).await;
+    /// ```
+    #[cfg(feature = "websocket")]
+    pub async fn new_ws(
+        ip_port: (&str, u16),
+        clean_interval: u64,
+        ok_handler: AsyncListenerOkHandler<P, S, R, C>,
+        error_handler: AsyncListenerErrorHandler<S, R, C>,
+    ) -> Self {
+        Self::try_new_ws(ip_port, clean_interval, ok_handler, error_handler)
             .await
-            .get_mut(pool_name)
-            .expect("Unknown Pool")
-            .add(socket.clone())
-            .await;
+            .unwrap()
     }
 
-    /// Gets a reference to the connection pools.
+    /// Creates a new `AsyncListener` that accepts WebSocket connections,
+    /// returning an error if the address can't be bound.
+    ///
+    /// # Arguments
+    ///
+    /// * `ip_port` - Tuple of IP address and port to bind to
+    /// * `clean_interval` - Interval in seconds for cleaning expired sessions
+    /// * `ok_handler` - Handler for successful packet processing
+    /// * `error_handler` - Handler for error conditions
     ///
     /// # Returns
     ///
-    /// * `PoolRef<S>` - Reference to the connection pools
-    pub fn get_pool_ref(&self) -> PoolRef<S> {
-        PoolRef(self.pools.clone())
+    /// * `Result<Self, Error>` - The configured `AsyncListener` instance
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::BindFailed` if the address is in use or can't be bound
+    #[cfg(feature = "websocket")]
+    pub async fn try_new_ws(
+        ip_port: (&str, u16),
+        clean_interval: u64,
+        ok_handler: AsyncListenerOkHandler<P, S, R, C>,
+        error_handler: AsyncListenerErrorHandler<S, R, C>,
+    ) -> Result<Self, Error> {
+        Self::try_new_ws_with_options(
+            ip_port,
+            BindOptions::default(),
+            clean_interval,
+            ok_handler,
+            error_handler,
+        )
+        .await
     }
 
-    /// Gets a reference to the shared resources.
+    /// Creates a new `AsyncListener` that accepts WebSocket connections,
+    /// binding with the given socket options and returning an error if the
+    /// address can't be bound.
+    ///
+    /// # Arguments
+    ///
+    /// * `ip_port` - Tuple of IP address and port to bind to
+    /// * `options` - Address reuse and backlog settings for the listening socket
+    /// * `clean_interval` - Interval in seconds for cleaning expired sessions
+    /// * `ok_handler` - Handler for successful packet processing
+    /// * `error_handler` - Handler for error conditions
     ///
     /// # Returns
     ///
-    /// * `ResourceRef<R>` - Reference to the shared resources
-    pub fn get_resources(&self) -> ResourceRef<R> {
-        self.resources.clone()
+    /// * `Result<Self, Error>` - The configured `AsyncListener` instance
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::BindFailed` if the address is in use or can't be bound
+    #[cfg(feature = "websocket")]
+    pub async fn try_new_ws_with_options(
+        ip_port: (&str, u16),
+        options: BindOptions,
+        clean_interval: u64,
+        ok_handler: AsyncListenerOkHandler<P, S, R, C>,
+        error_handler: AsyncListenerErrorHandler<S, R, C>,
+    ) -> Result<Self, Error> {
+        let listener = Self::bind_tcp(ip_port, options).await?;
+        Ok(Self::from_listener(
+            ListenerSocket::WebSocket(WsListener::new(listener)),
+            clean_interval,
+            ok_handler,
+            error_handler,
+        ))
     }
 
-    /// Handles the encryption handshake with a client.
+    /// Creates a new `AsyncListener` that doesn't bind any socket.
     ///
-    /// Performs key exchange and establishes encrypted communication.
+    /// Connections are handed to it with `accept_in_memory`, which makes it
+    /// possible to test handlers, authentication and sessions without touching
+    /// the network. `run` on such a listener only cleans up sessions.
     ///
     /// # Arguments
     ///
-    /// * `socket` - The client socket
+    /// * `clean_interval` - Interval in seconds for cleaning expired sessions
+    /// * `ok_handler` - Handler for successful packet processing
+    /// * `error_handler` - Handler for error conditions
     ///
     /// # Returns
     ///
-    /// * `std::io::Result<Encryptor>` - The configured encryptor or an error
-    async fn handle_encryption_handshake(&self, socket: &TSocket<S>) -> std::io::Result<Encryptor> {
+    /// * The configured `AsyncListener` instance
+    #[must_use]
+    pub fn new_in_memory(
+        clean_interval: u64,
+        ok_handler: AsyncListenerOkHandler<P, S, R, C>,
+        error_handler: AsyncListenerErrorHandler<S, R, C>,
+    ) -> Self {
+        Self::from_listener(
+            ListenerSocket::Memory,
+            clean_interval,
+            ok_handler,
+            error_handler,
+        )
+    }
 
-        let mut read_part = socket.read_part.lock().await;
-        
-        // Read length prefix
-        let mut length_buf = [0u8; 4];
-        read_part.read_exact(&mut length_buf).await?;
-        let length = u32::from_be_bytes(length_buf) as usize;
-
-        if length != 32 {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                "Invalid client public key length",
-            ));
-        }
-
-        // Read client's public key
-        let mut client_public_key = [0u8; 32];
-        read_part.read_exact(&mut client_public_key).await?;
-        drop(read_part);
+    /// Binds the TCP listener shared by the TCP and WebSocket constructors.
+    async fn bind_tcp(ip_port: (&str, u16), options: BindOptions) -> Result<TcpListener, Error> {
+        let (ip, port) = ip_port;
+        options
+            .bind(ip_port)
+            .await
+            .map_err(|e| Error::BindFailed(format!("{ip}:{port}: {e}")))
+    }
 
-        let key_exchange = KeyExchange::new();
-        let server_public = key_exchange.get_public_key();
+    fn from_listener(
+        listener: ListenerSocket,
+        clean_interval: u64,
+        ok_handler: AsyncListenerOkHandler<P, S, R, C>,
+        error_handler: AsyncListenerErrorHandler<S, R, C>,
+    ) -> Self {
+        Self {
+            listener,
+            ok_handler,
+            error_handler,
+            disconnect_handler: None,
+            middleware: Vec::new(),
+            authenticator: Authenticator::new(AuthType::None),
+            encryption: EncryptionConfig::default(),
+            compression: CompressionConfig::default(),
+            timeouts: TimeoutConfig::default(),
+            idle_timeout: None,
+            max_packet_size: framing::DEFAULT_MAX_FRAME_LEN,
+            max_transfer_size: chunking::DEFAULT_MAX_TRANSFER_LEN,
+            batching: None,
+            nodelay: None,
+            sessions: Arc::new(RwLock::new(Sessions::new())),
+            cleanup: CleanupSchedule::new(clean_interval),
+            expiry_policy: SessionExpiryPolicy::default(),
+            keep_alive_pool: TSockets::new(),
+            pools: Arc::new(RwLock::new(HashMap::new())),
+            resources: ResourceRef::new(R::new()),
+            max_connections: None,
+            max_conn_policy: MaxConnPolicy::default(),
+            dispatch_mode: DispatchMode::default(),
+            default_reply: None,
+            active_connections: Arc::new(AtomicUsize::new(0)),
+            connection_freed: Arc::new(Notify::new()),
+            rate_limiter: None,
+            message_rate: None,
+            report_rate_limited: false,
+            metrics: Arc::new(NoopMetrics),
+            _packet: PhantomData,
+        }
+    }
 
-        // Send length-prefixed public key
-        let mut response = Vec::new();
-        response.extend_from_slice(&(server_public.len() as u32).to_be_bytes());
-        response.extend_from_slice(&server_public);
-        
-        let mut write_part = socket.write_part.lock().await;
-        write_part.write_all(&response).await?;
-        write_part.flush().await?;
-        drop(write_part);
+    /// Registers a handler for a specific packet type.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet_type` - The packet type string that triggers this handler
+    /// * `handler` - The handler function to register
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The configured listener instance
+    #[must_use]
+    pub fn with_handler(
+        self,
+        packet_type: &str,
+        handler: AsyncListenerOkHandler<P, S, R, C>,
+    ) -> Self {
+        handler_registry::insert_flow_handler(packet_type, 0, move |sources, packet| {
+            let handler = handler.clone();
+            Box::pin(async move {
+                handler(sources, packet).await;
+                handler_registry::HandlerFlow::Continue
+            })
+        });
 
-        let shared_secret = key_exchange.compute_shared_secret(&client_public_key);
-        Ok(Encryptor::new(&shared_secret).expect("Failed to create encryptor"))
+        self
     }
 
-    /// Handles the authentication process for a client connection.
+    /// Registers a handler that runs when a client disconnects cleanly.
     ///
-    /// Processes various authentication methods including:
-    /// - Session ID authentication
-    /// - Username/password authentication
-    /// - No authentication (if configured)
+    /// The handler receives the client's `Packet::disconnect()` notice before the
+    /// connection is dropped, so it can remove the socket from pools or persist state.
     ///
     /// # Arguments
     ///
-    /// * `tsocket` - The client socket
+    /// * `handler` - The handler function to run on a clean disconnect
     ///
     /// # Returns
     ///
-    /// * `Result<Option<Encryptor>, Error>` - The encryption configuration or an error
-    async fn handle_authentication(
-        &mut self,
-        tsocket: &mut TSocket<S>,
-    ) -> Result<Option<Encryptor>, Error> {
-        self.sessions.write().await.clear_expired();
+    /// * `Self` - The configured listener instance
+    #[must_use]
+    pub fn with_disconnect_handler(mut self, handler: AsyncListenerOkHandler<P, S, R, C>) -> Self {
+        self.disconnect_handler = Some(handler);
+        self
+    }
 
-        // Step 1: Handle Encryption Setup
-        let encryptor = if self.encryption.enabled {
-            let enc = self
-                .handle_encryption_handshake(tsocket)
-                .await
-                .map_err(|e| Error::EncryptionError(e.to_string()))?;
-            tsocket.encryptor = Some(enc.clone()); // Set the encryptor in TSocket
-            Some(enc)
-        } else {
-            None
-        };
+    /// Adds a middleware to the chain that runs before the handlers.
+    ///
+    /// Middleware runs in the order it was added. A rejected packet is passed to
+    /// the error handler instead of the handlers, and the rest of the chain is skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `middleware` - The middleware to append to the chain
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The configured listener instance
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let listener = listener.with_middleware(Arc::new(RequireSession));
+    /// ```
+    #[must_use]
+    pub fn with_middleware(mut self, middleware: Arc<dyn Middleware<P, S, R, C>>) -> Self {
+        self.middleware.push(middleware);
+        self
+    }
 
-        // Step 2: Handle No Authentication Case
-        if matches!(self.authenticator.auth_type, AuthType::None) {
-            let session_id = uuid::Uuid::new_v4().to_string();
-            self.sessions
-                .write()
-                .await
-                .new_session(S::empty(session_id.clone()));
-            tsocket.session_id = Some(session_id.clone());
+    /// Configures encryption settings for the listener.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - Encryption configuration settings
+    ///
+    /// # Returns
+    ///
+    /// * The modified `AsyncListener` instance
+    #[must_use]
+    pub fn with_encryption_config(mut self, config: EncryptionConfig) -> Self {
+        self.encryption = config;
+        self
+    }
 
-            let mut ok = P::ok();
-            ok.session_id(Some(session_id));
-            tsocket.send(ok).await?;
+    /// Configures payload compression for all accepted connections.
+    ///
+    /// Clients must have compression enabled as well.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - Compression configuration settings
+    ///
+    /// # Returns
+    ///
+    /// * The modified `AsyncListener` instance
+    #[must_use]
+    pub const fn with_compression(mut self, config: CompressionConfig) -> Self {
+        self.compression = config;
+        self
+    }
 
-            return Ok(encryptor);
-        }
+    /// Configures the timeouts applied to every accepted connection.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeouts` - Timeout configuration settings
+    ///
+    /// # Returns
+    ///
+    /// * The modified `AsyncListener` instance
+    #[must_use]
+    pub const fn with_timeouts(mut self, timeouts: TimeoutConfig) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
 
-        // Step 3: Handle Authentication Cases
-        let packet = tsocket.recv::<P>().await?;
-        let body = packet.body();
+    /// Closes connections that go quiet for too long.
+    ///
+    /// Every packet from the client, keep-alives included, restarts the timer.
+    /// When it runs out, the client is sent `Packet::disconnect()`, the disconnect
+    /// handler runs with that packet, and the connection is closed.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - How long a connection may go without receiving a packet
+    ///
+    /// # Returns
+    ///
+    /// * The modified `AsyncListener` instance
+    #[must_use]
+    pub const fn with_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = Some(timeout);
+        self
+    }
 
-        // Case 3a: Session ID Authentication
-        if let Some(id) = body.session_id {
-            let session_result = {
-                let sessions = self.sessions.read().await;
-                sessions.get_session(&id).cloned()
-            };
+    /// Limits the size of the packets accepted from clients.
+    ///
+    /// A client announcing a larger packet is refused before any of the payload
+    /// is buffered: the error handler receives `Error::PacketTooLarge` and the
+    /// connection is closed. Defaults to 16 MB.
+    ///
+    /// # Arguments
+    ///
+    /// * `max` - Maximum packet size in bytes, as sent on the wire
+    ///
+    /// # Returns
+    ///
+    /// * The modified `AsyncListener` instance
+    #[must_use]
+    pub const fn with_max_packet_size(mut self, max: usize) -> Self {
+        self.max_packet_size = max;
+        self
+    }
 
-            if let Some(session) = session_result {
-                if session.is_expired() {
-                    return Err(Error::ExpriedSessionId(id));
-                }
-                tsocket.session_id = Some(id);
-                tsocket.send(P::ok()).await?;
-                return Ok(encryptor);
-            }
-            return Err(Error::InvalidSessionId(id));
+    /// Limits how much of a chunked transfer a connection may buffer.
+    ///
+    /// Chunks sent with `AsyncClient::send_chunked` are held until the last one
+    /// arrives. A transfer that would take a connection past this limit is refused:
+    /// the error handler receives `Error::PacketTooLarge` and the connection is
+    /// closed. Defaults to 64 MB.
+    ///
+    /// # Arguments
+    ///
+    /// * `max` - Maximum number of attachment bytes buffered per connection
+    ///
+    /// # Returns
+    ///
+    /// * The modified `AsyncListener` instance
+    #[must_use]
+    pub const fn with_max_transfer_size(mut self, max: usize) -> Self {
+        self.max_transfer_size = max;
+        self
+    }
+
+    /// Coalesces the packets sent to each connection into fewer flushes.
+    ///
+    /// Off by default, so every packet is flushed as soon as it is sent. With
+    /// batching on, replies wait up to `BatchConfig::max_delay` for other
+    /// packets to go out with them.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - When to flush the collected packets
+    ///
+    /// # Returns
+    ///
+    /// * The modified `AsyncListener` instance
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let listener = listener.with_batching(BatchConfig {
+    ///     max_batch: 128,
+    ///     max_delay: Duration::from_millis(5),
+    /// });
+    /// ```
+    #[must_use]
+    pub const fn with_batching(mut self, config: BatchConfig) -> Self {
+        self.batching = Some(config);
+        self
+    }
+
+    /// Enables or disables `TCP_NODELAY` on every accepted TCP connection.
+    ///
+    /// With it set, small packets are sent right away instead of being held
+    /// back by Nagle's algorithm, which favours latency over throughput. Left
+    /// unset, accepted connections keep the operating system's default.
+    ///
+    /// # Arguments
+    ///
+    /// * `nodelay` - Whether to disable Nagle's algorithm
+    ///
+    /// # Returns
+    ///
+    /// * The modified `AsyncListener` instance
+    #[must_use]
+    pub const fn with_nodelay(mut self, nodelay: bool) -> Self {
+        self.nodelay = Some(nodelay);
+        self
+    }
+
+    /// Replaces the default in-memory session store.
+    ///
+    /// Sessions issued by the listener are saved to the store and looked up from it
+    /// when a client authenticates with a session ID, so a persistent store such as
+    /// `JsonFileSessionStore` lets clients resume their sessions after a restart.
+    ///
+    /// # Arguments
+    ///
+    /// * `store` - The store to keep sessions in
+    ///
+    /// # Returns
+    ///
+    /// * The modified `AsyncListener` instance
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let store = JsonFileSessionStore::open("sessions.json").await?;
+    /// let listener = AsyncListener::new(("127.0.0.1", 8080), 30, ok_handler, error_handler)
+    ///     .await
+    ///     .with_session_store(store);
+    /// ```
+    #[must_use]
+    pub fn with_session_store(mut self, store: impl SessionStore<S> + 'static) -> Self {
+        self.sessions = Arc::new(store);
+        self
+    }
+
+    /// Sets how session lifespans are measured.
+    ///
+    /// With `SessionExpiryPolicy::Sliding`, every packet received on a session
+    /// restarts its lifespan, so clients that stay active are never logged out.
+    /// The default, `SessionExpiryPolicy::Fixed`, expires sessions a fixed time
+    /// after they were created.
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - The expiry policy to apply to sessions
+    ///
+    /// # Returns
+    ///
+    /// * The modified `AsyncListener` instance
+    #[must_use]
+    pub const fn with_session_expiry_policy(mut self, policy: SessionExpiryPolicy) -> Self {
+        self.expiry_policy = policy;
+        self
+    }
+
+    /// Returns the address the listener is bound to.
+    ///
+    /// # Returns
+    ///
+    /// * The bound socket address, or None for Unix domain sockets
+    #[must_use]
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Returns a handle to the expired session cleanup, for adjusting it while
+    /// the listener runs.
+    ///
+    /// # Returns
+    ///
+    /// * A `CleanupSchedule` controlling this listener's cleanup
+    pub fn cleanup_schedule(&self) -> CleanupSchedule {
+        self.cleanup.clone()
+    }
+
+    /// Sets how many seconds pass between expired session cleanups.
+    ///
+    /// # Arguments
+    ///
+    /// * `secs` - The new interval in seconds, at least one
+    pub fn set_cleanup_interval(&self, secs: u64) {
+        self.cleanup.set_interval(secs);
+    }
+
+    /// Stops the periodic expired session cleanup until `resume_cleanup` is called.
+    pub fn pause_cleanup(&self) {
+        self.cleanup.pause();
+    }
+
+    /// Restarts the periodic expired session cleanup after `pause_cleanup`.
+    pub fn resume_cleanup(&self) {
+        self.cleanup.resume();
+    }
+
+    /// Checks if encryption is enabled for this listener.
+    pub const fn is_encryption_enabled(&self) -> bool {
+        self.encryption.enabled
+    }
+
+    /// Configures authentication settings for the listener.
+    ///
+    /// # Arguments
+    ///
+    /// * `authenticator` - The authenticator instance to use for client authentication
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The configured listener instance
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tnet::{Authenticator, AuthType};
+    ///
+    /// async fn configure_auth(listener: AsyncListener<P, S, R>) {
+    ///     let auth = Authenticator::new(AuthType::UserPassword)
+    ///         .with_auth_fn(|user, pass| Box::pin(async move {
+    ///             // Authentication logic here
+    ///             Ok(())
+    ///         }));
+    ///     let listener = listener.with_authenticator(auth);
+    /// }
+    /// ```
+    #[must_use]
+    pub fn with_authenticator(mut self, authenticator: Authenticator) -> Self {
+        self.authenticator = authenticator;
+        self
+    }
+
+    /// Caps the number of concurrently connected clients.
+    ///
+    /// What happens to connections beyond the limit is controlled by
+    /// [`with_max_conn_policy`](Self::with_max_conn_policy), which defaults to
+    /// [`MaxConnPolicy::Reject`].
+    ///
+    /// # Arguments
+    ///
+    /// * `max` - Maximum number of live connections
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The configured listener instance
+    #[must_use]
+    pub const fn with_max_connections(mut self, max: usize) -> Self {
+        self.max_connections = Some(max);
+        self
+    }
+
+    /// Sets the policy applied once the connection limit has been reached.
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - Either reject new connections or wait for a free slot
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The configured listener instance
+    #[must_use]
+    pub const fn with_max_conn_policy(mut self, policy: MaxConnPolicy) -> Self {
+        self.max_conn_policy = policy;
+        self
+    }
+
+    /// Sets how handlers are run for received packets.
+    ///
+    /// See `DispatchMode` for the ordering each mode guarantees.
+    ///
+    /// # Arguments
+    ///
+    /// * `mode` - Sequential or parallel dispatch
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The configured listener instance
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let listener = listener.with_dispatch_mode(DispatchMode::Parallel { max_in_flight: 8 });
+    /// ```
+    #[must_use]
+    pub const fn with_dispatch_mode(mut self, mode: DispatchMode) -> Self {
+        self.dispatch_mode = mode;
+        self
+    }
+
+    /// Sends a default reply when the handlers for a packet finish without sending one.
+    ///
+    /// Without it a handler that forgets to answer leaves the client waiting in
+    /// `send_recv` until its receive timeout. The reply is stamped with the session
+    /// id and the request id of the packet like `HandlerSources::reply`. Only packets
+    /// sent with `TSocket::send` count as replies, so broadcasts don't.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - The reply to send, such as `P::ok()`
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The configured listener instance
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let listener = listener.with_default_reply(MyPacket::ok());
+    /// ```
+    #[must_use]
+    pub fn with_default_reply(mut self, packet: P) -> Self {
+        self.default_reply = Some(packet);
+        self
+    }
+
+    /// Returns the number of connections currently being served.
+    pub fn active_connections(&self) -> usize {
+        self.active_connections.load(Ordering::SeqCst)
+    }
+
+    /// Enables per-IP connection rate limiting.
+    ///
+    /// Connections from a peer that has exhausted its token bucket are
+    /// dropped right after being accepted, before any handshake or
+    /// authentication takes place.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - Sustained rate and burst size allowed per peer IP
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The configured listener instance
+    #[must_use]
+    pub fn with_rate_limit(mut self, config: RateLimitConfig) -> Self {
+        self.rate_limiter = Some(TokenBuckets::new(config));
+        self
+    }
+
+    /// Limits how many packets a single connection may send.
+    ///
+    /// Each connection gets its own token bucket holding up to `burst` packets
+    /// which refills at `msgs_per_sec` packets per second. Packets arriving
+    /// while the bucket is empty are dropped before they reach middleware or
+    /// handlers. Disconnect notices are never dropped.
+    ///
+    /// # Arguments
+    ///
+    /// * `msgs_per_sec` - Sustained number of packets allowed per second
+    /// * `burst` - Maximum number of packets a connection may send in a quick burst
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The configured listener instance
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let listener = listener
+    ///     .with_per_connection_rate(50, 100)
+    ///     .with_rate_limit_errors(true);
+    /// ```
+    #[must_use]
+    pub const fn with_per_connection_rate(mut self, msgs_per_sec: u32, burst: u32) -> Self {
+        self.message_rate = Some((msgs_per_sec, burst));
+        self
+    }
+
+    /// Passes `Error::RateLimited` to the error handler for every packet
+    /// dropped by `with_per_connection_rate`, so it can warn the client.
+    /// Dropped packets are discarded silently by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - Whether dropped packets are reported to the error handler
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The configured listener instance
+    #[must_use]
+    pub const fn with_rate_limit_errors(mut self, enabled: bool) -> Self {
+        self.report_rate_limited = enabled;
+        self
+    }
+
+    /// Reports the listener's activity to a metrics collector.
+    ///
+    /// Every accepted socket counts the packets and bytes it sends and
+    /// receives, and the listener counts opened and closed connections and
+    /// authentication results.
+    ///
+    /// # Arguments
+    ///
+    /// * `metrics` - The collector to report to
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The configured listener instance
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let metrics = Arc::new(AtomicMetrics::new());
+    /// let listener = listener.with_metrics(metrics.clone());
+    /// ```
+    #[must_use]
+    pub fn with_metrics(mut self, metrics: Arc<dyn Metrics>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Creates a new connection pool with the specified name.
+    ///
+    /// # Arguments
+    ///
+    /// * `pool_name` - Name for the new connection pool
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// async fn setup_pool(listener: &AsyncListener<P, S, R>) {
+    ///     listener.with_pool("main_pool").await;
+    /// }
+    /// ```
+    pub async fn with_pool(self, pool_name: impl ToString) -> Self {
+        self.pools
+            .write()
+            .await
+            .insert(pool_name.to_string(), TSockets::new());
+        self
+    }
+
+    /// Creates multiple connection pools with the specified names.
+    ///
+    /// # Arguments
+    ///
+    /// * `pool_names` - Names for the new connection pools
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// async fn setup_pools(listener: &AsyncListener<P, S, R>) {
+    ///     listener.with_pools(vec!["main_pool", "secondary_pool"]).await;
+    /// }
+    /// ```
+    pub async fn with_pools(self, pool_names: Vec<impl ToString>) -> Self {
+        for name in pool_names {
+            self.pools
+                .write()
+                .await
+                .insert(name.to_string(), TSockets::new());
         }
+        self
+    }
 
-        // Case 3b: Username/Password Authentication
-        if let (Some(username), Some(password)) = (body.username, body.password) {
-            match self.authenticator.authenticate(username, password).await {
-                Ok(_) => {
-                    // Create new session after successful authentication
-                    let session_id = uuid::Uuid::new_v4().to_string();
-                    self.sessions
-                        .write()
-                        .await
-                        .new_session(S::empty(session_id.clone()));
-                    tsocket.session_id = Some(session_id.clone());
+    /// Configures shared resources for the listener.
+    ///
+    /// # Arguments
+    ///
+    /// * `resource` - The resource instance to share across connections
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The configured listener instance
+    #[must_use]
+    pub fn with_resource(mut self, resource: R) -> Self {
+        self.resources = ResourceRef::new(resource);
+        self
+    }
 
-                    // Send OK response with new session ID
-                    let mut ok = P::ok();
-                    ok.session_id(Some(session_id));
-                    tsocket.send(ok).await?;
+    /// Configures shared resources created by `Resource::new_async`.
+    ///
+    /// Use this when the resource has to set things up before the listener
+    /// accepts connections, such as connecting to a database.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The configured listener instance
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let listener = AsyncListener::new(("127.0.0.1", 8080), 30, ok_handler, error_handler)
+    ///     .await
+    ///     .with_resource_async()
+    ///     .await;
+    /// ```
+    #[must_use]
+    pub async fn with_resource_async(mut self) -> Self {
+        self.resources = ResourceRef::new(R::new_async().await);
+        self
+    }
 
-                    Ok(encryptor)
-                }
-                Err(e) => {
-                    let err = P::error(e.clone());
-                    tsocket.send(err).await?;
+    /// Adds a socket to a specified connection pool.
+    ///
+    /// # Arguments
+    ///
+    /// * `pool_name` - Name of the pool to add the socket to
+    /// * `socket` - The socket to add
+    ///
+    /// # Panics
+    ///
+    /// * Panics if the specified pool doesn't exist
+    pub async fn add_socket_to_pool(&mut self, pool_name: &str, socket: &TSocket<S>) {
+        self.pools
+            .write()
+            .await
+            .get_mut(pool_name)
+            .expect("Unknown Pool")
+            .add(socket.clone())
+            .await;
+    }
+
+    /// Gets a reference to the connection pools.
+    ///
+    /// # Returns
+    ///
+    /// * `PoolRef<S>` - Reference to the connection pools
+    pub fn get_pool_ref(&self) -> PoolRef<S> {
+        PoolRef(self.pools.clone())
+    }
+
+    /// Gets a reference to the shared resources.
+    ///
+    /// # Returns
+    ///
+    /// * `ResourceRef<R>` - Reference to the shared resources
+    pub fn get_resources(&self) -> ResourceRef<R> {
+        self.resources.clone()
+    }
+
+    /// Returns the number of sessions in the listener's session store.
+    ///
+    /// Expired sessions count until they are cleared, which happens on the
+    /// cleanup schedule, on each new connection, or through `clear_expired_sessions`.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<usize, Error>` - The number of stored sessions
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::SessionStore` if the session store cannot be read
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let count = listener.session_count().await?;
+    /// println!("{count} sessions");
+    /// ```
+    pub async fn session_count(&self) -> Result<usize, Error> {
+        self.sessions.count().await
+    }
+
+    /// Lists the sessions in the listener's session store.
+    ///
+    /// Expiry times follow the listener's `SessionExpiryPolicy`. As with
+    /// `session_count`, expired sessions are listed until they are cleared.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<SessionSummary>, Error>` - The id, creation and expiry time of each session
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::SessionStore` if the session store cannot be read
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// for session in listener.active_sessions().await? {
+    ///     println!("{} expires at {}", session.id, session.expires_at);
+    /// }
+    /// ```
+    pub async fn active_sessions(&self) -> Result<Vec<SessionSummary>, Error> {
+        self.sessions.summaries(self.expiry_policy).await
+    }
 
-                    Err(e)
-                }
-            }
-        } else {
-            Err(Error::InvalidCredentials)
-        }
+    /// Removes every expired session from the listener's session store now,
+    /// instead of waiting for the next cleanup.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::SessionStore` if the session store cannot be written
+    pub async fn clear_expired_sessions(&self) -> Result<(), Error> {
+        self.sessions.clear_expired(self.expiry_policy).await
     }
 
     /// Broadcasts a packet to all connected clients.
@@ -651,6 +2013,39 @@ where
         Ok(())
     }
 
+    /// Runs the listener on a background task.
+    ///
+    /// Binding to port 0 and reading `RunningServer::local_addr` gives a free
+    /// port without picking one up front.
+    ///
+    /// # Returns
+    ///
+    /// * A `RunningServer` that reports the bound address and stops the listener
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let server = listener.spawn();
+    /// println!("Listening on {:?}", server.local_addr());
+    /// server.stop().await;
+    /// ```
+    pub fn spawn(mut self) -> RunningServer {
+        let local_addr = self.local_addr();
+        let (shutdown, stop) = oneshot::channel();
+        let task = tokio::spawn(async move {
+            tokio::select! {
+                () = self.run() => {}
+                _ = stop => info!("Server stopped"),
+            }
+        });
+
+        RunningServer {
+            local_addr,
+            shutdown,
+            task,
+        }
+    }
+
     /// Starts the listener and begins accepting connections.
     ///
     /// This is the main event loop that:
@@ -672,100 +2067,875 @@ where
     ///
     /// * Panics if accepting a connection fails unexpectedly
     pub async fn run(&mut self) {
-        println!("Server Started!");
+        info!("Server started");
+
+        tokio::spawn(CleanupSchedule::drive(
+            self.cleanup.0.subscribe(),
+            self.sessions.clone(),
+            self.expiry_policy,
+        ));
+
         loop {
-            let opt = match self.listener.accept().await {
+            let wait_limit = self
+                .max_connections
+                .filter(|_| self.max_conn_policy == MaxConnPolicy::Wait);
+            if let Some(max) = wait_limit {
+                while self.active_connections.load(Ordering::SeqCst) >= max {
+                    self.connection_freed.notified().await;
+                }
+            }
+
+            let (tsocket, ip) = match self
+                .listener
+                .accept(self.sessions.clone(), self.nodelay, self.timeouts)
+                .await
+            {
                 Ok(opt) => opt,
+                // The peer went away before it was accepted, which only affects that peer
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        std::io::ErrorKind::ConnectionAborted | std::io::ErrorKind::ConnectionReset
+                    ) =>
+                {
+                    debug!(error = %e, "Peer disconnected before it was accepted");
+                    continue;
+                }
                 Err(e) => {
-                    eprintln!("Failed to accept connection: {e}");
+                    warn!(error = %e, "Failed to accept connection");
                     break;
                 }
             };
 
-            let (socket, addr) = opt;
+            let addr = tsocket.addr.clone();
+
+            if ip.is_some_and(|ip| {
+                self.rate_limiter
+                    .as_mut()
+                    .is_some_and(|limiter| !limiter.try_acquire(ip))
+            }) {
+                info!(peer = %addr, "Rate limit exceeded, dropping connection");
+                continue;
+            }
+
+            self.serve_connection(tsocket).await;
+        }
+    }
+
+    /// Serves a connection arriving over an in-memory stream instead of a socket.
+    ///
+    /// The connection is authenticated and handled on its own task exactly like
+    /// one accepted by `run`, which makes it possible to test handlers without
+    /// binding a port. Rate limiting by IP doesn't apply. This returns as soon
+    /// as the task is spawned.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream` - The listener's end of a `tokio::io::duplex` pipe
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let mut listener = AsyncListener::new_in_memory(30, ok_handler, error_handler);
+    /// let (client_end, server_end) = tokio::io::duplex(64 * 1024);
+    /// let mut client = AsyncClient::<MyPacket>::from_duplex(client_end);
+    /// let (_, greeting) = tokio::join!(listener.accept_in_memory(server_end), client.recv());
+    /// let response = client.send_recv(MyPacket::ok()).await?;
+    /// ```
+    pub async fn accept_in_memory(&mut self, stream: DuplexStream) {
+        let tsocket = TSocket::new_in_memory(stream, self.sessions.clone());
+        self.serve_connection(tsocket).await;
+    }
 
-            println!("Accepted connection from {addr}");
+    /// Serves an accepted connection on a new task, authenticating it there first.
+    ///
+    /// The connection holds a slot towards `max_connections` from the moment
+    /// it is accepted, including while it authenticates.
+    ///
+    /// # Arguments
+    ///
+    /// * `tsocket` - The socket of the accepted connection
+    async fn serve_connection(&self, tsocket: TSocket<S>) {
+        let addr = tsocket.addr.clone();
+        info!(peer = %addr, "Accepted connection");
+
+        let mut tsocket = tsocket
+            .with_compression(self.compression)
+            .with_timeouts(self.timeouts)
+            .with_max_packet_size(self.max_packet_size)
+            .with_metrics(self.metrics.clone());
+        if let Some(batching) = self.batching {
+            tsocket = tsocket.with_batching(batching);
+        }
 
-            let mut tsocket = TSocket::new(socket, self.sessions.clone());
-            let ok_handler = self.ok_handler.clone();
-            let error_handler = self.error_handler.clone();
-            let mut keep_alive_pool = self.keep_alive_pool.clone();
-            let pools = self.pools.clone();
-            let resources = self.resources.clone();
+        let active = self.active_connections.load(Ordering::SeqCst);
+        if let Some(max) = self.max_connections.filter(|&max| active >= max) {
+            info!(peer = %addr, max, "Rejecting connection, limit reached");
+            if let Err(e) = tsocket.send(P::error(Error::TooManyConnections)).await {
+                warn!(peer = %addr, error = %e, "Failed to send rejection");
+            }
+            return;
+        }
 
-            let auth_resp = self.handle_authentication(&mut tsocket).await;
+        let ok_handler = self.ok_handler.clone();
+        let dispatch_mode = self.dispatch_mode;
+        let default_reply = self.default_reply.clone();
+        let error_handler = self.error_handler.clone();
+        let disconnect_handler = self.disconnect_handler.clone();
+        let middleware = self.middleware.clone();
+        let keep_alive_pool = self.keep_alive_pool.clone();
+        let pools = self.pools.clone();
+        let resources = self.resources.clone();
+        let connection_resources = Arc::new(C::new());
+        let sessions = self.sessions.clone();
+        let expiry_policy = self.expiry_policy;
+        let idle_timeout = self.idle_timeout;
+        let mut transfers = ChunkedTransfers::new(self.max_transfer_size);
+        let mut message_rate = self
+            .message_rate
+            .map(|(per_sec, burst)| TokenBucket::new(per_sec, burst));
+        let report_rate_limited = self.report_rate_limited;
+        // Connections without authentication may pick up an earlier session
+        let mut may_resume = matches!(self.authenticator.auth_type, AuthType::None);
+
+        let mut admission = Admission {
+            authenticator: self.authenticator.clone(),
+            encryption: self.encryption.clone(),
+            sessions: self.sessions.clone(),
+            expiry_policy: self.expiry_policy,
+            timeouts: self.timeouts,
+            max_packet_size: self.max_packet_size,
+            metrics: self.metrics.clone(),
+        };
+        let active_connections = self.active_connections.clone();
+        let connection_freed = self.connection_freed.clone();
+        let metrics = self.metrics.clone();
+        // Connections that are still authenticating hold a slot too
+        active_connections.fetch_add(1, Ordering::SeqCst);
 
-            if let Err(e) = auth_resp {
+        tokio::spawn(async move {
+            // Release the connection slot however this task ends
+            let _slot = scopeguard::guard((), move |()| {
+                active_connections.fetch_sub(1, Ordering::SeqCst);
+                connection_freed.notify_one();
+            });
+
+            // Authenticating here keeps a slow client from holding up the accept loop
+            if let Err(e) = admission.authenticate::<P>(&mut tsocket).await {
                 let sources = HandlerSources {
                     socket: tsocket,
                     pools: PoolRef(pools.clone()),
                     resources: resources.clone(),
+                    all_connections: keep_alive_pool.clone(),
+                    connection_resources: connection_resources.clone(),
                 };
                 error_handler(sources, e).await;
-            } else {
-                tokio::spawn(async move {
-                    loop {
-                        let resp = tsocket.recv::<P>().await;
+                return;
+            }
+
+            metrics.increment(Counter::ConnectionsOpened);
+            let _opened = scopeguard::guard((), move |()| {
+                metrics.increment(Counter::ConnectionsClosed);
+            });
+            keep_alive_pool.insert_connection(tsocket.clone()).await;
+
+            let mut idle_deadline = idle_timeout.map(|timeout| Instant::now() + timeout);
+            let in_flight = match dispatch_mode {
+                DispatchMode::Sequential => None,
+                DispatchMode::Parallel { max_in_flight } => {
+                    Some(Arc::new(Semaphore::new(max_in_flight.max(1))))
+                }
+            };
 
-                        if let Err(e) = resp.as_ref() {
-                            if e == &Error::ConnectionClosed {
-                                println!("Client disconnected.");
+            loop {
+                let resp = match idle_deadline {
+                    Some(deadline) => {
+                        match tokio::time::timeout_at(deadline, tsocket.recv::<P>()).await {
+                            Ok(resp) => resp,
+                            Err(_) => {
+                                info!(
+                                    peer = %addr,
+                                    session_id = ?tsocket.session_id,
+                                    "Closing idle connection"
+                                );
+                                if let Err(e) = tsocket.send(P::disconnect()).await {
+                                    debug!(
+                                        peer = %addr,
+                                        error = %e,
+                                        "Failed to send disconnect notice"
+                                    );
+                                }
+                                if let Some(handler) = &disconnect_handler {
+                                    let sources = HandlerSources {
+                                        socket: tsocket.clone(),
+                                        pools: PoolRef(pools.clone()),
+                                        resources: resources.clone(),
+                                        all_connections: keep_alive_pool.clone(),
+                                        connection_resources: connection_resources.clone(),
+                                    };
+                                    handler(sources, P::disconnect()).await;
+                                }
+                                let _ = tsocket.write_part.lock().await.shutdown().await;
                                 break;
                             }
+                        }
+                    }
+                    None => tsocket.recv::<P>().await,
+                };
 
-                            if e == &Error::ReadTimeout {
-                                tokio::time::sleep(tokio::time::Duration::from_secs(3)).await;
-                                continue;
+                let mut packet = match resp {
+                    Ok(packet) => packet,
+                    Err(Error::ConnectionClosed) => {
+                        info!(
+                            peer = %addr,
+                            session_id = ?tsocket.session_id,
+                            "Client disconnected"
+                        );
+                        break;
+                    }
+                    Err(Error::ReadTimeout) => {
+                        // Don't sleep past the idle deadline
+                        let pause = Instant::now() + Duration::from_secs(3);
+                        let wake = idle_deadline.map_or(pause, |deadline| deadline.min(pause));
+                        tokio::time::sleep_until(wake).await;
+                        continue;
+                    }
+                    Err(e) => {
+                        let sources = HandlerSources {
+                            socket: tsocket.clone(),
+                            pools: PoolRef(pools.clone()),
+                            resources: resources.clone(),
+                            all_connections: keep_alive_pool.clone(),
+                            connection_resources: connection_resources.clone(),
+                        };
+                        error_handler(sources, e.clone()).await;
+
+                        match e {
+                            // The frame was read whole, so only this packet is lost
+                            Error::ReplayDetected
+                            | Error::DecryptFailed
+                            | Error::TruncatedFrame
+                            | Error::InvalidKey(_)
+                            | Error::EncryptionError(_)
+                            | Error::Compression(_) => continue,
+                            // The rest of an oversized frame is never read, so the
+                            // stream can't be resynchronised, and a payload that
+                            // decompresses past the limit is treated the same
+                            Error::PacketTooLarge(_) => {
+                                warn!(
+                                    peer = %addr,
+                                    session_id = ?tsocket.session_id,
+                                    error = %e,
+                                    "Closing connection"
+                                );
+                                let _ = tsocket.write_part.lock().await.shutdown().await;
+                                break;
+                            }
+                            _ => {
+                                warn!(
+                                    peer = %addr,
+                                    session_id = ?tsocket.session_id,
+                                    error = %e,
+                                    "Closing connection after read error"
+                                );
+                                break;
                             }
+                        }
+                    }
+                };
 
-                            let sources = HandlerSources {
-                                socket: tsocket.clone(),
-                                pools: PoolRef(pools.clone()),
-                                resources: resources.clone(),
-                            };
-                            error_handler(sources, e.to_owned()).await;
+                idle_deadline = idle_timeout.map(|timeout| Instant::now() + timeout);
+                debug!(
+                    peer = %addr,
+                    session_id = ?tsocket.session_id,
+                    header = %packet.header(),
+                    "Received packet"
+                );
+
+                // Only the first packet can ask to resume a session
+                let presented = packet
+                    .body()
+                    .session_id
+                    .filter(|id| tsocket.session_id.as_ref() != Some(id));
+                if let Some(id) = presented.filter(|_| std::mem::take(&mut may_resume)) {
+                    if resume_session(&sessions, expiry_policy, &mut tsocket, id).await {
+                        keep_alive_pool.insert_connection(tsocket.clone()).await;
+                    }
+
+                    let mut ok = P::ok();
+                    ok.session_id(tsocket.session_id.clone());
+                    ok.body_mut().request_id = packet.body().request_id;
+                    if let Err(e) = tsocket.send(ok).await {
+                        warn!(
+                            peer = %addr,
+                            error = %e,
+                            "Failed to answer session resumption"
+                        );
+                        break;
+                    }
+                    continue;
+                }
+                may_resume = false;
+
+                if packet.is_disconnect() {
+                    info!(
+                        peer = %addr,
+                        session_id = ?tsocket.session_id,
+                        "Client disconnected cleanly"
+                    );
+                    if let Some(handler) = &disconnect_handler {
+                        let sources = HandlerSources {
+                            socket: tsocket.clone(),
+                            pools: PoolRef(pools.clone()),
+                            resources: resources.clone(),
+                            all_connections: keep_alive_pool.clone(),
+                            connection_resources: connection_resources.clone(),
+                        };
+                        handler(sources, packet).await;
+                    }
+                    break;
+                }
+
+                if message_rate
+                    .as_mut()
+                    .is_some_and(|bucket| !bucket.try_acquire())
+                {
+                    debug!(
+                        peer = %addr,
+                        session_id = ?tsocket.session_id,
+                        header = %packet.header(),
+                        "Message rate exceeded, dropping packet"
+                    );
+                    if report_rate_limited {
+                        let mut handler_socket = tsocket.clone();
+                        handler_socket.reply_request_id = packet.body().request_id;
+                        let sources = HandlerSources {
+                            socket: handler_socket,
+                            pools: PoolRef(pools.clone()),
+                            resources: resources.clone(),
+                            all_connections: keep_alive_pool.clone(),
+                            connection_resources: connection_resources.clone(),
+                        };
+                        error_handler(sources, Error::RateLimited).await;
+                    }
+                    continue;
+                }
+
+                // Chunks are held back until their transfer is complete
+                let request_id = packet.body_mut().request_id;
+                let packet = match transfers.accept(packet) {
+                    Ok(Some(packet)) => packet,
+                    Ok(None) => continue,
+                    Err(e) => {
+                        let too_large = matches!(e, Error::PacketTooLarge(_));
+                        let mut handler_socket = tsocket.clone();
+                        handler_socket.reply_request_id = request_id;
+                        let sources = HandlerSources {
+                            socket: handler_socket,
+                            pools: PoolRef(pools.clone()),
+                            resources: resources.clone(),
+                            all_connections: keep_alive_pool.clone(),
+                            connection_resources: connection_resources.clone(),
+                        };
+                        error_handler(sources, e).await;
+
+                        if too_large {
+                            warn!(
+                                peer = %addr,
+                                session_id = ?tsocket.session_id,
+                                "Closing connection, chunked transfer too large"
+                            );
+                            let _ = tsocket.write_part.lock().await.shutdown().await;
+                            break;
                         }
+                        continue;
+                    }
+                };
 
-                        let packet = resp.unwrap();
+                if packet.is_ping() {
+                    let mut response = P::pong();
+                    response.body_mut().request_id = packet.body().request_id;
+                    if let Err(e) = tsocket.send(response).await {
+                        warn!(
+                            peer = %addr,
+                            session_id = ?tsocket.session_id,
+                            error = %e,
+                            "Failed to answer ping"
+                        );
+                        break;
+                    }
+                } else if packet.header() == P::keep_alive().header() {
+                    let mut response = P::keep_alive();
+                    if let Some(id) = &tsocket.session_id {
+                        response.session_id(Some(id.clone()));
+                    }
+                    if let Err(e) = tsocket.send(response).await {
+                        warn!(
+                            peer = %addr,
+                            session_id = ?tsocket.session_id,
+                            error = %e,
+                            "Failed to send keepalive response"
+                        );
+                        break;
+                    }
+                } else {
+                    // Replies sent by the handlers echo the request id
+                    let mut handler_socket = tsocket.clone();
+                    handler_socket.reply_request_id = packet.body().request_id;
+
+                    let sources = HandlerSources {
+                        socket: handler_socket,
+                        pools: PoolRef(pools.clone()),
+                        resources: resources.clone(),
+                        all_connections: keep_alive_pool.clone(),
+                        connection_resources: connection_resources.clone(),
+                    };
+
+                    if let Err(e) = packet.validate() {
+                        debug!(
+                            peer = %addr,
+                            session_id = ?tsocket.session_id,
+                            error = %e,
+                            "Rejected invalid packet"
+                        );
+                        error_handler(sources, e).await;
+                        continue;
+                    }
 
-                        if packet.header() == P::keep_alive().header() {
-                            if let Some(first_ka_packet) = packet.body().is_first_keep_alive_packet
-                            {
-                                if first_ka_packet {
-                                    let socket_clone = tsocket.clone();
-                                    keep_alive_pool.add(socket_clone).await;
-                                }
-                            }
+                    let packet = match apply_middleware(&middleware, &sources, packet).await {
+                        Ok(packet) => packet,
+                        Err(e) => {
+                            debug!(
+                                peer = %addr,
+                                session_id = ?tsocket.session_id,
+                                error = %e,
+                                "Middleware rejected packet"
+                            );
+                            error_handler(sources, e).await;
+                            continue;
+                        }
+                    };
 
-                            let mut response = P::keep_alive();
-                            if let Some(id) = &tsocket.session_id {
-                                response.session_id(Some(id.clone()));
-                            }
-                            if let Err(e) = tsocket.send(response).await {
-                                eprintln!("Failed to send keepalive response: {e}");
-                                break;
-                            }
-                        } else {
-                            let sources = HandlerSources {
-                                socket: tsocket.clone(),
-                                pools: PoolRef(pools.clone()),
-                                resources: resources.clone(),
-                            };
-
-                            let handlers =
-                                handler_registry::get_handlers::<P, S, R>(&packet.header());
-
-                            if !handlers.is_empty() {
-                                for handler in handlers {
-                                    handler(sources.clone(), packet.clone()).await;
-                                }
-                            } else {
-                                ok_handler(sources, packet).await;
+                    match &in_flight {
+                        None => {
+                            dispatch(&ok_handler, sources, packet, false, default_reply.as_ref())
+                                .await;
+                        }
+                        Some(in_flight) => {
+                            // Waiting for a slot stops reading from this connection
+                            if let Ok(permit) = in_flight.clone().acquire_owned().await {
+                                let ok_handler = ok_handler.clone();
+                                let default_reply = default_reply.clone();
+                                tokio::spawn(async move {
+                                    dispatch(
+                                        &ok_handler,
+                                        sources,
+                                        packet,
+                                        true,
+                                        default_reply.as_ref(),
+                                    )
+                                    .await;
+                                    drop(permit);
+                                });
                             }
                         }
                     }
+                }
+
+                // Any packet on the session restarts a sliding lifespan
+                if let Some(id) = tsocket
+                    .session_id
+                    .as_ref()
+                    .filter(|_| expiry_policy == SessionExpiryPolicy::Sliding)
+                {
+                    sessions.touch(id).await.unwrap_or_else(|e| {
+                        warn!(session_id = %id, error = %e, "Failed to refresh session");
+                    });
+                }
+            }
+
+            keep_alive_pool.remove_connection(&tsocket).await;
+        });
+    }
+}
+
+/// The listener settings needed to secure and authenticate a new connection.
+///
+/// Every connection's task gets its own copy, so a client that is slow to
+/// finish the handshake or answer a challenge only holds up its own connection
+/// and never the accept loop. Clones of the authenticator share its lockout.
+#[derive(Clone)]
+struct Admission<S: session::Session> {
+    authenticator: Authenticator,
+    encryption: EncryptionConfig,
+    sessions: SessionStoreRef<S>,
+    expiry_policy: SessionExpiryPolicy,
+    timeouts: TimeoutConfig,
+    max_packet_size: usize,
+    metrics: Arc<dyn Metrics>,
+}
+
+impl<S: session::Session> Admission<S> {
+    /// Handles the encryption handshake with a client.
+    ///
+    /// Performs key exchange and establishes encrypted communication. A client
+    /// that sends a plaintext packet, or nothing within the receive timeout,
+    /// instead of its public key is refused with `Error::EncryptionRequired`.
+    ///
+    /// # Arguments
+    ///
+    /// * `socket` - The client socket
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Encryptor, Error>` - The configured encryptor or an error
+    async fn encryption_handshake<P: packet::Packet>(
+        &self,
+        socket: &TSocket<S>,
+    ) -> Result<Encryptor, Error> {
+        let mut read_part = socket.read_part.lock().await;
+
+        // Read client's public key frame, refusing clients that never send one
+        let frame = tokio::time::timeout(self.timeouts.recv, read_part.read_frame()).await;
+        drop(read_part);
+        let frame = match frame {
+            Ok(Ok(Some(frame))) => frame,
+            Ok(Ok(None)) | Err(_) => return Err(Error::EncryptionRequired),
+            Ok(Err(e)) => return Err(Error::EncryptionError(e.to_string())),
+        };
+
+        // A packet in place of the public key means the client skipped encryption
+        let plaintext = socket
+            .compression
+            .decompress_limited(&frame, self.max_packet_size)
+            .is_ok_and(|data| P::format().deserialize::<P>(&data).is_ok());
+        if plaintext {
+            return Err(Error::EncryptionRequired);
+        }
+
+        let client_hello = HandshakeHello::decode(&frame).ok_or(Error::EncryptionRequired)?;
+
+        // Our preference order wins among the suites the client offered
+        let supported = self.encryption.supported_suites();
+        let suite = match CipherSuite::negotiate(&supported, &client_hello.suites) {
+            Some(suite) => suite,
+            None => {
+                // An empty reply tells the client straight away that the handshake failed
+                let mut write_part = socket.write_part.lock().await;
+                framing::write_frame(&mut *write_part, &[])
+                    .await
+                    .map_err(|e| Error::EncryptionError(e.to_string()))?;
+                drop(write_part);
+
+                return Err(Error::EncryptionError(
+                    "No mutually supported cipher suite".to_string(),
+                ));
+            }
+        };
+
+        let key_exchange = KeyExchange::new();
+        let server_hello = HandshakeHello {
+            public_key: key_exchange.get_public_key(),
+            suites: vec![suite],
+        };
+
+        // Send our public key and the chosen suite as a single frame
+        let mut write_part = socket.write_part.lock().await;
+        framing::write_frame(&mut *write_part, &server_hello.encode())
+            .await
+            .map_err(|e| Error::EncryptionError(e.to_string()))?;
+        drop(write_part);
+
+        let shared_secret = key_exchange.compute_shared_secret(&client_hello.public_key);
+        Ok(Encryptor::new_with_suite(&shared_secret, suite)
+            .expect("Failed to create encryptor")
+            .with_role(Role::Server))
+    }
+
+    /// Handles the authentication process for a client connection.
+    ///
+    /// Processes various authentication methods including:
+    /// - Session ID authentication
+    /// - Pre-shared key authentication
+    /// - Challenge-response authentication
+    /// - Username/password authentication
+    /// - No authentication (if configured)
+    ///
+    /// # Arguments
+    ///
+    /// * `tsocket` - The client socket
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Option<Encryptor>, Error>` - The encryption configuration or an error
+    async fn authenticate<P: packet::Packet>(
+        &mut self,
+        tsocket: &mut TSocket<S>,
+    ) -> Result<Option<Encryptor>, Error> {
+        if let Err(e) = self.sessions.clear_expired(self.expiry_policy).await {
+            warn!(error = %e, "Failed to clear expired sessions");
+        }
+
+        // Step 1: Handle Encryption Setup
+        let encryptor = if self.encryption.enabled {
+            let enc = match self.encryption_handshake::<P>(tsocket).await {
+                Ok(enc) => enc,
+                Err(e) => {
+                    // Nothing unencrypted gets past a failed handshake but this refusal
+                    if e == Error::EncryptionRequired {
+                        let _ = tsocket.send(P::error(Error::EncryptionRequired)).await;
+                    }
+                    let _ = tsocket.write_part.lock().await.shutdown().await;
+                    return Err(e);
+                }
+            };
+            tsocket.encryptor = Some(enc.clone()); // Set the encryptor in TSocket
+            Some(enc)
+        } else {
+            None
+        };
+
+        // Step 2: Handle No Authentication Case
+        if matches!(self.authenticator.auth_type, AuthType::None) {
+            let session_id = self.authenticator.new_session_id(&SessionIdContext {
+                auth_type: &self.authenticator.auth_type,
+                username: None,
+                peer_ip: tsocket.peer_ip(),
+            });
+            self.sessions.save(S::empty(session_id.clone())).await?;
+            tsocket.session_id = Some(session_id.clone());
+
+            let mut ok = P::ok();
+            ok.session_id(Some(session_id));
+            tsocket.send(ok).await?;
+
+            return Ok(encryptor);
+        }
+
+        // Step 3: Handle Authentication Cases
+        let packet = tsocket.recv::<P>().await?;
+        let body = packet.body();
+        let mut request_id = body.request_id;
+
+        // Case 3a: Session ID Authentication
+        if let Some(id) = body.session_id {
+            let session_result = self.sessions.load(&id).await?;
+
+            if let Some(session) = session_result {
+                let last_active = self.sessions.last_active(&id).await?;
+                if self.expiry_policy.is_expired(&session, last_active) {
+                    return Err(Error::ExpriedSessionId(id));
+                }
+                tsocket.session_id = Some(id);
+                let mut ok = P::ok();
+                ok.body_mut().request_id = request_id;
+                tsocket.send(ok).await?;
+                return Ok(encryptor);
+            }
+            return Err(Error::InvalidSessionId(id));
+        }
+
+        let peer_ip = tsocket.peer_ip();
+        if self.authenticator.is_locked_out(peer_ip) {
+            let mut err = P::error(Error::AuthRateLimited);
+            err.body_mut().request_id = request_id;
+            tsocket.send(err).await?;
+            return Err(Error::AuthRateLimited);
+        }
+
+        let mut username = None;
+        let result = match self.authenticator.auth_type {
+            // Case 3b: Pre-Shared Key Authentication
+            AuthType::PreSharedKey => match body.token {
+                Some(token) => self.authenticator.authenticate_key(token).await,
+                None => return Err(Error::InvalidCredentials),
+            },
+            // Case 3c: Challenge-Response Authentication
+            AuthType::Challenge => {
+                let challenge = Authenticator::issue_challenge();
+                let mut prompt = P::ok();
+                prompt.body_mut().challenge = Some(challenge.clone());
+                prompt.body_mut().request_id = request_id;
+                tsocket.send(prompt).await?;
+
+                let reply = tsocket.recv::<P>().await?.body();
+                request_id = reply.request_id;
+                match reply.challenge_response {
+                    Some(response) => {
+                        self.authenticator
+                            .authenticate_challenge(challenge, response)
+                            .await
+                    }
+                    None => Err(Error::InvalidCredentials),
+                }
+            }
+            // Case 3d: Username/Password Authentication
+            _ => match (body.username, body.password) {
+                (Some(user), Some(password)) => {
+                    username = Some(user.clone());
+                    self.authenticator.authenticate(user, password).await
+                }
+                _ => return Err(Error::InvalidCredentials),
+            },
+        };
+        self.authenticator.record_attempt(peer_ip, result.is_ok());
+        self.metrics.increment(if result.is_ok() {
+            Counter::AuthSuccesses
+        } else {
+            Counter::AuthFailures
+        });
+
+        match result {
+            Ok(()) => {
+                // Create new session after successful authentication
+                let session_id = self.authenticator.new_session_id(&SessionIdContext {
+                    auth_type: &self.authenticator.auth_type,
+                    username: username.as_deref(),
+                    peer_ip,
                 });
+                self.sessions.save(S::empty(session_id.clone())).await?;
+                if let Some(username) = username {
+                    self.sessions
+                        .set_meta(&session_id, session::USERNAME_META_KEY, username)
+                        .await?;
+                }
+                tsocket.session_id = Some(session_id.clone());
+
+                // Send OK response with new session ID
+                let mut ok = P::ok();
+                ok.session_id(Some(session_id));
+                ok.body_mut().request_id = request_id;
+                tsocket.send(ok).await?;
+
+                Ok(encryptor)
+            }
+            Err(e) => {
+                let mut err = P::error(e.clone());
+                err.body_mut().request_id = request_id;
+                tsocket.send(err).await?;
+
+                Err(e)
             }
         }
     }
 }
+
+/// Moves a connection onto the session it presented, if that session is still valid.
+///
+/// The session minted for the connection is removed, so a client that keeps
+/// reconnecting holds on to one session instead of leaving one behind each time.
+///
+/// # Returns
+///
+/// * `bool` - Whether the connection now uses the presented session
+async fn resume_session<S: session::Session>(
+    sessions: &SessionStoreRef<S>,
+    expiry_policy: SessionExpiryPolicy,
+    tsocket: &mut TSocket<S>,
+    id: String,
+) -> bool {
+    let valid = match sessions.load(&id).await {
+        Ok(Some(session)) => {
+            let last_active = sessions.last_active(&id).await.unwrap_or(None);
+            !expiry_policy.is_expired(&session, last_active)
+        }
+        _ => false,
+    };
+    if !valid {
+        debug!(peer = %tsocket.addr, session_id = %id, "Presented session is not resumable");
+        return false;
+    }
+
+    info!(peer = %tsocket.addr, session_id = %id, "Resumed session");
+    if let Some(minted) = tsocket.session_id.replace(id)
+        && let Err(e) = sessions.remove(&minted).await
+    {
+        warn!(session_id = %minted, error = %e, "Failed to remove unused session");
+    }
+    true
+}
+
+/// Runs the handlers registered for a packet's header, or the listener's
+/// `ok_handler` if there are none.
+///
+/// # Arguments
+///
+/// * `parallel` - Run the registered handlers concurrently instead of in priority order
+/// * `default_reply` - Sent if the handlers finish without sending anything
+async fn dispatch<P, S, R, C>(
+    ok_handler: &AsyncListenerOkHandler<P, S, R, C>,
+    mut sources: HandlerSources<S, R, C>,
+    packet: P,
+    parallel: bool,
+    default_reply: Option<&P>,
+) where
+    P: packet::Packet + 'static,
+    S: session::Session + 'static,
+    R: resources::Resource + 'static,
+    C: resources::Resource + 'static,
+{
+    let header = packet.header();
+    let handlers = handler_registry::flow_handlers::<P, S, R, C>(&header);
+
+    // Sends through any clone of the handlers' socket mark the packet as answered
+    let tracked = default_reply.map(|_| {
+        let replied = sources.socket.track_replies();
+        (replied, sources.clone())
+    });
+
+    if handlers.is_empty() {
+        ok_handler(sources, packet).await;
+    } else if parallel {
+        join_all(
+            handlers
+                .iter()
+                .map(|handler| handler(sources.clone(), packet.clone())),
+        )
+        .await;
+    } else {
+        for handler in handlers {
+            if handler(sources.clone(), packet.clone()).await == handler_registry::HandlerFlow::Stop
+            {
+                break;
+            }
+        }
+    }
+
+    if let (Some(reply), Some((replied, mut reply_sources))) = (default_reply, tracked) {
+        if replied.load(Ordering::SeqCst) {
+            return;
+        }
+        debug!(
+            peer = %reply_sources.socket.addr,
+            header = %header,
+            "Handlers sent no reply, sending the default"
+        );
+        if let Err(e) = reply_sources.reply(reply.clone()).await {
+            warn!(peer = %reply_sources.socket.addr, error = %e, "Failed to send default reply");
+        }
+    }
+}
+
+/// Runs a packet through the middleware chain.
+///
+/// # Returns
+///
+/// * `Result<P, Error>` - The packet to dispatch, or the error the packet was rejected with
+async fn apply_middleware<P, S, R, C>(
+    middleware: &[Arc<dyn Middleware<P, S, R, C>>],
+    sources: &HandlerSources<S, R, C>,
+    mut packet: P,
+) -> Result<P, Error>
+where
+    S: session::Session,
+    R: resources::Resource,
+    C: resources::Resource,
+{
+    for layer in middleware {
+        match layer.process(sources, packet).await {
+            MiddlewareFlow::Pass(next) => packet = next,
+            MiddlewareFlow::Reject(e) => return Err(e),
+        }
+    }
+    Ok(packet)
+}
@@ -1,7 +1,13 @@
 pub mod authenticator;
+pub(crate) mod chunking;
 pub mod client;
 pub mod client_ext;
+pub(crate) mod connection;
+pub mod framing;
 pub mod listener;
 pub mod phantom_client;
 pub mod phantom_listener;
+pub mod rate_limit;
 pub mod socket;
+#[cfg(feature = "websocket")]
+pub mod websocket;
@@ -1,27 +1,32 @@
 use std::{
     sync::{
         Arc,
-        atomic::{AtomicBool, Ordering},
+        atomic::{AtomicBool, AtomicUsize, Ordering},
     },
     time::Duration,
 };
 
-use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
-    sync::{mpsc, Mutex},
-};
+use bytes::Bytes;
+use tokio::sync::{Mutex, mpsc};
+use tracing::{debug, info, warn};
 
 use crate::{
-    encrypt::{Encryptor, KeyExchange},
+    encrypt::{CipherSuite, Encryptor, HandshakeHello, KeyExchange, Role},
     errors::Error,
     packet::{Packet, PacketBody},
     phantom::{ClientConfig, PhantomPacket},
 };
 
-use super::client::{
-    ClientEncryption, ClientMessage, ConnectionHandler, EncryptionConfig, KeepAliveConfig,
+use super::{
+    client::{
+        ClientEncryption, ClientMessage, ConnectionHandler, EncryptionConfig, KeepAliveConfig,
+    },
+    connection, framing,
 };
 
+/// How long `recv` and `recv_raw` wait for a response.
+const RECV_TIMEOUT: Duration = Duration::from_secs(5);
+
 /// `AsyncPhantomClient` is a specialized network client for handling phantom protocol communications.
 ///
 /// This client provides functionality for:
@@ -44,6 +49,7 @@ use super::client::{
 /// * `keep_alive_cold_start` - Indicates if this is the first keep-alive cycle
 /// * `keep_alive_running` - Indicates if keep-alive is currently active
 /// * `response_rx` - Channel for receiving network responses
+/// * `connection_closed` - Indicates if the connection has failed or been closed
 pub struct AsyncPhantomClient {
     connection: ConnectionHandler,
     pub(crate) encryption: ClientEncryption,
@@ -53,7 +59,8 @@ pub struct AsyncPhantomClient {
     keep_alive: KeepAliveConfig,
     keep_alive_cold_start: Arc<Mutex<bool>>,
     keep_alive_running: Arc<AtomicBool>,
-    response_rx: mpsc::Receiver<Vec<u8>>,
+    response_rx: mpsc::Receiver<Bytes>,
+    connection_closed: Arc<AtomicBool>,
 }
 
 impl AsyncPhantomClient {
@@ -88,81 +95,23 @@ impl AsyncPhantomClient {
     /// }
     /// ```
     pub async fn new(ip: &str, port: u16) -> Result<Self, Error> {
-        println!("Connecting to phantom server at {}:{}", ip, port);
+        info!(peer = %format!("{ip}:{port}"), "Connecting to phantom server");
         let server = tokio::net::TcpStream::connect((ip, port))
             .await
             .map_err(|e| Error::IoError(e.to_string()))?;
 
-        println!("Connected to phantom server");
-
-        let (writer_tx, mut writer_rx) = mpsc::channel::<ClientMessage>(32);
-        let (reader_tx, reader_rx) = mpsc::channel::<Vec<u8>>(32);
-
-        // Split the connection
-        let (mut read_half, mut write_half) = server.into_split();
-
-        // Spawn writer task
-        tokio::spawn({
-            async move {
-                while let Some(msg) = writer_rx.recv().await {
-                    match msg {
-                        ClientMessage::Data(data) | ClientMessage::Keepalive(data) => {
-                            println!("DEBUG: Writing {} bytes to phantom server", data.len());
-                            if let Err(e) = write_half.write_all(&data).await {
-                                eprintln!("Write error: {e}");
-                                break;
-                            }
-                            if let Err(e) = write_half.flush().await {
-                                eprintln!("Flush error: {e}");
-                                break;
-                            }
-                        }
-                        ClientMessage::Ping(response) => {
-                            let _ = response.send(true);
-                        }
-                    }
-                }
-                println!("DEBUG: Writer task ended");
-            }
-        });
+        info!(peer = %format!("{ip}:{port}"), "Connected to phantom server");
 
-        // Clone reader_tx before moving it
-        let reader_tx_clone = reader_tx.clone();
-
-        // Spawn reader task
-        tokio::spawn({
-            async move {
-                println!("DEBUG: Reader task started");
-                let mut buf = vec![0; 4096];
-                loop {
-                    match read_half.read(&mut buf).await {
-                        Ok(n) if n > 0 => {
-                            println!("DEBUG: Read {} bytes from phantom server", n);
-                            let data = buf[..n].to_vec();
-                            if let Err(e) = reader_tx_clone.send(data).await {
-                                eprintln!("Reader send error: {e}");
-                                break;
-                            }
-                        }
-                        Ok(n) => {
-                            println!("DEBUG: Connection closed by phantom server ({} bytes)", n);
-                            break;
-                        }
-                        Err(e) => {
-                            eprintln!("Read error: {e}");
-                            break;
-                        }
-                    }
-                }
-                println!("DEBUG: Reader task ended");
-            }
-        });
+        let (read_half, write_half) = server.into_split();
+        let io = connection::spawn_io(
+            read_half,
+            write_half,
+            format!("{ip}:{port}"),
+            Arc::new(AtomicUsize::new(framing::DEFAULT_MAX_FRAME_LEN)),
+        );
 
         Ok(Self {
-            connection: ConnectionHandler {
-                writer_tx,
-                reader_tx,
-            },
+            connection: io.handler,
             encryption: ClientEncryption::None,
             session_id: None,
             user: None,
@@ -170,7 +119,8 @@ impl AsyncPhantomClient {
             keep_alive: KeepAliveConfig::default(),
             keep_alive_cold_start: Arc::new(Mutex::new(true)),
             keep_alive_running: Arc::new(AtomicBool::new(false)),
-            response_rx: reader_rx,
+            response_rx: io.response_rx,
+            connection_closed: io.connection_closed,
         })
     }
 
@@ -318,14 +268,19 @@ impl AsyncPhantomClient {
         }
 
         if let Some(key) = config.key {
+            // A pre-shared key skips the handshake, so use the first preference
+            let suite = config.supported_suites()[0];
             self.encryption = ClientEncryption::Encrypted(Box::new(
-                Encryptor::new(&key).expect("Failed to create encryptor"),
+                Encryptor::new_with_suite(&key, suite)
+                    .expect("Failed to create encryptor")
+                    .with_role(Role::Client),
             ));
             return Ok(self);
         }
 
         if config.auto_key_exchange {
-            self.establish_encrypted_connection().await?;
+            self.establish_encrypted_connection(&config.supported_suites())
+                .await?;
         }
 
         if let (Some(user), Some(pass)) = (&self.user, &self.pass) {
@@ -367,38 +322,56 @@ impl AsyncPhantomClient {
     /// # Returns
     ///
     /// * `std::io::Result<()>` - Success or failure of encryption setup
-    async fn establish_encrypted_connection(&mut self) -> std::io::Result<()> {
+    async fn establish_encrypted_connection(
+        &mut self,
+        suites: &[CipherSuite],
+    ) -> std::io::Result<()> {
         let key_exchange = KeyExchange::new();
-        let public_key = key_exchange.get_public_key();
+        let hello = HandshakeHello {
+            public_key: key_exchange.get_public_key(),
+            suites: suites.to_vec(),
+        };
 
-        // Send our public key
+        // Send our public key along with the cipher suites we support
         self.connection
             .writer_tx
-            .send(ClientMessage::Data(public_key.to_vec()))
+            .send(ClientMessage::Data(hello.encode().into(), None))
             .await
             .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.to_string()))?;
 
-        // Receive server's public key
-        let server_public = self.response_rx.recv().await.ok_or_else(|| {
+        // Receive server's public key and the suite it picked
+        let server_hello = self.response_rx.recv().await.ok_or_else(|| {
             std::io::Error::new(
                 std::io::ErrorKind::ConnectionReset,
                 "Connection closed while waiting for server's public key",
             )
         })?;
 
-        if server_public.len() != 32 {
+        if server_hello.is_empty() {
             return Err(std::io::Error::new(
                 std::io::ErrorKind::InvalidData,
-                "Invalid server public key length",
+                "Server does not support any offered cipher suite",
             ));
         }
 
-        let mut server_public_key = [0u8; 32];
-        server_public_key.copy_from_slice(&server_public[..32]);
+        let server_hello = HandshakeHello::decode(&server_hello).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "Invalid server public key length",
+            )
+        })?;
+        let suite = server_hello.chosen_suite(suites).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "Server picked a cipher suite we did not offer",
+            )
+        })?;
 
-        let shared_secret = key_exchange.compute_shared_secret(&server_public_key);
+        let shared_secret = key_exchange.compute_shared_secret(&server_hello.public_key);
         self.encryption = ClientEncryption::Encrypted(Box::new(
-            Encryptor::new(&shared_secret).expect("Failed to create encryptor"),
+            Encryptor::new_with_suite(&shared_secret, suite)
+                .expect("Failed to create encryptor")
+                .with_role(Role::Client),
         ));
 
         Ok(())
@@ -417,19 +390,18 @@ impl AsyncPhantomClient {
     /// # Errors
     ///
     /// Returns error if:
+    /// - The connection is closed
     /// - Sending data fails
     /// - Channel send fails
     pub async fn send(&mut self, packet: PhantomPacket) -> Result<(), Error> {
-        tokio::time::sleep(Duration::from_nanos(250_000)).await;
-
-        let data = match &self.encryption {
-            ClientEncryption::None => packet.ser(),
-            ClientEncryption::Encrypted(encryptor) => packet.encrypted_ser(encryptor),
-        };
+        if self.connection_closed.load(Ordering::SeqCst) {
+            return Err(Error::ConnectionClosed);
+        }
 
+        let encryptor = self.encryption.encryptor().cloned();
         self.connection
             .writer_tx
-            .send(ClientMessage::Data(data))
+            .send(ClientMessage::Data(packet.ser().into(), encryptor))
             .await
             .map_err(|e| Error::FailedPacketSend(e.to_string()))?;
         Ok(())
@@ -445,15 +417,10 @@ impl AsyncPhantomClient {
     ///
     /// Returns error if:
     /// - Connection is closed
+    /// - No response arrives within the receive timeout
     /// - Packet decryption fails
     pub async fn recv(&mut self) -> Result<PhantomPacket, Error> {
-        tokio::time::sleep(Duration::from_nanos(250_000)).await;
-
-        let data = self
-            .response_rx
-            .recv()
-            .await
-            .ok_or(Error::ConnectionClosed)?;
+        let data = self.recv_frame().await?;
 
         let packet = match &self.encryption {
             ClientEncryption::None => PhantomPacket::de(&data),
@@ -494,20 +461,20 @@ impl AsyncPhantomClient {
         &mut self,
         packet: PhantomPacket,
     ) -> Result<PhantomPacket, Error> {
-        println!("DEBUG: Sending phantom packet: {:?}", packet);
+        debug!(?packet, "Sending phantom packet");
 
         self.send(packet).await.map_err(|e| {
-            println!("DEBUG: Error sending packet: {:?}", e);
+            warn!(error = %e, "Error sending packet");
             e
         })?;
 
-        println!("DEBUG: Waiting for response...");
+        debug!("Waiting for response");
         let response = self.recv().await.map_err(|e| {
-            println!("DEBUG: Error receiving response: {:?}", e);
+            warn!(error = %e, "Error receiving response");
             e
         })?;
 
-        println!("DEBUG: Received response: {:?}", response);
+        debug!(?response, "Received response");
         Ok(response)
     }
 
@@ -523,10 +490,7 @@ impl AsyncPhantomClient {
             return Ok(());
         }
 
-        let session_id = self
-            .session_id
-            .clone()
-            .ok_or(Error::KeepAliveNoSessionId)?;
+        let session_id = self.session_id.clone().ok_or(Error::KeepAliveNoSessionId)?;
 
         let interval = self.keep_alive.interval;
         let encryption = self.encryption.clone();
@@ -550,13 +514,9 @@ impl AsyncPhantomClient {
 
                 packet.session_id(Some(session_id.clone()));
 
-                let data = match &encryption {
-                    ClientEncryption::None => packet.ser(),
-                    ClientEncryption::Encrypted(encryptor) => packet.encrypted_ser(encryptor),
-                };
-
+                let encryptor = encryption.encryptor().cloned();
                 if writer_tx
-                    .send(ClientMessage::Keepalive(data))
+                    .send(ClientMessage::Keepalive(packet.ser().into(), encryptor))
                     .await
                     .is_err()
                 {
@@ -593,6 +553,7 @@ impl AsyncPhantomClient {
     /// # Errors
     ///
     /// Returns error if:
+    /// - The connection is closed
     /// - Encryption fails
     /// - Send operation fails
     ///
@@ -602,18 +563,22 @@ impl AsyncPhantomClient {
     /// - Encryption fails
     /// - UTF-8 conversion fails
     pub async fn send_raw(&mut self, packet: Vec<u8>) -> Result<(), Error> {
-        tokio::time::sleep(Duration::from_nanos(250_000)).await;
-
-        let data = match &self.encryption {
-            ClientEncryption::Encrypted(encryptor) => encryptor.encrypt(&packet).unwrap(),
-            ClientEncryption::None => String::from_utf8(packet).unwrap(),
+        if self.connection_closed.load(Ordering::SeqCst) {
+            return Err(Error::ConnectionClosed);
         }
-        .as_bytes()
-        .to_vec();
+
+        let message = match &self.encryption {
+            ClientEncryption::Encrypted(encryptor) => {
+                ClientMessage::Data(packet.into(), Some((**encryptor).clone()))
+            }
+            ClientEncryption::None => {
+                ClientMessage::Data(String::from_utf8(packet).unwrap().into(), None)
+            }
+        };
 
         self.connection
             .writer_tx
-            .send(ClientMessage::Data(data))
+            .send(message)
             .await
             .map_err(|e| Error::FailedPacketSend(e.to_string()))
     }
@@ -628,6 +593,7 @@ impl AsyncPhantomClient {
     ///
     /// Returns error if:
     /// - Connection is closed
+    /// - No response arrives within the receive timeout
     /// - Decryption fails
     ///
     /// # Panics
@@ -636,31 +602,35 @@ impl AsyncPhantomClient {
     /// - Decryption fails
     /// - UTF-8 conversion fails
     pub async fn recv_raw(&mut self) -> Result<Vec<u8>, Error> {
-        let data = match tokio::time::timeout(Duration::from_secs(5), self.response_rx.recv()).await
-        {
-            Ok(Some(data)) => data,
-            Ok(None) => return Err(Error::ConnectionClosed),
-            Err(_) => return Err(Error::FailedPacketRead("Timeout waiting for response".to_string())),
-        };
+        let data = self.recv_frame().await?;
 
-        // For debugging
-        println!("DEBUG: Received raw data of length: {}", data.len());
+        debug!(bytes = data.len(), "Received raw data");
 
-        // No need to sleep here as we're already waiting in the timeout
         let data = match &self.encryption {
             ClientEncryption::Encrypted(encryptor) => {
                 let text = String::from_utf8_lossy(&data);
-                match encryptor.decrypt(&text) {
-                    Ok(decrypted) => decrypted,
-                    Err(e) => return Err(Error::EncryptionError(e.to_string())),
-                }
+                encryptor.decrypt(&text)?
             }
-            ClientEncryption::None => data,
+            ClientEncryption::None => Vec::from(data),
         };
 
         Ok(data)
     }
 
+    /// Waits for the next frame from the server.
+    ///
+    /// The response channel closes when the reader task ends, after any
+    /// frames that arrived before the connection closed are delivered.
+    async fn recv_frame(&mut self) -> Result<Bytes, Error> {
+        match tokio::time::timeout(RECV_TIMEOUT, self.response_rx.recv()).await {
+            Ok(Some(data)) => Ok(data),
+            Ok(None) => Err(Error::ConnectionClosed),
+            Err(_) => Err(Error::FailedPacketRead(
+                "Timeout waiting for response".to_string(),
+            )),
+        }
+    }
+
     /// Sends raw data and waits for a raw response.
     ///
     /// # Arguments
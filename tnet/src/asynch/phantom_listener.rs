@@ -1,18 +1,29 @@
-use crate::packet::{Packet, PacketBody};
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use crate::packet::Packet;
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
 use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
 
 use crate::{
     errors::Error,
-    phantom::PhantomPacket,
+    phantom::{ClientConfig, LoadBalanceStrategy, PhantomPacket},
     prelude::AsyncListener,
     resources::Resource,
     session::Session,
     wrap_handler,
 };
 
-use super::{listener::HandlerSources, phantom_client::AsyncPhantomClient};
+use super::{
+    authenticator::Authenticator,
+    client::{EncryptionConfig, TimeoutConfig},
+    listener::{HandlerSources, ResourceRef},
+    phantom_client::AsyncPhantomClient,
+};
 
 /// `PhantomSession` represents a session in the phantom network protocol.
 ///
@@ -65,19 +76,363 @@ impl Session for PhantomSession {
     }
 }
 
+/// Inspects or rewrites a relayed response before the relay sends it back.
+///
+/// Called with the relay request the client sent and the `relay-response`
+/// packet carrying the endpoint's answer in `recv_packet`. Whatever it returns
+/// is sent to the client instead.
+pub type RelayInterceptor =
+    Arc<dyn Fn(&PhantomPacket, PhantomPacket) -> PhantomPacket + Send + Sync>;
+
 /// `PhantomResources` serves as a container for any shared resources needed by the phantom network.
 ///
 /// This structure implements the `Resource` trait and can be extended to hold any
 /// application-specific resources that need to be shared across different parts of the network.
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct PhantomResources {}
+/// It also keeps the state used to balance relayed packets across endpoint pools.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PhantomResources {
+    balancer: LoadBalancer,
+    #[serde(skip)]
+    interceptor: Option<RelayInterceptor>,
+    #[serde(skip)]
+    cache: Option<ResponseCache>,
+}
 
 impl Resource for PhantomResources {
     fn new() -> Self {
-        Self {}
+        Self {
+            balancer: LoadBalancer::default(),
+            interceptor: None,
+            cache: None,
+        }
+    }
+}
+
+impl PhantomResources {
+    // Looks up a cached response, None when caching is off
+    fn cached_response(&mut self, key: &RelayCacheKey) -> Option<String> {
+        self.cache.as_mut().and_then(|cache| cache.get(key))
+    }
+
+    // Remembers a response, does nothing when caching is off
+    fn cache_response(&mut self, key: RelayCacheKey, response: String) {
+        if let Some(cache) = self.cache.as_mut() {
+            cache.insert(key, response);
+        }
+    }
+}
+
+impl fmt::Debug for PhantomResources {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PhantomResources")
+            .field("balancer", &self.balancer)
+            .field("interceptor", &self.interceptor.is_some())
+            .field("cache", &self.cache)
+            .finish()
+    }
+}
+
+/// Settings for caching relayed responses.
+///
+/// # Fields
+///
+/// * `capacity` - How many responses are kept before the least recently used is dropped
+/// * `ttl` - How long a response is served from the cache
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+/// use tnet::asynch::phantom_listener::RelayCacheConfig;
+///
+/// let config = RelayCacheConfig::default()
+///     .with_capacity(1024)
+///     .with_ttl(Duration::from_secs(60));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RelayCacheConfig {
+    pub capacity: usize,
+    pub ttl: Duration,
+}
+
+impl Default for RelayCacheConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 256,
+            ttl: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RelayCacheConfig {
+    #[must_use]
+    pub const fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    #[must_use]
+    pub const fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+}
+
+/// Relayed responses, dropped once they expire or when the cache is full.
+///
+/// # Fields
+///
+/// * `config` - Capacity and time to live
+/// * `entries` - Cached responses by key
+/// * `uses` - Incremented on every access, to find the least recently used entry
+#[derive(Debug, Clone)]
+struct ResponseCache {
+    config: RelayCacheConfig,
+    entries: HashMap<RelayCacheKey, CachedResponse>,
+    uses: u64,
+}
+
+#[derive(Debug, Clone)]
+struct CachedResponse {
+    response: String,
+    stored_at: Instant,
+    last_used: u64,
+}
+
+impl ResponseCache {
+    fn new(config: RelayCacheConfig) -> Self {
+        Self {
+            config,
+            entries: HashMap::new(),
+            uses: 0,
+        }
+    }
+
+    fn get(&mut self, key: &RelayCacheKey) -> Option<String> {
+        let ttl = self.config.ttl;
+        if self
+            .entries
+            .get(key)
+            .is_some_and(|entry| entry.stored_at.elapsed() >= ttl)
+        {
+            self.entries.remove(key);
+        }
+
+        self.uses += 1;
+        let uses = self.uses;
+        self.entries.get_mut(key).map(|entry| {
+            entry.last_used = uses;
+            entry.response.clone()
+        })
+    }
+
+    fn insert(&mut self, key: RelayCacheKey, response: String) {
+        if self.config.capacity == 0 {
+            return;
+        }
+
+        let ttl = self.config.ttl;
+        self.entries
+            .retain(|_, entry| entry.stored_at.elapsed() < ttl);
+
+        if !self.entries.contains_key(&key) && self.entries.len() >= self.config.capacity {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+
+        self.uses += 1;
+        self.entries.insert(
+            key,
+            CachedResponse {
+                response,
+                stored_at: Instant::now(),
+                last_used: self.uses,
+            },
+        );
+    }
+}
+
+/// Identifies a relay request in the response cache.
+///
+/// The key covers the client, the route with its credentials and the relayed
+/// packet. Entries are looked up by the whole key rather than a hash of it, so
+/// clients never get each other's responses.
+///
+/// # Fields
+///
+/// * `client` - Identifies the client, usually by its session id
+/// * `sent_packet` - The relayed packet
+/// * `route` - The target, hops and endpoints with their credentials, serialized
+/// * `load_balance` - How the endpoints are picked
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct RelayCacheKey {
+    client: String,
+    sent_packet: Option<String>,
+    route: String,
+    load_balance: LoadBalanceStrategy,
+}
+
+impl RelayCacheKey {
+    fn new(client: &str, packet: &PhantomPacket) -> Self {
+        Self {
+            client: client.to_string(),
+            sent_packet: packet.sent_packet.clone(),
+            route: serde_json::to_string(&(&packet.client_config, &packet.hops, &packet.endpoints))
+                .unwrap_or_default(),
+            load_balance: packet.load_balance,
+        }
+    }
+}
+
+/// How many clients a load balancer remembers the target server of.
+const MAX_STICKY_CLIENTS: usize = 4096;
+
+/// Assigns relay requests to the target servers of an endpoint pool.
+///
+/// # Fields
+///
+/// * `next` - Position of the next round-robin assignment
+/// * `active` - Relays in progress per target server
+/// * `sticky` - The target server each client was assigned to
+/// * `uses` - Incremented on every assignment, to find the least recently seen client
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct LoadBalancer {
+    next: usize,
+    active: HashMap<String, usize>,
+    sticky: HashMap<String, StickyTarget>,
+    uses: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StickyTarget {
+    endpoint: String,
+    last_used: u64,
+}
+
+impl LoadBalancer {
+    /// Picks the target server for a client's relay request and counts the
+    /// relay as in progress.
+    ///
+    /// A client that was already assigned to a server in the pool keeps it.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - Identifies the client, usually by its session id
+    /// * `pool` - The target servers to choose from, never empty
+    /// * `strategy` - How to assign clients seen for the first time
+    ///
+    /// # Returns
+    ///
+    /// * `usize` - The index of the chosen server in `pool`
+    fn acquire(
+        &mut self,
+        client: &str,
+        pool: &[ClientConfig],
+        strategy: LoadBalanceStrategy,
+    ) -> usize {
+        self.uses += 1;
+        let uses = self.uses;
+        let assigned = self.sticky.get_mut(client).and_then(|target| {
+            target.last_used = uses;
+            pool.iter()
+                .position(|config| endpoint_key(config) == target.endpoint)
+        });
+
+        let index = assigned.unwrap_or_else(|| match strategy {
+            LoadBalanceStrategy::RoundRobin => {
+                let index = self.next % pool.len();
+                self.next = self.next.wrapping_add(1);
+                index
+            }
+            LoadBalanceStrategy::LeastConnections => pool
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, config)| {
+                    self.active.get(&endpoint_key(config)).copied().unwrap_or(0)
+                })
+                .map_or(0, |(index, _)| index),
+        });
+
+        let key = endpoint_key(&pool[index]);
+        self.pin(client, key.clone());
+        *self.active.entry(key).or_default() += 1;
+        index
+    }
+
+    /// Remembers the target server of a client.
+    ///
+    /// Once `MAX_STICKY_CLIENTS` are remembered, the client seen least
+    /// recently is forgotten to make room.
+    fn pin(&mut self, client: &str, endpoint: String) {
+        if !self.sticky.contains_key(client) && self.sticky.len() >= MAX_STICKY_CLIENTS {
+            let oldest = self
+                .sticky
+                .iter()
+                .min_by_key(|(_, target)| target.last_used)
+                .map(|(client, _)| client.clone());
+            if let Some(oldest) = oldest {
+                self.sticky.remove(&oldest);
+            }
+        }
+
+        self.sticky.insert(
+            client.to_string(),
+            StickyTarget {
+                endpoint,
+                last_used: self.uses,
+            },
+        );
+    }
+
+    /// Forgets the target server of a client, so its next request is assigned afresh.
+    fn unpin(&mut self, client: &str) {
+        self.sticky.remove(client);
+    }
+
+    /// Marks a relay to the target server as finished.
+    ///
+    /// # Arguments
+    ///
+    /// * `endpoint` - The target server returned by `acquire`
+    fn release(&mut self, endpoint: &ClientConfig) {
+        if let Some(active) = self.active.get_mut(&endpoint_key(endpoint)) {
+            *active = active.saturating_sub(1);
+        }
+    }
+}
+
+/// Counts a relay to a target server as in progress until it is dropped.
+///
+/// Releasing on drop also covers relays whose future is cancelled midway.
+struct ActiveRelay {
+    resources: ResourceRef<PhantomResources>,
+    target: ClientConfig,
+}
+
+impl Drop for ActiveRelay {
+    fn drop(&mut self) {
+        if let Ok(mut resources) = self.resources.0.try_write() {
+            resources.balancer.release(&self.target);
+            return;
+        }
+
+        // Drop can't wait for the lock, so a contended release finishes on its own task
+        let resources = self.resources.clone();
+        let target = self.target.clone();
+        tokio::spawn(async move { resources.write().await.balancer.release(&target) });
     }
 }
 
+fn endpoint_key(config: &ClientConfig) -> String {
+    format!("{}:{}", config.server_addr, config.server_port)
+}
+
 /// `PhantomListener` is the main server component for handling phantom network communications.
 ///
 /// This listener is used to relay packets to another endpoint.
@@ -102,18 +457,15 @@ pub struct PhantomListener {
     pub server: AsyncListener<PhantomPacket, PhantomSession, PhantomResources>,
 }
 
-async fn ok(
-    sources: HandlerSources<PhantomSession, PhantomResources>,
-    packet: PhantomPacket,
-) {
-    println!("Phantom listener received packet: {:?}", packet);
+async fn ok(sources: HandlerSources<PhantomSession, PhantomResources>, packet: PhantomPacket) {
+    debug!(peer = %sources.socket.addr, ?packet, "Phantom listener received packet");
     let mut socket = sources.socket;
 
     if packet.header.as_str() == "relay" {
         let sent_packet = match &packet.sent_packet {
             Some(p) => p,
             None => {
-                println!("No packet to relay - sending error response");
+                warn!("No packet to relay - sending error response");
                 socket
                     .send(PhantomPacket::error(Error::Error(
                         "No packet to relay".to_string(),
@@ -127,7 +479,7 @@ async fn ok(
         let client_config = match &packet.client_config {
             Some(config) => config,
             None => {
-                println!("No client config - sending error response");
+                warn!("No client config - sending error response");
                 socket
                     .send(PhantomPacket::error(Error::InvalidClientConfig))
                     .await
@@ -136,92 +488,243 @@ async fn ok(
             }
         };
 
-        println!(
-            "Received a relay request from {:?} -> {}:{}",
-            socket.addr,
-            client_config.server_addr,
-            client_config.server_port
-        );
+        let client = socket
+            .session_id
+            .clone()
+            .unwrap_or_else(|| socket.addr.clone());
+        let cache_key = RelayCacheKey::new(&client, &packet);
+        let cached = sources.resources.write().await.cached_response(&cache_key);
 
-        // Create a new phantom client for the target server
-        match AsyncPhantomClient::from_client_config(client_config).await {
-            Ok(mut phantom_client) => {
-                println!("Successfully created phantom client, finalizing...");
-                phantom_client.finalize().await;
-                println!("Phantom client connection established");
-
-                // Wait a bit for the connection to stabilize
-                tokio::time::sleep(Duration::from_millis(300)).await;
-
-                // Get the raw bytes from the sent packet
-                let sent_bytes = sent_packet.as_bytes().to_vec();
-                println!(
-                    "Sending {} bytes to destination server...",
-                    sent_bytes.len()
-                );
+        let result = if let Some(response) = cached {
+            debug!(peer = %socket.addr, "Serving relay response from cache");
+            Ok(response)
+        } else {
+            let result = route_relay(
+                &sources.resources,
+                &socket.addr,
+                &client,
+                &packet,
+                client_config,
+                sent_packet,
+            )
+            .await;
+            if let Ok(response) = &result {
+                sources
+                    .resources
+                    .write()
+                    .await
+                    .cache_response(cache_key, response.clone());
+            }
+            result
+        };
+
+        match result {
+            Ok(response_str) => {
+                debug!(response = %response_str, "Response content");
+
+                // Create a relay-response packet
+                let mut response_packet = PhantomPacket {
+                    recv_packet: Some(response_str),
+                    ..PhantomPacket::response()
+                };
 
-                // Try to send the data and wait for response
-                match phantom_client.send_recv_raw(sent_bytes).await {
-                    Ok(response_data) => {
-                        println!(
-                            "Received response from destination ({} bytes)",
-                            response_data.len()
-                        );
-
-                        // Convert the response to a string
-                        let response_str = String::from_utf8(response_data).expect("Failed to convert response data to string");
-                        println!("Response content: {}", response_str);
-
-                        // Create a relay-response packet
-                        let response_packet = PhantomPacket {
-                            header: "relay-response".to_string(), 
-                            body: PacketBody::default(),
-                            sent_packet: None,
-                            recv_packet: Some(response_str),
-                            client_config: None,
-                        };
-
-                        println!(
-                            "Sending relay response back to client: {:?}",
-                            response_packet
-                        );
-                        if let Err(e) = socket.send(response_packet).await {
-                            eprintln!("Failed to send response back to client: {}", e);
-                        } else {
-                            println!("Response sent successfully to client");
-                        }
-                    }
-                    Err(e) => {
-                        eprintln!("Error receiving response from destination: {}", e);
-                        let err_packet = PhantomPacket::error(e.clone());
-                        println!("Sending error response: {:?}", err_packet);
-                        if let Err(send_err) = socket.send(err_packet).await {
-                            eprintln!("Also failed to send error response: {}", send_err);
-                        }
-                    }
+                let interceptor = sources.resources.read().await.interceptor.clone();
+                if let Some(interceptor) = interceptor {
+                    response_packet = interceptor(&packet, response_packet);
+                }
+
+                debug!(?response_packet, "Sending relay response back to client");
+                if let Err(e) = socket.send(response_packet).await {
+                    warn!(error = %e, "Failed to send response back to client");
+                } else {
+                    debug!("Response sent successfully to client");
                 }
             }
             Err(e) => {
-                eprintln!("Failed to create phantom client: {}", e);
-                let err_packet = PhantomPacket::error(e.clone());
-                println!("Sending error response: {:?}", err_packet);
+                warn!(error = %e, "Failed to relay packet");
+                let err_packet = PhantomPacket::error(e);
+                debug!(?err_packet, "Sending error response");
                 if let Err(send_err) = socket.send(err_packet).await {
-                    eprintln!("Also failed to send error response: {}", send_err);
+                    warn!(error = %send_err, "Also failed to send error response");
                 }
             }
         }
     } else {
-        println!("Received non-relay packet: {:?}", packet);
+        debug!(?packet, "Received non-relay packet");
         let _ = socket.send(PhantomPacket::ok()).await;
     }
 }
 
-async fn bad(
-    sources: HandlerSources<PhantomSession, PhantomResources>,
-    error: Error,
-) {
+/// Sends a relay request on its way and waits for the target server's response.
+///
+/// # Arguments
+///
+/// * `resources` - The relay's resources, holding the load balancer
+/// * `peer` - Address of the client that sent the request, for logging
+/// * `client` - Identifies the client to the load balancer
+/// * `packet` - The relay request
+/// * `client_config` - Configuration for connecting to the target server
+/// * `sent_packet` - The serialized packet to deliver
+///
+/// # Returns
+///
+/// * `Result<String, Error>` - The target server's response or an error
+async fn route_relay(
+    resources: &ResourceRef<PhantomResources>,
+    peer: &str,
+    client: &str,
+    packet: &PhantomPacket,
+    client_config: &ClientConfig,
+    sent_packet: &str,
+) -> Result<String, Error> {
+    match packet.hops.first() {
+        Some(next_hop) => {
+            info!(
+                peer = %peer,
+                next_hop = %format!("{}:{}", next_hop.server_addr, next_hop.server_port),
+                remaining_hops = packet.hops.len() - 1,
+                "Forwarding a relay request to the next relay"
+            );
+            forward_to_relay(packet).await
+        }
+        None if packet.endpoints.is_empty() => {
+            info!(
+                peer = %peer,
+                target_addr = %endpoint_key(client_config),
+                "Received a relay request"
+            );
+            send_to_endpoint(client_config, sent_packet).await
+        }
+        None => {
+            let mut pool: Vec<ClientConfig> = std::iter::once(client_config.clone())
+                .chain(packet.endpoints.iter().cloned())
+                .collect();
+
+            // A failed server is left out and the request goes to another one
+            loop {
+                let index =
+                    resources
+                        .write()
+                        .await
+                        .balancer
+                        .acquire(client, &pool, packet.load_balance);
+                let active = ActiveRelay {
+                    resources: resources.clone(),
+                    target: pool.remove(index),
+                };
+
+                info!(
+                    peer = %peer,
+                    target_addr = %endpoint_key(&active.target),
+                    strategy = ?packet.load_balance,
+                    "Received a balanced relay request"
+                );
+                let result = send_to_endpoint(&active.target, sent_packet).await;
+                let Err(e) = result else {
+                    return result;
+                };
+
+                resources.write().await.balancer.unpin(client);
+                if pool.is_empty() {
+                    return Err(e);
+                }
+                warn!(
+                    peer = %peer,
+                    target_addr = %endpoint_key(&active.target),
+                    error = %e,
+                    "Target server failed, choosing another"
+                );
+            }
+        }
+    }
+}
+
+/// Delivers the relayed packet to the target server.
+///
+/// # Arguments
+///
+/// * `client_config` - Configuration for connecting to the target server
+/// * `sent_packet` - The serialized packet to deliver
+///
+/// # Returns
+///
+/// * `Result<String, Error>` - The target server's response or an error
+async fn send_to_endpoint(
+    client_config: &ClientConfig,
+    sent_packet: &str,
+) -> Result<String, Error> {
+    // Create a new phantom client for the target server
+    let mut phantom_client = AsyncPhantomClient::from_client_config(client_config).await?;
+    debug!("Created phantom client, finalizing");
+    phantom_client.finalize().await;
+    debug!("Phantom client connection established");
+
+    // Wait a bit for the connection to stabilize
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    // Get the raw bytes from the sent packet
+    let sent_bytes = sent_packet.as_bytes().to_vec();
+    debug!(bytes = sent_bytes.len(), "Sending to destination server");
+
+    let response_data = phantom_client.send_recv_raw(sent_bytes).await?;
+    debug!(
+        bytes = response_data.len(),
+        "Received response from destination"
+    );
+
+    String::from_utf8(response_data).map_err(|e| Error::FailedPacketRead(e.to_string()))
+}
+
+/// Forwards a relay request to the first of its remaining hops.
+///
+/// The next relay receives the packet without its own hop, so it either
+/// forwards it again or delivers it to the target server.
+///
+/// # Arguments
+///
+/// * `packet` - The relay request, with at least one hop left
+///
+/// # Returns
+///
+/// * `Result<String, Error>` - The target server's response or an error
+async fn forward_to_relay(packet: &PhantomPacket) -> Result<String, Error> {
+    let mut hops = packet.hops.clone();
+    let next_hop = hops.remove(0);
+
+    let mut relay = AsyncPhantomClient::from_client_config(&next_hop).await?;
+    relay.finalize().await;
+
+    let forwarded = PhantomPacket {
+        hops,
+        ..packet.clone()
+    };
+    relay.send(forwarded).await?;
+
+    // Skip the session and finalize acknowledgements until the relay answers
+    loop {
+        let response = relay.recv().await?;
+        match response.header.as_str() {
+            "relay-response" => {
+                return response
+                    .recv_packet
+                    .ok_or_else(|| Error::Error("Relay response was empty".to_string()));
+            }
+            "ERROR" => {
+                return Err(Error::Error(
+                    response
+                        .body
+                        .error_string
+                        .unwrap_or_else(|| "Next relay failed".to_string()),
+                ));
+            }
+            _ => debug!(header = %response.header, "Skipping packet from next relay"),
+        }
+    }
+}
+
+async fn bad(sources: HandlerSources<PhantomSession, PhantomResources>, error: Error) {
     let mut socket = sources.socket;
-    eprintln!("Error in phantom listener: {error}");
+    warn!(peer = %socket.addr, error = %error, "Error in phantom listener");
     let _ = socket.send(PhantomPacket::error(error)).await;
 }
 
@@ -238,4 +741,148 @@ impl PhantomListener {
 
         Self { server }
     }
+
+    /// Sets a closure that sees every relayed response before it goes back to the client.
+    ///
+    /// The closure gets the client's relay request and the `relay-response`
+    /// packet holding the endpoint's answer, and returns the packet to send,
+    /// so it can log, cache or rewrite responses. Errors from the endpoint are
+    /// not passed to it.
+    ///
+    /// # Arguments
+    ///
+    /// * `interceptor` - Called with the relay request and the response packet
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The configured listener instance
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let listener = PhantomListener::new(None)
+    ///     .await
+    ///     .with_relay_interceptor(|request, response| {
+    ///         println!("Relayed {:?}", request.sent_packet);
+    ///         response
+    ///     });
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the listener's resources are locked elsewhere
+    #[must_use]
+    pub fn with_relay_interceptor<F>(self, interceptor: F) -> Self
+    where
+        F: Fn(&PhantomPacket, PhantomPacket) -> PhantomPacket + Send + Sync + 'static,
+    {
+        self.update_resources(|resources| resources.interceptor = Some(Arc::new(interceptor)))
+    }
+
+    /// Serves repeated relay requests from a cache instead of contacting the endpoint again.
+    ///
+    /// Only successful responses are cached. A request is answered from the
+    /// cache when the same client sends the same packet over the same route,
+    /// with the same credentials, within the time to live. Only enable this
+    /// when relaying requests that are safe to answer twice with the same response.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - Size and time to live of the cache
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The configured listener instance
+    ///
+    /// # Panics
+    ///
+    /// Panics if the listener's resources are locked elsewhere
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let listener = PhantomListener::new(None)
+    ///     .await
+    ///     .with_response_cache(RelayCacheConfig::default().with_ttl(Duration::from_secs(5)));
+    /// ```
+    #[must_use]
+    pub fn with_response_cache(self, config: RelayCacheConfig) -> Self {
+        self.update_resources(|resources| resources.cache = Some(ResponseCache::new(config)))
+    }
+
+    /// Requires clients to authenticate with the relay itself before it accepts relay requests.
+    ///
+    /// This is separate from the credentials in each request, which the relay
+    /// uses to authenticate with the endpoint.
+    ///
+    /// # Arguments
+    ///
+    /// * `authenticator` - The authenticator clients of the relay must pass
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The configured listener instance
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let listener = PhantomListener::new(None).await.with_authenticator(
+    ///     Authenticator::new(AuthType::UserPassword).with_auth_fn(|user, pass| {
+    ///         Box::pin(async move {
+    ///             if user == "relay" && pass == "secret" {
+    ///                 Ok(())
+    ///             } else {
+    ///                 Err(Error::InvalidCredentials)
+    ///             }
+    ///         })
+    ///     }),
+    /// );
+    /// ```
+    #[must_use]
+    pub fn with_authenticator(mut self, authenticator: Authenticator) -> Self {
+        self.server = self.server.with_authenticator(authenticator);
+        self
+    }
+
+    /// Configures encryption between clients and the relay.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The encryption configuration to use
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The configured listener instance
+    #[must_use]
+    pub fn with_encryption_config(mut self, config: EncryptionConfig) -> Self {
+        self.server = self.server.with_encryption_config(config);
+        self
+    }
+
+    /// Configures the timeouts for connections to the relay.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeouts` - The timeout configuration to use
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The configured listener instance
+    #[must_use]
+    pub fn with_timeouts(mut self, timeouts: TimeoutConfig) -> Self {
+        self.server = self.server.with_timeouts(timeouts);
+        self
+    }
+
+    fn update_resources(self, update: impl FnOnce(&mut PhantomResources)) -> Self {
+        // Nothing else holds the resources before the listener runs
+        let resources = self.server.get_resources();
+        update(
+            &mut resources
+                .0
+                .try_write()
+                .expect("Phantom listener resources are in use"),
+        );
+        self
+    }
 }
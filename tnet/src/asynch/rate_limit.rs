@@ -0,0 +1,264 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    hash::Hash,
+    time::{Duration, Instant},
+};
+
+/// Configuration for the listener's per-IP connection rate limiter.
+///
+/// Each peer IP gets a token bucket holding up to `burst` tokens which refills
+/// at `per_ip_per_sec` tokens per second. Every accepted connection consumes
+/// one token; connections arriving while the bucket is empty are dropped
+/// before authentication.
+///
+/// # Fields
+///
+/// * `per_ip_per_sec` - Sustained number of connections allowed per second from one IP
+/// * `burst` - Maximum number of connections one IP may open in a quick burst
+///
+/// # Example
+///
+/// ```rust
+/// use tnet::asynch::rate_limit::RateLimitConfig;
+///
+/// let config = RateLimitConfig {
+///     per_ip_per_sec: 5,
+///     burst: 10,
+/// };
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimitConfig {
+    pub per_ip_per_sec: u32,
+    pub burst: u32,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            per_ip_per_sec: 10,
+            burst: 20,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl Bucket {
+    // Refills the bucket for the time since the last call, then takes a token if one is left
+    fn try_take(&mut self, now: Instant, rate: f64, burst: f64) -> bool {
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = elapsed.mul_add(rate, self.tokens).min(burst);
+        self.last_refill = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// A single token bucket, such as the message rate of one connection.
+///
+/// The bucket starts full with `burst` tokens and refills at `per_sec` tokens
+/// per second.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct TokenBucket {
+    rate: f64,
+    burst: f64,
+    bucket: Bucket,
+}
+
+impl TokenBucket {
+    pub(crate) fn new(per_sec: u32, burst: u32) -> Self {
+        let burst = f64::from(burst.max(1));
+        Self {
+            rate: f64::from(per_sec),
+            burst,
+            bucket: Bucket {
+                tokens: burst,
+                last_refill: Instant::now(),
+            },
+        }
+    }
+
+    /// Takes a token from the bucket.
+    ///
+    /// # Returns
+    ///
+    /// * `true` if a token was available, `false` if the caller should be throttled
+    pub(crate) fn try_acquire(&mut self) -> bool {
+        self.bucket.try_take(Instant::now(), self.rate, self.burst)
+    }
+}
+
+/// A collection of token buckets keyed by `K`.
+///
+/// Buckets are created lazily on first use and start full. Buckets that have
+/// refilled completely are pruned on a timer, and once `MAX_KEYS` are tracked
+/// the key tracked the longest makes room for a new one.
+///
+/// # Fields
+///
+/// * `order` - The tracked keys, oldest first
+/// * `last_prune` - When full buckets were last pruned
+#[derive(Debug)]
+pub(crate) struct TokenBuckets<K> {
+    rate: f64,
+    burst: f64,
+    buckets: HashMap<K, Bucket>,
+    order: VecDeque<K>,
+    last_prune: Instant,
+}
+
+impl<K: Eq + Hash + Clone> TokenBuckets<K> {
+    /// Most keys tracked at once.
+    pub(crate) const MAX_KEYS: usize = 65_536;
+
+    /// How often buckets that have refilled completely are pruned.
+    const PRUNE_INTERVAL: Duration = Duration::from_secs(1);
+
+    pub(crate) fn new(config: RateLimitConfig) -> Self {
+        Self {
+            rate: f64::from(config.per_ip_per_sec),
+            burst: f64::from(config.burst.max(1)),
+            buckets: HashMap::new(),
+            order: VecDeque::new(),
+            last_prune: Instant::now(),
+        }
+    }
+
+    /// Takes a token from the bucket for `key`.
+    ///
+    /// # Returns
+    ///
+    /// * `true` if a token was available, `false` if the caller should be throttled
+    pub(crate) fn try_acquire(&mut self, key: K) -> bool {
+        let now = Instant::now();
+
+        if now.duration_since(self.last_prune) >= Self::PRUNE_INTERVAL {
+            self.prune(now);
+        }
+
+        if !self.buckets.contains_key(&key) {
+            if self.buckets.len() >= Self::MAX_KEYS
+                && let Some(oldest) = self.order.pop_front()
+            {
+                self.buckets.remove(&oldest);
+            }
+            self.order.push_back(key.clone());
+        }
+
+        let bucket = self.buckets.entry(key).or_insert(Bucket {
+            tokens: self.burst,
+            last_refill: now,
+        });
+
+        bucket.try_take(now, self.rate, self.burst)
+    }
+
+    // Drops buckets that have refilled completely, a new bucket starts full anyway
+    fn prune(&mut self, now: Instant) {
+        let (rate, burst) = (self.rate, self.burst);
+        self.buckets.retain(|_, bucket| {
+            let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+            elapsed.mul_add(rate, bucket.tokens) < burst
+        });
+
+        let buckets = &self.buckets;
+        self.order.retain(|key| buckets.contains_key(key));
+        self.last_prune = now;
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct FailedAttempts {
+    count: u32,
+    window_start: Instant,
+    locked_until: Option<Instant>,
+}
+
+/// Failed authentication attempts keyed by `K`.
+///
+/// A key that fails `max_attempts` times within `window` is locked out for
+/// `cooldown`. A successful authentication forgets the key's failures.
+#[derive(Debug, Clone)]
+pub(crate) struct AuthLockout<K> {
+    max_attempts: u32,
+    window: Duration,
+    cooldown: Duration,
+    attempts: HashMap<K, FailedAttempts>,
+}
+
+impl<K: Eq + Hash> AuthLockout<K> {
+    /// Once this many keys are tracked, entries that are neither locked nor inside their window are pruned.
+    const PRUNE_THRESHOLD: usize = 1024;
+
+    pub(crate) fn new(max_attempts: u32, window: Duration, cooldown: Duration) -> Self {
+        Self {
+            max_attempts: max_attempts.max(1),
+            window,
+            cooldown,
+            attempts: HashMap::new(),
+        }
+    }
+
+    /// Checks whether `key` is currently locked out, clearing an expired lockout.
+    ///
+    /// # Returns
+    ///
+    /// * `true` if attempts from `key` should be rejected
+    pub(crate) fn is_locked(&mut self, key: &K) -> bool {
+        match self
+            .attempts
+            .get(key)
+            .and_then(|attempts| attempts.locked_until)
+        {
+            Some(until) if until > Instant::now() => true,
+            Some(_) => {
+                self.attempts.remove(key);
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// Records a failed attempt from `key`, locking it out once it reaches the limit.
+    pub(crate) fn record_failure(&mut self, key: K) {
+        let now = Instant::now();
+
+        if self.attempts.len() >= Self::PRUNE_THRESHOLD {
+            let window = self.window;
+            self.attempts.retain(|_, attempts| {
+                attempts.locked_until.is_some_and(|until| until > now)
+                    || now.duration_since(attempts.window_start) <= window
+            });
+        }
+
+        let attempts = self.attempts.entry(key).or_insert(FailedAttempts {
+            count: 0,
+            window_start: now,
+            locked_until: None,
+        });
+
+        if now.duration_since(attempts.window_start) > self.window {
+            attempts.count = 0;
+            attempts.window_start = now;
+        }
+
+        attempts.count += 1;
+        if attempts.count >= self.max_attempts {
+            attempts.locked_until = Some(now + self.cooldown);
+        }
+    }
+
+    /// Forgets the failures recorded for `key`.
+    pub(crate) fn record_success(&mut self, key: &K) {
+        self.attempts.remove(key);
+    }
+}
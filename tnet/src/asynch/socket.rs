@@ -1,21 +1,54 @@
-use std::{sync::Arc, vec::IntoIter};
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    },
+    time::Duration,
+    vec::IntoIter,
+};
 
+use bytes::Bytes;
+#[cfg(unix)]
+use tokio::net::UnixStream;
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
-    net::{
-        TcpStream,
-        tcp::{OwnedReadHalf, OwnedWriteHalf},
-    },
+    io::{AsyncRead, AsyncWrite, AsyncWriteExt, BufWriter, DuplexStream},
+    net::TcpStream,
     sync::{Mutex, RwLock},
 };
+#[cfg(feature = "websocket")]
+use tokio_tungstenite::WebSocketStream;
+use tracing::{debug, warn};
 
+use super::{
+    client::TimeoutConfig,
+    framing::{self, FrameReader},
+};
 use crate::{
+    compression::CompressionConfig,
     encrypt::Encryptor,
     errors::Error,
+    metrics::{Counter, Metrics, NoopMetrics, Observation},
     packet::Packet,
-    session::{self, Sessions},
+    session::{self, SessionStoreRef},
 };
 
+/// The outcome of broadcasting a packet to a collection of sockets.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BroadcastReport {
+    /// Number of sockets the packet was delivered to
+    pub delivered: usize,
+    /// Number of disconnected sockets removed from the collection
+    pub pruned: usize,
+}
+
+impl std::ops::AddAssign for BroadcastReport {
+    fn add_assign(&mut self, other: Self) {
+        self.delivered += other.delivered;
+        self.pruned += other.pruned;
+    }
+}
+
 /// A thread-safe collection of network sockets that can be shared across multiple tasks.
 ///
 /// `TSockets` provides a way to manage multiple socket connections in a thread-safe manner,
@@ -127,6 +160,57 @@ where
             .retain(|s| s.session_id != socket.session_id);
     }
 
+    /// Adds a connection, replacing any entry for the same session or connection.
+    ///
+    /// A session resumed on a new connection keeps a single entry.
+    pub(crate) async fn insert_connection(&self, socket: TSocket<S>) {
+        let mut sockets = self.sockets.write().await;
+        sockets.retain(|s| {
+            s.session_id != socket.session_id && !Arc::ptr_eq(&s.write_part, &socket.write_part)
+        });
+        sockets.push(socket);
+        drop(sockets);
+    }
+
+    /// Removes this exact connection from the collection.
+    ///
+    /// Unlike `remove`, other connections resumed on the same session are kept.
+    pub(crate) async fn remove_connection(&self, socket: &TSocket<S>) {
+        self.sockets
+            .write()
+            .await
+            .retain(|s| !Arc::ptr_eq(&s.write_part, &socket.write_part));
+    }
+
+    /// Removes the socket with the given session id from the collection.
+    ///
+    /// Useful when a handler only knows the session id, for example when kicking a user.
+    ///
+    /// # Arguments
+    ///
+    /// * `session_id`: The session id of the socket to remove
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - `true` if a socket was removed
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use tnet::socket::TSockets;
+    /// # async fn example(mut sockets: TSockets<Session>) {
+    /// sockets.remove_by_session_id("some-session-id").await;
+    /// # }
+    /// ```
+    pub async fn remove_by_session_id(&mut self, session_id: &str) -> bool {
+        let mut sockets = self.sockets.write().await;
+        let before = sockets.len();
+        sockets.retain(|s| s.session_id.as_deref() != Some(session_id));
+        let removed = sockets.len() != before;
+        drop(sockets);
+        removed
+    }
+
     /// Removes a batch of sockets from the collection.
     ///
     /// # Arguments
@@ -153,15 +237,67 @@ where
             .retain(|s| !ses_ids.contains(&s.session_id));
     }
 
+    /// Returns the number of sockets in the collection.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use tnet::socket::TSockets;
+    /// # async fn example(sockets: TSockets<Session>) {
+    /// println!("{} users online", sockets.len().await);
+    /// # }
+    /// ```
+    pub async fn len(&self) -> usize {
+        self.sockets.read().await.len()
+    }
+
+    /// Returns `true` if the collection holds no sockets.
+    pub async fn is_empty(&self) -> bool {
+        self.sockets.read().await.is_empty()
+    }
+
+    /// Checks whether a socket with the given session id is in the collection.
+    ///
+    /// # Arguments
+    ///
+    /// * `session_id`: The session id to look for
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use tnet::socket::TSockets;
+    /// # async fn example(sockets: TSockets<Session>) {
+    /// if sockets.contains("some-session-id").await {
+    ///     println!("Already joined");
+    /// }
+    /// # }
+    /// ```
+    pub async fn contains(&self, session_id: &str) -> bool {
+        self.sockets
+            .read()
+            .await
+            .iter()
+            .any(|s| s.session_id.as_deref() == Some(session_id))
+    }
+
     /// Broadcasts a packet to all connected sockets.
     ///
+    /// Sockets whose connection has closed are removed from the collection once the
+    /// broadcast finishes, so dead clients don't linger in a pool forever.
+    ///
     /// # Arguments
     ///
     /// * `packet`: The packet to broadcast to all connections
     ///
-    /// # Panics
+    /// # Returns
+    ///
+    /// * `Result<BroadcastReport, Error>` - How many sockets received the packet and
+    ///   how many disconnected sockets were pruned
+    ///
+    /// # Errors
     ///
-    /// This function will panic if sending to any socket fails
+    /// Returns `Error::Broadcast` if sending to a live socket fails. Sockets that
+    /// fail because their connection closed are pruned instead and don't cause an error
     ///
     /// # Example
     ///
@@ -172,41 +308,204 @@ where
     /// sockets.broadcast(packet).await;
     /// # }
     /// ```
-    pub async fn broadcast<P: Packet>(&self, packet: P) -> Result<(), Error> {
-        let errors = {
-            let mut errors = Vec::new();
-
-            // Get a copy of all the sockets we need to send to
-            let sockets_to_broadcast = {
-                let sockets = self.sockets.read().await;
-                sockets.clone()
-            };
-
-            // Explicitly mark as broadcast - this is crucial
-            let broadcast_packet = packet.set_broadcasting();
-
-            println!(
-                "DEBUG: Broadcasting packet: {:?} to {} sockets",
-                broadcast_packet.header(),
-                sockets_to_broadcast.len()
-            );
-
-            // Send to each socket
-            for mut socket in sockets_to_broadcast {
-                match socket.send(broadcast_packet.clone()).await {
-                    Ok(_) => println!("DEBUG: Successfully sent broadcast to a socket"),
-                    Err(e) => {
-                        errors.push(e);
-                        println!("DEBUG: Failed to send broadcast to a socket");
-                    }
-                }
+    pub async fn broadcast<P: Packet>(&self, packet: P) -> Result<BroadcastReport, Error> {
+        self.broadcast_preserialized(&PreparedBroadcast::new(packet))
+            .await
+    }
+
+    /// Broadcasts a packet that was already serialized to all connected sockets.
+    ///
+    /// The packet is not serialized again. Sockets sharing compression settings
+    /// and encryptor receive the same bytes, and the others only re-run their
+    /// own compression or encryption. Preparing the packet once lets it be sent
+    /// to several pools, or several times, without serializing it each time.
+    ///
+    /// # Arguments
+    ///
+    /// * `prepared`: The serialized packet to broadcast
+    ///
+    /// # Returns
+    ///
+    /// * `Result<BroadcastReport, Error>` - The delivery and pruning counts, as for `broadcast`
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Broadcast` if sending to a live socket fails
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use tnet::socket::{PreparedBroadcast, TSockets};
+    /// # use tnet::packet::Packet;
+    /// # async fn example<P: Packet>(sockets: &TSockets<Session>, packet: P) {
+    /// let prepared = PreparedBroadcast::new(packet);
+    /// sockets.broadcast_preserialized(&prepared).await;
+    /// # }
+    /// ```
+    pub async fn broadcast_preserialized(
+        &self,
+        prepared: &PreparedBroadcast,
+    ) -> Result<BroadcastReport, Error> {
+        self.broadcast_filtered(prepared, |_| true).await
+    }
+
+    /// Broadcasts a packet to every socket except `exclude`.
+    ///
+    /// Sockets are matched by `session_id`, the same way `remove` identifies them,
+    /// so a client never receives its own broadcast back.
+    ///
+    /// # Arguments
+    ///
+    /// * `exclude`: The socket to skip, usually the one that sent the original packet
+    /// * `packet`: The packet to broadcast
+    ///
+    /// # Returns
+    ///
+    /// * `Result<BroadcastReport, Error>` - The delivery and pruning counts, as for `broadcast`
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Broadcast` if sending to a live socket fails
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use tnet::socket::{TSockets, TSocket};
+    /// # use tnet::packet::Packet;
+    /// # async fn example<P: Packet>(sockets: &TSockets<Session>, sender: &TSocket<Session>, packet: P) {
+    /// sockets.broadcast_except(sender, packet).await;
+    /// # }
+    /// ```
+    pub async fn broadcast_except<P: Packet>(
+        &self,
+        exclude: &TSocket<S>,
+        packet: P,
+    ) -> Result<BroadcastReport, Error> {
+        self.broadcast_filtered(&PreparedBroadcast::new(packet), |s| {
+            s.session_id != exclude.session_id
+        })
+        .await
+    }
+
+    /// Broadcasts a packet to every socket whose session matches `pred`.
+    ///
+    /// The sockets are copied out of the pool before any session is loaded, so
+    /// the pool lock is never held across a session store call.
+    /// Sockets without a session are skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `pred`: Returns `true` for sessions that should receive the packet
+    /// * `packet`: The packet to broadcast
+    ///
+    /// # Returns
+    ///
+    /// * `Result<BroadcastReport, Error>` - The delivery and pruning counts, as for `broadcast`
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Broadcast` if sending to a live socket fails
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use tnet::socket::TSockets;
+    /// # use tnet::packet::Packet;
+    /// # async fn example<P: Packet>(sockets: &TSockets<Session>, packet: P) {
+    /// sockets.broadcast_where(|s| s.is_admin(), packet).await;
+    /// # }
+    /// ```
+    pub async fn broadcast_where<P: Packet, F: Fn(&S) -> bool + Sync>(
+        &self,
+        pred: F,
+        packet: P,
+    ) -> Result<BroadcastReport, Error> {
+        self.broadcast_where_preserialized(&pred, &PreparedBroadcast::new(packet))
+            .await
+    }
+
+    pub(crate) async fn broadcast_where_preserialized<F: Fn(&S) -> bool + Sync>(
+        &self,
+        pred: &F,
+        prepared: &PreparedBroadcast,
+    ) -> Result<BroadcastReport, Error> {
+        let snapshot = self.sockets.read().await.clone();
+
+        let mut matching = Vec::new();
+        for socket in snapshot {
+            if socket.get_session().await.is_some_and(|s| pred(&s)) {
+                matching.push(socket);
             }
+        }
+
+        self.broadcast_to_sockets(prepared, matching).await
+    }
 
-            errors
+    async fn broadcast_filtered(
+        &self,
+        prepared: &PreparedBroadcast,
+        include: impl Fn(&TSocket<S>) -> bool,
+    ) -> Result<BroadcastReport, Error> {
+        // Get a copy of all the sockets we need to send to
+        let sockets_to_broadcast = {
+            let sockets = self.sockets.read().await;
+            sockets
+                .iter()
+                .filter(|s| include(s))
+                .cloned()
+                .collect::<Vec<_>>()
         };
 
+        self.broadcast_to_sockets(prepared, sockets_to_broadcast)
+            .await
+    }
+
+    async fn broadcast_to_sockets(
+        &self,
+        prepared: &PreparedBroadcast,
+        sockets_to_broadcast: Vec<TSocket<S>>,
+    ) -> Result<BroadcastReport, Error> {
+        let mut report = BroadcastReport::default();
+        let mut errors = Vec::new();
+        let mut dead = Vec::new();
+        let mut encoder = BroadcastEncoder::new(prepared);
+
+        debug!(
+            header = %prepared.header(),
+            sockets = sockets_to_broadcast.len(),
+            "Broadcasting packet"
+        );
+
+        // Send to each socket
+        for socket in sockets_to_broadcast {
+            match socket.send_broadcast(&mut encoder).await {
+                Ok(_) => {
+                    report.delivered += 1;
+                    debug!(peer = %socket.addr, "Sent broadcast");
+                }
+                // The client has gone away, so drop it from the pool below
+                Err(Error::ConnectionClosed | Error::IoError(_)) => {
+                    debug!(peer = %socket.addr, "Socket disconnected during broadcast, pruning it");
+                    dead.push(socket.write_part);
+                }
+                Err(e) => {
+                    warn!(peer = %socket.addr, error = %e, "Failed to send broadcast");
+                    errors.push(e);
+                }
+            }
+        }
+
+        if !dead.is_empty() {
+            let mut sockets = self.sockets.write().await;
+            let before = sockets.len();
+            // Match the exact connection, since anonymous sockets share a `None` id
+            sockets.retain(|s| !dead.iter().any(|d| Arc::ptr_eq(d, &s.write_part)));
+            report.pruned = before - sockets.len();
+            drop(sockets);
+        }
+
         if errors.is_empty() {
-            Ok(())
+            Ok(report)
         } else {
             Err(Error::Broadcast(format!("Broadcast errors: {:?}", errors)))
         }
@@ -268,10 +567,236 @@ where
     }
 }
 
-/// A thread-safe wrapper around a TCP socket with session management and encryption capabilities.
+/// The read half of the stream underlying a `TSocket`.
+pub type SocketReader = Box<dyn AsyncRead + Send + Sync + Unpin>;
+
+/// The write half of the stream underlying a `TSocket`.
+pub type SocketWriter = Box<dyn AsyncWrite + Send + Sync + Unpin>;
+
+/// Size of the buffer a batching `TSocket` collects frames in.
+const BATCH_BUFFER_LEN: usize = 64 * 1024;
+
+/// Configuration for coalescing the writes of a `TSocket`.
+///
+/// Without batching every sent packet is flushed on its own. With it, packets
+/// are collected and flushed together once `max_batch` of them are waiting or
+/// `max_delay` has passed since the first one, trading a little latency for
+/// fewer syscalls and TCP segments when many small packets are sent.
+///
+/// # Fields
+///
+/// * `max_batch` - Number of waiting packets that triggers a flush
+/// * `max_delay` - Longest time a packet waits for the rest of its batch
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+/// use tnet::asynch::socket::BatchConfig;
+///
+/// let batching = BatchConfig {
+///     max_batch: 128,
+///     max_delay: Duration::from_millis(5),
+/// };
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatchConfig {
+    pub max_batch: usize,
+    pub max_delay: Duration,
+}
+
+impl Default for BatchConfig {
+    fn default() -> Self {
+        Self {
+            max_batch: 64,
+            max_delay: Duration::from_millis(2),
+        }
+    }
+}
+
+/// The unflushed packets of a batching `TSocket`, shared by its clones.
+///
+/// Only touched while the socket's writer is locked.
+#[derive(Debug)]
+struct WriteBatch {
+    config: BatchConfig,
+    pending: AtomicUsize,
+    // Bumped by every flush, so a delayed flush can tell its batch already went out
+    epoch: AtomicU64,
+}
+
+impl WriteBatch {
+    const fn new(config: BatchConfig) -> Self {
+        Self {
+            config,
+            pending: AtomicUsize::new(0),
+            epoch: AtomicU64::new(0),
+        }
+    }
+
+    // Flushes the writer and starts a new batch
+    async fn flush(&self, socket: &mut SocketWriter) -> std::io::Result<()> {
+        self.pending.store(0, Ordering::SeqCst);
+        self.epoch.fetch_add(1, Ordering::SeqCst);
+        socket.flush().await
+    }
+
+    /// Writes a frame into the batch, flushing if it is full.
+    ///
+    /// The first frame of a batch schedules a flush after `max_delay`, which is
+    /// skipped if the batch fills up first.
+    async fn write(
+        self: &Arc<Self>,
+        writer: &Arc<Mutex<SocketWriter>>,
+        socket: &mut SocketWriter,
+        data: &[u8],
+    ) -> std::io::Result<()> {
+        framing::write_frame_unflushed(socket, data).await?;
+
+        let pending = self.pending.fetch_add(1, Ordering::SeqCst) + 1;
+        if pending >= self.config.max_batch {
+            return self.flush(socket).await;
+        }
+
+        if pending == 1 {
+            let epoch = self.epoch.load(Ordering::SeqCst);
+            let batch = self.clone();
+            let writer = writer.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(batch.config.max_delay).await;
+                let mut socket = writer.lock().await;
+                if batch.epoch.load(Ordering::SeqCst) != epoch {
+                    return;
+                }
+                if let Err(e) = batch.flush(&mut socket).await {
+                    debug!(error = %e, "Failed to flush batched packets");
+                }
+            });
+        }
+        Ok(())
+    }
+}
+
+/// A broadcast packet serialized once so it can be sent to many sockets.
+///
+/// Every socket still applies its own compression and encryption, but they all
+/// start from the same serialized bytes, and sockets with identical settings
+/// share the finished frame.
+///
+/// # Example
+///
+/// ```rust
+/// use tnet::asynch::socket::PreparedBroadcast;
+///
+/// let prepared = PreparedBroadcast::new(packet);
+/// lobby.broadcast_preserialized(&prepared).await?;
+/// spectators.broadcast_preserialized(&prepared).await?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct PreparedBroadcast {
+    header: String,
+    data: Bytes,
+}
+
+impl PreparedBroadcast {
+    /// Marks a packet as a broadcast and serializes it.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - The packet to broadcast
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as `Packet::ser`
+    #[must_use]
+    pub fn new<P: Packet>(packet: P) -> Self {
+        let packet = packet.set_broadcasting();
+        Self {
+            header: packet.header(),
+            data: Bytes::from(packet.ser()),
+        }
+    }
+
+    /// Returns the header of the prepared packet.
+    #[must_use]
+    pub fn header(&self) -> &str {
+        &self.header
+    }
+
+    /// Returns the serialized packet, before compression and encryption.
+    #[must_use]
+    pub const fn serialized(&self) -> &Bytes {
+        &self.data
+    }
+}
+
+/// Produces the bytes each socket writes for a prepared broadcast.
+///
+/// Compressed payloads are kept per compression setting and ciphertext per
+/// encryptor, so each distinct encoding is only built once per broadcast.
+/// Only sockets holding clones of the same `Encryptor` share ciphertext, and
+/// since they also share its counter, their frames are ordered per encryptor
+/// rather than per socket.
+struct BroadcastEncoder<'a> {
+    prepared: &'a PreparedBroadcast,
+    compressed: Vec<(CompressionConfig, Bytes)>,
+    encrypted: Vec<(Encryptor, CompressionConfig, Bytes)>,
+}
+
+impl<'a> BroadcastEncoder<'a> {
+    const fn new(prepared: &'a PreparedBroadcast) -> Self {
+        Self {
+            prepared,
+            compressed: Vec::new(),
+            encrypted: Vec::new(),
+        }
+    }
+
+    /// Returns the payload compressed with `config`, before encryption.
+    fn compressed(&mut self, config: CompressionConfig) -> Result<Bytes, Error> {
+        if !config.is_enabled() {
+            return Ok(self.prepared.data.clone());
+        }
+
+        if let Some((_, data)) = self.compressed.iter().find(|(c, _)| *c == config) {
+            return Ok(data.clone());
+        }
+
+        let data = Bytes::from(config.compress(self.prepared.data.to_vec())?);
+        self.compressed.push((config, data.clone()));
+        Ok(data)
+    }
+
+    /// Returns the bytes to write to `socket`, given its compressed payload.
+    ///
+    /// Called with the socket's writer locked, see `TSocket::write_frame`.
+    fn seal_for<S: session::Session>(
+        &mut self,
+        socket: &TSocket<S>,
+        plaintext: Bytes,
+    ) -> Result<Bytes, Error> {
+        let encryptor = match &socket.encryptor {
+            Some(encryptor) => encryptor,
+            None => return Ok(plaintext),
+        };
+
+        if let Some((_, _, data)) = self.encrypted.iter().find(|(enc, config, _)| {
+            *config == socket.compression && enc.is_same_instance(encryptor)
+        }) {
+            return Ok(data.clone());
+        }
+
+        let data = Bytes::from(encryptor.encrypt(&plaintext)?.into_bytes());
+        self.encrypted
+            .push((encryptor.clone(), socket.compression, data.clone()));
+        Ok(data)
+    }
+}
+
+/// A thread-safe wrapper around a socket with session management and encryption capabilities.
 ///
-/// `TSocket` provides a high-level interface for handling TCP connections with integrated
-/// session management and optional encryption.
+/// `TSocket` provides a high-level interface for handling TCP (or, on unix, Unix domain
+/// socket) connections with integrated session management and optional encryption.
 ///
 /// # Type Parameters
 ///
@@ -295,12 +820,21 @@ pub struct TSocket<S>
 where
     S: session::Session,
 {
-    pub read_part: Arc<Mutex<OwnedReadHalf>>,
-    pub write_part: Arc<Mutex<OwnedWriteHalf>>,
+    pub read_part: Arc<Mutex<FrameReader<SocketReader>>>,
+    pub write_part: Arc<Mutex<SocketWriter>>,
     pub session_id: Option<String>,
     pub encryptor: Option<Encryptor>,
+    pub compression: CompressionConfig,
+    pub timeouts: TimeoutConfig,
+    pub reply_request_id: Option<u64>,
     pub addr: String,
-    sessions: Arc<RwLock<Sessions<S>>>,
+    max_packet_size: usize,
+    nodelay: Option<bool>,
+    sessions: SessionStoreRef<S>,
+    metrics: Arc<dyn Metrics>,
+    batch: Option<Arc<WriteBatch>>,
+    // Set once a packet is sent, shared by clones handed to the same handlers
+    replied: Option<Arc<AtomicBool>>,
 }
 
 impl<S> TSocket<S>
@@ -309,25 +843,143 @@ where
 {
     /// Creates a new `TSocket` instance.
     ///
+    /// If the peer has already disconnected its address can't be read, and the
+    /// socket reports no `peer_addr`. It then fails on first use like any closed
+    /// connection.
+    ///
     /// # Arguments
     ///
     /// * `socket`: The TCP stream to wrap
-    /// * `sessions`: The session manager
+    /// * `sessions`: The session store
     ///
     /// # Returns
     ///
     /// * A new `TSocket` instance
-    pub fn new(socket: TcpStream, sessions: Arc<RwLock<Sessions<S>>>) -> Self {
-        let addr = socket.peer_addr().unwrap().to_string();
+    pub fn new(socket: TcpStream, sessions: SessionStoreRef<S>) -> Self {
+        let addr = socket.peer_addr().map_or_else(
+            |e| {
+                debug!(error = %e, "Peer address unavailable, peer may have disconnected");
+                "unknown".to_string()
+            },
+            |addr| addr.to_string(),
+        );
+        let nodelay = socket.nodelay().ok();
         let (read, write) = socket.into_split();
 
+        let mut tsocket = Self::from_parts(Box::new(read), Box::new(write), addr, sessions);
+        tsocket.nodelay = nodelay;
+        tsocket
+    }
+
+    /// Creates a `TSocket` for a freshly accepted connection, using the peer
+    /// address reported by `accept` rather than asking the socket again.
+    pub(crate) fn accepted(
+        socket: TcpStream,
+        addr: SocketAddr,
+        sessions: SessionStoreRef<S>,
+    ) -> Self {
+        let nodelay = socket.nodelay().ok();
+        let (read, write) = socket.into_split();
+
+        let mut tsocket =
+            Self::from_parts(Box::new(read), Box::new(write), addr.to_string(), sessions);
+        tsocket.nodelay = nodelay;
+        tsocket
+    }
+
+    /// Creates a new `TSocket` instance over an upgraded WebSocket connection.
+    ///
+    /// Packets are framed as usual and carried in binary messages.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream`: The WebSocket stream, after the HTTP upgrade
+    /// * `addr`: The peer's address
+    /// * `sessions`: The session store
+    ///
+    /// # Returns
+    ///
+    /// * A new `TSocket` instance
+    #[cfg(feature = "websocket")]
+    pub fn new_ws(
+        stream: WebSocketStream<TcpStream>,
+        addr: SocketAddr,
+        sessions: SessionStoreRef<S>,
+    ) -> Self {
+        let nodelay = stream.get_ref().nodelay().ok();
+        let (read, write) = tokio::io::split(super::websocket::WsStream::new(stream));
+
+        let mut tsocket =
+            Self::from_parts(Box::new(read), Box::new(write), addr.to_string(), sessions);
+        tsocket.nodelay = nodelay;
+        tsocket
+    }
+
+    /// Creates a new `TSocket` instance over a Unix domain socket.
+    ///
+    /// # Arguments
+    ///
+    /// * `socket`: The Unix stream to wrap
+    /// * `sessions`: The session store
+    ///
+    /// # Returns
+    ///
+    /// * A new `TSocket` instance
+    #[cfg(unix)]
+    pub fn new_uds(socket: UnixStream, sessions: SessionStoreRef<S>) -> Self {
+        // Client ends are usually unnamed, so fall back to a generic address
+        let addr = socket
+            .peer_addr()
+            .ok()
+            .and_then(|addr| addr.as_pathname().map(|path| path.display().to_string()))
+            .unwrap_or_else(|| "unix".to_string());
+        let (read, write) = socket.into_split();
+
+        Self::from_parts(Box::new(read), Box::new(write), addr, sessions)
+    }
+
+    /// Creates a new `TSocket` instance over an in-memory stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream`: The listener's end of a `tokio::io::duplex` pipe
+    /// * `sessions`: The session store
+    ///
+    /// # Returns
+    ///
+    /// * A new `TSocket` instance
+    pub fn new_in_memory(stream: DuplexStream, sessions: SessionStoreRef<S>) -> Self {
+        let (read, write) = tokio::io::split(stream);
+
+        Self::from_parts(
+            Box::new(read),
+            Box::new(write),
+            "memory".to_string(),
+            sessions,
+        )
+    }
+
+    fn from_parts(
+        read: SocketReader,
+        write: SocketWriter,
+        addr: String,
+        sessions: SessionStoreRef<S>,
+    ) -> Self {
         Self {
-            read_part: Arc::new(Mutex::new(read)),
+            read_part: Arc::new(Mutex::new(FrameReader::new(read))),
             write_part: Arc::new(Mutex::new(write)),
             session_id: None,
             encryptor: None,
+            compression: CompressionConfig::default(),
+            timeouts: TimeoutConfig::default(),
+            reply_request_id: None,
             addr,
+            max_packet_size: framing::DEFAULT_MAX_FRAME_LEN,
+            nodelay: None,
             sessions,
+            metrics: Arc::new(NoopMetrics),
+            batch: None,
+            replied: None,
         }
     }
 
@@ -346,6 +998,97 @@ where
         self
     }
 
+    /// Enables payload compression on the socket.
+    ///
+    /// # Arguments
+    ///
+    /// * `compression`: The compression settings to use for sent and received packets
+    ///
+    /// # Returns
+    ///
+    /// * The modified `TSocket` instance
+    #[must_use]
+    pub const fn with_compression(mut self, compression: CompressionConfig) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Coalesces sent packets into fewer flushes.
+    ///
+    /// Sent packets are buffered and flushed together as described in
+    /// `BatchConfig`. Call this before the socket is cloned or used; the writer
+    /// of a socket that is already in use is left unbuffered.
+    ///
+    /// # Arguments
+    ///
+    /// * `config`: When to flush the collected packets
+    ///
+    /// # Returns
+    ///
+    /// * The modified `TSocket` instance
+    #[must_use]
+    pub fn with_batching(mut self, config: BatchConfig) -> Self {
+        // A socket that already batches keeps its buffer and only takes the new settings
+        if let (None, Ok(mut writer)) = (&self.batch, self.write_part.try_lock()) {
+            let inner = std::mem::replace(&mut *writer, Box::new(tokio::io::sink()));
+            *writer = Box::new(BufWriter::with_capacity(BATCH_BUFFER_LEN, inner));
+        }
+        self.batch = Some(Arc::new(WriteBatch::new(config)));
+        self
+    }
+
+    /// Configures the socket's timeouts.
+    ///
+    /// Only `TimeoutConfig::read` applies to a `TSocket`, bounding how long
+    /// `recv` waits for data.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeouts`: The timeout settings to use
+    ///
+    /// # Returns
+    ///
+    /// * The modified `TSocket` instance
+    #[must_use]
+    pub const fn with_timeouts(mut self, timeouts: TimeoutConfig) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    /// Limits the size of the packets the socket accepts.
+    ///
+    /// A peer announcing a larger packet makes `recv` fail with
+    /// `Error::PacketTooLarge` before any of the payload is buffered. The limit
+    /// applies from the next `recv`, even if another clone is receiving.
+    ///
+    /// # Arguments
+    ///
+    /// * `max`: Maximum packet size in bytes, as sent on the wire
+    ///
+    /// # Returns
+    ///
+    /// * The modified `TSocket` instance
+    #[must_use]
+    pub const fn with_max_packet_size(mut self, max: usize) -> Self {
+        self.max_packet_size = max;
+        self
+    }
+
+    /// Reports the socket's traffic to a metrics collector.
+    ///
+    /// # Arguments
+    ///
+    /// * `metrics`: The collector counting packets and bytes sent and received
+    ///
+    /// # Returns
+    ///
+    /// * The modified `TSocket` instance
+    #[must_use]
+    pub fn with_metrics(mut self, metrics: Arc<dyn Metrics>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
     /// Associates a session ID with the socket.
     ///
     /// # Arguments
@@ -361,6 +1104,59 @@ where
         self
     }
 
+    /// Returns the address of the connected peer.
+    ///
+    /// # Returns
+    ///
+    /// * The peer's socket address, or None for Unix domain socket connections,
+    ///   which have no network address
+    #[must_use]
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.addr.parse().ok()
+    }
+
+    /// Returns the IP address of the connected peer.
+    ///
+    /// # Returns
+    ///
+    /// * The peer's IP address, or None for Unix domain socket connections
+    #[must_use]
+    pub fn peer_ip(&self) -> Option<IpAddr> {
+        self.peer_addr().map(|addr| addr.ip())
+    }
+
+    /// Checks if packets on this connection are encrypted.
+    ///
+    /// # Returns
+    ///
+    /// * true once an encrypted handshake has completed, false otherwise
+    #[must_use]
+    pub const fn is_encrypted(&self) -> bool {
+        self.encryptor.is_some()
+    }
+
+    /// Checks if Nagle's algorithm was disabled on this connection.
+    ///
+    /// # Returns
+    ///
+    /// * The `TCP_NODELAY` setting of the connection when the socket was
+    ///   created, or None if it isn't a TCP connection
+    #[must_use]
+    pub const fn nodelay(&self) -> Option<bool> {
+        self.nodelay
+    }
+
+    /// Starts recording whether a packet is sent through this socket or its clones.
+    ///
+    /// # Returns
+    ///
+    /// * A flag that is set once `send` succeeds
+    pub(crate) fn track_replies(&mut self) -> Arc<AtomicBool> {
+        let replied = Arc::new(AtomicBool::new(false));
+        self.replied = Some(replied.clone());
+        replied
+    }
+
     /// Retrieves the current session associated with this socket.
     ///
     /// # Returns
@@ -368,14 +1164,14 @@ where
     /// * An Option containing the current session if it exists
     pub async fn get_session(&self) -> Option<S> {
         if let Some(id) = &self.session_id {
-            let sessions = self.sessions.read().await;
-            sessions.get_session(id).cloned()
+            self.sessions.load(id).await.ok().flatten()
         } else {
             None
         }
     }
 
-    /// Updates the current session using the provided function.
+    /// Updates the current session using the provided function and saves it back
+    /// to the session store.
     ///
     /// # Arguments
     ///
@@ -388,16 +1184,72 @@ where
     /// # Errors
     ///
     /// Returns `Error::InvalidSessionId` if no session ID is set or if the session ID is invalid
+    /// Returns `Error::SessionStore` if the session store cannot be read or written
     pub async fn update_session<F, T>(&self, f: F) -> Result<T, Error>
     where
         F: FnOnce(&mut S) -> T + Send,
     {
         if let Some(id) = &self.session_id {
-            let mut sessions = self.sessions.write().await;
-            sessions.get_session_mut(id).map_or_else(
-                || Err(Error::InvalidSessionId(id.clone())),
-                |session| Ok(f(session)),
-            )
+            let mut session = self
+                .sessions
+                .load(id)
+                .await?
+                .ok_or_else(|| Error::InvalidSessionId(id.clone()))?;
+            let result = f(&mut session);
+            self.sessions.save(session).await?;
+            Ok(result)
+        } else {
+            Err(Error::InvalidSessionId("No session ID".to_string()))
+        }
+    }
+
+    /// Retrieves a metadata value stored on the current session.
+    ///
+    /// # Arguments
+    ///
+    /// * `key`: The metadata key
+    ///
+    /// # Returns
+    ///
+    /// * An Option containing the value if the current session has one for `key`
+    pub async fn get_meta(&self, key: &str) -> Option<String> {
+        if let Some(id) = &self.session_id {
+            self.sessions.get_meta(id, key).await.ok().flatten()
+        } else {
+            None
+        }
+    }
+
+    /// Returns the username the current session authenticated with.
+    ///
+    /// Only sessions created through username and password authentication have
+    /// one; the listener records it under `session::USERNAME_META_KEY`.
+    ///
+    /// # Returns
+    ///
+    /// * An Option containing the authenticated username
+    pub async fn username(&self) -> Option<String> {
+        self.get_meta(session::USERNAME_META_KEY).await
+    }
+
+    /// Stores a metadata value on the current session.
+    ///
+    /// Metadata lives alongside the session in the listener's session store, so it
+    /// is visible to every later packet on the same session without needing a
+    /// custom `Session` type.
+    ///
+    /// # Arguments
+    ///
+    /// * `key`: The metadata key
+    /// * `value`: The value to store
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::InvalidSessionId` if no session ID is set or if the session ID is invalid
+    /// Returns `Error::SessionStore` if the session store cannot be written
+    pub async fn set_meta(&self, key: &str, value: impl Into<String>) -> Result<(), Error> {
+        if let Some(id) = &self.session_id {
+            self.sessions.set_meta(id, key, value.into()).await
         } else {
             Err(Error::InvalidSessionId("No session ID".to_string()))
         }
@@ -405,6 +1257,9 @@ where
 
     /// Sends a packet through the socket, with optional encryption.
     ///
+    /// If `reply_request_id` is set, non-broadcast packets without a request id
+    /// are stamped with it so the client can match them to its request.
+    ///
     /// # Arguments
     ///
     /// * `packet`: The packet to send
@@ -416,34 +1271,55 @@ where
     /// # Errors
     ///
     /// Returns `Error::IoError` if writing to the socket fails
-    pub async fn send<P: Packet>(&mut self, packet: P) -> Result<(), Error> {
-        let data = self
-            .encryptor
-            .as_ref()
-            .map_or_else(|| packet.ser(), |encryptor| packet.encrypted_ser(encryptor));
-        let header = packet.header();
-        let mut socket = self
-            .write_part
-            .try_lock()
-            .map_err(|e| {
-                panic!("PacketHeader-{header} ::: Socket lock held esle where. \n \n {e} \n")
-            })
-            .unwrap();
-
-        socket
-            .write_all(&data)
-            .await
-            .map_err(|e| Error::IoError(e.to_string()))?;
-        socket
-            .flush()
+    pub async fn send<P: Packet>(&mut self, mut packet: P) -> Result<(), Error> {
+        if let Some(id) = self.reply_request_id {
+            let body = packet.body_mut();
+            if body.request_id.is_none() && !body.is_broadcast_packet.unwrap_or(false) {
+                body.request_id = Some(id);
+            }
+        }
+
+        let plaintext = Bytes::from(self.compression.encode(&packet, None));
+        self.write_encoded(|| self.seal(plaintext)).await?;
+
+        if let Some(replied) = &self.replied {
+            replied.store(true, Ordering::SeqCst);
+        }
+        Ok(())
+    }
+
+    /// Sends a prepared broadcast, reusing any encoding shared with other sockets.
+    async fn send_broadcast(&self, encoder: &mut BroadcastEncoder<'_>) -> Result<(), Error> {
+        let plaintext = encoder.compressed(self.compression)?;
+        self.write_encoded(|| encoder.seal_for(self, plaintext))
             .await
-            .map_err(|e| Error::IoError(e.to_string()))?;
-        drop(socket);
+    }
+
+    /// Encrypts an encoded packet for the peer, if the connection is encrypted.
+    fn seal(&self, data: Bytes) -> Result<Bytes, Error> {
+        match &self.encryptor {
+            Some(encryptor) => Ok(Bytes::from(encryptor.encrypt(&data)?.into_bytes())),
+            None => Ok(data),
+        }
+    }
+
+    /// Writes an encoded packet as a single frame and counts it as sent.
+    async fn write_encoded(
+        &self,
+        seal: impl FnOnce() -> Result<Bytes, Error>,
+    ) -> Result<(), Error> {
+        let len = self.write_frame(seal).await?;
+
+        self.metrics.increment(Counter::PacketsSent);
+        self.metrics.observe(Observation::BytesSent, len as u64);
         Ok(())
     }
 
     /// Receives a packet from the socket, with optional decryption.
     ///
+    /// Reading and writing are locked separately, so broadcasts and replies sent
+    /// through clones of this socket are not held up by a pending `recv`.
+    ///
     /// # Returns
     ///
     /// * A Result containing the received packet or an error
@@ -452,23 +1328,20 @@ where
     ///
     /// Returns `Error::IoError` if reading from the socket fails
     /// Returns `Error::ConnectionClosed` if the connection is closed
+    /// Returns `Error::PacketTooLarge` if the peer announces a packet over the size limit
+    /// Returns `Error::Compression` if a compressed payload cannot be decompressed
     pub async fn recv<P: Packet>(&mut self) -> Result<P, Error> {
-        let mut buf = vec![0; 4096];
-        let n = {
-            let mut socket = self
-                .read_part
-                .try_lock()
-                .map_err(|e| panic!("Recv Socket lock held esle where. \n \n {e} \n"))
-                .unwrap();
-
-            // Set up a timeout to prevent holding the lock for too long
-            match tokio::time::timeout(std::time::Duration::from_secs(1), socket.read(&mut buf))
-                .await
-            {
+        let frame = {
+            let mut socket = self.read_part.lock().await;
+            socket.set_max_frame_len(self.max_packet_size);
+
+            // Set up a timeout to prevent holding the lock for too long. A partially
+            // read frame stays buffered for the next call.
+            match tokio::time::timeout(self.timeouts.read, socket.read_frame()).await {
                 Ok(res) => {
-                    let n = res.map_err(|e| Error::IoError(e.to_string()))?;
+                    let frame = res.map_err(|e| framing::read_error(&e))?;
                     drop(socket);
-                    n
+                    frame
                 }
                 Err(_) => {
                     drop(socket);
@@ -477,19 +1350,16 @@ where
             }
         };
 
-        if n == 0 {
-            return Err(Error::ConnectionClosed);
-        }
+        let frame = frame.ok_or(Error::ConnectionClosed)?;
+        self.metrics.increment(Counter::PacketsReceived);
+        self.metrics
+            .observe(Observation::BytesReceived, frame.len() as u64);
 
-        buf.truncate(n);
-
-        Ok(self
-            .encryptor
-            .as_ref()
-            .map_or_else(|| P::de(&buf), |encryptor| P::encrypted_de(&buf, encryptor)))
+        self.compression
+            .decode_limited(&frame, self.encryptor.as_ref(), self.max_packet_size)
     }
 
-    /// Sends raw data through the socket.
+    /// Sends raw data through the socket as a single frame.
     ///
     /// # Arguments
     ///
@@ -503,20 +1373,37 @@ where
     ///
     /// Returns `Error::IoError` if writing to the socket fails
     pub async fn send_raw(&mut self, packet: Vec<u8>) -> Result<(), Error> {
+        self.write_frame(|| Ok(Bytes::from(packet))).await?;
+        Ok(())
+    }
+
+    /// Writes a single frame, flushing it now or with its batch.
+    ///
+    /// `seal` produces the frame once the writer is locked. Encrypting there
+    /// assigns counters in write order, so concurrent senders can't push a
+    /// frame out behind more than a replay window of newer ones.
+    ///
+    /// # Returns
+    ///
+    /// * The length of the written frame
+    async fn write_frame(
+        &self,
+        seal: impl FnOnce() -> Result<Bytes, Error>,
+    ) -> Result<usize, Error> {
+        // Concurrent senders on cloned sockets queue up behind each other here
         let mut socket = self.write_part.lock().await;
-        socket
-            .write_all(&packet)
-            .await
-            .map_err(|e| Error::IoError(e.to_string()))?;
-        socket
-            .flush()
-            .await
-            .map_err(|e| Error::IoError(e.to_string()))?;
+        let data = seal()?;
+
+        match &self.batch {
+            Some(batch) => batch.write(&self.write_part, &mut socket, &data).await,
+            None => framing::write_frame(&mut *socket, &data).await,
+        }
+        .map_err(|e| Error::IoError(e.to_string()))?;
         drop(socket);
-        Ok(())
+        Ok(data.len())
     }
 
-    /// Receives raw data from the socket.
+    /// Receives a single frame of raw data from the socket.
     ///
     /// # Returns
     ///
@@ -526,25 +1413,20 @@ where
     ///
     /// Returns `Error::IoError` if reading from the socket fails
     /// Returns `Error::ConnectionClosed` if the connection is closed
+    /// Returns `Error::PacketTooLarge` if the peer announces a packet over the size limit
     pub async fn recv_raw(&mut self) -> Result<Vec<u8>, Error> {
-        let mut buf = vec![0; 4096];
-        let n = {
+        let frame = {
             let mut socket = self.read_part.lock().await;
+            socket.set_max_frame_len(self.max_packet_size);
             let res = socket
-                .read(&mut buf)
+                .read_frame()
                 .await
-                .map_err(|e| Error::IoError(e.to_string()))?;
+                .map_err(|e| framing::read_error(&e))?;
             drop(socket);
             res
         };
 
-        if n == 0 {
-            return Err(Error::ConnectionClosed);
-        }
-
-        buf.truncate(n);
-
-        Ok(buf)
+        frame.map(Vec::from).ok_or(Error::ConnectionClosed)
     }
 }
 
@@ -574,12 +1456,13 @@ pub trait BroadcastExt<S: session::Session> {
 impl<S: session::Session> BroadcastExt<S> for (TSocket<S>, TSocket<S>) {
     async fn broadcast<P: Packet>(&self, packet: P) -> Result<(), Error> {
         let mut errors = Vec::new();
-        let packet = packet.set_broadcasting();
+        let prepared = PreparedBroadcast::new(packet);
+        let mut encoder = BroadcastEncoder::new(&prepared);
 
-        if let Err(e) = self.0.clone().send(packet.clone()).await {
+        if let Err(e) = self.0.send_broadcast(&mut encoder).await {
             errors.push(e);
         }
-        if let Err(e) = self.1.clone().send(packet).await {
+        if let Err(e) = self.1.send_broadcast(&mut encoder).await {
             errors.push(e);
         }
 
@@ -597,15 +1480,16 @@ impl<S: session::Session> BroadcastExt<S> for (TSocket<S>, TSocket<S>) {
 impl<S: session::Session> BroadcastExt<S> for (TSocket<S>, TSocket<S>, TSocket<S>) {
     async fn broadcast<P: Packet>(&self, packet: P) -> Result<(), Error> {
         let mut errors = Vec::new();
-        let packet = packet.set_broadcasting();
+        let prepared = PreparedBroadcast::new(packet);
+        let mut encoder = BroadcastEncoder::new(&prepared);
 
-        if let Err(e) = self.0.clone().send(packet.clone()).await {
+        if let Err(e) = self.0.send_broadcast(&mut encoder).await {
             errors.push(e);
         }
-        if let Err(e) = self.1.clone().send(packet.clone()).await {
+        if let Err(e) = self.1.send_broadcast(&mut encoder).await {
             errors.push(e);
         }
-        if let Err(e) = self.2.clone().send(packet).await {
+        if let Err(e) = self.2.send_broadcast(&mut encoder).await {
             errors.push(e);
         }
 
@@ -623,12 +1507,13 @@ impl<S: session::Session> BroadcastExt<S> for (TSocket<S>, TSocket<S>, TSocket<S
 impl<S: session::Session> BroadcastExt<S> for (&TSocket<S>, &TSocket<S>) {
     async fn broadcast<P: Packet>(&self, packet: P) -> Result<(), Error> {
         let mut errors = Vec::new();
-        let packet = packet.set_broadcasting();
+        let prepared = PreparedBroadcast::new(packet);
+        let mut encoder = BroadcastEncoder::new(&prepared);
 
-        if let Err(e) = self.0.clone().send(packet.clone()).await {
+        if let Err(e) = self.0.send_broadcast(&mut encoder).await {
             errors.push(e);
         }
-        if let Err(e) = self.1.clone().send(packet).await {
+        if let Err(e) = self.1.send_broadcast(&mut encoder).await {
             errors.push(e);
         }
 
@@ -646,15 +1531,16 @@ impl<S: session::Session> BroadcastExt<S> for (&TSocket<S>, &TSocket<S>) {
 impl<S: session::Session> BroadcastExt<S> for (&TSocket<S>, &TSocket<S>, &TSocket<S>) {
     async fn broadcast<P: Packet>(&self, packet: P) -> Result<(), Error> {
         let mut errors = Vec::new();
-        let packet = packet.set_broadcasting();
+        let prepared = PreparedBroadcast::new(packet);
+        let mut encoder = BroadcastEncoder::new(&prepared);
 
-        if let Err(e) = self.0.clone().send(packet.clone()).await {
+        if let Err(e) = self.0.send_broadcast(&mut encoder).await {
             errors.push(e);
         }
-        if let Err(e) = self.1.clone().send(packet.clone()).await {
+        if let Err(e) = self.1.send_broadcast(&mut encoder).await {
             errors.push(e);
         }
-        if let Err(e) = self.2.clone().send(packet).await {
+        if let Err(e) = self.2.send_broadcast(&mut encoder).await {
             errors.push(e);
         }
 
@@ -672,10 +1558,11 @@ impl<S: session::Session> BroadcastExt<S> for (&TSocket<S>, &TSocket<S>, &TSocke
 impl<S: session::Session> BroadcastExt<S> for &[TSocket<S>] {
     async fn broadcast<P: Packet>(&self, packet: P) -> Result<(), Error> {
         let mut errors = Vec::new();
-        let packet = packet.set_broadcasting();
+        let prepared = PreparedBroadcast::new(packet);
+        let mut encoder = BroadcastEncoder::new(&prepared);
 
         for socket in self.iter() {
-            if let Err(e) = socket.clone().send(packet.clone()).await {
+            if let Err(e) = socket.send_broadcast(&mut encoder).await {
                 errors.push(e);
             }
         }
@@ -694,10 +1581,11 @@ impl<S: session::Session> BroadcastExt<S> for &[TSocket<S>] {
 impl<S: session::Session> BroadcastExt<S> for [TSocket<S>] {
     async fn broadcast<P: Packet>(&self, packet: P) -> Result<(), Error> {
         let mut errors = Vec::new();
-        let packet = packet.set_broadcasting();
+        let prepared = PreparedBroadcast::new(packet);
+        let mut encoder = BroadcastEncoder::new(&prepared);
 
         for socket in self.iter() {
-            if let Err(e) = socket.clone().send(packet.clone()).await {
+            if let Err(e) = socket.send_broadcast(&mut encoder).await {
                 errors.push(e);
             }
         }
@@ -716,18 +1604,17 @@ impl<S: session::Session> BroadcastExt<S> for [TSocket<S>] {
 impl<S: session::Session> BroadcastExt<S> for [&TSocket<S>] {
     async fn broadcast<P: Packet>(&self, packet: P) -> Result<(), Error> {
         let mut errors = Vec::new();
-        let packet = packet.set_broadcasting();
+        let prepared = PreparedBroadcast::new(packet);
+        let mut encoder = BroadcastEncoder::new(&prepared);
 
-        let mut socket_idx = 0;
         for socket in self {
-            socket_idx += 1;
             let sock = *socket;
 
-            println!("Sending for socket {}", socket_idx);
-            if let Err(e) = sock.clone().send(packet.clone()).await {
+            debug!(peer = %sock.addr, "Sending broadcast");
+            if let Err(e) = sock.send_broadcast(&mut encoder).await {
+                warn!(peer = %sock.addr, error = %e, "Failed to send broadcast");
                 errors.push(e);
             }
-            println!("Sent for socket {}", socket_idx);
         }
 
         if errors.is_empty() {
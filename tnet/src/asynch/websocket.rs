@@ -0,0 +1,208 @@
+//! WebSocket transport, so browser clients can talk to a tnet server.
+//!
+//! After the HTTP upgrade, tnet frames travel inside WebSocket binary messages.
+//! Every flush sends whatever was written since the last one as a single
+//! message, so a message carries one or more complete length-prefixed frames.
+//! Readers treat the payloads as one continuous byte stream, which means the
+//! usual `FrameReader` works unchanged and encryption, compression and
+//! authentication still happen at the packet layer.
+
+use std::{
+    io,
+    net::SocketAddr,
+    pin::Pin,
+    task::{Context, Poll, ready},
+    time::Duration,
+};
+
+use bytes::{Buf, Bytes, BytesMut};
+use futures::{Sink, Stream};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::{TcpListener, TcpStream},
+    sync::{Mutex, mpsc},
+};
+use tokio_tungstenite::{
+    WebSocketStream,
+    tungstenite::{self, Message},
+};
+use tracing::{debug, warn};
+
+/// How many upgraded connections may wait for `accept` before upgrades stall.
+const UPGRADED_QUEUE_LEN: usize = 64;
+
+/// A TCP listener that performs the WebSocket upgrade for every connection.
+///
+/// Upgrades run on their own tasks, so a client that is slow to send its HTTP
+/// request doesn't hold up the connections behind it.
+pub struct WsListener {
+    listener: TcpListener,
+    upgraded_tx: mpsc::Sender<(WebSocketStream<TcpStream>, SocketAddr)>,
+    upgraded_rx: Mutex<mpsc::Receiver<(WebSocketStream<TcpStream>, SocketAddr)>>,
+}
+
+impl WsListener {
+    /// Creates a new `WsListener` accepting on an already bound TCP listener.
+    ///
+    /// # Arguments
+    ///
+    /// * `listener` - The bound TCP listener
+    ///
+    /// # Returns
+    ///
+    /// * A new `WsListener` instance
+    #[must_use]
+    pub fn new(listener: TcpListener) -> Self {
+        let (upgraded_tx, upgraded_rx) = mpsc::channel(UPGRADED_QUEUE_LEN);
+        Self {
+            listener,
+            upgraded_tx,
+            upgraded_rx: Mutex::new(upgraded_rx),
+        }
+    }
+
+    /// Returns the address the listener is bound to.
+    ///
+    /// # Returns
+    ///
+    /// * The bound socket address, or None if it can't be determined
+    #[must_use]
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.listener.local_addr().ok()
+    }
+
+    /// Waits for the next connection that completed the WebSocket upgrade.
+    ///
+    /// # Arguments
+    ///
+    /// * `nodelay` - `TCP_NODELAY` setting for accepted sockets, if any
+    /// * `handshake_timeout` - How long a client may take to finish the upgrade
+    ///
+    /// # Returns
+    ///
+    /// * The upgraded stream and the peer's address
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if accepting a TCP connection fails
+    pub(crate) async fn accept(
+        &self,
+        nodelay: Option<bool>,
+        handshake_timeout: Duration,
+    ) -> io::Result<(WebSocketStream<TcpStream>, SocketAddr)> {
+        loop {
+            tokio::select! {
+                accepted = self.listener.accept() => {
+                    let (socket, addr) = accepted?;
+                    if let Err(e) = nodelay.map_or(Ok(()), |nodelay| socket.set_nodelay(nodelay)) {
+                        warn!(peer = %addr, error = %e, "Failed to set TCP_NODELAY");
+                    }
+                    let upgraded_tx = self.upgraded_tx.clone();
+                    tokio::spawn(async move {
+                        match tokio::time::timeout(
+                            handshake_timeout,
+                            tokio_tungstenite::accept_async(socket),
+                        )
+                        .await
+                        {
+                            Ok(Ok(stream)) => {
+                                let _ = upgraded_tx.send((stream, addr)).await;
+                            }
+                            Ok(Err(e)) => {
+                                debug!(peer = %addr, error = %e, "WebSocket upgrade failed");
+                            }
+                            Err(_) => debug!(peer = %addr, "WebSocket upgrade timed out"),
+                        }
+                    });
+                }
+                // The listener holds a sender, so the channel never closes
+                Some(upgraded) = async { self.upgraded_rx.lock().await.recv().await } => {
+                    return Ok(upgraded);
+                }
+            }
+        }
+    }
+}
+
+/// Byte stream over the binary messages of a WebSocket connection.
+///
+/// Writes are buffered until `poll_flush`, which sends them as one binary
+/// message. Text messages are ignored, and a close message reads as the end of
+/// the stream.
+pub(crate) struct WsStream<T> {
+    inner: WebSocketStream<T>,
+    read_buf: Bytes,
+    write_buf: BytesMut,
+}
+
+impl<T> WsStream<T> {
+    pub(crate) fn new(inner: WebSocketStream<T>) -> Self {
+        Self {
+            inner,
+            read_buf: Bytes::new(),
+            write_buf: BytesMut::new(),
+        }
+    }
+}
+
+fn ws_error(error: tungstenite::Error) -> io::Error {
+    match error {
+        tungstenite::Error::Io(e) => e,
+        tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed => {
+            io::ErrorKind::BrokenPipe.into()
+        }
+        e => io::Error::other(e),
+    }
+}
+
+impl<T: AsyncRead + AsyncWrite + Unpin> AsyncRead for WsStream<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        while self.read_buf.is_empty() {
+            match ready!(Pin::new(&mut self.inner).poll_next(cx)) {
+                Some(Ok(Message::Binary(payload))) => self.read_buf = payload,
+                Some(Ok(Message::Close(_)) | Err(tungstenite::Error::ConnectionClosed)) | None => {
+                    return Poll::Ready(Ok(()));
+                }
+                // Pings are answered by tungstenite itself
+                Some(Ok(_)) => {}
+                Some(Err(e)) => return Poll::Ready(Err(ws_error(e))),
+            }
+        }
+
+        let len = self.read_buf.len().min(buf.remaining());
+        buf.put_slice(&self.read_buf[..len]);
+        self.read_buf.advance(len);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<T: AsyncRead + AsyncWrite + Unpin> AsyncWrite for WsStream<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.write_buf.extend_from_slice(buf);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if !self.write_buf.is_empty() {
+            ready!(Pin::new(&mut self.inner).poll_ready(cx)).map_err(ws_error)?;
+            let payload = self.write_buf.split().freeze();
+            Pin::new(&mut self.inner)
+                .start_send(Message::Binary(payload))
+                .map_err(ws_error)?;
+        }
+        Pin::new(&mut self.inner).poll_flush(cx).map_err(ws_error)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        ready!(self.as_mut().poll_flush(cx))?;
+        Pin::new(&mut self.inner).poll_close(cx).map_err(ws_error)
+    }
+}
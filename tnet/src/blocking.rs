@@ -0,0 +1,195 @@
+//! A synchronous client for code that doesn't run on an async runtime.
+//!
+//! [`BlockingClient`] wraps an [`AsyncClient`] together with its own
+//! current-thread Tokio runtime and blocks on every call, so CLI tools and
+//! plugins can talk to a tnet server without becoming async themselves.
+//!
+//! The client must not be used from within an async context: creating,
+//! calling or dropping it on a runtime thread panics. Because the runtime only
+//! runs while a call is blocking, background work such as keep-alives and
+//! broadcast processing pauses between calls.
+
+use std::future::Future;
+
+use tokio::runtime::{Builder, Runtime};
+
+use crate::{
+    asynch::client::{AsyncClient, EncryptionConfig},
+    errors::Error,
+    packet::Packet,
+};
+
+/// A blocking wrapper around [`AsyncClient`].
+///
+/// # Example
+///
+/// ```rust
+/// use tnet::blocking::BlockingClient;
+///
+/// let mut client = BlockingClient::<MyPacket>::new("127.0.0.1", 8080)?
+///     .configure(|client| client.with_credentials("user", "pass"));
+/// client.finalize();
+///
+/// let response = client.send_recv(MyPacket::ok())?;
+/// ```
+pub struct BlockingClient<P: Packet> {
+    client: AsyncClient<P>,
+    runtime: Runtime,
+}
+
+impl<P: Packet> BlockingClient<P> {
+    /// Connects to a server, blocking until the connection is established.
+    ///
+    /// # Arguments
+    ///
+    /// * `ip` - Server IP address or hostname
+    /// * `port` - Server port number
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Self, Error>` - The connected client or an error
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the runtime cannot be created or the connection fails
+    ///
+    /// # Panics
+    ///
+    /// Panics if called from within an async context.
+    pub fn new(ip: &str, port: u16) -> Result<Self, Error> {
+        let runtime = Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|e| Error::IoError(e.to_string()))?;
+        let client = runtime.block_on(AsyncClient::new(ip, port))?;
+
+        Ok(Self { client, runtime })
+    }
+
+    /// Applies the `AsyncClient` builder methods to the wrapped client.
+    ///
+    /// # Arguments
+    ///
+    /// * `configure` - Takes the wrapped client and returns it configured
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let client = BlockingClient::<MyPacket>::new("127.0.0.1", 8080)?
+    ///     .configure(|client| client.with_token("secret"));
+    /// ```
+    #[must_use]
+    pub fn configure<F>(self, configure: F) -> Self
+    where
+        F: FnOnce(AsyncClient<P>) -> AsyncClient<P>,
+    {
+        Self {
+            client: configure(self.client),
+            runtime: self.runtime,
+        }
+    }
+
+    /// Sets up encryption, blocking until key exchange and authentication finish.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The encryption configuration
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Self, Error>` - The configured client or an error
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of `AsyncClient::with_encryption_config`
+    pub fn with_encryption_config(self, config: EncryptionConfig) -> Result<Self, Error> {
+        let client = self
+            .runtime
+            .block_on(self.client.with_encryption_config(config))?;
+
+        Ok(Self {
+            client,
+            runtime: self.runtime,
+        })
+    }
+
+    /// Finalizes the client setup, blocking until the server answers.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as `AsyncClient::finalize`.
+    pub fn finalize(&mut self)
+    where
+        P: 'static,
+    {
+        self.block_on(|client| client.finalize());
+    }
+
+    /// Sends a packet, blocking until it has been written to the connection.
+    ///
+    /// The runtime stops between calls, so waiting only for the packet to be
+    /// queued could leave it unsent until the next call.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - The packet to send
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), Error>` - Success or failure of the send operation
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of `AsyncClient::send` and `AsyncClient::flush`
+    pub fn send(&mut self, packet: P) -> Result<(), Error> {
+        self.block_on(|client| async move {
+            client.send(packet).await?;
+            client.flush().await
+        })
+    }
+
+    /// Receives a packet, blocking until one arrives or the receive times out.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<P, Error>` - The received packet or an error
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of `AsyncClient::recv`
+    pub fn recv(&mut self) -> Result<P, Error> {
+        self.block_on(AsyncClient::recv)
+    }
+
+    /// Sends a packet and blocks until its response arrives.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - The packet to send
+    ///
+    /// # Returns
+    ///
+    /// * `Result<P, Error>` - The response packet or an error
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of `AsyncClient::send_recv`
+    pub fn send_recv(&mut self, packet: P) -> Result<P, Error> {
+        self.block_on(|client| client.send_recv(packet))
+    }
+
+    /// Returns the wrapped client.
+    #[must_use]
+    pub const fn client(&self) -> &AsyncClient<P> {
+        &self.client
+    }
+
+    /// Runs one of the wrapped client's async methods to completion.
+    fn block_on<'a, F, Fut>(&'a mut self, call: F) -> Fut::Output
+    where
+        F: FnOnce(&'a mut AsyncClient<P>) -> Fut,
+        Fut: Future + 'a,
+    {
+        self.runtime.block_on(call(&mut self.client))
+    }
+}
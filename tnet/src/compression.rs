@@ -0,0 +1,304 @@
+use std::io::{Read, Write};
+
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
+
+use crate::{asynch::framing, encrypt::Encryptor, errors::Error, packet::Packet};
+
+/// Flag byte prepended to payloads that were sent uncompressed.
+const FLAG_RAW: u8 = 0;
+/// Flag byte prepended to gzip compressed payloads.
+const FLAG_GZIP: u8 = 1;
+/// Flag byte prepended to zstd compressed payloads.
+const FLAG_ZSTD: u8 = 2;
+
+/// The compression algorithm applied to outgoing payloads.
+///
+/// # Variants
+///
+/// * `None` - Compression is disabled, the wire format is left untouched
+/// * `Gzip` - Deflate based gzip compression
+/// * `Zstd` - Zstandard compression, usually faster with a better ratio
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CompressionAlgorithm {
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+}
+
+/// Configuration settings for payload compression.
+///
+/// When an algorithm other than `None` is selected every payload is prefixed
+/// with a one-byte flag telling the receiver whether (and how) it was
+/// compressed. Only payloads of at least `min_size` bytes are compressed,
+/// smaller ones are sent as is behind the flag. Compression happens before
+/// encryption, so the flag itself is never visible on the wire when
+/// encryption is enabled.
+///
+/// Both ends of a connection must enable compression, though they may
+/// choose different algorithms.
+///
+/// # Fields
+///
+/// * `algorithm` - The algorithm used for outgoing payloads
+/// * `min_size` - Minimum serialized size in bytes before compression kicks in
+///
+/// # Example
+///
+/// ```rust
+/// use tnet::compression::{CompressionAlgorithm, CompressionConfig};
+///
+/// let config = CompressionConfig {
+///     algorithm: CompressionAlgorithm::Zstd,
+///     min_size: 512,
+/// };
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompressionConfig {
+    pub algorithm: CompressionAlgorithm,
+    pub min_size: usize,
+}
+
+impl CompressionConfig {
+    /// Creates a new configuration using gzip for payloads of 1 KiB or more.
+    #[must_use]
+    pub const fn default_on() -> Self {
+        Self {
+            algorithm: CompressionAlgorithm::Gzip,
+            min_size: 1024,
+        }
+    }
+
+    /// Checks if compression is enabled.
+    #[must_use]
+    pub const fn is_enabled(&self) -> bool {
+        !matches!(self.algorithm, CompressionAlgorithm::None)
+    }
+
+    /// Compresses a payload and prepends the flag byte.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The serialized payload
+    ///
+    /// # Returns
+    ///
+    /// * The flagged payload, or `data` untouched if compression is disabled
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Compression` if the compressor fails
+    pub fn compress(&self, data: Vec<u8>) -> Result<Vec<u8>, Error> {
+        if !self.is_enabled() {
+            return Ok(data);
+        }
+
+        if data.len() < self.min_size {
+            let mut out = Vec::with_capacity(data.len() + 1);
+            out.push(FLAG_RAW);
+            out.extend_from_slice(&data);
+            return Ok(out);
+        }
+
+        let mut out = Vec::with_capacity(data.len() / 4 + 1);
+        match self.algorithm {
+            CompressionAlgorithm::Gzip => {
+                out.push(FLAG_GZIP);
+                let mut encoder = GzEncoder::new(out, Compression::default());
+                encoder
+                    .write_all(&data)
+                    .map_err(|e| Error::Compression(e.to_string()))?;
+                encoder
+                    .finish()
+                    .map_err(|e| Error::Compression(e.to_string()))
+            }
+            CompressionAlgorithm::Zstd => {
+                out.push(FLAG_ZSTD);
+                zstd::stream::copy_encode(data.as_slice(), &mut out, 0)
+                    .map_err(|e| Error::Compression(e.to_string()))?;
+                Ok(out)
+            }
+            CompressionAlgorithm::None => unreachable!(),
+        }
+    }
+
+    /// Strips the flag byte and decompresses a payload if needed.
+    ///
+    /// The output is limited to the default maximum packet size, see
+    /// `decompress_limited`.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The flagged payload
+    ///
+    /// # Returns
+    ///
+    /// * The original serialized payload, or `data` untouched if compression is disabled
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Compression` if the flag is unknown or the payload is corrupt
+    /// and `Error::PacketTooLarge` if it decompresses past the limit
+    pub fn decompress(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        self.decompress_limited(data, framing::DEFAULT_MAX_FRAME_LEN)
+    }
+
+    /// Strips the flag byte and decompresses a payload of at most `max_len` bytes.
+    ///
+    /// Decompression stops as soon as the output passes the limit, so a small
+    /// frame can't expand into an arbitrarily large allocation.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The flagged payload
+    /// * `max_len` - Largest decompressed payload accepted, in bytes
+    ///
+    /// # Returns
+    ///
+    /// * The original serialized payload, or `data` untouched if compression is disabled
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Compression` if the flag is unknown or the payload is corrupt
+    /// and `Error::PacketTooLarge` if it decompresses past `max_len`
+    pub fn decompress_limited(&self, data: &[u8], max_len: usize) -> Result<Vec<u8>, Error> {
+        if !self.is_enabled() {
+            return Ok(data.to_vec());
+        }
+
+        let (flag, payload) = data
+            .split_first()
+            .ok_or_else(|| Error::Compression("Empty payload".to_string()))?;
+
+        let out = match *flag {
+            FLAG_RAW => payload.to_vec(),
+            FLAG_GZIP => read_limited(GzDecoder::new(payload), max_len)?,
+            FLAG_ZSTD => {
+                let decoder = zstd::stream::read::Decoder::new(payload)
+                    .map_err(|e| Error::Compression(e.to_string()))?;
+                read_limited(decoder, max_len)?
+            }
+            other => {
+                return Err(Error::Compression(format!(
+                    "Unknown compression flag {other}"
+                )));
+            }
+        };
+
+        if out.len() > max_len {
+            return Err(Error::PacketTooLarge(out.len()));
+        }
+        Ok(out)
+    }
+
+    /// Serializes, compresses and optionally encrypts a packet for the wire.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - The packet to encode
+    /// * `encryptor` - The encryptor to use, if the connection is encrypted
+    ///
+    /// # Returns
+    ///
+    /// * The bytes to write to the socket
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as `Packet::ser` and `Packet::encrypted_ser`
+    pub fn encode<P: Packet>(&self, packet: &P, encryptor: Option<&Encryptor>) -> Vec<u8> {
+        if !self.is_enabled() {
+            return encryptor.map_or_else(|| packet.ser(), |enc| packet.encrypted_ser(enc));
+        }
+
+        let data = self
+            .compress(packet.ser())
+            .expect("Failed to compress packet");
+
+        match encryptor {
+            Some(enc) => enc
+                .encrypt(&data)
+                .expect("Failed to encrypt data")
+                .into_bytes(),
+            None => data,
+        }
+    }
+
+    /// Decrypts, decompresses and deserializes a packet read from the wire.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The bytes read from the socket
+    /// * `encryptor` - The encryptor to use, if the connection is encrypted
+    ///
+    /// # Returns
+    ///
+    /// * The decoded packet
+    ///
+    /// # Errors
+    ///
+    /// Returns the `Error` converted from the `EncryptError` if decryption fails,
+    /// `Error::Compression` if decompression fails and `Error::PacketTooLarge` if
+    /// the payload decompresses past the default maximum packet size
+    pub fn decode<P: Packet>(
+        &self,
+        data: &[u8],
+        encryptor: Option<&Encryptor>,
+    ) -> Result<P, Error> {
+        self.decode_limited(data, encryptor, framing::DEFAULT_MAX_FRAME_LEN)
+    }
+
+    /// Decodes a packet like `decode`, decompressing at most `max_len` bytes.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The bytes read from the socket
+    /// * `encryptor` - The encryptor to use, if the connection is encrypted
+    /// * `max_len` - Largest decompressed payload accepted, in bytes
+    ///
+    /// # Returns
+    ///
+    /// * The decoded packet
+    ///
+    /// # Errors
+    ///
+    /// Returns the `Error` converted from the `EncryptError` if decryption fails,
+    /// `Error::Compression` if decompression fails and `Error::PacketTooLarge` if
+    /// the payload decompresses past `max_len`
+    pub fn decode_limited<P: Packet>(
+        &self,
+        data: &[u8],
+        encryptor: Option<&Encryptor>,
+        max_len: usize,
+    ) -> Result<P, Error> {
+        let data = match encryptor {
+            Some(enc) => enc.decrypt(&String::from_utf8_lossy(data))?,
+            None => data.to_vec(),
+        };
+
+        if !self.is_enabled() {
+            return Ok(P::de(&data));
+        }
+
+        Ok(P::de(&self.decompress_limited(&data, max_len)?))
+    }
+}
+
+// Reads a decompressor to the end, stopping one byte past `max_len` so an
+// oversized payload is detected without inflating all of it
+fn read_limited(decoder: impl Read, max_len: usize) -> Result<Vec<u8>, Error> {
+    let mut out = Vec::new();
+    decoder
+        .take((max_len as u64).saturating_add(1))
+        .read_to_end(&mut out)
+        .map_err(|e| Error::Compression(e.to_string()))?;
+    Ok(out)
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            algorithm: CompressionAlgorithm::None,
+            min_size: 1024,
+        }
+    }
+}
@@ -1,13 +1,380 @@
-use base64::engine::general_purpose::STANDARD as BASE64;
+use std::sync::{
+    Arc, Mutex,
+    atomic::{AtomicU64, Ordering},
+};
+
 use base64::Engine;
-use tcrypt::key_exchange::{protocol::SecureChannel, DHKeyExchange};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use chacha20poly1305::{
+    ChaCha20Poly1305, Nonce,
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+};
+use serde::{Deserialize, Serialize};
+use tcrypt::key_exchange::{DHKeyExchange, protocol::SecureChannel};
 use tcrypt::prelude::X25519PublicKey as PublicKey;
-use tcrypt::EncryptionError;
 
-/// Provides encryption and decryption capabilities using AES-256-GCM.
+use crate::errors::Error;
+
+/// How many frames behind the newest one a frame may arrive and still be accepted.
+///
+/// Frames can be encrypted in one order and written in another (a keepalive racing
+/// a regular send, for example), so the receiver remembers which of the last
+/// `REPLAY_WINDOW` counters it has seen instead of demanding a strictly increasing
+/// sequence. Anything older than that, or any counter seen before, is rejected.
+pub const REPLAY_WINDOW: u64 = 64;
+
+/// Size of the message counter placed in front of every plaintext.
+const COUNTER_LEN: usize = 8;
+
+/// Size of the random nonce placed in front of every ciphertext.
+const NONCE_LEN: usize = 12;
+
+/// Size of the authentication tag at the end of every ciphertext.
+const TAG_LEN: usize = 16;
+
+/// Top bit of the message counter, set on frames sent by the server.
+const SERVER_BIT: u64 = 1 << 63;
+
+/// Errors produced by an `Encryptor`.
+///
+/// Each failure mode has its own variant so callers can tell a misconfigured key
+/// from a damaged or tampered frame. Converting into `Error` keeps them distinct:
+/// `InvalidKey`, `DecryptFailed`, `Truncated` and `Replayed` become
+/// `Error::InvalidKey`, `Error::DecryptFailed`, `Error::TruncatedFrame` and
+/// `Error::ReplayDetected`.
+///
+/// # Example
+///
+/// ```rust
+/// use tnet::encrypt::{EncryptError, Encryptor};
+///
+/// assert!(matches!(Encryptor::new(&[0; 5]), Err(EncryptError::InvalidKey(_))));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum EncryptError {
+    /// The key has the wrong length for the cipher suite.
+    #[error("Invalid encryption key: {0}")]
+    InvalidKey(String),
+    /// The cipher refused to encrypt the data.
+    #[error("Encryption failed")]
+    EncryptFailed,
+    /// The frame failed authentication, because of the wrong key or a corrupt or tampered ciphertext.
+    #[error("Failed to decrypt frame")]
+    DecryptFailed,
+    /// The frame is too short to hold a nonce, tag and message counter.
+    #[error("Truncated encrypted frame")]
+    Truncated,
+    /// The frame is not valid base64.
+    #[error("Malformed encrypted frame: {0}")]
+    Malformed(String),
+    /// The frame's message counter was already accepted or is too old.
+    #[error("Replayed encrypted frame")]
+    Replayed,
+}
+
+impl From<EncryptError> for Error {
+    fn from(error: EncryptError) -> Self {
+        match error {
+            EncryptError::InvalidKey(reason) => Self::InvalidKey(reason),
+            EncryptError::DecryptFailed => Self::DecryptFailed,
+            EncryptError::Truncated => Self::TruncatedFrame,
+            EncryptError::Replayed => Self::ReplayDetected,
+            EncryptError::EncryptFailed | EncryptError::Malformed(_) => {
+                Self::EncryptionError(error.to_string())
+            }
+        }
+    }
+}
+
+/// The AEAD cipher protecting an encrypted connection.
+///
+/// Clients offer the suites they support during the key exchange and the server
+/// picks one, so both sides always agree. A peer that doesn't list any suites is
+/// treated as supporting only `Aes256Gcm`.
+///
+/// # Example
+///
+/// ```rust
+/// use tnet::encrypt::{CipherSuite, Encryptor};
+///
+/// let key = Encryptor::generate_key();
+/// let encryptor = Encryptor::new_with_suite(&key, CipherSuite::ChaCha20Poly1305).unwrap();
+/// assert_eq!(encryptor.suite(), CipherSuite::ChaCha20Poly1305);
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum CipherSuite {
+    #[default]
+    Aes256Gcm,
+    ChaCha20Poly1305,
+}
+
+impl CipherSuite {
+    /// Every supported suite, in the default order of preference.
+    pub const ALL: [Self; 2] = [Self::Aes256Gcm, Self::ChaCha20Poly1305];
+
+    /// Returns the byte identifying this suite during the key exchange.
+    #[must_use]
+    pub const fn id(self) -> u8 {
+        match self {
+            Self::Aes256Gcm => 1,
+            Self::ChaCha20Poly1305 => 2,
+        }
+    }
+
+    /// Looks up a suite by the byte sent during the key exchange.
+    ///
+    /// # Arguments
+    ///
+    /// * `id`: The suite identifier
+    ///
+    /// # Returns
+    ///
+    /// * `Option<CipherSuite>` - The suite, or `None` if the identifier is unknown
+    #[must_use]
+    pub const fn from_id(id: u8) -> Option<Self> {
+        match id {
+            1 => Some(Self::Aes256Gcm),
+            2 => Some(Self::ChaCha20Poly1305),
+            _ => None,
+        }
+    }
+
+    /// Picks the first suite in `preferred` that also appears in `offered`.
+    ///
+    /// # Arguments
+    ///
+    /// * `preferred`: The suites this side supports, most preferred first
+    /// * `offered`: The suites the peer supports
+    ///
+    /// # Returns
+    ///
+    /// * `Option<CipherSuite>` - The agreed suite, or `None` if there is no overlap
+    #[must_use]
+    pub fn negotiate(preferred: &[Self], offered: &[Self]) -> Option<Self> {
+        preferred
+            .iter()
+            .copied()
+            .find(|suite| offered.contains(suite))
+    }
+}
+
+/// The message each side sends during the key exchange.
+///
+/// On the wire this is the 32-byte public key followed by one byte per cipher
+/// suite. The client lists every suite it supports and the server answers with
+/// the single suite it picked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HandshakeHello {
+    pub public_key: [u8; 32],
+    pub suites: Vec<CipherSuite>,
+}
+
+impl HandshakeHello {
+    /// Encodes the message for sending as a single frame.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<u8>` - The public key followed by the suite identifiers
+    #[must_use]
+    pub fn encode(&self) -> Vec<u8> {
+        let mut data = self.public_key.to_vec();
+        data.extend(self.suites.iter().map(|suite| suite.id()));
+        data
+    }
+
+    /// Decodes a message received during the key exchange.
+    ///
+    /// Unknown suite identifiers are skipped, and a message carrying only a
+    /// public key is read as offering `CipherSuite::Aes256Gcm`.
+    ///
+    /// # Arguments
+    ///
+    /// * `data`: The received frame
+    ///
+    /// # Returns
+    ///
+    /// * `Option<HandshakeHello>` - The message, or `None` if it is too short to hold a key
+    #[must_use]
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        let public_key = data.get(..32)?.try_into().ok()?;
+
+        let mut suites = data[32..]
+            .iter()
+            .filter_map(|&id| CipherSuite::from_id(id))
+            .collect::<Vec<_>>();
+        if data.len() == 32 {
+            suites.push(CipherSuite::Aes256Gcm);
+        }
+
+        Some(Self { public_key, suites })
+    }
+
+    /// Reads the suite a server picked in reply to the suites a client offered.
+    ///
+    /// # Arguments
+    ///
+    /// * `offered`: The suites the client listed in its own hello
+    ///
+    /// # Returns
+    ///
+    /// * `Option<CipherSuite>` - The picked suite, or `None` if it wasn't one the client offered
+    #[must_use]
+    pub fn chosen_suite(&self, offered: &[CipherSuite]) -> Option<CipherSuite> {
+        self.suites
+            .first()
+            .copied()
+            .filter(|suite| offered.contains(suite))
+    }
+}
+
+/// Which end of a connection an `Encryptor` belongs to.
+///
+/// Both ends derive the same key, so the role is what tells their frames apart.
+/// It is stored in the top bit of each authenticated message counter, and an
+/// encryptor with a role rejects frames carrying its own, which stops a frame
+/// from being reflected back to the peer that sent it.
+///
+/// # Example
+///
+/// ```rust
+/// use tnet::encrypt::{EncryptError, Encryptor, Role};
+///
+/// let key = Encryptor::generate_key();
+/// let client = Encryptor::new(&key).unwrap().with_role(Role::Client);
+/// let server = Encryptor::new(&key).unwrap().with_role(Role::Server);
+///
+/// let encrypted = client.encrypt(b"hello").unwrap();
+/// assert_eq!(client.decrypt(&encrypted), Err(EncryptError::Replayed));
+/// assert_eq!(server.decrypt(&encrypted).unwrap(), b"hello");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Role {
+    Client,
+    Server,
+}
+
+impl Role {
+    /// Returns the counter bit marking frames sent by this role.
+    const fn direction_bit(self) -> u64 {
+        match self {
+            Self::Client => 0,
+            Self::Server => SERVER_BIT,
+        }
+    }
+}
+
+/// The cipher state behind an `Encryptor`.
+#[derive(Clone)]
+enum Cipher {
+    Aes256Gcm(Box<SecureChannel>),
+    ChaCha20Poly1305(ChaCha20Poly1305),
+}
+
+impl Cipher {
+    fn new(key: &[u8], suite: CipherSuite) -> Result<Self, EncryptError> {
+        match suite {
+            CipherSuite::Aes256Gcm => SecureChannel::new(key)
+                .map(|channel| Self::Aes256Gcm(Box::new(channel)))
+                .map_err(|e| EncryptError::InvalidKey(e.to_string())),
+            CipherSuite::ChaCha20Poly1305 => ChaCha20Poly1305::new_from_slice(key)
+                .map(Self::ChaCha20Poly1305)
+                .map_err(|e| EncryptError::InvalidKey(e.to_string())),
+        }
+    }
+
+    fn encrypt(&self, data: &[u8]) -> Result<Vec<u8>, EncryptError> {
+        match self {
+            Self::Aes256Gcm(channel) => channel
+                .encrypt(data)
+                .map_err(|_| EncryptError::EncryptFailed),
+            Self::ChaCha20Poly1305(cipher) => {
+                let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+                let encrypted = cipher
+                    .encrypt(&nonce, data)
+                    .map_err(|_| EncryptError::EncryptFailed)?;
+
+                let mut out = nonce.to_vec();
+                out.extend(encrypted);
+                Ok(out)
+            }
+        }
+    }
+
+    fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>, EncryptError> {
+        if data.len() < NONCE_LEN + TAG_LEN {
+            return Err(EncryptError::Truncated);
+        }
+        match self {
+            Self::Aes256Gcm(channel) => channel
+                .decrypt(data)
+                .map_err(|_| EncryptError::DecryptFailed),
+            Self::ChaCha20Poly1305(cipher) => {
+                let (nonce, encrypted) = data.split_at(NONCE_LEN);
+                cipher
+                    .decrypt(Nonce::from_slice(nonce), encrypted)
+                    .map_err(|_| EncryptError::DecryptFailed)
+            }
+        }
+    }
+}
+
+/// Tracks which recent message counters have already been accepted.
+#[derive(Default)]
+struct ReplayWindow {
+    highest: Option<u64>,
+    // Bit `n` is set once `highest - n` has been accepted
+    seen: u64,
+}
+
+impl ReplayWindow {
+    /// Records `counter`, returning `false` if it was already seen or is too old.
+    const fn accept(&mut self, counter: u64) -> bool {
+        let highest = match self.highest {
+            Some(highest) => highest,
+            None => {
+                self.highest = Some(counter);
+                self.seen = 1;
+                return true;
+            }
+        };
+
+        if counter > highest {
+            let shift = counter - highest;
+            self.seen = if shift >= REPLAY_WINDOW {
+                0
+            } else {
+                self.seen << shift
+            };
+            self.seen |= 1;
+            self.highest = Some(counter);
+            return true;
+        }
+
+        let offset = highest - counter;
+        if offset >= REPLAY_WINDOW || self.seen & (1 << offset) != 0 {
+            return false;
+        }
+
+        self.seen |= 1 << offset;
+        true
+    }
+}
+
+/// Provides encryption and decryption capabilities using an AEAD cipher.
+///
+/// This struct encapsulates the encryption logic for the chosen `CipherSuite`
+/// (AES-256-GCM unless told otherwise), providing methods for secure data
+/// encryption and decryption.
+///
+/// Every message carries a counter inside the authenticated plaintext. The
+/// counter increases with each call to `encrypt`, and `decrypt` rejects any
+/// counter it has already accepted, or one more than `REPLAY_WINDOW` behind the
+/// newest, with `EncryptError::Replayed`. Clones share both counters, so an
+/// encrypted message can only be decrypted once by an encryptor and its clones.
 ///
-/// This struct encapsulates the encryption logic using the AES-256-GCM algorithm,
-/// providing methods for secure data encryption and decryption.
+/// Connections give each end a `Role` so that frames sent in one direction are
+/// never accepted in the other. An encryptor without a role accepts frames from
+/// either side, including its own, and is only suited to local use.
 ///
 /// # Example
 ///
@@ -24,11 +391,15 @@ use tcrypt::EncryptionError;
 /// ```
 #[derive(Clone)]
 pub struct Encryptor {
-    channel: SecureChannel,
+    cipher: Cipher,
+    suite: CipherSuite,
+    role: Option<Role>,
+    send_counter: Arc<AtomicU64>,
+    replay_window: Arc<Mutex<ReplayWindow>>,
 }
 
 impl Encryptor {
-    /// Creates a new Encryptor instance with the provided key.
+    /// Creates a new Encryptor instance with the provided key, using AES-256-GCM.
     ///
     /// # Arguments
     ///
@@ -37,12 +408,68 @@ impl Encryptor {
     /// # Returns
     ///
     /// * A new `Encryptor` instance
-    pub fn new(key: &[u8]) -> Result<Self, EncryptionError> {
+    ///
+    /// # Errors
+    ///
+    /// Returns `EncryptError::InvalidKey` if the key is not 32 bytes long
+    pub fn new(key: &[u8]) -> Result<Self, EncryptError> {
+        Self::new_with_suite(key, CipherSuite::default())
+    }
+
+    /// Creates a new Encryptor instance with the provided key and cipher suite.
+    ///
+    /// # Arguments
+    ///
+    /// * `key`: A 32-byte array representing the encryption key
+    /// * `suite`: The cipher to encrypt with
+    ///
+    /// # Returns
+    ///
+    /// * A new `Encryptor` instance
+    ///
+    /// # Errors
+    ///
+    /// Returns `EncryptError::InvalidKey` if the key is not 32 bytes long
+    pub fn new_with_suite(key: &[u8], suite: CipherSuite) -> Result<Self, EncryptError> {
         Ok(Self {
-            channel: SecureChannel::new(key)?,
+            cipher: Cipher::new(key, suite)?,
+            suite,
+            role: None,
+            send_counter: Arc::new(AtomicU64::new(0)),
+            replay_window: Arc::new(Mutex::new(ReplayWindow::default())),
         })
     }
 
+    /// Sets which end of the connection this encryptor belongs to.
+    ///
+    /// Frames are then marked with this role, and frames carrying it are
+    /// rejected with `EncryptError::Replayed`. Both ends must set opposite roles.
+    ///
+    /// # Arguments
+    ///
+    /// * `role`: The end of the connection this encryptor is used on
+    ///
+    /// # Returns
+    ///
+    /// * The encryptor with the role set
+    #[must_use]
+    pub const fn with_role(mut self, role: Role) -> Self {
+        self.role = Some(role);
+        self
+    }
+
+    /// Returns the cipher suite this encryptor uses.
+    #[must_use]
+    pub const fn suite(&self) -> CipherSuite {
+        self.suite
+    }
+
+    /// Returns the end of the connection this encryptor belongs to, if set.
+    #[must_use]
+    pub const fn role(&self) -> Option<Role> {
+        self.role
+    }
+
     /// Generates a new random 32-byte encryption key.
     ///
     /// # Returns
@@ -57,7 +484,24 @@ impl Encryptor {
         key
     }
 
-    /// Encrypts the provided data using AES-256-GCM.
+    /// Checks whether two encryptors are clones of the same instance.
+    ///
+    /// Clones share the key and the send counter, so a frame encrypted by one
+    /// can be delivered to the peer of any of them.
+    ///
+    /// # Arguments
+    ///
+    /// * `other`: The encryptor to compare with
+    ///
+    /// # Returns
+    ///
+    /// * `true` if both encryptors were cloned from the same instance
+    #[must_use]
+    pub fn is_same_instance(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.send_counter, &other.send_counter)
+    }
+
+    /// Encrypts the provided data with the configured cipher suite.
     ///
     /// # Arguments
     ///
@@ -69,7 +513,7 @@ impl Encryptor {
     ///
     /// # Errors
     ///
-    /// Returns an error if encryption fails or if the data cannot be processed
+    /// Returns `EncryptError::EncryptFailed` if the cipher cannot encrypt the data
     ///
     /// # Example
     ///
@@ -79,8 +523,15 @@ impl Encryptor {
     /// let encryptor = Encryptor::new(&key);
     /// let encrypted = encryptor.encrypt(b"Secret data").unwrap();
     /// ```
-    pub fn encrypt(&self, data: &[u8]) -> Result<String, EncryptionError> {
-        let encrypted = self.channel.encrypt(data)?;
+    pub fn encrypt(&self, data: &[u8]) -> Result<String, EncryptError> {
+        let counter = self.send_counter.fetch_add(1, Ordering::SeqCst)
+            | self.role.map_or(0, Role::direction_bit);
+
+        let mut plaintext = Vec::with_capacity(COUNTER_LEN + data.len());
+        plaintext.extend_from_slice(&counter.to_be_bytes());
+        plaintext.extend_from_slice(data);
+
+        let encrypted = self.cipher.encrypt(&plaintext)?;
         Ok(BASE64.encode(&encrypted))
     }
 
@@ -96,10 +547,12 @@ impl Encryptor {
     ///
     /// # Errors
     ///
-    /// Returns an error if:
-    /// - The input is not valid Base64
-    /// - The input data is too short
-    /// - Decryption fails
+    /// * `EncryptError::Malformed` if the input is not valid Base64
+    /// * `EncryptError::Truncated` if the input is too short to be a frame
+    /// * `EncryptError::DecryptFailed` if the frame fails authentication
+    /// * `EncryptError::Replayed` if the message was already decrypted, is
+    ///   more than `REPLAY_WINDOW` messages older than the newest one seen, or
+    ///   was sent by an encryptor with the same `Role`
     ///
     /// # Example
     ///
@@ -110,11 +563,37 @@ impl Encryptor {
     /// let encrypted = encryptor.encrypt(b"Secret data").unwrap();
     /// let decrypted = encryptor.decrypt(&encrypted).unwrap();
     /// ```
-    pub fn decrypt(&self, data: &str) -> Result<Vec<u8>, EncryptionError> {
+    pub fn decrypt(&self, data: &str) -> Result<Vec<u8>, EncryptError> {
         let decoded = BASE64
             .decode(data)
-            .map_err(|e| EncryptionError::DecryptionFailed(e.to_string()))?;
-        self.channel.decrypt(&decoded)
+            .map_err(|e| EncryptError::Malformed(e.to_string()))?;
+        let mut plaintext = self.cipher.decrypt(&decoded)?;
+
+        let counter: [u8; COUNTER_LEN] = plaintext
+            .get(..COUNTER_LEN)
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or(EncryptError::Truncated)?;
+        let counter = u64::from_be_bytes(counter);
+
+        // A frame in our own direction was reflected back at us
+        if let Some(role) = self.role
+            && counter & SERVER_BIT == role.direction_bit()
+        {
+            return Err(EncryptError::Replayed);
+        }
+
+        // Only authenticated counters reach the window, so forged frames can't move it
+        let accepted = self
+            .replay_window
+            .lock()
+            .expect("Replay window lock poisoned")
+            .accept(counter);
+        if !accepted {
+            return Err(EncryptError::Replayed);
+        }
+
+        plaintext.drain(..COUNTER_LEN);
+        Ok(plaintext)
     }
 }
 
@@ -26,6 +26,9 @@ pub enum Error {
     #[error("Encryption error: {0}")]
     EncryptionError(String),
 
+    #[error("Encryption is required but the client did not complete the handshake")]
+    EncryptionRequired,
+
     #[error("Session ID is required for a keep alive session")]
     KeepAliveNoSessionId,
 
@@ -34,22 +37,79 @@ pub enum Error {
 
     #[error("Invalid Client Config - There was none")]
     UnwrappedInvalidClientConfig,
-    
+
     #[error("Invalid pool {0}")]
-    InvalidPool(String),        
-    
+    InvalidPool(String),
+
     #[error("Failed to send packet {0}")]
     FailedPacketSend(String),
-    
+
     #[error("Failed to read packet {0}")]
     FailedPacketRead(String),
-    
+
     #[error("Broadcast: {0}")]
     Broadcast(String),
-    
+
     #[error("Read timeout")]
     ReadTimeout,
-    
+
+    #[error("Too many connections")]
+    TooManyConnections,
+
+    #[error("Serialization error: {0}")]
+    Serialization(String),
+
+    #[error("Compression error: {0}")]
+    Compression(String),
+
+    #[error("Failed to resolve address: {0}")]
+    ResolutionFailed(String),
+
+    #[error("Send queue is full")]
+    WouldBlock,
+
+    #[error("Session store error: {0}")]
+    SessionStore(String),
+
+    #[error("Replayed encrypted frame")]
+    ReplayDetected,
+
+    #[error("Too many failed authentication attempts")]
+    AuthRateLimited,
+
+    #[error("Invalid encryption key: {0}")]
+    InvalidKey(String),
+
+    #[error("Failed to decrypt frame")]
+    DecryptFailed,
+
+    #[error("Truncated encrypted frame")]
+    TruncatedFrame,
+
+    #[error("Reconnection time limit reached")]
+    ReconnectTimedOut,
+
+    #[error("Packet of {0} bytes exceeds the size limit")]
+    PacketTooLarge(usize),
+
+    #[error("Key exchange failed: {0}")]
+    KeyExchangeFailed(String),
+
+    #[error("No session ID received")]
+    NoSessionId,
+
+    #[error("Invalid authentication response: {0}")]
+    AuthResponseInvalid(String),
+
+    #[error("Invalid packet: {0}")]
+    InvalidPacket(String),
+
+    #[error("Failed to bind listener: {0}")]
+    BindFailed(String),
+
+    #[error("Message rate limit exceeded")]
+    RateLimited,
+
     #[error("{0}")]
     Error(String),
-}
\ No newline at end of file
+}
@@ -17,6 +17,7 @@ use crate::packet::Packet;
 use crate::resources::Resource;
 use crate::session::Session;
 use futures::future::BoxFuture;
+use tracing::debug;
 
 /// Type alias for packet handler functions.
 ///
@@ -27,15 +28,63 @@ use futures::future::BoxFuture;
 /// * `P` - The packet type implementing the `Packet` trait
 /// * `S` - The session type implementing the `Session` trait
 /// * `R` - The resource type implementing the `Resource` trait
-pub type HandlerFn<P, S, R> =
-    Arc<dyn Fn(HandlerSources<S, R>, P) -> BoxFuture<'static, ()> + Send + Sync>;
+/// * `C` - The per-connection resource type, `()` when unused
+pub type HandlerFn<P, S, R, C = ()> =
+    Arc<dyn Fn(HandlerSources<S, R, C>, P) -> BoxFuture<'static, ()> + Send + Sync>;
+
+/// Tells the listener whether to keep running the handler chain for a packet.
+///
+/// # Variants
+///
+/// * `Continue` - Run the next handler registered for the header
+/// * `Stop` - Skip every remaining handler for this packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HandlerFlow {
+    #[default]
+    Continue,
+    Stop,
+}
+
+/// Type alias for packet handler functions that can halt the handler chain.
+///
+/// # Type Parameters
+///
+/// * `P` - The packet type implementing the `Packet` trait
+/// * `S` - The session type implementing the `Session` trait
+/// * `R` - The resource type implementing the `Resource` trait
+/// * `C` - The per-connection resource type, `()` when unused
+pub type FlowHandlerFn<P, S, R, C = ()> =
+    Arc<dyn Fn(HandlerSources<S, R, C>, P) -> BoxFuture<'static, HandlerFlow> + Send + Sync>;
+
+/// A single slot in the handler registry.
+///
+/// `handlers` holds a `Vec<(i32, FlowHandlerFn<P, S, R>)>` of priorities and
+/// handlers for the concrete types the slot was registered with, kept sorted by
+/// priority descending. The header and handler count are kept alongside so
+/// entries can be inspected without knowing those types.
+struct RegistryEntry {
+    header: String,
+    handlers: Box<dyn std::any::Any + Send + Sync>,
+    count: usize,
+}
 
 /// Global registry for packet handlers.
 ///
 /// This static variable holds all registered packet handlers in a thread-safe container.
 /// It's initialized on first use.
-static HANDLER_REGISTRY: OnceLock<Mutex<HashMap<String, Box<dyn std::any::Any + Send + Sync>>>> =
-    OnceLock::new();
+static HANDLER_REGISTRY: OnceLock<Mutex<HashMap<String, RegistryEntry>>> = OnceLock::new();
+
+/// Builds the registry key for a header and a packet/session/resource combination.
+fn registry_key<P, S, R, C>(packet_type: &str) -> String {
+    format!(
+        "{}_{}_{}_{}_{}",
+        packet_type,
+        std::any::type_name::<P>(),
+        std::any::type_name::<S>(),
+        std::any::type_name::<R>(),
+        std::any::type_name::<C>()
+    )
+}
 
 /// Registers a handler function for a specific packet type.
 ///
@@ -80,42 +129,249 @@ pub fn register_handler<P, S, R>(
     S: Session + 'static,
     R: Resource + 'static,
 {
-    // Create a registry key
-    let key = format!(
-        "{}_{}_{}_{}",
-        packet_type,
-        std::any::type_name::<P>(),
-        std::any::type_name::<S>(),
-        std::any::type_name::<R>()
-    );
+    register_handler_with_priority(packet_type, 0, handler);
+}
+
+/// Registers a handler function for a specific packet type with a priority.
+///
+/// Handlers for the same header run in order of priority, highest first.
+/// Handlers sharing a priority run in registration order. `register_handler`
+/// registers with priority `0`.
+///
+/// # Type Parameters
+///
+/// * `P` - The packet type implementing the `Packet` trait
+/// * `S` - The session type implementing the `Session` trait
+/// * `R` - The resource type implementing the `Resource` trait
+///
+/// # Arguments
+///
+/// * `packet_type` - The packet header string this handler will respond to
+/// * `priority` - Ordering priority, higher values run first
+/// * `handler` - The handler function
+///
+/// # Example
+///
+/// ```rust
+/// use tnet::prelude::*;
+///
+/// // Runs before every priority 0 LOGIN handler
+/// register_handler_with_priority::<MyPacket, MySession, MyResource>(
+///     "LOGIN",
+///     100,
+///     |sources, packet| Box::pin(audit_login(sources, packet))
+/// );
+/// ```
+pub fn register_handler_with_priority<P, S, R>(
+    packet_type: &str,
+    priority: i32,
+    handler: impl Fn(HandlerSources<S, R>, P) -> BoxFuture<'static, ()> + Send + Sync + 'static,
+) where
+    P: Packet + 'static,
+    S: Session + 'static,
+    R: Resource + 'static,
+{
+    let handler = Arc::new(handler);
+    register_flow_handler_with_priority(packet_type, priority, move |sources, packet| {
+        let handler = handler.clone();
+        Box::pin(async move {
+            handler(sources, packet).await;
+            HandlerFlow::Continue
+        })
+    });
+}
+
+/// Registers a handler that decides whether later handlers run.
+///
+/// Works like `register_handler`, except the handler returns a `HandlerFlow`.
+/// Returning `HandlerFlow::Stop` prevents every lower priority handler for the
+/// header from seeing the packet, which is useful for checks such as
+/// authorization that should gate the rest of the chain.
+///
+/// # Type Parameters
+///
+/// * `P` - The packet type implementing the `Packet` trait
+/// * `S` - The session type implementing the `Session` trait
+/// * `R` - The resource type implementing the `Resource` trait
+///
+/// # Arguments
+///
+/// * `packet_type` - The packet header string this handler will respond to
+/// * `handler` - The handler function
+///
+/// # Example
+///
+/// ```rust
+/// use tnet::prelude::*;
+///
+/// register_flow_handler::<MyPacket, MySession, MyResource>(
+///     "ADMIN",
+///     |sources, packet| Box::pin(async move {
+///         if packet.session_id.is_none() {
+///             return HandlerFlow::Stop;
+///         }
+///         HandlerFlow::Continue
+///     })
+/// );
+/// ```
+pub fn register_flow_handler<P, S, R>(
+    packet_type: &str,
+    handler: impl Fn(HandlerSources<S, R>, P) -> BoxFuture<'static, HandlerFlow> + Send + Sync + 'static,
+) where
+    P: Packet + 'static,
+    S: Session + 'static,
+    R: Resource + 'static,
+{
+    register_flow_handler_with_priority(packet_type, 0, handler);
+}
+
+/// Registers a handler that decides whether later handlers run, with a priority.
+///
+/// See `register_flow_handler` and `register_handler_with_priority`.
+///
+/// # Type Parameters
+///
+/// * `P` - The packet type implementing the `Packet` trait
+/// * `S` - The session type implementing the `Session` trait
+/// * `R` - The resource type implementing the `Resource` trait
+///
+/// # Arguments
+///
+/// * `packet_type` - The packet header string this handler will respond to
+/// * `priority` - Ordering priority, higher values run first
+/// * `handler` - The handler function
+pub fn register_flow_handler_with_priority<P, S, R>(
+    packet_type: &str,
+    priority: i32,
+    handler: impl Fn(HandlerSources<S, R>, P) -> BoxFuture<'static, HandlerFlow> + Send + Sync + 'static,
+) where
+    P: Packet + 'static,
+    S: Session + 'static,
+    R: Resource + 'static,
+{
+    insert_flow_handler::<P, S, R, ()>(packet_type, priority, handler);
+}
+
+/// Registers a flow handler for listeners with per-connection resources of type `C`.
+///
+/// The public registration functions use this with `C = ()`.
+pub(crate) fn insert_flow_handler<P, S, R, C>(
+    packet_type: &str,
+    priority: i32,
+    handler: impl Fn(HandlerSources<S, R, C>, P) -> BoxFuture<'static, HandlerFlow>
+    + Send
+    + Sync
+    + 'static,
+) where
+    P: Packet + 'static,
+    S: Session + 'static,
+    R: Resource + 'static,
+    C: Resource + 'static,
+{
+    let key = registry_key::<P, S, R, C>(packet_type);
 
     // Wrap the handler in an Arc
-    let handler = Arc::new(handler) as HandlerFn<P, S, R>;
+    let handler = Arc::new(handler) as FlowHandlerFn<P, S, R, C>;
 
     let registry = HANDLER_REGISTRY.get_or_init(|| Mutex::new(HashMap::new()));
     if let Ok(mut reg) = registry.lock() {
-        if let Some(existing) = reg.get_mut(&key) {
-            if let Some(handlers) = existing.downcast_mut::<Vec<HandlerFn<P, S, R>>>() {
-                handlers.push(handler);
-                return;
-            }
-            // If downcast fails, this is the first handler of this type
-            // Replace with a new Vec containing both the old and new handlers
-            if let Some(old_handler) = existing.downcast_ref::<HandlerFn<P, S, R>>() {
-                let mut handlers = Vec::new();
-                let old_handler_clone = old_handler.clone();
-                handlers.push(old_handler_clone);
-                handlers.push(handler);
-                reg.insert(key, Box::new(handlers));
-                return;
-            }
-        }
+        let entry = reg.entry(key).or_insert_with(|| RegistryEntry {
+            header: packet_type.to_string(),
+            handlers: Box::new(Vec::<(i32, FlowHandlerFn<P, S, R, C>)>::new()),
+            count: 0,
+        });
 
-        // If we get here, there was no existing handler, so add this one
-        reg.insert(key, Box::new(handler));
+        if let Some(handlers) = entry
+            .handlers
+            .downcast_mut::<Vec<(i32, FlowHandlerFn<P, S, R, C>)>>()
+        {
+            // Insert after every handler with the same or a higher priority
+            let index = handlers.partition_point(|(existing, _)| *existing >= priority);
+            handlers.insert(index, (priority, handler));
+            entry.count = handlers.len();
+        }
     }
 }
 
+/// Removes every handler registered for a header with the given packet,
+/// session and resource types.
+///
+/// Handlers registered for the same header with other types are left alone.
+///
+/// # Type Parameters
+///
+/// * `P` - The packet type implementing the `Packet` trait
+/// * `S` - The session type implementing the `Session` trait
+/// * `R` - The resource type implementing the `Resource` trait
+///
+/// # Arguments
+///
+/// * `packet_type` - The packet header string to remove handlers for
+///
+/// # Returns
+///
+/// * `usize` - The number of handlers removed
+///
+/// # Example
+///
+/// ```rust
+/// use tnet::handler_registry::deregister_handlers;
+///
+/// let removed = deregister_handlers::<MyPacket, MySession, MyResource>("LOGIN");
+/// println!("Removed {removed} LOGIN handlers");
+/// ```
+pub fn deregister_handlers<P, S, R>(packet_type: &str) -> usize
+where
+    P: Packet + 'static,
+    S: Session + 'static,
+    R: Resource + 'static,
+{
+    let key = registry_key::<P, S, R, ()>(packet_type);
+
+    HANDLER_REGISTRY
+        .get()
+        .and_then(|registry| registry.lock().ok())
+        .and_then(|mut reg| reg.remove(&key))
+        .map_or(0, |entry| entry.count)
+}
+
+/// Removes every handler registered for a header, whatever their types.
+///
+/// # Arguments
+///
+/// * `packet_type` - The packet header string to remove handlers for
+///
+/// # Returns
+///
+/// * `usize` - The number of handlers removed
+///
+/// # Example
+///
+/// ```rust
+/// use tnet::handler_registry::deregister_all_for_header;
+///
+/// let removed = deregister_all_for_header("LOGIN");
+/// ```
+pub fn deregister_all_for_header(packet_type: &str) -> usize {
+    let Some(mut reg) = HANDLER_REGISTRY
+        .get()
+        .and_then(|registry| registry.lock().ok())
+    else {
+        return 0;
+    };
+
+    let mut removed = 0;
+    reg.retain(|_, entry| {
+        if entry.header == packet_type {
+            removed += entry.count;
+            false
+        } else {
+            true
+        }
+    });
+    removed
+}
+
 /// Retrieves a handler for a specific packet type.
 ///
 /// This function looks up the first registered handler for the specified packet type
@@ -160,7 +416,7 @@ where
 /// Retrieves all handlers for a specific packet type.
 ///
 /// This function looks up all registered handlers for the specified packet type
-/// in the global registry.
+/// in the global registry, ordered by priority descending.
 ///
 /// # Type Parameters
 ///
@@ -194,47 +450,90 @@ where
     S: Session + 'static,
     R: Resource + 'static,
 {
-    // Create the key
-    let key = format!(
-        "{}_{}_{}_{}",
-        packet_type,
-        std::any::type_name::<P>(),
-        std::any::type_name::<S>(),
-        std::any::type_name::<R>()
-    );
+    get_flow_handlers::<P, S, R>(packet_type)
+        .into_iter()
+        .map(|handler| {
+            Arc::new(move |sources, packet| {
+                let handler = handler.clone();
+                Box::pin(async move {
+                    handler(sources, packet).await;
+                }) as BoxFuture<'static, ()>
+            }) as HandlerFn<P, S, R>
+        })
+        .collect()
+}
 
-    #[cfg(test)]
-    println!("Looking up handlers for key: {}", key);
+/// Retrieves all handlers for a specific packet type, including their flow result.
+///
+/// Returns the same handlers as `get_handlers`, in the same order, but each
+/// one reports a `HandlerFlow` so callers can stop the chain early. Handlers
+/// registered through `register_handler` always return `HandlerFlow::Continue`.
+///
+/// # Type Parameters
+///
+/// * `P` - The packet type implementing the `Packet` trait
+/// * `S` - The session type implementing the `Session` trait
+/// * `R` - The resource type implementing the `Resource` trait
+///
+/// # Arguments
+///
+/// * `packet_type` - The packet header string to look up
+///
+/// # Returns
+///
+/// * `Vec<FlowHandlerFn<P, S, R>>` - A vector of handler functions, empty if none found
+///
+/// # Example
+///
+/// ```rust
+/// use tnet::prelude::*;
+///
+/// for handler in get_flow_handlers::<MyPacket, MySession, MyResource>("LOGIN") {
+///     if handler(sources.clone(), packet.clone()).await == HandlerFlow::Stop {
+///         break;
+///     }
+/// }
+/// ```
+pub fn get_flow_handlers<P, S, R>(packet_type: &str) -> Vec<FlowHandlerFn<P, S, R>>
+where
+    P: Packet + 'static,
+    S: Session + 'static,
+    R: Resource + 'static,
+{
+    flow_handlers::<P, S, R, ()>(packet_type)
+}
+
+/// Gets the flow handlers registered for listeners with per-connection
+/// resources of type `C`, highest priority first.
+pub(crate) fn flow_handlers<P, S, R, C>(packet_type: &str) -> Vec<FlowHandlerFn<P, S, R, C>>
+where
+    P: Packet + 'static,
+    S: Session + 'static,
+    R: Resource + 'static,
+    C: Resource + 'static,
+{
+    let key = registry_key::<P, S, R, C>(packet_type);
+
+    debug!(key = %key, "Looking up handlers");
 
     // Look up the handler(s)
     let registry = HANDLER_REGISTRY.get_or_init(|| Mutex::new(HashMap::new()));
     if let Ok(reg) = registry.lock() {
-        #[cfg(test)]
-        {
-            println!("Registry contains {} entries", reg.len());
-            for k in reg.keys() {
-                println!("  Registry has key: {}", k);
-            }
-        }
+        debug!(entries = reg.len(), keys = ?reg.keys().collect::<Vec<_>>(), "Handler registry contents");
 
-        if let Some(handler) = reg.get(&key) {
-            // Try to downcast to Vec first
-            if let Some(handlers) = handler.downcast_ref::<Vec<HandlerFn<P, S, R>>>() {
-                #[cfg(test)]
-                println!("Found {} handlers for key: {}", handlers.len(), key);
-                return handlers.clone();
-            }
-
-            // If not a Vec, try as a single handler
-            if let Some(single_handler) = handler.downcast_ref::<HandlerFn<P, S, R>>() {
-                #[cfg(test)]
-                println!("Found single handler for key: {}", key);
-                return vec![single_handler.clone()];
-            }
+        if let Some(handlers) = reg.get(&key).and_then(|entry| {
+            entry
+                .handlers
+                .downcast_ref::<Vec<(i32, FlowHandlerFn<P, S, R, C>)>>()
+        }) {
+            debug!(key = %key, handlers = handlers.len(), "Found handlers");
+            return handlers
+                .iter()
+                .map(|(_, handler)| handler.clone())
+                .collect();
         }
 
-        #[cfg(test)]
-        println!("No handlers found for key: {}", key);
+        debug!(key = %key, "No handlers found");
     }
 
     Vec::new()
@@ -307,7 +606,7 @@ pub fn register_test_handler<P, S, R>(
 pub fn reset_registry() {
     if let Some(registry) = HANDLER_REGISTRY.get() {
         if let Ok(mut reg) = registry.lock() {
-            println!("Clearing handler registry with {} entries", reg.len());
+            debug!(entries = reg.len(), "Clearing handler registry");
             reg.clear();
         }
     }
@@ -21,6 +21,7 @@
 //!
 //! - [`AsyncClient`](asynch::client::AsyncClient): Client implementation for connecting to servers
 //! - [`AsyncListener`](asynch::listener::AsyncListener): Server implementation for handling connections
+//! - [`BlockingClient`](blocking::BlockingClient): Synchronous wrapper around `AsyncClient`
 //! - [`Packet`](packet::Packet): Trait for defining network packet formats
 //! - [`Session`](session::Session): Trait for managing client sessions
 //! - [`Authenticator`](asynch::authenticator::Authenticator): Handles authentication
@@ -99,9 +100,12 @@ use std::sync::Mutex;
 use once_cell::sync::Lazy;
 
 pub mod asynch;
+pub mod blocking;
+pub mod compression;
 pub mod encrypt;
 pub mod errors;
 pub mod macros;
+pub mod metrics;
 pub mod packet;
 pub mod phantom;
 pub mod resources;
@@ -114,17 +118,6 @@ pub mod prelude;
 mod tests;
 static PACKET_REGISTRY: Lazy<Mutex<Vec<(String, String)>>> = Lazy::new(|| Mutex::new(Vec::new()));
 
-pub fn refresh_packet_registry() -> bool {
-    use std::fs;
-    let temp_dir = std::env::temp_dir().join("tnet_registry");
-    let trigger_file = temp_dir.join("tnet_rebuild_trigger");
-
-    match fs::create_dir_all(&temp_dir) {
-        Ok(_) => fs::write(&trigger_file, "refresh").is_ok(),
-        Err(_) => false,
-    }
-}
-
 pub fn register_packet_type(field_name: &str, type_name: &str) {
     if let Ok(mut registry) = PACKET_REGISTRY.lock() {
         registry.push((field_name.to_string(), type_name.to_string()));
@@ -58,3 +58,117 @@ macro_rules! wrap_handler {
     };
 }
 
+/// Creates a wrapped handler from an async function returning `Result<(), Error>`.
+///
+/// Works like `wrap_handler!`, but when the function returns an error it is passed
+/// to the given error handler along with the same sources, so handlers can use `?`
+/// instead of reporting each failure themselves.
+///
+/// # Arguments
+///
+/// * The fallible handler function
+/// * The error handler the returned errors are routed to
+///
+/// # Returns
+///
+/// Returns an `Arc`-wrapped closure usable as an `AsyncListenerOkHandler`.
+///
+/// # Example
+///
+/// ```rust
+/// use tnet::{wrap_handler, wrap_try_handler};
+///
+/// async fn handle_login(
+///     sources: HandlerSources<MySession, MyResource>,
+///     packet: MyPacket,
+/// ) -> Result<(), Error> {
+///     let mut socket = sources.socket;
+///     check_credentials(&packet)?;
+///     socket.send(MyPacket::ok()).await
+/// }
+///
+/// let listener = AsyncListener::new(
+///     ("127.0.0.1", 8080),
+///     30,
+///     wrap_try_handler!(handle_login, handle_error),
+///     wrap_handler!(handle_error),
+/// )
+/// .await;
+/// ```
+#[macro_export]
+macro_rules! wrap_try_handler {
+    ($func:expr, $error_handler:expr) => {
+        std::sync::Arc::new(move |sources, packet| {
+            let error_sources = std::clone::Clone::clone(&sources);
+            Box::pin(async move {
+                if let Err(e) = $func(sources, packet).await {
+                    $error_handler(error_sources, e).await;
+                }
+            }) as std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send + 'static>>
+        })
+    };
+}
+
+/// Creates a wrapped handler from an async function returning the response to send.
+///
+/// The function returns `Option<P>`; a `Some` packet is sent back to the client
+/// through `HandlerSources::reply`, so it carries the session id and the request
+/// id of the packet it answers, and `None` sends nothing. This saves handlers from
+/// sending the response themselves and leaving the client waiting when they forget.
+///
+/// # Arguments
+///
+/// * The handler function returning the optional response
+/// * Optionally, the error handler that failures to send the response are routed to;
+///   without one they are ignored
+///
+/// # Returns
+///
+/// Returns an `Arc`-wrapped closure usable as an `AsyncListenerOkHandler`.
+///
+/// # Example
+///
+/// ```rust
+/// use tnet::{wrap_handler, wrap_reply_handler};
+///
+/// async fn handle_ping(
+///     _sources: HandlerSources<MySession, MyResource>,
+///     packet: MyPacket,
+/// ) -> Option<MyPacket> {
+///     (packet.header() == "PING").then(MyPacket::ok)
+/// }
+///
+/// let listener = AsyncListener::new(
+///     ("127.0.0.1", 8080),
+///     30,
+///     wrap_reply_handler!(handle_ping, handle_error),
+///     wrap_handler!(handle_error),
+/// )
+/// .await;
+/// ```
+#[macro_export]
+macro_rules! wrap_reply_handler {
+    ($func:expr) => {
+        std::sync::Arc::new(move |sources, packet| {
+            let mut reply_sources = std::clone::Clone::clone(&sources);
+            Box::pin(async move {
+                if let Some(response) = $func(sources, packet).await {
+                    let _ = reply_sources.reply(response).await;
+                }
+            }) as std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send + 'static>>
+        })
+    };
+    ($func:expr, $error_handler:expr) => {
+        std::sync::Arc::new(move |sources, packet| {
+            let mut reply_sources = std::clone::Clone::clone(&sources);
+            Box::pin(async move {
+                if let Some(response) = $func(sources, packet).await {
+                    let error_sources = std::clone::Clone::clone(&reply_sources);
+                    if let Err(e) = reply_sources.reply(response).await {
+                        $error_handler(error_sources, e).await;
+                    }
+                }
+            }) as std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send + 'static>>
+        })
+    };
+}
@@ -0,0 +1,199 @@
+//! Hooks for collecting connection and traffic metrics.
+//!
+//! Clients and listeners report what they do through the [`Metrics`] trait,
+//! so applications can forward the numbers to whatever monitoring system they
+//! use. [`NoopMetrics`] is used when nothing is configured, and
+//! [`AtomicMetrics`] keeps simple in-process counters that can be read back
+//! with [`AtomicMetrics::snapshot`].
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Events counted by a [`Metrics`] implementation.
+///
+/// # Variants
+///
+/// * `PacketsSent` - A packet was handed to the connection for writing
+/// * `PacketsReceived` - A packet was read from the connection
+/// * `ConnectionsOpened` - A connection was established
+/// * `ConnectionsClosed` - An established connection was closed or lost
+/// * `AuthSuccesses` - A client passed authentication
+/// * `AuthFailures` - A client failed authentication
+/// * `ReconnectAttempts` - The client tried to reconnect to the server
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Counter {
+    PacketsSent,
+    PacketsReceived,
+    ConnectionsOpened,
+    ConnectionsClosed,
+    AuthSuccesses,
+    AuthFailures,
+    ReconnectAttempts,
+}
+
+/// Values observed by a [`Metrics`] implementation.
+///
+/// # Variants
+///
+/// * `BytesSent` - Size of an outgoing payload, after compression and encryption
+/// * `BytesReceived` - Size of an incoming payload, before decryption and decompression
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Observation {
+    BytesSent,
+    BytesReceived,
+}
+
+/// Receives metrics from clients and listeners.
+///
+/// Methods are called inline on the connection's hot path, so implementations
+/// should be cheap and must not block.
+///
+/// # Example
+///
+/// ```rust
+/// use tnet::metrics::{Counter, Metrics, Observation};
+///
+/// struct StatsdMetrics;
+///
+/// impl Metrics for StatsdMetrics {
+///     fn increment(&self, counter: Counter) {
+///         // Forward the counter to statsd
+///     }
+///
+///     fn observe(&self, observation: Observation, value: u64) {
+///         // Record the value in a histogram
+///     }
+/// }
+/// ```
+pub trait Metrics: Send + Sync {
+    /// Increments a counter by one.
+    ///
+    /// # Arguments
+    ///
+    /// * `counter` - The counter to increment
+    fn increment(&self, counter: Counter);
+
+    /// Records an observed value.
+    ///
+    /// # Arguments
+    ///
+    /// * `observation` - What was observed
+    /// * `value` - The observed value
+    fn observe(&self, observation: Observation, value: u64);
+}
+
+/// A `Metrics` implementation that discards everything.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoopMetrics;
+
+impl Metrics for NoopMetrics {
+    fn increment(&self, _counter: Counter) {}
+
+    fn observe(&self, _observation: Observation, _value: u64) {}
+}
+
+/// A point-in-time copy of the counters kept by [`AtomicMetrics`].
+///
+/// # Fields
+///
+/// * `packets_sent` - Packets handed to the connection for writing
+/// * `packets_received` - Packets read from the connection
+/// * `bytes_sent` - Total size of the outgoing payloads
+/// * `bytes_received` - Total size of the incoming payloads
+/// * `connections_opened` - Connections established so far
+/// * `active_connections` - Connections currently open
+/// * `auth_successes` - Successful authentications
+/// * `auth_failures` - Failed authentications
+/// * `reconnect_attempts` - Reconnection attempts made by the client
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MetricsSnapshot {
+    pub packets_sent: u64,
+    pub packets_received: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub connections_opened: u64,
+    pub active_connections: u64,
+    pub auth_successes: u64,
+    pub auth_failures: u64,
+    pub reconnect_attempts: u64,
+}
+
+/// A `Metrics` implementation backed by atomic counters.
+///
+/// # Example
+///
+/// ```rust
+/// use std::sync::Arc;
+/// use tnet::metrics::AtomicMetrics;
+///
+/// let metrics = Arc::new(AtomicMetrics::new());
+/// let client = AsyncClient::<MyPacket>::new("127.0.0.1", 8080)
+///     .await?
+///     .with_metrics(metrics.clone());
+///
+/// println!("{:?}", metrics.snapshot());
+/// ```
+#[derive(Debug, Default)]
+pub struct AtomicMetrics {
+    packets_sent: AtomicU64,
+    packets_received: AtomicU64,
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+    connections_opened: AtomicU64,
+    connections_closed: AtomicU64,
+    auth_successes: AtomicU64,
+    auth_failures: AtomicU64,
+    reconnect_attempts: AtomicU64,
+}
+
+impl AtomicMetrics {
+    /// Creates a new set of counters, all starting at zero.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads the current value of every counter.
+    ///
+    /// # Returns
+    ///
+    /// * `MetricsSnapshot` - The counter values at the time of the call
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        let connections_opened = self.connections_opened.load(Ordering::Relaxed);
+        let connections_closed = self.connections_closed.load(Ordering::Relaxed);
+
+        MetricsSnapshot {
+            packets_sent: self.packets_sent.load(Ordering::Relaxed),
+            packets_received: self.packets_received.load(Ordering::Relaxed),
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+            connections_opened,
+            active_connections: connections_opened.saturating_sub(connections_closed),
+            auth_successes: self.auth_successes.load(Ordering::Relaxed),
+            auth_failures: self.auth_failures.load(Ordering::Relaxed),
+            reconnect_attempts: self.reconnect_attempts.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Metrics for AtomicMetrics {
+    fn increment(&self, counter: Counter) {
+        let counter = match counter {
+            Counter::PacketsSent => &self.packets_sent,
+            Counter::PacketsReceived => &self.packets_received,
+            Counter::ConnectionsOpened => &self.connections_opened,
+            Counter::ConnectionsClosed => &self.connections_closed,
+            Counter::AuthSuccesses => &self.auth_successes,
+            Counter::AuthFailures => &self.auth_failures,
+            Counter::ReconnectAttempts => &self.reconnect_attempts,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn observe(&self, observation: Observation, value: u64) {
+        let total = match observation {
+            Observation::BytesSent => &self.bytes_sent,
+            Observation::BytesReceived => &self.bytes_received,
+        };
+        total.fetch_add(value, Ordering::Relaxed);
+    }
+}
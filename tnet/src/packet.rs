@@ -11,10 +11,20 @@ use crate::{encrypt::Encryptor, errors::Error};
 ///
 /// * `username`: Optional username for authentication
 /// * `password`: Optional password for authentication
+/// * `token`: Optional pre-shared key or API token for authentication
+/// * `challenge`: Optional nonce the server asks the client to sign
+/// * `challenge_response`: Optional answer to a server challenge
 /// * `session_id`: Optional session identifier for maintaining state
 /// * `error_string`: Optional error message for error handling
 /// * `is_first_keep_alive_packet`: Optional flag for initial keepalive packets
 /// * `is_broadcast_packet`: Optional flag for broadcast messages
+/// * `request_id`: Optional id correlating a request with its response
+/// * `is_disconnect_packet`: Optional flag for clean disconnect notices
+/// * `is_stream_end`: Optional flag marking the last response of a stream
+/// * `is_ping_packet`: Optional flag for latency probes
+/// * `is_pong_packet`: Optional flag for the server's answers to latency probes
+/// * `attachment`: Optional binary payload, such as a file chunk
+/// * `chunk`: Optional position within a chunked transfer
 ///
 /// # Example
 ///
@@ -24,20 +34,41 @@ use crate::{encrypt::Encryptor, errors::Error};
 /// let body = PacketBody {
 ///     username: Some("user123".to_string()),
 ///     password: Some("pass123".to_string()),
+///     token: None,
+///     challenge: None,
+///     challenge_response: None,
 ///     session_id: None,
 ///     error_string: None,
 ///     is_first_keep_alive_packet: Some(false),
 ///     is_broadcast_packet: None,
+///     request_id: None,
+///     is_disconnect_packet: None,
+///     is_stream_end: None,
+///     is_ping_packet: None,
+///     is_pong_packet: None,
+///     attachment: None,
+///     chunk: None,
 /// };
 /// ```
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct PacketBody {
     pub username: Option<String>,
     pub password: Option<String>,
+    pub token: Option<String>,
+    pub challenge: Option<String>,
+    pub challenge_response: Option<String>,
     pub session_id: Option<String>,
     pub error_string: Option<String>,
     pub is_first_keep_alive_packet: Option<bool>,
     pub is_broadcast_packet: Option<bool>,
+    pub request_id: Option<u64>,
+    pub is_disconnect_packet: Option<bool>,
+    pub is_stream_end: Option<bool>,
+    pub is_ping_packet: Option<bool>,
+    pub is_pong_packet: Option<bool>,
+    #[serde(default, with = "attachment_bytes")]
+    pub attachment: Option<Vec<u8>>,
+    pub chunk: Option<PacketChunk>,
 }
 
 impl PacketBody {
@@ -80,6 +111,230 @@ impl PacketBody {
             ..Default::default()
         }
     }
+
+    /// Creates a new packet body carrying binary data.
+    ///
+    /// Binary formats such as bincode and MessagePack store the bytes as-is.
+    /// Text formats such as the default JSON carry them as a base64 string,
+    /// about a third larger. Enable compression on the connection when sending
+    /// large or compressible attachments.
+    ///
+    /// # Arguments
+    ///
+    /// * `data`: The binary data to attach
+    ///
+    /// # Returns
+    ///
+    /// * A new `PacketBody` instance with the specified attachment
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tnet::packet::PacketBody;
+    ///
+    /// let chunk = std::fs::read("image.png")?;
+    /// let body = PacketBody::with_attachment(chunk);
+    /// ```
+    #[must_use]
+    pub fn with_attachment(data: impl Into<Vec<u8>>) -> Self {
+        Self {
+            attachment: Some(data.into()),
+            ..Default::default()
+        }
+    }
+
+    /// Returns the attached binary data, if any.
+    ///
+    /// # Returns
+    ///
+    /// * The attachment's bytes, or None if the body has no attachment
+    #[must_use]
+    pub fn attachment(&self) -> Option<&[u8]> {
+        self.attachment.as_deref()
+    }
+}
+
+/// Marks a packet as one piece of a chunked transfer.
+///
+/// `AsyncClient::send_chunked` splits a large attachment over several packets
+/// carrying this marker, and the listener puts the attachment back together
+/// before the packet reaches the handlers.
+///
+/// # Fields
+///
+/// * `transfer_id`: Identifies the transfer among others on the same connection
+/// * `index`: Zero-based position of this chunk in the transfer
+/// * `count`: Number of chunks in the transfer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PacketChunk {
+    pub transfer_id: u64,
+    pub index: u32,
+    pub count: u32,
+}
+
+/// Encodes `PacketBody::attachment` as raw bytes in binary formats and as a
+/// base64 string in human-readable ones, rather than as a list of numbers.
+///
+/// Decoding also accepts a list of numbers, as sent by older peers.
+mod attachment_bytes {
+    use std::fmt;
+
+    use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+    use serde::{
+        Deserializer, Serialize, Serializer,
+        de::{self, SeqAccess, Visitor},
+    };
+
+    struct RawBytes<'a>(&'a [u8]);
+
+    impl Serialize for RawBytes<'_> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_bytes(self.0)
+        }
+    }
+
+    pub fn serialize<S: Serializer>(
+        data: &Option<Vec<u8>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match data {
+            Some(data) if serializer.is_human_readable() => {
+                serializer.serialize_some(&BASE64.encode(data))
+            }
+            Some(data) => serializer.serialize_some(&RawBytes(data)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Vec<u8>>, D::Error> {
+        deserializer.deserialize_option(AttachmentVisitor)
+    }
+
+    struct AttachmentVisitor;
+
+    impl<'de> Visitor<'de> for AttachmentVisitor {
+        type Value = Option<Vec<u8>>;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("an optional attachment")
+        }
+
+        fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_some<D: Deserializer<'de>>(
+            self,
+            deserializer: D,
+        ) -> Result<Self::Value, D::Error> {
+            if deserializer.is_human_readable() {
+                deserializer.deserialize_any(BytesVisitor).map(Some)
+            } else {
+                deserializer.deserialize_byte_buf(BytesVisitor).map(Some)
+            }
+        }
+    }
+
+    struct BytesVisitor;
+
+    impl<'de> Visitor<'de> for BytesVisitor {
+        type Value = Vec<u8>;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("bytes, a base64 string or a list of bytes")
+        }
+
+        fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+            Ok(v.to_vec())
+        }
+
+        fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<Self::Value, E> {
+            Ok(v)
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+            BASE64.decode(v).map_err(E::custom)
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            let mut data = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(4096));
+            while let Some(byte) = seq.next_element()? {
+                data.push(byte);
+            }
+            Ok(data)
+        }
+    }
+}
+
+/// The wire format used to encode a packet before it is (optionally) encrypted
+/// and written to the socket.
+///
+/// Both ends of a connection must agree on the format, which is why it is
+/// declared on the packet type itself through [`Packet::format`].
+///
+/// # Variants
+///
+/// * `Bincode` - Compact binary encoding, Rust-to-Rust only
+/// * `Json` - Human readable, the default and easiest to debug
+/// * `MessagePack` - Compact binary encoding with broad cross-language support
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SerializationFormat {
+    Bincode,
+    #[default]
+    Json,
+    MessagePack,
+}
+
+impl SerializationFormat {
+    /// Serializes a value using this format.
+    ///
+    /// # Arguments
+    ///
+    /// * `value`: The value to serialize
+    ///
+    /// # Returns
+    ///
+    /// * The encoded bytes
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Serialization` if the value cannot be encoded
+    pub fn serialize<T: Serialize + ?Sized>(self, value: &T) -> Result<Vec<u8>, Error> {
+        match self {
+            Self::Bincode => bincode::serialize(value).map_err(|e| e.to_string()),
+            Self::Json => serde_json::to_vec(value).map_err(|e| e.to_string()),
+            Self::MessagePack => rmp_serde::to_vec_named(value).map_err(|e| e.to_string()),
+        }
+        .map_err(Error::Serialization)
+    }
+
+    /// Deserializes a value using this format.
+    ///
+    /// # Arguments
+    ///
+    /// * `data`: The encoded bytes
+    ///
+    /// # Returns
+    ///
+    /// * The decoded value
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Serialization` if the bytes are not a valid encoding of `T`
+    pub fn deserialize<T: DeserializeOwned>(self, data: &[u8]) -> Result<T, Error> {
+        match self {
+            Self::Bincode => bincode::deserialize(data).map_err(|e| e.to_string()),
+            Self::Json => serde_json::from_slice(data).map_err(|e| e.to_string()),
+            Self::MessagePack => rmp_serde::from_slice(data).map_err(|e| e.to_string()),
+        }
+        .map_err(Error::Serialization)
+    }
 }
 
 /// The `Packet` trait defines the interface for network communication packets.
@@ -142,6 +397,17 @@ impl PacketBody {
 /// }
 /// ```
 pub trait Packet: Serialize + DeserializeOwned + Clone + Send + Sync {
+    /// The wire format used by `ser`, `de` and their encrypted counterparts.
+    ///
+    /// Override this to switch a packet type away from JSON.
+    ///
+    /// # Returns
+    ///
+    /// * The `SerializationFormat` for this packet type
+    fn format() -> SerializationFormat {
+        SerializationFormat::Json
+    }
+
     /// Serializes and encrypts the packet using the provided encryptor.
     ///
     /// # Arguments
@@ -152,11 +418,11 @@ pub trait Packet: Serialize + DeserializeOwned + Clone + Send + Sync {
     ///
     /// * A Vec<u8> containing the encrypted packet data
     fn encrypted_ser(&self, encryptor: &Encryptor) -> Vec<u8> {
-        let json_data = serde_json::to_string(self).expect("Failed to serialize packet to JSON");
+        let data = Self::format()
+            .serialize(self)
+            .expect("Failed to serialize packet");
 
-        let encrypted = encryptor
-            .encrypt(json_data.as_bytes())
-            .expect("Failed to encrypt data");
+        let encrypted = encryptor.encrypt(&data).expect("Failed to encrypt data");
 
         encrypted.as_bytes().to_vec()
     }
@@ -179,7 +445,8 @@ pub trait Packet: Serialize + DeserializeOwned + Clone + Send + Sync {
             .decrypt(&encrypted_str)
             .unwrap_or_else(|e| panic!("Decryption failed: {}", e));
 
-        serde_json::from_slice(&decrypted)
+        Self::format()
+            .deserialize(&decrypted)
             .unwrap_or_else(|e| panic!("Failed to deserialize packet: {}", e))
     }
 
@@ -189,7 +456,7 @@ pub trait Packet: Serialize + DeserializeOwned + Clone + Send + Sync {
     ///
     /// * A Vec<u8> containing the serialized packet data
     fn ser(&self) -> Vec<u8> {
-        serde_json::to_vec(self).unwrap()
+        Self::format().serialize(self).unwrap()
     }
 
     /// Serializes the packet to a JSON string.
@@ -212,7 +479,9 @@ pub trait Packet: Serialize + DeserializeOwned + Clone + Send + Sync {
     /// * A new instance of the implementing type
     #[must_use]
     fn de(data: &[u8]) -> Self {
-        serde_json::from_slice(data).unwrap_or_else(|_| Self::ok())
+        Self::format()
+            .deserialize(data)
+            .unwrap_or_else(|_| Self::ok())
     }
 
     /// Converts serialized packet data to a JSON string.
@@ -323,6 +592,72 @@ pub trait Packet: Serialize + DeserializeOwned + Clone + Send + Sync {
     /// * A new instance representing a keepalive message
     fn keep_alive() -> Self;
 
+    /// Creates a packet telling the server the client is leaving cleanly.
+    ///
+    /// The listener runs its disconnect handler when one of these arrives and then
+    /// drops the connection, instead of waiting for the read to fail.
+    ///
+    /// # Returns
+    ///
+    /// * A new instance marked as a disconnect notice
+    fn disconnect() -> Self {
+        let mut packet = Self::ok();
+        packet.body_mut().is_disconnect_packet = Some(true);
+        packet
+    }
+
+    /// Checks if this is a disconnect packet.
+    ///
+    /// # Returns
+    ///
+    /// * true if the sender is closing the connection, false otherwise
+    fn is_disconnect(&self) -> bool {
+        self.body().is_disconnect_packet.unwrap_or(false)
+    }
+
+    /// Creates a packet asking the server to answer straight away.
+    ///
+    /// The listener replies with `pong` itself instead of passing the packet to
+    /// a handler, so the round trip only measures the connection.
+    ///
+    /// # Returns
+    ///
+    /// * A new instance marked as a ping
+    fn ping() -> Self {
+        let mut packet = Self::ok();
+        packet.body_mut().is_ping_packet = Some(true);
+        packet
+    }
+
+    /// Creates the server's answer to a ping.
+    ///
+    /// # Returns
+    ///
+    /// * A new "OK" instance marked as a ping answer
+    fn pong() -> Self {
+        let mut packet = Self::ok();
+        packet.body_mut().is_pong_packet = Some(true);
+        packet
+    }
+
+    /// Checks if this is a ping.
+    ///
+    /// # Returns
+    ///
+    /// * true if the packet is a latency probe, false otherwise
+    fn is_ping(&self) -> bool {
+        self.body().is_ping_packet.unwrap_or(false)
+    }
+
+    /// Checks if this is the server's answer to a ping.
+    ///
+    /// # Returns
+    ///
+    /// * true if the packet answers a latency probe, false otherwise
+    fn is_pong(&self) -> bool {
+        self.body().is_pong_packet.unwrap_or(false)
+    }
+
     /// Marks the packet as a broadcast packet.
     ///
     /// # Returns
@@ -336,12 +671,74 @@ pub trait Packet: Serialize + DeserializeOwned + Clone + Send + Sync {
 
     /// Checks if this is a broadcast packet.
     ///
+    /// The flag set by `set_broadcasting` travels with the packet, so clients
+    /// use this to tell broadcasts apart from replies to their own requests.
+    ///
     /// # Returns
     ///
     /// * true if this is a broadcast packet, false otherwise
-    fn is_broadcasting(&self) -> bool {
+    fn is_broadcast(&self) -> bool {
         self.body().is_broadcast_packet.unwrap_or(false)
     }
+
+    /// Checks if this is a broadcast packet.
+    ///
+    /// # Returns
+    ///
+    /// * true if this is a broadcast packet, false otherwise
+    #[deprecated(note = "renamed to `is_broadcast`")]
+    fn is_broadcasting(&self) -> bool {
+        self.is_broadcast()
+    }
+
+    /// Marks the packet as the last response of a stream.
+    ///
+    /// # Returns
+    ///
+    /// * A new instance that ends the client's `send_recv_stream`
+    #[must_use]
+    fn set_stream_end(mut self) -> Self {
+        self.body_mut().is_stream_end = Some(true);
+        self
+    }
+
+    /// Checks if this packet ends a response stream.
+    ///
+    /// # Returns
+    ///
+    /// * true if this is the last response of a stream, false otherwise
+    fn is_stream_end(&self) -> bool {
+        self.body().is_stream_end.unwrap_or(false)
+    }
+
+    /// Checks the packet's invariants before it reaches the handlers.
+    ///
+    /// The listener calls this on every received packet other than keepalives
+    /// and disconnect notices, and hands a failure to the error handler instead
+    /// of dispatching the packet. The default accepts every packet.
+    ///
+    /// # Returns
+    ///
+    /// * Ok(()) if the packet is valid, Error otherwise
+    ///
+    /// # Errors
+    ///
+    /// Returns the error describing why the packet is invalid, conventionally
+    /// `Error::InvalidPacket`
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// fn validate(&self) -> Result<(), Error> {
+    ///     if self.body().username.is_none() {
+    ///         return Err(Error::InvalidPacket("missing username".to_string()));
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    fn validate(&self) -> Result<(), Error> {
+        Ok(())
+    }
 }
 
 pub mod registry {
@@ -360,10 +757,7 @@ pub mod registry {
             let mut registry = PACKET_REGISTRY.write().unwrap();
             registry.insert(field_name.to_string(), type_id);
         }
-        println!(
-            "Registered packet type: {} with type_id {:?}",
-            field_name, type_id
-        );
+        tracing::debug!(field = field_name, ?type_id, "Registered packet type");
     }
 
     // Generic function to register a type
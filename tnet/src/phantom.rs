@@ -26,6 +26,9 @@ use crate::{
 /// * `server_addr` - The target server address
 /// * `server_port` - The target server port
 /// * `enc_conf` - Encryption configuration for the connection
+/// * `hops` - Further relays to pass through, in order, before the target server
+/// * `endpoints` - Further target servers sharing the load with `server_addr`
+/// * `load_balance` - How the last relay spreads packets across the target servers
 ///
 /// # Example
 ///
@@ -39,6 +42,9 @@ use crate::{
 ///     server_addr: "target.server.com",
 ///     server_port: 8080,
 ///     enc_conf: EncryptionConfig::default_on(),
+///     hops: &[],
+///     endpoints: &[],
+///     load_balance: LoadBalanceStrategy::RoundRobin,
 /// };
 ///
 /// // Convert to ClientConfig
@@ -52,6 +58,9 @@ pub struct PhantomConf<'a> {
     pub server_addr: &'a str,
     pub server_port: u16,
     pub enc_conf: EncryptionConfig,
+    pub hops: &'a [Self],
+    pub endpoints: &'a [Self],
+    pub load_balance: LoadBalanceStrategy,
 }
 
 impl<'a> From<&'a ClientConfig> for PhantomConf<'a> {
@@ -63,10 +72,29 @@ impl<'a> From<&'a ClientConfig> for PhantomConf<'a> {
             password: value.pass.as_deref(),
             server_addr: value.server_addr.as_str(),
             server_port: value.server_port,
+            hops: &[],
+            endpoints: &[],
+            load_balance: LoadBalanceStrategy::default(),
         }
     }
 }
 
+/// How a relay spreads packets across a pool of target servers.
+///
+/// Whatever the strategy, a client keeps being relayed to the server it was
+/// first assigned to, so session state on the target servers stays intact.
+///
+/// # Variants
+///
+/// * `RoundRobin` - Assign new clients to the servers in turn
+/// * `LeastConnections` - Assign new clients to the server with the fewest relays in progress
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum LoadBalanceStrategy {
+    #[default]
+    RoundRobin,
+    LeastConnections,
+}
+
 /// Configuration for a phantom client connection.
 ///
 /// `ClientConfig` contains all the information needed for a phantom client to
@@ -128,6 +156,14 @@ impl From<&PhantomConf<'_>> for ClientConfig {
 /// * `sent_packet` - Optional serialized packet to be sent to the target server
 /// * `recv_packet` - Optional serialized response from the target server
 /// * `client_config` - Optional configuration for connecting to the target server
+/// * `hops` - Relays the packet still has to pass through before the target server
+/// * `endpoints` - Further target servers sharing the load with `client_config`
+/// * `load_balance` - How the last relay spreads packets across the target servers
+///
+/// Each relay pops the first entry of `hops` and forwards the packet to it with
+/// the remaining hops. The relay that receives the packet with no hops left
+/// delivers it to the target server described by `client_config`, or to one of
+/// the `endpoints` when a pool of target servers is configured.
 ///
 /// # Example
 ///
@@ -149,6 +185,9 @@ impl From<&PhantomConf<'_>> for ClientConfig {
 ///     server_addr: "target.com",
 ///     server_port: 8080,
 ///     enc_conf: EncryptionConfig::default(),
+///     hops: &[],
+///     endpoints: &[],
+///     load_balance: LoadBalanceStrategy::RoundRobin,
 /// };
 ///
 /// // Create the packet to relay
@@ -164,6 +203,12 @@ pub struct PhantomPacket {
     pub sent_packet: Option<String>,
     pub recv_packet: Option<String>,
     pub client_config: Option<ClientConfig>,
+    #[serde(default)]
+    pub hops: Vec<ClientConfig>,
+    #[serde(default)]
+    pub endpoints: Vec<ClientConfig>,
+    #[serde(default)]
+    pub load_balance: LoadBalanceStrategy,
 }
 
 impl PhantomPacket {
@@ -195,6 +240,9 @@ impl PhantomPacket {
         Self {
             header: conf.header.to_string(),
             client_config: Some(ClientConfig::from(conf)),
+            hops: conf.hops.iter().map(ClientConfig::from).collect(),
+            endpoints: conf.endpoints.iter().map(ClientConfig::from).collect(),
+            load_balance: conf.load_balance,
             sent_packet: Some(up_ser),
             ..Default::default()
         }
@@ -258,6 +306,9 @@ impl Packet for PhantomPacket {
             sent_packet: None,
             recv_packet: None,
             client_config: None,
+            hops: Vec::new(),
+            endpoints: Vec::new(),
+            load_balance: LoadBalanceStrategy::default(),
         }
     }
 
@@ -285,6 +336,9 @@ impl Default for PhantomPacket {
             sent_packet: None,
             recv_packet: None,
             client_config: None,
+            hops: Vec::new(),
+            endpoints: Vec::new(),
+            load_balance: LoadBalanceStrategy::default(),
         }
     }
 }
@@ -13,31 +13,52 @@
 
 pub use crate::{
     asynch::{
-        authenticator::{AuthFunction, AuthType, Authenticator},
-        client::{AsyncClient, ClientEncryption, EncryptionConfig},
+        authenticator::{
+            AuthFunction, AuthType, Authenticator, SessionIdContext, SessionIdFunction,
+        },
+        client::{
+            AsyncClient, ClientEncryption, ConnectionStatus, EncryptionConfig, SendRecvOptions,
+            TimeoutConfig,
+        },
         listener::{
-            AsyncListener, AsyncListenerErrorHandler, AsyncListenerOkHandler, HandlerSources,
-            PoolRef, ResourceRef,
+            AsyncListener, AsyncListenerErrorHandler, AsyncListenerOkHandler, BindOptions,
+            CleanupSchedule, DispatchMode, HandlerSources, MaxConnPolicy, Middleware,
+            MiddlewareFlow, PoolRef, ResourceRef, RunningServer,
         },
         phantom_client::AsyncPhantomClient,
-        phantom_listener::{PhantomListener, PhantomResources, PhantomSession},
-        socket::TSocket,
+        phantom_listener::{
+            PhantomListener, PhantomResources, PhantomSession, RelayCacheConfig, RelayInterceptor,
+        },
+        rate_limit::RateLimitConfig,
+        socket::{BatchConfig, BroadcastReport, PreparedBroadcast, TSocket},
     },
+    blocking::BlockingClient,
     include_tnet_packet,
-    phantom::{ClientConfig, PhantomConf, PhantomPacket},
+    phantom::{ClientConfig, LoadBalanceStrategy, PhantomConf, PhantomPacket},
 };
 
-pub use crate::handler_registry::{HandlerRegistration, get_handler, register_handler};
+pub use crate::handler_registry::{
+    HandlerFlow, HandlerRegistration, deregister_all_for_header, deregister_handlers, get_handler,
+    register_flow_handler, register_flow_handler_with_priority, register_handler,
+    register_handler_with_priority,
+};
 
 pub use std::str::FromStr;
 pub use tnet_macros::{ParseEnumString, register_scan_dir, tlisten_for, tpacket};
 
-pub use crate::encrypt::{Encryptor, KeyExchange};
+pub use crate::compression::{CompressionAlgorithm, CompressionConfig};
+pub use crate::encrypt::{CipherSuite, EncryptError, Encryptor, KeyExchange};
 pub use crate::errors::Error;
-pub use crate::packet::{Packet as ImplPacket, PacketBody};
+pub use crate::metrics::{AtomicMetrics, Metrics, MetricsSnapshot, NoopMetrics};
+pub use crate::packet::{Packet as ImplPacket, PacketBody, SerializationFormat};
 pub use crate::resources::Resource as ImplResource;
-pub use crate::session::{Session as ImplSession, Sessions};
+pub use crate::session::{
+    JsonFileSessionStore, Session as ImplSession, SessionExpiryPolicy, SessionStore,
+    SessionSummary, Sessions,
+};
 pub use crate::wrap_handler;
+pub use crate::wrap_reply_handler;
+pub use crate::wrap_try_handler;
 
 pub use futures::future::BoxFuture;
 pub use serde::de::DeserializeOwned;
@@ -1,6 +1,24 @@
+use std::future::Future;
 
 /// Resource struct holds anything you find relevant that you need
 /// on a per packet basis.
 pub trait Resource: Clone + Send + Sync {
     fn new() -> Self;
-}
\ No newline at end of file
+
+    /// Creates the resource with asynchronous setup, such as opening a
+    /// database pool or loading a file.
+    ///
+    /// Used by `AsyncListener::with_resource_async`. Defaults to `new`.
+    ///
+    /// # Returns
+    ///
+    /// * A future resolving to the initialized resource
+    fn new_async() -> impl Future<Output = Self> + Send {
+        async { Self::new() }
+    }
+}
+
+/// No resources, the default for per-connection resources.
+impl Resource for () {
+    fn new() -> Self {}
+}
@@ -1,11 +1,24 @@
 use std::{
+    collections::{BTreeMap, HashMap, HashSet},
     fmt::Debug,
+    io,
+    path::PathBuf,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
-use serde::{de::DeserializeOwned, Serialize};
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use tokio::sync::RwLock;
 
-use crate::encrypt::Encryptor;
+use crate::{encrypt::Encryptor, errors::Error};
+
+/// Metadata key under which the listener records the username a session
+/// authenticated with.
+pub const USERNAME_META_KEY: &str = "tnet.username";
 
 /// `Sessions` is a container type that manages a collection of session instances.
 /// It provides functionality for creating, retrieving, and managing sessions.
@@ -31,12 +44,17 @@ use crate::encrypt::Encryptor;
 ///
 /// let mut sessions = Sessions::<MySession>::new();
 /// ```
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound = "")]
 pub struct Sessions<S>
 where
     S: Session,
 {
     sessions: Vec<S>,
+    #[serde(default)]
+    last_active: BTreeMap<String, u64>,
+    #[serde(default)]
+    metadata: BTreeMap<String, HashMap<String, String>>,
 }
 
 impl<S> Sessions<S>
@@ -47,6 +65,8 @@ where
     pub const fn new() -> Self {
         Self {
             sessions: Vec::new(),
+            last_active: BTreeMap::new(),
+            metadata: BTreeMap::new(),
         }
     }
 
@@ -79,7 +99,7 @@ where
     /// # Returns
     ///
     /// * `Option<&S>`: Some reference to the session if found, None otherwise
-    #[must_use] 
+    #[must_use]
     pub fn get_session(&self, id: &str) -> Option<&S> {
         self.sessions.iter().find(|s| s.id() == id)
     }
@@ -104,12 +124,139 @@ where
     /// * `id`: The ID of the session to delete
     pub fn delete_session(&mut self, id: &str) {
         self.sessions.retain(|s| s.id() != id);
+        self.last_active.remove(id);
+        self.metadata.remove(id);
+    }
+
+    /// Records activity on a session, restarting its lifespan under
+    /// `SessionExpiryPolicy::Sliding`. Unknown IDs are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `id`: The ID of the session that was active
+    ///
+    /// # Returns
+    ///
+    /// * `true` if the recorded activity time changed, `false` otherwise
+    pub fn touch(&mut self, id: &str) -> bool {
+        if self.get_session(id).is_none() {
+            return false;
+        }
+
+        let now = unix_now();
+        self.last_active.insert(id.to_string(), now) != Some(now)
+    }
+
+    /// Returns when a session was last active, if activity has been recorded for it.
+    ///
+    /// # Arguments
+    ///
+    /// * `id`: The ID of the session
+    #[must_use]
+    pub fn last_active(&self, id: &str) -> Option<u64> {
+        self.last_active.get(id).copied()
+    }
+
+    /// Retrieves a metadata value stored on a session.
+    ///
+    /// # Arguments
+    ///
+    /// * `id`: The ID of the session
+    /// * `key`: The metadata key
+    ///
+    /// # Returns
+    ///
+    /// * `Option<&str>`: The value if the session has one for `key`, None otherwise
+    #[must_use]
+    pub fn get_meta(&self, id: &str, key: &str) -> Option<&str> {
+        self.metadata
+            .get(id)
+            .and_then(|meta| meta.get(key))
+            .map(String::as_str)
+    }
+
+    /// Stores a metadata value on a session, replacing any previous value for `key`.
+    ///
+    /// # Arguments
+    ///
+    /// * `id`: The ID of the session
+    /// * `key`: The metadata key
+    /// * `value`: The value to store
+    ///
+    /// # Returns
+    ///
+    /// * `true` if the session exists and the value was stored, `false` otherwise
+    pub fn set_meta(&mut self, id: &str, key: &str, value: String) -> bool {
+        if self.get_session(id).is_none() {
+            return false;
+        }
+
+        self.metadata
+            .entry(id.to_string())
+            .or_default()
+            .insert(key.to_string(), value);
+        true
+    }
+
+    /// Returns the number of sessions in the container, expired or not.
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.sessions.len()
+    }
+
+    /// Returns `true` if the container holds no sessions.
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.sessions.is_empty()
+    }
+
+    /// Summarizes every session in the container.
+    ///
+    /// # Arguments
+    ///
+    /// * `policy`: How each session's expiry time is measured
+    ///
+    /// # Returns
+    ///
+    /// * A summary of each session, in the order they were added
+    #[must_use]
+    pub fn summaries(&self, policy: SessionExpiryPolicy) -> Vec<SessionSummary> {
+        self.sessions
+            .iter()
+            .map(|s| SessionSummary {
+                id: s.id().to_string(),
+                created_at: s.created_at(),
+                expires_at: policy.expires_at(s, self.last_active(s.id())),
+            })
+            .collect()
     }
 
     /// Removes all expired sessions from the container.
     /// This should be called periodically to clean up expired sessions.
     pub fn clear_expired(&mut self) {
-        self.sessions.retain(|s| !s.is_expired());
+        self.clear_expired_with(SessionExpiryPolicy::Fixed);
+    }
+
+    /// Removes all sessions that have expired under the given policy.
+    ///
+    /// # Arguments
+    ///
+    /// * `policy`: How a session's expiry time is measured
+    ///
+    /// # Returns
+    ///
+    /// * The number of sessions removed
+    pub fn clear_expired_with(&mut self, policy: SessionExpiryPolicy) -> usize {
+        let before = self.sessions.len();
+        let last_active = &self.last_active;
+        self.sessions
+            .retain(|s| !policy.is_expired(s, last_active.get(s.id()).copied()));
+
+        let live: HashSet<&str> = self.sessions.iter().map(Session::id).collect();
+        self.last_active.retain(|id, _| live.contains(id.as_str()));
+        self.metadata.retain(|id, _| live.contains(id.as_str()));
+
+        before - self.sessions.len()
     }
 }
 
@@ -122,6 +269,510 @@ where
     }
 }
 
+/// A snapshot of a stored session, for listing sessions without their contents.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionSummary {
+    /// The session's ID
+    pub id: String,
+    /// When the session was created, in seconds since the Unix epoch
+    pub created_at: u64,
+    /// When the session expires under the listener's expiry policy, in seconds
+    /// since the Unix epoch
+    pub expires_at: u64,
+}
+
+/// How long a session stays valid.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SessionExpiryPolicy {
+    /// Sessions expire `lifespan` after they were created, however active they are.
+    #[default]
+    Fixed,
+    /// Sessions expire `lifespan` after the last packet received on them, so active
+    /// clients are never logged out mid-use.
+    Sliding,
+}
+
+impl SessionExpiryPolicy {
+    /// Checks whether a session has expired under this policy.
+    ///
+    /// # Arguments
+    ///
+    /// * `session`: The session to check
+    /// * `last_active`: When the session was last active, if known
+    ///
+    /// # Returns
+    ///
+    /// * `true` if the session has expired, `false` otherwise
+    pub fn is_expired<S: Session>(self, session: &S, last_active: Option<u64>) -> bool {
+        match self {
+            Self::Fixed => session.is_expired(),
+            Self::Sliding => {
+                let since = last_active.unwrap_or(0).max(session.created_at());
+                since + session.lifespan().as_secs() <= unix_now()
+            }
+        }
+    }
+
+    /// Returns when a session expires under this policy.
+    ///
+    /// # Arguments
+    ///
+    /// * `session`: The session to check
+    /// * `last_active`: When the session was last active, if known
+    ///
+    /// # Returns
+    ///
+    /// * The expiry time, in seconds since the Unix epoch
+    pub fn expires_at<S: Session>(self, session: &S, last_active: Option<u64>) -> u64 {
+        let since = match self {
+            Self::Fixed => session.created_at(),
+            Self::Sliding => last_active.unwrap_or(0).max(session.created_at()),
+        };
+        since + session.lifespan().as_secs()
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// A backend that stores the sessions issued by a listener.
+///
+/// The listener and its sockets only reach sessions through this trait, so swapping
+/// the store with `AsyncListener::with_session_store` changes where sessions live
+/// without touching authentication. `RwLock<Sessions<S>>` is the default in-memory
+/// store, and [`JsonFileSessionStore`] keeps sessions on disk so they survive a restart.
+///
+/// # Type Parameters
+///
+/// * `S`: A type that implements the `Session` trait
+pub trait SessionStore<S>: Send + Sync
+where
+    S: Session,
+{
+    /// Looks up a session by its ID.
+    ///
+    /// # Arguments
+    ///
+    /// * `id`: The ID of the session to load
+    ///
+    /// # Returns
+    ///
+    /// * The session if one is stored under `id`, `None` otherwise
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::SessionStore` if the backend cannot be read
+    fn load<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<Option<S>, Error>>;
+
+    /// Stores a session, replacing any session that has the same ID.
+    ///
+    /// # Arguments
+    ///
+    /// * `session`: The session to store
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::SessionStore` if the backend cannot be written
+    fn save(&self, session: S) -> BoxFuture<'_, Result<(), Error>>;
+
+    /// Removes a session by its ID. Removing an unknown ID is not an error.
+    ///
+    /// # Arguments
+    ///
+    /// * `id`: The ID of the session to remove
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::SessionStore` if the backend cannot be written
+    fn remove<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<(), Error>>;
+
+    /// Records activity on a session. Touching an unknown ID is not an error.
+    ///
+    /// # Arguments
+    ///
+    /// * `id`: The ID of the session that was active
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::SessionStore` if the backend cannot be written
+    fn touch<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<(), Error>>;
+
+    /// Returns when a session was last touched, if it has been.
+    ///
+    /// # Arguments
+    ///
+    /// * `id`: The ID of the session
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::SessionStore` if the backend cannot be read
+    fn last_active<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<Option<u64>, Error>>;
+
+    /// Retrieves a metadata value stored on a session.
+    ///
+    /// # Arguments
+    ///
+    /// * `id`: The ID of the session
+    /// * `key`: The metadata key
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::SessionStore` if the backend cannot be read
+    fn get_meta<'a>(
+        &'a self,
+        id: &'a str,
+        key: &'a str,
+    ) -> BoxFuture<'a, Result<Option<String>, Error>>;
+
+    /// Stores a metadata value on a session, replacing any previous value for `key`.
+    ///
+    /// # Arguments
+    ///
+    /// * `id`: The ID of the session
+    /// * `key`: The metadata key
+    /// * `value`: The value to store
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::InvalidSessionId` if no session is stored under `id`
+    /// Returns `Error::SessionStore` if the backend cannot be written
+    fn set_meta<'a>(
+        &'a self,
+        id: &'a str,
+        key: &'a str,
+        value: String,
+    ) -> BoxFuture<'a, Result<(), Error>>;
+
+    /// Removes all sessions that have expired under the given policy.
+    ///
+    /// # Arguments
+    ///
+    /// * `policy`: How a session's expiry time is measured
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::SessionStore` if the backend cannot be written
+    fn clear_expired(&self, policy: SessionExpiryPolicy) -> BoxFuture<'_, Result<(), Error>>;
+
+    /// Returns the number of stored sessions, including expired ones not yet cleared.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::SessionStore` if the backend cannot be read
+    fn count(&self) -> BoxFuture<'_, Result<usize, Error>>;
+
+    /// Summarizes every stored session, including expired ones not yet cleared.
+    ///
+    /// # Arguments
+    ///
+    /// * `policy`: How each session's expiry time is measured
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::SessionStore` if the backend cannot be read
+    fn summaries(
+        &self,
+        policy: SessionExpiryPolicy,
+    ) -> BoxFuture<'_, Result<Vec<SessionSummary>, Error>>;
+}
+
+/// A shared handle to a session store.
+pub type SessionStoreRef<S> = Arc<dyn SessionStore<S>>;
+
+impl<S> SessionStore<S> for RwLock<Sessions<S>>
+where
+    S: Session,
+{
+    fn load<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<Option<S>, Error>> {
+        Box::pin(async move { Ok(self.read().await.get_session(id).cloned()) })
+    }
+
+    fn save(&self, session: S) -> BoxFuture<'_, Result<(), Error>> {
+        Box::pin(async move {
+            let mut sessions = self.write().await;
+            sessions.delete_session(session.id());
+            sessions.new_session(session);
+            drop(sessions);
+            Ok(())
+        })
+    }
+
+    fn remove<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            self.write().await.delete_session(id);
+            Ok(())
+        })
+    }
+
+    fn touch<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            self.write().await.touch(id);
+            Ok(())
+        })
+    }
+
+    fn last_active<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<Option<u64>, Error>> {
+        Box::pin(async move { Ok(self.read().await.last_active(id)) })
+    }
+
+    fn get_meta<'a>(
+        &'a self,
+        id: &'a str,
+        key: &'a str,
+    ) -> BoxFuture<'a, Result<Option<String>, Error>> {
+        Box::pin(async move { Ok(self.read().await.get_meta(id, key).map(str::to_string)) })
+    }
+
+    fn set_meta<'a>(
+        &'a self,
+        id: &'a str,
+        key: &'a str,
+        value: String,
+    ) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            if self.write().await.set_meta(id, key, value) {
+                Ok(())
+            } else {
+                Err(Error::InvalidSessionId(id.to_string()))
+            }
+        })
+    }
+
+    fn clear_expired(&self, policy: SessionExpiryPolicy) -> BoxFuture<'_, Result<(), Error>> {
+        Box::pin(async move {
+            self.write().await.clear_expired_with(policy);
+            Ok(())
+        })
+    }
+
+    fn count(&self) -> BoxFuture<'_, Result<usize, Error>> {
+        Box::pin(async move { Ok(self.read().await.len()) })
+    }
+
+    fn summaries(
+        &self,
+        policy: SessionExpiryPolicy,
+    ) -> BoxFuture<'_, Result<Vec<SessionSummary>, Error>> {
+        Box::pin(async move { Ok(self.read().await.summaries(policy)) })
+    }
+}
+
+/// How long recorded activity may wait before `JsonFileSessionStore` writes it out.
+const TOUCH_FLUSH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// A session store that keeps its sessions in a JSON file.
+///
+/// Every change rewrites the file, so sessions issued before a restart can still be
+/// resolved by a listener that opens the same file afterwards. The file is replaced
+/// atomically, so a crash mid-write leaves the previous contents intact.
+///
+/// Activity recorded by `touch` is the exception: it is written at most every 30
+/// seconds, along with the next change or cleanup, or when `flush` is called. A
+/// crash loses at most that much activity, which can only make sliding sessions
+/// expire early.
+///
+/// # Example
+///
+/// ```rust
+/// use tnet::session::JsonFileSessionStore;
+///
+/// let store = JsonFileSessionStore::<MySession>::open("sessions.json").await?;
+/// let listener = AsyncListener::new(("127.0.0.1", 8080), 30, ok_handler, error_handler)
+///     .await
+///     .with_session_store(store);
+/// ```
+pub struct JsonFileSessionStore<S>
+where
+    S: Session,
+{
+    path: PathBuf,
+    sessions: RwLock<Sessions<S>>,
+    touched: AtomicBool,
+    persisted_at: AtomicU64,
+}
+
+impl<S> JsonFileSessionStore<S>
+where
+    S: Session,
+{
+    /// Opens a store backed by the file at `path`, loading any sessions already saved there.
+    ///
+    /// The file is created on the first write if it does not exist yet.
+    ///
+    /// # Arguments
+    ///
+    /// * `path`: The JSON file to keep sessions in
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::SessionStore` if the file exists but cannot be read or parsed
+    pub async fn open(path: impl Into<PathBuf>) -> Result<Self, Error> {
+        let path = path.into();
+        let sessions = match tokio::fs::read(&path).await {
+            Ok(data) => {
+                serde_json::from_slice(&data).map_err(|e| Error::SessionStore(e.to_string()))?
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Sessions::new(),
+            Err(e) => return Err(Error::SessionStore(e.to_string())),
+        };
+
+        Ok(Self {
+            path,
+            sessions: RwLock::new(sessions),
+            touched: AtomicBool::new(false),
+            persisted_at: AtomicU64::new(unix_now()),
+        })
+    }
+
+    /// Writes any activity recorded since the file was last written.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::SessionStore` if the file cannot be written
+    pub async fn flush(&self) -> Result<(), Error> {
+        let sessions = self.sessions.write().await;
+        if !self.touched.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        let result = self.persist(&sessions).await;
+        drop(sessions);
+        result
+    }
+
+    /// Writes recorded activity once it has waited `TOUCH_FLUSH_INTERVAL`.
+    async fn flush_touches(&self, sessions: &Sessions<S>) -> Result<(), Error> {
+        let waited = unix_now().saturating_sub(self.persisted_at.load(Ordering::SeqCst));
+        if !self.touched.load(Ordering::SeqCst) || waited < TOUCH_FLUSH_INTERVAL.as_secs() {
+            return Ok(());
+        }
+        self.persist(sessions).await
+    }
+
+    /// Writes the sessions to a temporary file and moves it over the store file.
+    async fn persist(&self, sessions: &Sessions<S>) -> Result<(), Error> {
+        let data = serde_json::to_vec(sessions).map_err(|e| Error::SessionStore(e.to_string()))?;
+        let tmp = self.path.with_extension("tmp");
+
+        tokio::fs::write(&tmp, data)
+            .await
+            .map_err(|e| Error::SessionStore(e.to_string()))?;
+        tokio::fs::rename(&tmp, &self.path)
+            .await
+            .map_err(|e| Error::SessionStore(e.to_string()))?;
+
+        // Every write includes the activity recorded so far
+        self.touched.store(false, Ordering::SeqCst);
+        self.persisted_at.store(unix_now(), Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+impl<S> SessionStore<S> for JsonFileSessionStore<S>
+where
+    S: Session,
+{
+    fn load<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<Option<S>, Error>> {
+        Box::pin(async move { Ok(self.sessions.read().await.get_session(id).cloned()) })
+    }
+
+    fn save(&self, session: S) -> BoxFuture<'_, Result<(), Error>> {
+        Box::pin(async move {
+            // Holding the lock while writing keeps the file in step with memory
+            let mut sessions = self.sessions.write().await;
+            sessions.delete_session(session.id());
+            sessions.new_session(session);
+            let result = self.persist(&sessions).await;
+            drop(sessions);
+            result
+        })
+    }
+
+    fn remove<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let mut sessions = self.sessions.write().await;
+            if sessions.get_session(id).is_none() {
+                return Ok(());
+            }
+            sessions.delete_session(id);
+            let result = self.persist(&sessions).await;
+            drop(sessions);
+            result
+        })
+    }
+
+    fn touch<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let mut sessions = self.sessions.write().await;
+            if sessions.touch(id) {
+                self.touched.store(true, Ordering::SeqCst);
+            }
+            let result = self.flush_touches(&sessions).await;
+            drop(sessions);
+            result
+        })
+    }
+
+    fn last_active<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<Option<u64>, Error>> {
+        Box::pin(async move { Ok(self.sessions.read().await.last_active(id)) })
+    }
+
+    fn get_meta<'a>(
+        &'a self,
+        id: &'a str,
+        key: &'a str,
+    ) -> BoxFuture<'a, Result<Option<String>, Error>> {
+        Box::pin(async move {
+            let sessions = self.sessions.read().await;
+            Ok(sessions.get_meta(id, key).map(str::to_string))
+        })
+    }
+
+    fn set_meta<'a>(
+        &'a self,
+        id: &'a str,
+        key: &'a str,
+        value: String,
+    ) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let mut sessions = self.sessions.write().await;
+            if !sessions.set_meta(id, key, value) {
+                return Err(Error::InvalidSessionId(id.to_string()));
+            }
+            let result = self.persist(&sessions).await;
+            drop(sessions);
+            result
+        })
+    }
+
+    fn clear_expired(&self, policy: SessionExpiryPolicy) -> BoxFuture<'_, Result<(), Error>> {
+        Box::pin(async move {
+            let mut sessions = self.sessions.write().await;
+            let result = if sessions.clear_expired_with(policy) == 0 {
+                self.flush_touches(&sessions).await
+            } else {
+                self.persist(&sessions).await
+            };
+            drop(sessions);
+            result
+        })
+    }
+
+    fn count(&self) -> BoxFuture<'_, Result<usize, Error>> {
+        Box::pin(async move { Ok(self.sessions.read().await.len()) })
+    }
+
+    fn summaries(
+        &self,
+        policy: SessionExpiryPolicy,
+    ) -> BoxFuture<'_, Result<Vec<SessionSummary>, Error>> {
+        Box::pin(async move { Ok(self.sessions.read().await.summaries(policy)) })
+    }
+}
+
 /// The `Session` trait defines the interface for session management in the application.
 /// It provides methods for session identification, lifetime management, and serialization.
 ///
@@ -248,7 +899,7 @@ pub trait Session: Debug + Clone + Send + Sync + Serialize + DeserializeOwned {
     /// # Returns
     ///
     /// * A new session instance
-    #[must_use] 
+    #[must_use]
     fn encrypted_de(data: &[u8], encryptor: &Encryptor) -> Self {
         let encrypted = String::from_utf8_lossy(data);
         let decrypted = encryptor.decrypt(&encrypted).unwrap();
@@ -273,7 +924,7 @@ pub trait Session: Debug + Clone + Send + Sync + Serialize + DeserializeOwned {
     /// # Returns
     ///
     /// * A new session instance
-    #[must_use] 
+    #[must_use]
     fn de(data: &[u8]) -> Self {
         serde_json::from_slice(data).unwrap()
     }
@@ -0,0 +1,134 @@
+use std::{
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+use tokio::sync::oneshot;
+
+use super::{MyPacket, MyResource, MySession};
+use crate::{
+    asynch::listener::{AsyncListener, HandlerSources},
+    blocking::BlockingClient,
+    errors::Error,
+    packet::{Packet, PacketBody},
+    wrap_handler,
+};
+
+async fn pong(sources: HandlerSources<MySession, MyResource>, packet: MyPacket) {
+    if packet.header() != "PING" {
+        return;
+    }
+
+    let mut socket = sources.socket;
+    let mut reply = MyPacket {
+        header: "PONG".to_string(),
+        body: PacketBody::default(),
+    };
+    reply.body_mut().request_id = packet.body().request_id;
+    if let Err(e) = socket.send(reply).await {
+        eprintln!("Failed to send response: {e}");
+    }
+}
+
+static NOTIFICATIONS_HANDLED: AtomicUsize = AtomicUsize::new(0);
+
+async fn count_notification(_sources: HandlerSources<MySession, MyResource>, packet: MyPacket) {
+    if packet.header() == "NOTIFY" {
+        NOTIFICATIONS_HANDLED.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+async fn log_error(_sources: HandlerSources<MySession, MyResource>, error: Error) {
+    println!("Server error: {error}");
+}
+
+#[test]
+fn test_blocking_client_round_trip() {
+    let (tx, rx) = oneshot::channel();
+    let (ready_tx, ready_rx) = std::sync::mpsc::channel();
+
+    // The server gets its own runtime on a separate thread
+    let server_thread = thread::spawn(move || {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async move {
+            let mut server = AsyncListener::new(
+                ("127.0.0.1", 8260),
+                30,
+                wrap_handler!(pong),
+                wrap_handler!(log_error),
+            )
+            .await;
+            ready_tx.send(()).unwrap();
+
+            tokio::select! {
+                _ = server.run() => {},
+                _ = rx => println!("Server shutting down"),
+            }
+        });
+    });
+    ready_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+
+    let mut client = BlockingClient::<MyPacket>::new("127.0.0.1", 8260).unwrap();
+
+    // The server greets every new connection with its session id
+    assert_eq!(client.recv().unwrap().header(), "OK");
+
+    let ping = MyPacket {
+        header: "PING".to_string(),
+        body: PacketBody::default(),
+    };
+    client.send(ping.clone()).unwrap();
+    assert_eq!(client.recv().unwrap().header(), "PONG");
+
+    assert_eq!(client.send_recv(ping).unwrap().header(), "PONG");
+
+    drop(client);
+    let _ = tx.send(());
+    server_thread.join().unwrap();
+}
+
+#[test]
+fn test_blocking_send_writes_without_a_following_recv() {
+    let (tx, rx) = oneshot::channel();
+    let (ready_tx, ready_rx) = std::sync::mpsc::channel();
+
+    let server_thread = thread::spawn(move || {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async move {
+            let mut server = AsyncListener::new(
+                ("127.0.0.1", 8261),
+                30,
+                wrap_handler!(count_notification),
+                wrap_handler!(log_error),
+            )
+            .await;
+            ready_tx.send(()).unwrap();
+
+            tokio::select! {
+                _ = server.run() => {},
+                _ = rx => println!("Server shutting down"),
+            }
+        });
+    });
+    ready_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+
+    let mut client = BlockingClient::<MyPacket>::new("127.0.0.1", 8261).unwrap();
+    client
+        .send(MyPacket {
+            header: "NOTIFY".to_string(),
+            body: PacketBody::default(),
+        })
+        .unwrap();
+
+    // The client's runtime is idle from here on, so the packet must already be written
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while NOTIFICATIONS_HANDLED.load(Ordering::SeqCst) == 0 && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(20));
+    }
+    assert_eq!(NOTIFICATIONS_HANDLED.load(Ordering::SeqCst), 1);
+
+    drop(client);
+    let _ = tx.send(());
+    server_thread.join().unwrap();
+}
@@ -0,0 +1,1298 @@
+use std::{
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use futures::StreamExt;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+    sync::{mpsc, oneshot},
+    task::JoinHandle,
+};
+
+use super::{MyPacket, MyResource, MySession};
+use crate::{
+    asynch::{
+        authenticator::{AuthType, Authenticator},
+        client::{
+            AsyncClient, ConnectionStatus, EncryptionConfig, Endpoint, KeepAliveConfig,
+            ReconnectionConfig, SendRecvOptions, TimeoutConfig, WRITE_QUEUE_CAPACITY,
+        },
+        framing,
+        listener::{AsyncListener, HandlerSources},
+    },
+    encrypt::CipherSuite,
+    errors::Error,
+    packet::{Packet, PacketBody},
+    wrap_handler,
+};
+
+fn packet(header: &str) -> MyPacket {
+    MyPacket {
+        header: header.to_string(),
+        body: PacketBody::default(),
+    }
+}
+
+async fn log_error(_sources: HandlerSources<MySession, MyResource>, error: Error) {
+    println!("Server error: {error}");
+}
+
+#[tokio::test]
+async fn test_send_recv_skips_interleaved_packets() {
+    let (tx, rx) = oneshot::channel();
+
+    // Pushes a broadcast and a reply to some other request before the real reply
+    async fn handle_ok(sources: HandlerSources<MySession, MyResource>, _packet: MyPacket) {
+        let mut socket = sources.socket;
+
+        socket
+            .send(packet("NEWS").set_broadcasting())
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let mut stale = packet("STALE");
+        stale.body_mut().request_id = Some(u64::MAX);
+        socket.send(stale).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        socket.send(packet("PONG")).await.unwrap();
+    }
+
+    let mut server = AsyncListener::new(
+        ("127.0.0.1", 8220),
+        30,
+        wrap_handler!(handle_ok),
+        wrap_handler!(log_error),
+    )
+    .await;
+
+    let server_handle = tokio::spawn(async move {
+        tokio::select! {
+            _ = server.run() => {},
+            _ = rx => println!("Server shutting down"),
+        }
+    });
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let broadcasts = Arc::new(AtomicUsize::new(0));
+    let broadcasts_clone = broadcasts.clone();
+
+    let mut client = AsyncClient::<MyPacket>::new("127.0.0.1", 8220)
+        .await
+        .unwrap()
+        .with_broadcast_handler(Box::new(move |packet: MyPacket| {
+            assert_eq!(packet.header(), "NEWS");
+            broadcasts_clone.fetch_add(1, Ordering::SeqCst);
+        }));
+
+    // Session OK sent unsolicited by the no-auth path
+    assert_eq!(client.recv().await.unwrap().header(), "OK");
+
+    let response = client.send_recv(packet("PING")).await.unwrap();
+    assert_eq!(response.header(), "PONG");
+    assert!(response.body().request_id.is_some());
+    assert_eq!(broadcasts.load(Ordering::SeqCst), 1);
+
+    // Request ids keep incrementing across calls
+    let next = client.send_recv(packet("PING")).await.unwrap();
+    assert_eq!(next.header(), "PONG");
+    assert_eq!(
+        next.body().request_id,
+        response.body().request_id.map(|id| id + 1)
+    );
+    assert_eq!(broadcasts.load(Ordering::SeqCst), 2);
+
+    let _ = tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(2), server_handle).await;
+}
+
+#[tokio::test]
+async fn test_subscribe_collects_pushed_packets() {
+    let (tx, rx) = oneshot::channel();
+
+    async fn handle_ok(sources: HandlerSources<MySession, MyResource>, _packet: MyPacket) {
+        let mut socket = sources.socket;
+        socket.send(packet("SUBSCRIBED")).await.unwrap();
+
+        for i in 0..3 {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            socket
+                .send(packet(&format!("FEED_{i}")).set_broadcasting())
+                .await
+                .unwrap();
+        }
+    }
+
+    let mut server = AsyncListener::new(
+        ("127.0.0.1", 8221),
+        30,
+        wrap_handler!(handle_ok),
+        wrap_handler!(log_error),
+    )
+    .await;
+
+    let server_handle = tokio::spawn(async move {
+        tokio::select! {
+            _ = server.run() => {},
+            _ = rx => println!("Server shutting down"),
+        }
+    });
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut client = AsyncClient::<MyPacket>::new("127.0.0.1", 8221)
+        .await
+        .unwrap();
+    assert_eq!(client.recv().await.unwrap().header(), "OK");
+
+    let mut stream = Box::pin(client.subscribe());
+
+    let response = client.send_recv(packet("SUBSCRIBE")).await.unwrap();
+    assert_eq!(response.header(), "SUBSCRIBED");
+
+    let mut headers = Vec::new();
+    while headers.len() < 3 {
+        let pushed = tokio::time::timeout(Duration::from_secs(2), stream.next())
+            .await
+            .expect("Timed out waiting for pushed packet")
+            .expect("Stream ended early")
+            .unwrap();
+        headers.push(pushed.header());
+    }
+
+    assert_eq!(headers, vec!["FEED_0", "FEED_1", "FEED_2"]);
+
+    let _ = tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(2), server_handle).await;
+}
+
+#[tokio::test]
+async fn test_send_recv_stream_yields_until_end_marker() {
+    let (tx, rx) = oneshot::channel();
+
+    // Answers with three rows, ended by the flag for QUERY and by a header for TAIL
+    async fn handle_ok(sources: HandlerSources<MySession, MyResource>, request: MyPacket) {
+        let mut socket = sources.socket;
+        for i in 0..3 {
+            socket.send(packet(&format!("ROW_{i}"))).await.unwrap();
+        }
+
+        let end = match request.header().as_str() {
+            "QUERY" => packet("DONE").set_stream_end(),
+            _ => packet("END"),
+        };
+        socket.send(end).await.unwrap();
+    }
+
+    let mut server = AsyncListener::new(
+        ("127.0.0.1", 8228),
+        30,
+        wrap_handler!(handle_ok),
+        wrap_handler!(log_error),
+    )
+    .await;
+
+    let server_handle = tokio::spawn(async move {
+        tokio::select! {
+            _ = server.run() => {},
+            _ = rx => println!("Server shutting down"),
+        }
+    });
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut client = AsyncClient::<MyPacket>::new("127.0.0.1", 8228)
+        .await
+        .unwrap()
+        .with_stream_end_header("END");
+    assert_eq!(client.recv().await.unwrap().header(), "OK");
+
+    for request in ["QUERY", "TAIL"] {
+        let rows = tokio::time::timeout(
+            Duration::from_secs(5),
+            client.send_recv_stream(packet(request)).collect::<Vec<_>>(),
+        )
+        .await
+        .expect("Stream never ended");
+
+        let headers = rows
+            .into_iter()
+            .map(|row| row.unwrap().header())
+            .collect::<Vec<_>>();
+        assert_eq!(headers, ["ROW_0", "ROW_1", "ROW_2"]);
+    }
+
+    let _ = tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(2), server_handle).await;
+}
+
+// Starts a listener that answers every packet with PONG
+async fn spawn_pong_server(ip_port: (&str, u16)) -> (oneshot::Sender<()>, JoinHandle<()>) {
+    spawn_encrypted_pong_server(ip_port, EncryptionConfig::default()).await
+}
+
+// Same as `spawn_pong_server`, with the given encryption settings
+async fn spawn_encrypted_pong_server(
+    ip_port: (&str, u16),
+    encryption: EncryptionConfig,
+) -> (oneshot::Sender<()>, JoinHandle<()>) {
+    let (tx, rx) = oneshot::channel();
+
+    async fn handle_ok(sources: HandlerSources<MySession, MyResource>, _packet: MyPacket) {
+        let mut socket = sources.socket;
+        socket.send(packet("PONG")).await.unwrap();
+    }
+
+    let mut server = AsyncListener::new(
+        ip_port,
+        30,
+        wrap_handler!(handle_ok),
+        wrap_handler!(log_error),
+    )
+    .await
+    .with_encryption_config(encryption);
+
+    let server_handle = tokio::spawn(async move {
+        tokio::select! {
+            _ = server.run() => {},
+            _ = rx => println!("Server shutting down"),
+        }
+    });
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    (tx, server_handle)
+}
+
+#[tokio::test]
+async fn test_connect_by_hostname() {
+    let (tx, server_handle) = spawn_pong_server(("127.0.0.1", 8222)).await;
+
+    let mut client = AsyncClient::<MyPacket>::new("localhost", 8222)
+        .await
+        .unwrap();
+    assert_eq!(client.recv().await.unwrap().header(), "OK");
+
+    let response = client.send_recv(packet("PING")).await.unwrap();
+    assert_eq!(response.header(), "PONG");
+
+    let _ = tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(2), server_handle).await;
+}
+
+#[tokio::test]
+async fn test_connect_to_ipv6_literal() {
+    let (tx, server_handle) = spawn_pong_server(("::1", 8223)).await;
+
+    let mut client = AsyncClient::<MyPacket>::new("::1", 8223).await.unwrap();
+    assert_eq!(client.recv().await.unwrap().header(), "OK");
+
+    let response = client.send_recv(packet("PING")).await.unwrap();
+    assert_eq!(response.header(), "PONG");
+
+    let _ = tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(2), server_handle).await;
+}
+
+#[tokio::test]
+async fn test_unresolvable_host() {
+    let result = AsyncClient::<MyPacket>::new("tnet.invalid", 8224).await;
+    assert!(matches!(result, Err(Error::ResolutionFailed(_))));
+}
+
+#[tokio::test]
+async fn test_try_send_now_reports_full_queue() {
+    // Accepts the connection but never reads, so the writer task stalls
+    let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let accept = tokio::spawn(async move { listener.accept().await.unwrap() });
+
+    let mut client = AsyncClient::<MyPacket>::new("127.0.0.1", port)
+        .await
+        .unwrap();
+    let _server_stream = accept.await.unwrap();
+
+    assert_eq!(client.pending_writes(), 0);
+
+    let large = packet(&"x".repeat(256 * 1024));
+    let mut result = Ok(());
+    for _ in 0..1024 {
+        result = client.try_send_now(large.clone());
+        if result.is_err() {
+            break;
+        }
+    }
+
+    assert_eq!(result, Err(Error::WouldBlock));
+    assert_eq!(client.pending_writes(), WRITE_QUEUE_CAPACITY);
+}
+
+#[tokio::test]
+async fn test_keepalive_gives_up_after_max_failures() {
+    // Answers the finalize request, then stops reading so the writer stalls
+    let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let accept = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        framing::write_frame(&mut stream, &MyPacket::ok().ser())
+            .await
+            .unwrap();
+        stream
+    });
+
+    let mut client = AsyncClient::<MyPacket>::new("127.0.0.1", port)
+        .await
+        .unwrap()
+        .with_keep_alive(
+            KeepAliveConfig {
+                interval: 1,
+                ..KeepAliveConfig::default_on()
+            }
+            .with_max_failures(1)
+            .with_probe_every(0),
+        )
+        .with_timeouts(TimeoutConfig {
+            send: Duration::from_millis(100),
+            ..TimeoutConfig::default()
+        });
+    client.finalize().await;
+    let _server_stream = accept.await.unwrap();
+    assert_eq!(client.status(), ConnectionStatus::Connected);
+
+    // Keep the send queue full so the next keep-alive cannot be queued
+    let large = packet(&"x".repeat(256 * 1024));
+    let deadline = Instant::now() + Duration::from_secs(3);
+    while client.status() != ConnectionStatus::Closed && Instant::now() < deadline {
+        while client.try_send_now(large.clone()).is_ok() {}
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(client.status(), ConnectionStatus::Closed);
+}
+
+#[tokio::test]
+async fn test_recv_honours_configured_timeout() {
+    // Accepts the connection but never writes anything back
+    let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let accept = tokio::spawn(async move { listener.accept().await.unwrap() });
+
+    let mut client = AsyncClient::<MyPacket>::new("127.0.0.1", port)
+        .await
+        .unwrap()
+        .with_timeouts(TimeoutConfig {
+            recv: Duration::from_millis(50),
+            ..TimeoutConfig::default()
+        });
+    let _server_stream = accept.await.unwrap();
+
+    let start = Instant::now();
+    let result = client.recv().await;
+    let elapsed = start.elapsed();
+
+    assert!(result.is_err());
+    assert!(elapsed >= Duration::from_millis(50), "{elapsed:?}");
+    assert!(elapsed < Duration::from_millis(500), "{elapsed:?}");
+}
+
+#[tokio::test]
+async fn test_send_recv_with_no_retries_fails_once() {
+    // Accepts connections but never answers
+    let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let accepts = Arc::new(AtomicUsize::new(0));
+    let server_accepts = accepts.clone();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            server_accepts.fetch_add(1, Ordering::SeqCst);
+            tokio::spawn(async move {
+                let mut discarded = Vec::new();
+                let _ = stream.read_to_end(&mut discarded).await;
+            });
+        }
+    });
+
+    let reconnects = Arc::new(AtomicUsize::new(0));
+    let on_reconnect = reconnects.clone();
+    let mut client = AsyncClient::<MyPacket>::new("127.0.0.1", port)
+        .await
+        .unwrap()
+        .with_reconnection(ReconnectionConfig {
+            auto_reconnect: true,
+            ..ReconnectionConfig::default()
+        })
+        .with_on_reconnect(move |_, _| {
+            on_reconnect.fetch_add(1, Ordering::SeqCst);
+        });
+
+    let options = SendRecvOptions::default()
+        .with_timeout(Duration::from_millis(100))
+        .with_retries(0);
+    let start = Instant::now();
+    let result = client.send_recv_with(packet("PING"), options).await;
+    let elapsed = start.elapsed();
+
+    assert!(matches!(result, Err(Error::IoError(_))), "{result:?}");
+    assert!(elapsed < Duration::from_millis(500), "{elapsed:?}");
+    assert_eq!(reconnects.load(Ordering::SeqCst), 0);
+    assert_eq!(accepts.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_status_reports_closed_after_server_drops() {
+    let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let accept = tokio::spawn(async move { listener.accept().await.unwrap() });
+
+    let mut client = AsyncClient::<MyPacket>::new("127.0.0.1", port)
+        .await
+        .unwrap();
+    let (mut server_stream, _) = accept.await.unwrap();
+
+    // Nothing has arrived from the server yet
+    assert_eq!(client.status(), ConnectionStatus::Connecting);
+    assert!(!client.is_connected());
+
+    framing::write_frame(&mut server_stream, &MyPacket::ok().ser())
+        .await
+        .unwrap();
+    assert_eq!(client.recv().await.unwrap().header(), "OK");
+    assert_eq!(client.status(), ConnectionStatus::Connected);
+    assert!(client.is_connected());
+
+    drop(server_stream);
+
+    let deadline = Instant::now() + Duration::from_secs(2);
+    while client.status() != ConnectionStatus::Closed && Instant::now() < deadline {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(client.status(), ConnectionStatus::Closed);
+    assert!(!client.is_connected());
+}
+
+// A receive that is already waiting should fail as soon as the server closes
+#[tokio::test]
+async fn test_pending_recv_fails_on_close() {
+    let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let accept = tokio::spawn(async move { listener.accept().await.unwrap() });
+
+    let mut client = AsyncClient::<MyPacket>::new("127.0.0.1", port)
+        .await
+        .unwrap();
+    let (server_stream, _) = accept.await.unwrap();
+
+    let pending = tokio::spawn(async move {
+        let started = Instant::now();
+        (client.recv().await, started.elapsed())
+    });
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    drop(server_stream);
+
+    let (result, elapsed) = pending.await.unwrap();
+    assert!(matches!(result, Err(Error::ConnectionClosed)), "{result:?}");
+    assert!(elapsed < Duration::from_secs(1), "recv took {elapsed:?}");
+}
+
+#[tokio::test]
+async fn test_lifecycle_callbacks_follow_reconnect() {
+    // Answers the finalize request, then drops the connection and stops listening
+    let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let first_server = tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let (read_half, mut write_half) = stream.into_split();
+        let mut frames = framing::FrameReader::new(read_half);
+        let request = MyPacket::de(&frames.read_frame().await.unwrap().unwrap());
+
+        let mut reply = MyPacket::ok();
+        reply.body_mut().request_id = request.body().request_id;
+        framing::write_frame(&mut write_half, &reply.ser())
+            .await
+            .unwrap();
+    });
+
+    let connects = Arc::new(AtomicUsize::new(0));
+    let disconnects = Arc::new(AtomicUsize::new(0));
+    let reconnects = Arc::new(Mutex::new(Vec::new()));
+
+    let mut client = AsyncClient::<MyPacket>::new("127.0.0.1", port)
+        .await
+        .unwrap()
+        .with_reconnection(ReconnectionConfig {
+            auto_reconnect: true,
+            initial_retry_delay: 0.1,
+            reinitialize: false,
+            ..ReconnectionConfig::default()
+        })
+        .with_on_connect({
+            let connects = connects.clone();
+            move || {
+                connects.fetch_add(1, Ordering::SeqCst);
+            }
+        })
+        .with_on_disconnect({
+            let disconnects = disconnects.clone();
+            move || {
+                disconnects.fetch_add(1, Ordering::SeqCst);
+            }
+        })
+        .with_on_reconnect({
+            let reconnects = reconnects.clone();
+            move |attempt, endpoint: &Endpoint| {
+                reconnects.lock().unwrap().push((attempt, endpoint.clone()));
+            }
+        });
+
+    client.finalize().await;
+    first_server.await.unwrap();
+    assert_eq!(connects.load(Ordering::SeqCst), 1);
+
+    let deadline = Instant::now() + Duration::from_secs(2);
+    while client.status() != ConnectionStatus::Closed && Instant::now() < deadline {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(disconnects.load(Ordering::SeqCst), 0);
+
+    let (tx, server_handle) = spawn_pong_server(("127.0.0.1", port)).await;
+
+    // Sending on the dropped connection makes the client reconnect and retry
+    assert!(client.send_recv(packet("PING")).await.is_ok());
+
+    assert_eq!(connects.load(Ordering::SeqCst), 1);
+    assert_eq!(disconnects.load(Ordering::SeqCst), 1);
+    assert_eq!(
+        *reconnects.lock().unwrap(),
+        [(1, Endpoint::Tcp("127.0.0.1".to_string(), port))]
+    );
+
+    let _ = tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(2), server_handle).await;
+}
+
+#[tokio::test]
+async fn test_reconnect_resumes_session() {
+    let (tx, rx) = oneshot::channel();
+
+    // Shuts down its side of the connection on DROP, otherwise reports the session
+    async fn handle_ok(sources: HandlerSources<MySession, MyResource>, packet: MyPacket) {
+        let mut socket = sources.socket;
+        if packet.header == "DROP" {
+            let _ = socket.write_part.lock().await.shutdown().await;
+            return;
+        }
+
+        let mut reply = MyPacket::ok();
+        reply.body_mut().session_id = socket.session_id.clone();
+        socket.send(reply).await.unwrap();
+    }
+
+    let mut server = AsyncListener::new(
+        ("127.0.0.1", 8227),
+        30,
+        wrap_handler!(handle_ok),
+        wrap_handler!(log_error),
+    )
+    .await
+    .with_authenticator(
+        Authenticator::new(AuthType::UserPassword).with_auth_fn(|user, pass| {
+            Box::pin(async move {
+                if user == "user" && pass == "pass" {
+                    Ok(())
+                } else {
+                    Err(Error::InvalidCredentials)
+                }
+            })
+        }),
+    );
+
+    let server_handle = tokio::spawn(async move {
+        tokio::select! {
+            _ = server.run() => {},
+            _ = rx => println!("Server shutting down"),
+        }
+    });
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut client = AsyncClient::<MyPacket>::new("127.0.0.1", 8227)
+        .await
+        .unwrap()
+        .with_credentials("user", "pass")
+        .with_reconnection(ReconnectionConfig {
+            auto_reconnect: true,
+            initial_retry_delay: 0.05,
+            reinitialize: false,
+            ..ReconnectionConfig::default()
+        });
+
+    client.finalize().await;
+    let session_id = client.session_id().map(str::to_string);
+    assert!(session_id.is_some());
+
+    client.send(packet("DROP")).await.unwrap();
+    let deadline = Instant::now() + Duration::from_secs(2);
+    while client.status() != ConnectionStatus::Closed && Instant::now() < deadline {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(client.status(), ConnectionStatus::Closed);
+
+    // The retried request reaches the handler on the resumed session
+    let mut reply = client.send_recv(packet("WHOAMI")).await.unwrap();
+    assert_eq!(reply.session_id(None), session_id);
+    assert_eq!(client.session_id().map(str::to_string), session_id);
+
+    let _ = tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(2), server_handle).await;
+}
+
+// Joins the chat pool on JOIN, broadcasts to it on SAY and hangs up on DROP
+async fn chat_member(sources: HandlerSources<MySession, MyResource>, request: MyPacket) {
+    let mut socket = sources.socket;
+    let mut pools = sources.pools;
+    match request.header.as_str() {
+        "JOIN" => pools.insert("chat", &socket).await,
+        "SAY" => {
+            pools.broadcast_to("chat", packet("CHAT")).await.unwrap();
+        }
+        "DROP" => {
+            let _ = socket.write_part.lock().await.shutdown().await;
+            return;
+        }
+        _ => {}
+    }
+    socket.send(MyPacket::ok()).await.unwrap();
+}
+
+async fn start_chat_server(port: u16) -> (oneshot::Sender<()>, JoinHandle<()>) {
+    let (tx, rx) = oneshot::channel();
+    let mut server = AsyncListener::new(
+        ("127.0.0.1", port),
+        30,
+        wrap_handler!(chat_member),
+        wrap_handler!(log_error),
+    )
+    .await
+    .with_pool("chat")
+    .await;
+
+    let handle = tokio::spawn(async move {
+        tokio::select! {
+            _ = server.run() => {},
+            _ = rx => println!("Server shutting down"),
+        }
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    (tx, handle)
+}
+
+#[tokio::test]
+async fn test_resubscribe_rejoins_pools_after_reconnect() {
+    let (stop, server_handle) = start_chat_server(8229).await;
+
+    let mut client = AsyncClient::<MyPacket>::new("127.0.0.1", 8229)
+        .await
+        .unwrap()
+        .with_reconnection(ReconnectionConfig {
+            auto_reconnect: true,
+            initial_retry_delay: 0.05,
+            ..ReconnectionConfig::default()
+        })
+        .with_resubscribe(vec![packet("JOIN")]);
+    client.finalize().await;
+    let mut pushed = Box::pin(client.subscribe());
+
+    assert_eq!(
+        client.send_recv(packet("JOIN")).await.unwrap().header(),
+        "OK"
+    );
+    assert_eq!(
+        client.send_recv(packet("SAY")).await.unwrap().header(),
+        "OK"
+    );
+    let chat = tokio::time::timeout(Duration::from_secs(2), pushed.next())
+        .await
+        .expect("Timed out waiting for broadcast")
+        .unwrap()
+        .unwrap();
+    assert_eq!(chat.header(), "CHAT");
+
+    // Restart the server, losing every pool membership
+    client.send(packet("DROP")).await.unwrap();
+    let deadline = Instant::now() + Duration::from_secs(2);
+    while client.status() != ConnectionStatus::Closed && Instant::now() < deadline {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    let _ = stop.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(2), server_handle).await;
+    let (stop, server_handle) = start_chat_server(8229).await;
+
+    // The reconnect rejoins the pool before SAY is retried
+    assert_eq!(
+        client.send_recv(packet("SAY")).await.unwrap().header(),
+        "OK"
+    );
+    // Subscribers may first hear that the old connection closed
+    let chat = tokio::time::timeout(Duration::from_secs(2), async {
+        loop {
+            if let Some(Ok(chat)) = pushed.next().await {
+                break chat;
+            }
+        }
+    })
+    .await
+    .expect("Timed out waiting for broadcast after reconnect");
+    assert_eq!(chat.header(), "CHAT");
+
+    let _ = stop.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(2), server_handle).await;
+}
+
+/// Records the message of every warning emitted while it is the default subscriber.
+#[derive(Clone, Default)]
+struct WarnRecorder(Arc<Mutex<Vec<String>>>);
+
+impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for WarnRecorder {
+    fn on_event(
+        &self,
+        event: &tracing::Event<'_>,
+        _ctx: tracing_subscriber::layer::Context<'_, S>,
+    ) {
+        struct Message(String);
+
+        impl tracing::field::Visit for Message {
+            fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+                if field.name() == "message" {
+                    self.0 = format!("{value:?}");
+                }
+            }
+        }
+
+        if *event.metadata().level() == tracing::Level::WARN {
+            let mut message = Message(String::new());
+            event.record(&mut message);
+            self.0.lock().unwrap().push(message.0);
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_reconnect_logs_warning() {
+    use tracing_subscriber::layer::SubscriberExt;
+
+    let recorder = WarnRecorder::default();
+    let _guard =
+        tracing::subscriber::set_default(tracing_subscriber::registry().with(recorder.clone()));
+
+    let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let accept = tokio::spawn(async move { listener.accept().await.unwrap() });
+
+    let mut client = AsyncClient::<MyPacket>::new("127.0.0.1", port)
+        .await
+        .unwrap()
+        .with_reconnection(ReconnectionConfig {
+            auto_reconnect: true,
+            max_attempts: Some(1),
+            initial_retry_delay: 0.01,
+            reinitialize: false,
+            ..ReconnectionConfig::default()
+        });
+
+    // Accept and immediately drop the connection, leaving nothing listening
+    drop(accept.await.unwrap());
+
+    let deadline = Instant::now() + Duration::from_secs(2);
+    while client.status() != ConnectionStatus::Closed && Instant::now() < deadline {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    assert!(client.send_recv(packet("PING")).await.is_err());
+    assert!(
+        recorder
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|message| message == "Connection lost, reconnecting")
+    );
+}
+
+#[tokio::test]
+async fn test_close_runs_disconnect_handler() {
+    let (tx, rx) = oneshot::channel();
+    let (left_tx, mut left_rx) = mpsc::channel(1);
+
+    async fn handle_ok(_sources: HandlerSources<MySession, MyResource>, _packet: MyPacket) {}
+
+    let mut server = AsyncListener::new(
+        ("127.0.0.1", 8225),
+        30,
+        wrap_handler!(handle_ok),
+        wrap_handler!(log_error),
+    )
+    .await
+    .with_disconnect_handler(Arc::new(move |sources, packet: MyPacket| {
+        let left_tx = left_tx.clone();
+        Box::pin(async move {
+            assert!(packet.is_disconnect());
+            let _ = left_tx.send(sources.socket.session_id).await;
+        })
+    }));
+
+    let server_handle = tokio::spawn(async move {
+        tokio::select! {
+            _ = server.run() => {},
+            _ = rx => println!("Server shutting down"),
+        }
+    });
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut client = AsyncClient::<MyPacket>::new("127.0.0.1", 8225)
+        .await
+        .unwrap();
+    let welcome = client.recv().await.unwrap();
+    assert_eq!(welcome.header(), "OK");
+
+    client.close().await.unwrap();
+    assert_eq!(
+        client.send(packet("PING")).await,
+        Err(Error::ConnectionClosed)
+    );
+
+    let session_id = tokio::time::timeout(Duration::from_secs(2), left_rx.recv())
+        .await
+        .expect("Disconnect handler never ran")
+        .unwrap();
+    assert_eq!(session_id, welcome.body().session_id);
+
+    let _ = tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(2), server_handle).await;
+}
+
+#[tokio::test]
+async fn test_key_exchange_negotiates_cipher_suite() {
+    let server_config =
+        EncryptionConfig::default_on().with_cipher_suites([CipherSuite::ChaCha20Poly1305]);
+    let (tx, server_handle) = spawn_encrypted_pong_server(("127.0.0.1", 8226), server_config).await;
+
+    // The client prefers AES but also offers ChaCha20, the only suite the server accepts
+    let mut client = AsyncClient::<MyPacket>::new("127.0.0.1", 8226)
+        .await
+        .unwrap()
+        .with_encryption_config(EncryptionConfig::default_on())
+        .await
+        .unwrap();
+    assert_eq!(
+        client.encryption.suite(),
+        Some(CipherSuite::ChaCha20Poly1305)
+    );
+    assert_eq!(client.recv().await.unwrap().header(), "OK");
+
+    let response = client.send_recv(packet("PING")).await.unwrap();
+    assert_eq!(response.header(), "PONG");
+
+    // Without any suite in common the handshake fails
+    let result = AsyncClient::<MyPacket>::new("127.0.0.1", 8226)
+        .await
+        .unwrap()
+        .with_encryption_config(
+            EncryptionConfig::default_on().with_cipher_suites([CipherSuite::Aes256Gcm]),
+        )
+        .await;
+    assert!(matches!(result, Err(Error::KeyExchangeFailed(_))));
+
+    let _ = tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(2), server_handle).await;
+}
+
+/// Answers the first request on a fresh connection with `reply`.
+async fn spawn_auth_reply_server(mut reply: MyPacket) -> u16 {
+    let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let (read_half, mut write_half) = stream.into_split();
+        let mut frames = framing::FrameReader::new(read_half);
+        let request = MyPacket::de(&frames.read_frame().await.unwrap().unwrap());
+
+        reply.body_mut().request_id = request.body().request_id;
+        framing::write_frame(&mut write_half, &reply.ser())
+            .await
+            .unwrap();
+        // Hold the connection open until the client hangs up
+        let _ = frames.read_frame().await;
+    });
+    port
+}
+
+#[tokio::test]
+async fn test_auth_failures_return_typed_errors() {
+    // Authenticate in plaintext so the fake server can answer directly
+    let config = EncryptionConfig {
+        enabled: true,
+        key: None,
+        auto_key_exchange: false,
+        cipher_suites: Vec::new(),
+    };
+
+    let port = spawn_auth_reply_server(MyPacket::ok()).await;
+    let result = AsyncClient::<MyPacket>::new("127.0.0.1", port)
+        .await
+        .unwrap()
+        .with_credentials("user", "pass")
+        .with_encryption_config(config.clone())
+        .await;
+    assert!(matches!(result, Err(Error::NoSessionId)));
+
+    let port = spawn_auth_reply_server(MyPacket::error(Error::InvalidCredentials)).await;
+    let result = AsyncClient::<MyPacket>::new("127.0.0.1", port)
+        .await
+        .unwrap()
+        .with_credentials("user", "pass")
+        .with_encryption_config(config.clone())
+        .await;
+    assert!(matches!(result, Err(Error::InvalidCredentials)));
+
+    let maintenance = Error::Error("Down for maintenance".to_string());
+    let port = spawn_auth_reply_server(MyPacket::error(maintenance.clone())).await;
+    let result = AsyncClient::<MyPacket>::new("127.0.0.1", port)
+        .await
+        .unwrap()
+        .with_credentials("user", "pass")
+        .with_encryption_config(config)
+        .await;
+    match result {
+        Err(Error::AuthResponseInvalid(reason)) => {
+            assert_eq!(reason, maintenance.to_string());
+        }
+        other => panic!("Expected AuthResponseInvalid, got {:?}", other.err()),
+    }
+}
+
+#[tokio::test]
+async fn test_wrong_password_reports_invalid_credentials() {
+    let (tx, rx) = oneshot::channel();
+
+    async fn handle_ok(sources: HandlerSources<MySession, MyResource>, _packet: MyPacket) {
+        let mut socket = sources.socket;
+        socket.send(MyPacket::ok()).await.unwrap();
+    }
+
+    let mut server = AsyncListener::new(
+        ("127.0.0.1", 8219),
+        30,
+        wrap_handler!(handle_ok),
+        wrap_handler!(log_error),
+    )
+    .await
+    .with_encryption_config(EncryptionConfig::default_on())
+    .with_authenticator(
+        Authenticator::new(AuthType::UserPassword).with_auth_fn(|user, pass| {
+            Box::pin(async move {
+                if user == "user" && pass == "pass" {
+                    Ok(())
+                } else {
+                    Err(Error::InvalidCredentials)
+                }
+            })
+        }),
+    );
+
+    let server_handle = tokio::spawn(async move {
+        tokio::select! {
+            _ = server.run() => {},
+            _ = rx => println!("Server shutting down"),
+        }
+    });
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let result = AsyncClient::<MyPacket>::new("127.0.0.1", 8219)
+        .await
+        .unwrap()
+        .with_credentials("user", "wrong")
+        .with_encryption_config(EncryptionConfig::default_on())
+        .await;
+    assert!(matches!(result, Err(Error::InvalidCredentials)));
+
+    let _ = tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(2), server_handle).await;
+}
+
+static PINGS_HANDLED: AtomicUsize = AtomicUsize::new(0);
+
+async fn count_handled(_sources: HandlerSources<MySession, MyResource>, _packet: MyPacket) {
+    PINGS_HANDLED.fetch_add(1, Ordering::SeqCst);
+}
+
+#[tokio::test]
+async fn test_ping_measures_round_trip() {
+    let server = AsyncListener::new(
+        ("127.0.0.1", 0),
+        30,
+        wrap_handler!(count_handled),
+        wrap_handler!(log_error),
+    )
+    .await
+    .spawn();
+    let port = server.local_addr().unwrap().port();
+
+    let mut client = AsyncClient::<MyPacket>::new("127.0.0.1", port)
+        .await
+        .unwrap();
+    assert_eq!(client.recv().await.unwrap().header(), "OK");
+
+    for _ in 0..3 {
+        let latency = client.ping().await.unwrap();
+        assert!(latency > Duration::ZERO);
+        assert!(
+            latency < Duration::from_secs(1),
+            "Implausible latency {latency:?}"
+        );
+    }
+
+    // The listener answers pings itself
+    assert_eq!(PINGS_HANDLED.load(Ordering::SeqCst), 0);
+
+    server.stop().await;
+}
+
+// Only the listener's pong counts as an answer, not any OK for the same request
+#[tokio::test]
+async fn test_ping_requires_pong() {
+    let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let (read_half, mut write_half) = stream.into_split();
+        let mut frames = framing::FrameReader::new(read_half);
+        let request = MyPacket::de(&frames.read_frame().await.unwrap().unwrap());
+        assert!(request.is_ping());
+
+        let mut reply = MyPacket::ok();
+        reply.body_mut().request_id = request.body().request_id;
+        framing::write_frame(&mut write_half, &reply.ser())
+            .await
+            .unwrap();
+        // Keep the connection open until the client is done
+        let _ = frames.read_frame().await;
+    });
+
+    let mut client = AsyncClient::<MyPacket>::new("127.0.0.1", port)
+        .await
+        .unwrap();
+    let result = client.ping().await;
+    assert!(matches!(result, Err(Error::InvalidPacket(_))), "{result:?}");
+
+    drop(client);
+    server.await.unwrap();
+}
+
+static TRANSFERS_HANDLED: AtomicUsize = AtomicUsize::new(0);
+
+async fn echo_attachment(sources: HandlerSources<MySession, MyResource>, packet: MyPacket) {
+    TRANSFERS_HANDLED.fetch_add(1, Ordering::SeqCst);
+    let mut response = MyPacket::ok();
+    response.body_mut().attachment = packet.body().attachment;
+    let mut socket = sources.socket;
+    let _ = socket.send(response).await;
+}
+
+#[tokio::test]
+async fn test_send_chunked_reassembles_attachment() {
+    const TOTAL: usize = 2 * 1024 * 1024;
+    const CHUNK: usize = 64 * 1024;
+
+    let server = AsyncListener::new(
+        ("127.0.0.1", 0),
+        30,
+        wrap_handler!(echo_attachment),
+        wrap_handler!(log_error),
+    )
+    .await
+    .spawn();
+    let port = server.local_addr().unwrap().port();
+
+    let mut client = AsyncClient::<MyPacket>::new("127.0.0.1", port)
+        .await
+        .unwrap();
+    assert_eq!(client.recv().await.unwrap().header(), "OK");
+
+    let payload: Vec<u8> = (0..TOTAL).map(|i| (i % 251) as u8).collect();
+    let mut packet = MyPacket::ok();
+    packet.body_mut().attachment = Some(payload.clone());
+
+    let mut progress = Vec::new();
+    let response = client
+        .send_chunked(packet, CHUNK, |sent, total| progress.push((sent, total)))
+        .await
+        .unwrap();
+
+    assert_eq!(progress.len(), TOTAL / CHUNK);
+    assert!(progress.windows(2).all(|pair| pair[0].0 < pair[1].0));
+    assert_eq!(progress.last(), Some(&(TOTAL, TOTAL)));
+
+    // The handler runs once, with the whole attachment
+    assert_eq!(TRANSFERS_HANDLED.load(Ordering::SeqCst), 1);
+    assert!(response.body().attachment == Some(payload));
+
+    server.stop().await;
+}
+
+async fn report_encryption(sources: HandlerSources<MySession, MyResource>, _packet: MyPacket) {
+    let mut socket = sources.socket;
+    let mut response = MyPacket::ok();
+    response.body_mut().username = Some(socket.is_encrypted().to_string());
+    let _ = socket.send(response).await;
+}
+
+#[tokio::test]
+async fn test_is_encrypted_reflects_handshake() {
+    let encrypted_server = AsyncListener::new(
+        ("127.0.0.1", 0),
+        30,
+        wrap_handler!(report_encryption),
+        wrap_handler!(log_error),
+    )
+    .await
+    .with_encryption_config(EncryptionConfig::default_on())
+    .spawn();
+    let plain_server = AsyncListener::new(
+        ("127.0.0.1", 0),
+        30,
+        wrap_handler!(report_encryption),
+        wrap_handler!(log_error),
+    )
+    .await
+    .spawn();
+
+    let mut client =
+        AsyncClient::<MyPacket>::new("127.0.0.1", encrypted_server.local_addr().unwrap().port())
+            .await
+            .unwrap()
+            .with_encryption_config(EncryptionConfig::default_on())
+            .await
+            .unwrap();
+    assert_eq!(client.recv().await.unwrap().header(), "OK");
+    assert!(client.is_encrypted());
+    let response = client.send_recv(MyPacket::ok()).await.unwrap();
+    assert_eq!(response.body().username.as_deref(), Some("true"));
+
+    let mut client =
+        AsyncClient::<MyPacket>::new("127.0.0.1", plain_server.local_addr().unwrap().port())
+            .await
+            .unwrap();
+    assert_eq!(client.recv().await.unwrap().header(), "OK");
+    assert!(!client.is_encrypted());
+    let response = client.send_recv(MyPacket::ok()).await.unwrap();
+    assert_eq!(response.body().username.as_deref(), Some("false"));
+
+    encrypted_server.stop().await;
+    plain_server.stop().await;
+}
+
+async fn report_nodelay(sources: HandlerSources<MySession, MyResource>, _packet: MyPacket) {
+    let mut socket = sources.socket;
+    let mut response = MyPacket::ok();
+    response.body_mut().username = socket.nodelay().map(|nodelay| nodelay.to_string());
+    let _ = socket.send(response).await;
+}
+
+#[tokio::test]
+async fn test_nodelay_is_applied_to_both_ends() {
+    let server = AsyncListener::new(
+        ("127.0.0.1", 0),
+        30,
+        wrap_handler!(report_nodelay),
+        wrap_handler!(log_error),
+    )
+    .await
+    .with_nodelay(true)
+    .spawn();
+    let port = server.local_addr().unwrap().port();
+
+    let client = AsyncClient::<MyPacket>::new("127.0.0.1", port)
+        .await
+        .unwrap()
+        .with_nodelay(false);
+    assert_eq!(client.nodelay(), Some(false));
+
+    let mut client = client.with_nodelay(true);
+    assert_eq!(client.nodelay(), Some(true));
+    assert_eq!(client.recv().await.unwrap().header(), "OK");
+
+    let response = client.send_recv(MyPacket::ok()).await.unwrap();
+    assert_eq!(response.body().username.as_deref(), Some("true"));
+
+    server.stop().await;
+}
+
+// Broadcasts a headline ahead of every reply
+async fn broadcast_then_reply(sources: HandlerSources<MySession, MyResource>, request: MyPacket) {
+    let mut socket = sources.socket;
+    let mut news = packet("NEWS").set_broadcasting();
+    news.body_mut().username = Some(format!("headline for {}", request.header()));
+    socket.send(news).await.unwrap();
+
+    let reply = if request.header() == "PING" {
+        "PONG"
+    } else {
+        "DIRECT"
+    };
+    socket.send(packet(reply)).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_broadcast_handler_receives_decoded_packets() {
+    let server = AsyncListener::new(
+        ("127.0.0.1", 0),
+        30,
+        wrap_handler!(broadcast_then_reply),
+        wrap_handler!(log_error),
+    )
+    .await
+    .spawn();
+    let port = server.local_addr().unwrap().port();
+
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let received_clone = received.clone();
+    let mut client = AsyncClient::<MyPacket>::new("127.0.0.1", port)
+        .await
+        .unwrap()
+        .with_broadcast_handler(Box::new(move |packet: MyPacket| {
+            received_clone.lock().unwrap().push(packet);
+        }));
+    assert_eq!(client.recv().await.unwrap().header(), "OK");
+
+    let response = client.send_recv(packet("PING")).await.unwrap();
+    assert_eq!(response.header(), "PONG");
+
+    // A plain recv skips the broadcast too
+    client.send(packet("PUSH")).await.unwrap();
+    assert_eq!(client.recv().await.unwrap().header(), "DIRECT");
+
+    let headlines: Vec<_> = received
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|packet| (packet.header(), packet.body().username))
+        .collect();
+    assert_eq!(
+        headlines,
+        [
+            ("NEWS".to_string(), Some("headline for PING".to_string())),
+            ("NEWS".to_string(), Some("headline for PUSH".to_string())),
+        ]
+    );
+
+    server.stop().await;
+}
@@ -0,0 +1,231 @@
+use std::{sync::Arc, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::AsyncReadExt,
+    net::{TcpListener, TcpStream},
+    sync::{RwLock, oneshot},
+};
+
+use super::{MyResource, MySession};
+use crate::{
+    asynch::{
+        authenticator::{AuthType, Authenticator},
+        client::{AsyncClient, EncryptionConfig},
+        framing::FRAME_HEADER_LEN,
+        listener::{AsyncListener, HandlerSources},
+        socket::TSocket,
+    },
+    compression::{CompressionAlgorithm, CompressionConfig},
+    errors::Error,
+    packet::{Packet, PacketBody},
+    session::Sessions,
+    wrap_handler,
+};
+
+const PAYLOAD_SIZE: usize = 500 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct BulkPacket {
+    header: String,
+    body: PacketBody,
+    data: Option<String>,
+}
+
+impl Packet for BulkPacket {
+    fn header(&self) -> String {
+        self.header.clone()
+    }
+
+    fn body(&self) -> PacketBody {
+        self.body.clone()
+    }
+
+    fn body_mut(&mut self) -> &mut PacketBody {
+        &mut self.body
+    }
+
+    fn ok() -> Self {
+        Self {
+            header: "OK".to_string(),
+            body: PacketBody::default(),
+            data: None,
+        }
+    }
+
+    fn error(error: Error) -> Self {
+        Self {
+            header: "ERROR".to_string(),
+            body: PacketBody::with_error_string(error.to_string()),
+            data: None,
+        }
+    }
+
+    fn keep_alive() -> Self {
+        Self {
+            header: "KEEPALIVE".to_string(),
+            body: PacketBody::default(),
+            data: None,
+        }
+    }
+}
+
+fn bulk_packet() -> BulkPacket {
+    BulkPacket {
+        header: "BULK".to_string(),
+        body: PacketBody::default(),
+        data: Some("tnet-compression-".repeat(PAYLOAD_SIZE / 17)),
+    }
+}
+
+#[test]
+fn test_small_payloads_are_flagged_but_not_compressed() {
+    let config = CompressionConfig::default_on();
+    let data = b"tiny".to_vec();
+
+    let framed = config.compress(data.clone()).unwrap();
+    assert_eq!(framed.len(), data.len() + 1);
+    assert_eq!(config.decompress(&framed).unwrap(), data);
+}
+
+#[test]
+fn test_decompression_stops_at_the_size_limit() {
+    let data = vec![0u8; 8 * 1024 * 1024];
+    for algorithm in [CompressionAlgorithm::Gzip, CompressionAlgorithm::Zstd] {
+        let config = CompressionConfig {
+            algorithm,
+            min_size: 1024,
+        };
+        let framed = config.compress(data.clone()).unwrap();
+        assert!(framed.len() < 64 * 1024);
+
+        assert_eq!(
+            config.decompress_limited(&framed, 1024 * 1024),
+            Err(Error::PacketTooLarge(1024 * 1024 + 1))
+        );
+        assert_eq!(
+            config.decompress_limited(&framed, data.len()).unwrap(),
+            data
+        );
+    }
+}
+
+#[test]
+fn test_disabled_compression_leaves_wire_format_untouched() {
+    let packet = bulk_packet();
+    let encoded = CompressionConfig::default().encode(&packet, None);
+    assert_eq!(encoded, packet.ser());
+}
+
+#[tokio::test]
+async fn test_large_packet_is_compressed_on_the_wire() {
+    let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (client, accepted) = tokio::join!(TcpStream::connect(addr), listener.accept());
+    let mut client = client.unwrap();
+
+    let config = CompressionConfig {
+        algorithm: CompressionAlgorithm::Gzip,
+        min_size: 1024,
+    };
+    let mut socket =
+        TSocket::<MySession>::new(accepted.unwrap().0, Arc::new(RwLock::new(Sessions::new())))
+            .with_compression(config);
+
+    let packet = bulk_packet();
+    let plaintext_size = packet.ser().len();
+    socket.send(packet).await.unwrap();
+
+    let mut wire = Vec::new();
+    let mut buf = vec![0; 64 * 1024];
+    while let Ok(Ok(n)) =
+        tokio::time::timeout(Duration::from_millis(200), client.read(&mut buf)).await
+    {
+        if n == 0 {
+            break;
+        }
+        wire.extend_from_slice(&buf[..n]);
+    }
+
+    assert!(plaintext_size >= PAYLOAD_SIZE);
+    assert!(
+        wire.len() * 50 < plaintext_size,
+        "{} bytes on the wire for a {} byte packet",
+        wire.len(),
+        plaintext_size
+    );
+
+    let decoded: BulkPacket = config.decode(&wire[FRAME_HEADER_LEN..], None).unwrap();
+    assert_eq!(decoded.data, bulk_packet().data);
+}
+
+#[tokio::test]
+async fn test_compressed_encrypted_round_trip() {
+    let (tx, rx) = oneshot::channel();
+
+    async fn handle_ok(sources: HandlerSources<MySession, MyResource>, packet: BulkPacket) {
+        let mut socket = sources.socket;
+        let mut response = BulkPacket::ok();
+        response.data = packet.data.map(|data| data.len().to_string());
+        if let Err(e) = socket.send(response).await {
+            eprintln!("Failed to send response: {e}");
+        }
+    }
+
+    async fn handle_error(_sources: HandlerSources<MySession, MyResource>, error: Error) {
+        println!("Server error: {error}");
+    }
+
+    let compression = CompressionConfig {
+        algorithm: CompressionAlgorithm::Zstd,
+        min_size: 256,
+    };
+
+    let mut server = AsyncListener::new(
+        ("127.0.0.1", 8210),
+        30,
+        wrap_handler!(handle_ok),
+        wrap_handler!(handle_error),
+    )
+    .await
+    .with_encryption_config(EncryptionConfig::default_on())
+    .with_compression(compression)
+    .with_authenticator(
+        Authenticator::new(AuthType::UserPassword).with_auth_fn(|user, pass| {
+            Box::pin(async move {
+                if user == "admin" && pass == "password" {
+                    Ok(())
+                } else {
+                    Err(Error::InvalidCredentials)
+                }
+            })
+        }),
+    );
+
+    let server_handle = tokio::spawn(async move {
+        tokio::select! {
+            _ = server.run() => {},
+            _ = rx => println!("Server shutting down"),
+        }
+    });
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut client = AsyncClient::<BulkPacket>::new("127.0.0.1", 8210)
+        .await
+        .unwrap()
+        .with_compression(compression)
+        .with_credentials("admin", "password")
+        .with_encryption_config(EncryptionConfig::default_on())
+        .await
+        .unwrap();
+
+    let response = client.send_recv(bulk_packet()).await.unwrap();
+    assert_eq!(
+        response.data,
+        Some(bulk_packet().data.unwrap().len().to_string())
+    );
+
+    let _ = tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(2), server_handle).await;
+}
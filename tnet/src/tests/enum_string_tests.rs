@@ -0,0 +1,81 @@
+use std::str::FromStr;
+
+use tnet_macros::ParseEnumString;
+
+#[derive(Debug, Clone, PartialEq, ParseEnumString)]
+enum Header {
+    Ok,
+    KeepAlive,
+    Error(String),
+    Move(i32, i32),
+    Chat { room: String, text: String },
+    Ping(),
+    Pong {},
+}
+
+#[derive(Debug, Clone, PartialEq, ParseEnumString)]
+#[enum_str(separator = "/")]
+enum Route {
+    #[enum_str(name = "home")]
+    Home,
+    #[enum_str(name = "user")]
+    User(u64),
+}
+
+#[test]
+fn test_unit_variants() {
+    assert_eq!(Header::KeepAlive.to_string(), "KeepAlive");
+    assert_eq!(Header::from_str("Ok").unwrap(), Header::Ok);
+    assert_eq!(Header::from("KeepAlive"), Header::KeepAlive);
+    assert_eq!(
+        Header::from_str("Unknown").unwrap_err(),
+        "Unknown variant: Unknown"
+    );
+}
+
+#[test]
+fn test_tuple_variants() {
+    let error = Header::Error("Bad things".to_string());
+    assert_eq!(error.to_string(), "Error:Bad things");
+    assert_eq!(Header::from_str("Error:Bad things").unwrap(), error);
+
+    // The last field keeps any remaining separators
+    assert_eq!(
+        Header::from_str("Error:a:b").unwrap(),
+        Header::Error("a:b".to_string())
+    );
+
+    let movement = Header::Move(3, -4);
+    assert_eq!(movement.to_string(), "Move:3:-4");
+    assert_eq!(Header::from_str("Move:3:-4").unwrap(), movement);
+
+    assert!(Header::from_str("Move:3").is_err());
+    assert!(Header::from_str("Move:x:1").is_err());
+}
+
+#[test]
+fn test_struct_variants() {
+    let chat = Header::Chat {
+        room: "lobby".to_string(),
+        text: "hi there".to_string(),
+    };
+    assert_eq!(chat.to_string(), "Chat:lobby:hi there");
+    assert_eq!(Header::from_str("Chat:lobby:hi there").unwrap(), chat);
+    assert_eq!(Header::from(String::from("Chat:lobby:hi there")), chat);
+}
+
+#[test]
+fn test_custom_names_and_separator() {
+    assert_eq!(Route::Home.to_string(), "home");
+    assert_eq!(Route::User(42).to_string(), "user/42");
+    assert_eq!(Route::from_str("user/42").unwrap(), Route::User(42));
+    assert!(Route::from_str("User/42").is_err());
+}
+
+#[test]
+fn test_zero_field_variants() {
+    assert_eq!(Header::Ping().to_string(), "Ping");
+    assert_eq!(Header::from_str("Ping").unwrap(), Header::Ping());
+    assert_eq!(Header::Pong {}.to_string(), "Pong");
+    assert_eq!(Header::from_str("Pong").unwrap(), Header::Pong {});
+}
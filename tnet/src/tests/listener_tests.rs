@@ -0,0 +1,1952 @@
+use std::{
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use bytes::Bytes;
+use futures::{StreamExt, future::BoxFuture};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    sync::oneshot,
+};
+
+use super::{MyPacket, MyResource, MySession};
+use crate::{
+    asynch::{
+        authenticator::{AuthType, Authenticator},
+        client::{AsyncClient, ClientMessage, EncryptionConfig, TimeoutConfig},
+        framing,
+        listener::{
+            AsyncListener, BindOptions, DispatchMode, HandlerSources, MaxConnPolicy, Middleware,
+            MiddlewareFlow,
+        },
+        rate_limit::{RateLimitConfig, TokenBuckets},
+        socket::BroadcastReport,
+    },
+    compression::CompressionConfig,
+    errors::Error,
+    handler_registry,
+    packet::{Packet, PacketBody},
+    session::Session,
+    wrap_handler, wrap_reply_handler, wrap_try_handler,
+};
+
+async fn echo_ok(sources: HandlerSources<MySession, MyResource>, _packet: MyPacket) {
+    let mut socket = sources.socket;
+    if let Err(e) = socket.send(MyPacket::ok()).await {
+        eprintln!("Failed to send response: {e}");
+    }
+}
+
+const CHALLENGE_SECRET: &[u8] = b"challenge-secret";
+
+fn sign_challenge(secret: &[u8], challenge: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).unwrap();
+    mac.update(challenge.as_bytes());
+    BASE64.encode(mac.finalize().into_bytes())
+}
+
+async fn log_error(_sources: HandlerSources<MySession, MyResource>, error: Error) {
+    println!("Server error: {error}");
+}
+
+#[tokio::test]
+async fn test_max_connections_rejects_extra_client() {
+    let (tx, rx) = oneshot::channel();
+
+    let mut server = AsyncListener::new(
+        ("127.0.0.1", 8200),
+        30,
+        wrap_handler!(echo_ok),
+        wrap_handler!(log_error),
+    )
+    .await
+    .with_max_connections(2)
+    .with_max_conn_policy(MaxConnPolicy::Reject);
+
+    let server_handle = tokio::spawn(async move {
+        tokio::select! {
+            _ = server.run() => {},
+            _ = rx => println!("Server shutting down"),
+        }
+    });
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // The first two clients get the unsolicited session OK from the no-auth path
+    let mut first = AsyncClient::<MyPacket>::new("127.0.0.1", 8200)
+        .await
+        .unwrap();
+    assert_eq!(first.recv().await.unwrap().header(), "OK");
+
+    let mut second = AsyncClient::<MyPacket>::new("127.0.0.1", 8200)
+        .await
+        .unwrap();
+    assert_eq!(second.recv().await.unwrap().header(), "OK");
+
+    let mut third = AsyncClient::<MyPacket>::new("127.0.0.1", 8200)
+        .await
+        .unwrap();
+    let rejection = third.recv().await.unwrap();
+    assert_eq!(rejection.header(), "ERROR");
+    assert_eq!(
+        rejection.body().error_string,
+        Some(Error::TooManyConnections.to_string())
+    );
+
+    let _ = tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(2), server_handle).await;
+}
+
+#[tokio::test]
+async fn test_rate_limit_drops_excess_connections() {
+    let (tx, rx) = oneshot::channel();
+
+    let mut server = AsyncListener::new(
+        ("127.0.0.1", 8201),
+        30,
+        wrap_handler!(echo_ok),
+        wrap_handler!(log_error),
+    )
+    .await
+    .with_rate_limit(RateLimitConfig {
+        per_ip_per_sec: 1,
+        burst: 2,
+    });
+
+    let server_handle = tokio::spawn(async move {
+        tokio::select! {
+            _ = server.run() => {},
+            _ = rx => println!("Server shutting down"),
+        }
+    });
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut accepted = 0;
+    let mut dropped = 0;
+
+    for _ in 0..5 {
+        let mut stream = TcpStream::connect(("127.0.0.1", 8201)).await.unwrap();
+        let mut buf = vec![0; 4096];
+
+        // Accepted connections receive the no-auth session OK; dropped ones see EOF
+        match tokio::time::timeout(Duration::from_secs(2), stream.read(&mut buf)).await {
+            Ok(Ok(n)) if n > 0 => accepted += 1,
+            Ok(_) => dropped += 1,
+            Err(_) => panic!("Timed out waiting for the server"),
+        }
+    }
+
+    assert_eq!(accepted, 2);
+    assert_eq!(dropped, 3);
+
+    let _ = tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(2), server_handle).await;
+}
+
+// The limiter keeps a bounded number of peers even when their buckets never refill
+#[test]
+fn test_rate_limit_evicts_oldest_peer_at_capacity() {
+    let mut limiter = TokenBuckets::new(RateLimitConfig {
+        per_ip_per_sec: 0,
+        burst: 1,
+    });
+
+    assert!(limiter.try_acquire(0));
+    assert!(!limiter.try_acquire(0));
+
+    // Filling the limiter pushes out the first peer, whose bucket starts over
+    for key in 1..=TokenBuckets::<usize>::MAX_KEYS {
+        assert!(limiter.try_acquire(key));
+    }
+    assert!(limiter.try_acquire(0));
+    assert!(!limiter.try_acquire(0));
+}
+
+async fn chat_room(sources: HandlerSources<MySession, MyResource>, packet: MyPacket) {
+    let mut socket = sources.socket;
+    let mut pools = sources.pools;
+
+    let chat = MyPacket {
+        header: "CHAT".to_string(),
+        body: PacketBody::default(),
+    };
+    let result = match packet.header.as_str() {
+        "JOIN" => {
+            pools.insert("chat", &socket).await;
+            Ok(BroadcastReport::default())
+        }
+        "SAY" => pools.broadcast_to_except("chat", &socket, chat).await,
+        _ => pools.broadcast_except(&socket, chat).await,
+    };
+    result.unwrap();
+
+    socket.send(MyPacket::ok()).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_broadcast_except_skips_sender() {
+    let (tx, rx) = oneshot::channel();
+
+    let mut server = AsyncListener::new(
+        ("127.0.0.1", 8202),
+        30,
+        wrap_handler!(chat_room),
+        wrap_handler!(log_error),
+    )
+    .await
+    .with_pool("chat")
+    .await;
+
+    let server_handle = tokio::spawn(async move {
+        tokio::select! {
+            _ = server.run() => {},
+            _ = rx => println!("Server shutting down"),
+        }
+    });
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut clients = Vec::new();
+    let mut streams = Vec::new();
+    for _ in 0..3 {
+        let mut client = AsyncClient::<MyPacket>::new("127.0.0.1", 8202)
+            .await
+            .unwrap();
+        assert_eq!(client.recv().await.unwrap().header(), "OK");
+
+        streams.push(Box::pin(client.subscribe()));
+        let join = MyPacket {
+            header: "JOIN".to_string(),
+            body: PacketBody::default(),
+        };
+        assert_eq!(client.send_recv(join).await.unwrap().header(), "OK");
+        clients.push(client);
+    }
+
+    for header in ["SAY", "SHOUT"] {
+        let message = MyPacket {
+            header: header.to_string(),
+            body: PacketBody::default(),
+        };
+        assert_eq!(clients[0].send_recv(message).await.unwrap().header(), "OK");
+
+        for stream in &mut streams[1..] {
+            let pushed = tokio::time::timeout(Duration::from_secs(2), stream.next())
+                .await
+                .expect("Timed out waiting for broadcast")
+                .expect("Stream ended early")
+                .unwrap();
+            assert_eq!(pushed.header(), "CHAT");
+        }
+    }
+
+    // The sender never hears its own messages
+    let echoed = tokio::time::timeout(Duration::from_millis(300), streams[0].next()).await;
+    assert!(echoed.is_err(), "Sender received its own broadcast");
+
+    let _ = tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(2), server_handle).await;
+}
+
+#[tokio::test]
+async fn test_pre_shared_key_authentication() {
+    let (tx, rx) = oneshot::channel();
+
+    let mut server = AsyncListener::new(
+        ("127.0.0.1", 8203),
+        30,
+        wrap_handler!(echo_ok),
+        wrap_handler!(log_error),
+    )
+    .await
+    .with_authenticator(
+        Authenticator::new(AuthType::PreSharedKey).with_key_fn(|key| {
+            Box::pin(async move {
+                if key == "secret-key" {
+                    Ok(())
+                } else {
+                    Err(Error::InvalidCredentials)
+                }
+            })
+        }),
+    );
+
+    let server_handle = tokio::spawn(async move {
+        tokio::select! {
+            _ = server.run() => {},
+            _ = rx => println!("Server shutting down"),
+        }
+    });
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut valid = AsyncClient::<MyPacket>::new("127.0.0.1", 8203)
+        .await
+        .unwrap()
+        .with_token("secret-key");
+    let mut accepted = valid.send_recv(MyPacket::ok()).await.unwrap();
+    assert_eq!(accepted.header(), "OK");
+    assert!(accepted.session_id(None).is_some());
+    assert_eq!(
+        valid.send_recv(MyPacket::ok()).await.unwrap().header(),
+        "OK"
+    );
+
+    let mut invalid = AsyncClient::<MyPacket>::new("127.0.0.1", 8203)
+        .await
+        .unwrap()
+        .with_token("wrong-key");
+    let rejection = invalid.send_recv(MyPacket::ok()).await.unwrap();
+    assert_eq!(rejection.header(), "ERROR");
+    assert_eq!(
+        rejection.body().error_string,
+        Some(Error::InvalidCredentials.to_string())
+    );
+
+    let _ = tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(2), server_handle).await;
+}
+
+#[tokio::test]
+async fn test_challenge_response_authentication() {
+    let (tx, rx) = oneshot::channel();
+
+    let mut server = AsyncListener::new(
+        ("127.0.0.1", 8204),
+        30,
+        wrap_handler!(echo_ok),
+        wrap_handler!(log_error),
+    )
+    .await
+    .with_authenticator(Authenticator::new(AuthType::Challenge).with_challenge_fn(
+        |challenge, response| {
+            Box::pin(async move {
+                if response == sign_challenge(CHALLENGE_SECRET, &challenge) {
+                    Ok(())
+                } else {
+                    Err(Error::InvalidCredentials)
+                }
+            })
+        },
+    ));
+
+    let server_handle = tokio::spawn(async move {
+        tokio::select! {
+            _ = server.run() => {},
+            _ = rx => println!("Server shutting down"),
+        }
+    });
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut valid = AsyncClient::<MyPacket>::new("127.0.0.1", 8204)
+        .await
+        .unwrap()
+        .with_challenge_responder(|challenge| sign_challenge(CHALLENGE_SECRET, &challenge));
+    let mut accepted = valid.send_recv(MyPacket::ok()).await.unwrap();
+    assert_eq!(accepted.header(), "OK");
+    assert!(accepted.session_id(None).is_some());
+    assert_eq!(
+        valid.send_recv(MyPacket::ok()).await.unwrap().header(),
+        "OK"
+    );
+
+    let mut invalid = AsyncClient::<MyPacket>::new("127.0.0.1", 8204)
+        .await
+        .unwrap()
+        .with_challenge_responder(|challenge| sign_challenge(b"wrong-secret", &challenge));
+    let rejection = invalid.send_recv(MyPacket::ok()).await.unwrap();
+    assert_eq!(rejection.header(), "ERROR");
+    assert_eq!(
+        rejection.body().error_string,
+        Some(Error::InvalidCredentials.to_string())
+    );
+
+    let _ = tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(2), server_handle).await;
+}
+
+#[tokio::test]
+async fn test_unanswered_challenge_does_not_block_other_clients() {
+    let (tx, rx) = oneshot::channel();
+
+    let mut server = AsyncListener::new(
+        ("127.0.0.1", 8235),
+        30,
+        wrap_handler!(echo_ok),
+        wrap_handler!(log_error),
+    )
+    .await
+    .with_timeouts(TimeoutConfig {
+        read: Duration::from_secs(10),
+        ..TimeoutConfig::default()
+    })
+    .with_authenticator(Authenticator::new(AuthType::Challenge).with_challenge_fn(
+        |challenge, response| {
+            Box::pin(async move {
+                if response == sign_challenge(CHALLENGE_SECRET, &challenge) {
+                    Ok(())
+                } else {
+                    Err(Error::InvalidCredentials)
+                }
+            })
+        },
+    ));
+
+    let server_handle = tokio::spawn(async move {
+        tokio::select! {
+            _ = server.run() => {},
+            _ = rx => println!("Server shutting down"),
+        }
+    });
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // Asks for a challenge and then never answers it
+    let mut stalled = TcpStream::connect(("127.0.0.1", 8235)).await.unwrap();
+    stalled
+        .write_all(&framing::encode_frame(&MyPacket::ok().ser()))
+        .await
+        .unwrap();
+    let mut prompt = [0; 4];
+    stalled.read_exact(&mut prompt).await.unwrap();
+
+    let accepted = tokio::time::timeout(Duration::from_secs(3), async {
+        let mut client = AsyncClient::<MyPacket>::new("127.0.0.1", 8235)
+            .await
+            .unwrap()
+            .with_challenge_responder(|challenge| sign_challenge(CHALLENGE_SECRET, &challenge));
+        client.send_recv(MyPacket::ok()).await.unwrap()
+    })
+    .await
+    .expect("Authentication was held up by the stalled client");
+    assert_eq!(accepted.header(), "OK");
+
+    drop(stalled);
+    let _ = tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(2), server_handle).await;
+}
+
+static LOCKOUT_AUTH_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[tokio::test]
+async fn test_failed_authentication_locks_out_ip() {
+    let (tx, rx) = oneshot::channel();
+
+    let mut server = AsyncListener::new(
+        ("127.0.0.1", 8205),
+        30,
+        wrap_handler!(echo_ok),
+        wrap_handler!(log_error),
+    )
+    .await
+    .with_authenticator(
+        Authenticator::new(AuthType::UserPassword)
+            .with_auth_fn(|_username, _password| {
+                LOCKOUT_AUTH_CALLS.fetch_add(1, Ordering::SeqCst);
+                Box::pin(async { Err(Error::InvalidCredentials) })
+            })
+            .with_lockout(5, Duration::from_secs(60), Duration::from_secs(60)),
+    );
+
+    let server_handle = tokio::spawn(async move {
+        tokio::select! {
+            _ = server.run() => {},
+            _ = rx => println!("Server shutting down"),
+        }
+    });
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    for _ in 0..5 {
+        let mut client = AsyncClient::<MyPacket>::new("127.0.0.1", 8205)
+            .await
+            .unwrap()
+            .with_credentials("admin", "wrong");
+        let rejection = client.send_recv(MyPacket::ok()).await.unwrap();
+        assert_eq!(
+            rejection.body().error_string,
+            Some(Error::InvalidCredentials.to_string())
+        );
+    }
+    assert_eq!(LOCKOUT_AUTH_CALLS.load(Ordering::SeqCst), 5);
+
+    let mut client = AsyncClient::<MyPacket>::new("127.0.0.1", 8205)
+        .await
+        .unwrap()
+        .with_credentials("admin", "wrong");
+    let rejection = client.send_recv(MyPacket::ok()).await.unwrap();
+    assert_eq!(rejection.header(), "ERROR");
+    assert_eq!(
+        rejection.body().error_string,
+        Some(Error::AuthRateLimited.to_string())
+    );
+
+    // The locked out attempt never reached the auth function
+    assert_eq!(LOCKOUT_AUTH_CALLS.load(Ordering::SeqCst), 5);
+
+    let _ = tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(2), server_handle).await;
+}
+
+#[tokio::test]
+async fn test_in_memory_transport_authenticates_and_serves() {
+    let mut server =
+        AsyncListener::new_in_memory(30, wrap_handler!(echo_ok), wrap_handler!(log_error))
+            .with_authenticator(Authenticator::new(AuthType::UserPassword).with_auth_fn(
+                |username, password| {
+                    Box::pin(async move {
+                        if username == "admin" && password == "password" {
+                            Ok(())
+                        } else {
+                            Err(Error::InvalidCredentials)
+                        }
+                    })
+                },
+            ));
+    assert!(server.local_addr().is_none());
+
+    let (client_end, server_end) = tokio::io::duplex(64 * 1024);
+    let mut client =
+        AsyncClient::<MyPacket>::from_duplex(client_end).with_credentials("admin", "password");
+
+    let ((), accepted) = tokio::join!(
+        server.accept_in_memory(server_end),
+        client.send_recv(MyPacket::ok())
+    );
+    let mut accepted = accepted.unwrap();
+    assert_eq!(accepted.header(), "OK");
+    assert!(accepted.session_id(None).is_some());
+
+    let response = client.send_recv(MyPacket::ok()).await.unwrap();
+    assert_eq!(response.header(), "OK");
+}
+
+#[tokio::test]
+async fn test_idle_timeout_drops_silent_client() {
+    let (tx, rx) = oneshot::channel();
+
+    let mut server = AsyncListener::new(
+        ("127.0.0.1", 8206),
+        30,
+        wrap_handler!(echo_ok),
+        wrap_handler!(log_error),
+    )
+    .await
+    .with_idle_timeout(Duration::from_millis(500));
+
+    let server_handle = tokio::spawn(async move {
+        tokio::select! {
+            _ = server.run() => {},
+            _ = rx => println!("Server shutting down"),
+        }
+    });
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut silent = TcpStream::connect(("127.0.0.1", 8206)).await.unwrap();
+
+    // Keep-alives restart the timer for the active client
+    let mut active = AsyncClient::<MyPacket>::new("127.0.0.1", 8206)
+        .await
+        .unwrap();
+    assert_eq!(active.recv().await.unwrap().header(), "OK");
+
+    for _ in 0..6 {
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        active.send(MyPacket::keep_alive()).await.unwrap();
+    }
+
+    // The silent client was disconnected long ago
+    let mut received = Vec::new();
+    let read = tokio::time::timeout(Duration::from_secs(2), silent.read_to_end(&mut received))
+        .await
+        .expect("Silent client was not disconnected");
+    assert!(read.is_ok());
+
+    let response = active.send_recv(MyPacket::ok()).await.unwrap();
+    assert_eq!(response.header(), "OK");
+    assert!(!response.is_disconnect());
+
+    let _ = tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(2), server_handle).await;
+}
+
+static MIDDLEWARE_HANDLED: AtomicUsize = AtomicUsize::new(0);
+
+struct RequireSession;
+
+impl Middleware<MyPacket, MySession, MyResource> for RequireSession {
+    fn process<'a>(
+        &'a self,
+        _sources: &'a HandlerSources<MySession, MyResource>,
+        packet: MyPacket,
+    ) -> BoxFuture<'a, MiddlewareFlow<MyPacket>> {
+        Box::pin(async move {
+            if packet.body().session_id.is_some() {
+                MiddlewareFlow::Pass(packet)
+            } else {
+                MiddlewareFlow::Reject(Error::InvalidSessionId("missing".to_string()))
+            }
+        })
+    }
+}
+
+async fn count_handled(sources: HandlerSources<MySession, MyResource>, packet: MyPacket) {
+    MIDDLEWARE_HANDLED.fetch_add(1, Ordering::SeqCst);
+    echo_ok(sources, packet).await;
+}
+
+async fn reply_error(sources: HandlerSources<MySession, MyResource>, error: Error) {
+    let mut socket = sources.socket;
+    if let Err(e) = socket.send(MyPacket::error(error)).await {
+        eprintln!("Failed to send error: {e}");
+    }
+}
+
+#[tokio::test]
+async fn test_middleware_rejects_packets_without_session() {
+    let (tx, rx) = oneshot::channel();
+
+    let mut server = AsyncListener::new(
+        ("127.0.0.1", 8207),
+        30,
+        wrap_handler!(count_handled),
+        wrap_handler!(reply_error),
+    )
+    .await
+    .with_middleware(std::sync::Arc::new(RequireSession));
+
+    let server_handle = tokio::spawn(async move {
+        tokio::select! {
+            _ = server.run() => {},
+            _ = rx => println!("Server shutting down"),
+        }
+    });
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut client = AsyncClient::<MyPacket>::new("127.0.0.1", 8207)
+        .await
+        .unwrap();
+    let session_id = client.recv().await.unwrap().body().session_id;
+    assert!(session_id.is_some());
+
+    let rejection = client.send_recv(MyPacket::ok()).await.unwrap();
+    assert_eq!(rejection.header(), "ERROR");
+    assert_eq!(MIDDLEWARE_HANDLED.load(Ordering::SeqCst), 0);
+
+    let mut packet = MyPacket::ok();
+    packet.body_mut().session_id = session_id;
+    let response = client.send_recv(packet).await.unwrap();
+    assert_eq!(response.header(), "OK");
+    assert_eq!(MIDDLEWARE_HANDLED.load(Ordering::SeqCst), 1);
+
+    let _ = tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(2), server_handle).await;
+}
+
+static OVERSIZED_ERRORS: std::sync::Mutex<Vec<Error>> = std::sync::Mutex::new(Vec::new());
+
+async fn record_error(_sources: HandlerSources<MySession, MyResource>, error: Error) {
+    OVERSIZED_ERRORS.lock().unwrap().push(error);
+}
+
+#[tokio::test]
+async fn test_oversized_packet_closes_connection() {
+    let (tx, rx) = oneshot::channel();
+
+    let mut server = AsyncListener::new(
+        ("127.0.0.1", 8208),
+        30,
+        wrap_handler!(echo_ok),
+        wrap_handler!(record_error),
+    )
+    .await
+    .with_max_packet_size(1024);
+
+    let server_handle = tokio::spawn(async move {
+        tokio::select! {
+            _ = server.run() => {},
+            _ = rx => println!("Server shutting down"),
+        }
+    });
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // Announce a 4 GB frame but only send a few bytes of it
+    let mut stream = TcpStream::connect(("127.0.0.1", 8208)).await.unwrap();
+    stream.write_all(&u32::MAX.to_be_bytes()).await.unwrap();
+    stream.write_all(b"not the whole payload").await.unwrap();
+
+    let mut received = Vec::new();
+    let read = tokio::time::timeout(Duration::from_secs(2), stream.read_to_end(&mut received))
+        .await
+        .expect("Connection was not closed");
+    assert!(read.is_ok());
+
+    assert_eq!(
+        *OVERSIZED_ERRORS.lock().unwrap(),
+        vec![Error::PacketTooLarge(u32::MAX as usize)]
+    );
+
+    let _ = tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(2), server_handle).await;
+}
+
+const FLOOD_BROADCASTS: usize = 200;
+
+async fn flood(sources: HandlerSources<MySession, MyResource>, packet: MyPacket) {
+    let mut socket = sources.socket;
+
+    if packet.header() == "FLOOD" {
+        // Broadcast from another task while the listener keeps reading this socket
+        let mut pools = sources.pools;
+        pools.insert("flood", &socket).await;
+        tokio::spawn(async move {
+            for _ in 0..FLOOD_BROADCASTS {
+                let chat = MyPacket {
+                    header: "CHAT".to_string(),
+                    body: PacketBody::default(),
+                };
+                let report = pools.broadcast_to("flood", chat).await.unwrap();
+                assert_eq!(report.delivered, 1);
+            }
+        });
+    }
+
+    socket.send(MyPacket::ok()).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_broadcast_while_receiving_on_same_socket() {
+    let (tx, rx) = oneshot::channel();
+
+    let mut server = AsyncListener::new(
+        ("127.0.0.1", 8209),
+        30,
+        wrap_handler!(flood),
+        wrap_handler!(log_error),
+    )
+    .await
+    .with_pool("flood")
+    .await;
+
+    let server_handle = tokio::spawn(async move {
+        tokio::select! {
+            _ = server.run() => {},
+            _ = rx => println!("Server shutting down"),
+        }
+    });
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut client = AsyncClient::<MyPacket>::new("127.0.0.1", 8209)
+        .await
+        .unwrap();
+    assert_eq!(client.recv().await.unwrap().header(), "OK");
+
+    let mut stream = Box::pin(client.subscribe());
+    let counter = tokio::spawn(async move {
+        let mut received = 0;
+        while received < FLOOD_BROADCASTS {
+            let pushed = tokio::time::timeout(Duration::from_secs(5), stream.next())
+                .await
+                .expect("Timed out waiting for broadcast")
+                .expect("Stream ended early")
+                .unwrap();
+            assert_eq!(pushed.header(), "CHAT");
+            received += 1;
+        }
+        received
+    });
+
+    let start = MyPacket {
+        header: "FLOOD".to_string(),
+        body: PacketBody::default(),
+    };
+    assert_eq!(client.send_recv(start).await.unwrap().header(), "OK");
+
+    // Requests keep being answered while the broadcasts are written
+    for _ in 0..50 {
+        let ping = MyPacket {
+            header: "PING".to_string(),
+            body: PacketBody::default(),
+        };
+        assert_eq!(client.send_recv(ping).await.unwrap().header(), "OK");
+    }
+
+    assert_eq!(counter.await.unwrap(), FLOOD_BROADCASTS);
+
+    let _ = tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(2), server_handle).await;
+}
+
+// A packet whose `note` must be filled in before handlers see it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct NotePacket {
+    header: String,
+    body: PacketBody,
+    note: String,
+}
+
+impl NotePacket {
+    fn with_note(note: &str) -> Self {
+        Self {
+            header: "NOTE".to_string(),
+            body: PacketBody::default(),
+            note: note.to_string(),
+        }
+    }
+}
+
+impl Packet for NotePacket {
+    fn header(&self) -> String {
+        self.header.clone()
+    }
+
+    fn body(&self) -> PacketBody {
+        self.body.clone()
+    }
+
+    fn body_mut(&mut self) -> &mut PacketBody {
+        &mut self.body
+    }
+
+    fn ok() -> Self {
+        Self {
+            header: "OK".to_string(),
+            body: PacketBody::default(),
+            note: String::new(),
+        }
+    }
+
+    fn error(error: Error) -> Self {
+        Self {
+            header: "ERROR".to_string(),
+            body: PacketBody::with_error_string(error.to_string()),
+            note: String::new(),
+        }
+    }
+
+    fn keep_alive() -> Self {
+        Self {
+            header: "KEEPALIVE".to_string(),
+            body: PacketBody::default(),
+            note: String::new(),
+        }
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        if self.note.is_empty() {
+            return Err(Error::InvalidPacket("empty note".to_string()));
+        }
+        Ok(())
+    }
+}
+
+static VALIDATED_HANDLED: AtomicUsize = AtomicUsize::new(0);
+
+async fn accept_note(sources: HandlerSources<MySession, MyResource>, _packet: NotePacket) {
+    VALIDATED_HANDLED.fetch_add(1, Ordering::SeqCst);
+    let mut socket = sources.socket;
+    socket.send(NotePacket::ok()).await.unwrap();
+}
+
+async fn reject_note(sources: HandlerSources<MySession, MyResource>, error: Error) {
+    let mut socket = sources.socket;
+    socket.send(NotePacket::error(error)).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_invalid_packet_goes_to_error_handler() {
+    let (tx, rx) = oneshot::channel();
+
+    let mut server = AsyncListener::new(
+        ("127.0.0.1", 8211),
+        30,
+        wrap_handler!(accept_note),
+        wrap_handler!(reject_note),
+    )
+    .await;
+
+    let server_handle = tokio::spawn(async move {
+        tokio::select! {
+            _ = server.run() => {},
+            _ = rx => println!("Server shutting down"),
+        }
+    });
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut client = AsyncClient::<NotePacket>::new("127.0.0.1", 8211)
+        .await
+        .unwrap();
+    assert_eq!(client.recv().await.unwrap().header(), "OK");
+
+    let rejection = client.send_recv(NotePacket::with_note("")).await.unwrap();
+    assert_eq!(rejection.header(), "ERROR");
+    assert_eq!(
+        rejection.body().error_string,
+        Some(Error::InvalidPacket("empty note".to_string()).to_string())
+    );
+    assert_eq!(VALIDATED_HANDLED.load(Ordering::SeqCst), 0);
+
+    let response = client
+        .send_recv(NotePacket::with_note("hello"))
+        .await
+        .unwrap();
+    assert_eq!(response.header(), "OK");
+    assert_eq!(VALIDATED_HANDLED.load(Ordering::SeqCst), 1);
+
+    let _ = tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(2), server_handle).await;
+}
+
+async fn reply_peer_ip(sources: HandlerSources<MySession, MyResource>, _packet: MyPacket) {
+    let mut socket = sources.socket;
+    let response = MyPacket {
+        header: socket
+            .peer_ip()
+            .map(|ip| ip.to_string())
+            .unwrap_or_default(),
+        body: PacketBody::default(),
+    };
+    socket.send(response).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_handler_reads_peer_ip() {
+    let (tx, rx) = oneshot::channel();
+
+    let mut server = AsyncListener::new(
+        ("127.0.0.1", 8212),
+        30,
+        wrap_handler!(reply_peer_ip),
+        wrap_handler!(log_error),
+    )
+    .await;
+
+    let server_handle = tokio::spawn(async move {
+        tokio::select! {
+            _ = server.run() => {},
+            _ = rx => println!("Server shutting down"),
+        }
+    });
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut client = AsyncClient::<MyPacket>::new("127.0.0.1", 8212)
+        .await
+        .unwrap();
+    assert_eq!(client.recv().await.unwrap().header(), "OK");
+
+    let response = client.send_recv(MyPacket::ok()).await.unwrap();
+    assert_eq!(response.header(), "127.0.0.1");
+
+    let _ = tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(2), server_handle).await;
+}
+
+// A session carrying a role, so broadcasts can pick sockets by it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RoleSession {
+    id: String,
+    created_at: u64,
+    role: Option<String>,
+}
+
+impl Session for RoleSession {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn created_at(&self) -> u64 {
+        self.created_at
+    }
+
+    fn lifespan(&self) -> Duration {
+        Duration::from_secs(3600)
+    }
+
+    fn empty(id: String) -> Self {
+        Self {
+            id,
+            created_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            role: None,
+        }
+    }
+}
+
+async fn role_room(sources: HandlerSources<RoleSession, MyResource>, packet: MyPacket) {
+    let mut socket = sources.socket;
+    let mut pools = sources.pools;
+
+    match packet.header().as_str() {
+        "ADMIN" => {
+            socket
+                .update_session(|s| s.role = Some("admin".to_string()))
+                .await
+                .unwrap();
+            pools.insert("room", &socket).await;
+        }
+        "JOIN" => pools.insert("room", &socket).await,
+        "ANNOUNCE" => {
+            let announce = MyPacket {
+                header: "ANNOUNCE".to_string(),
+                body: PacketBody::default(),
+            };
+            let report = pools
+                .broadcast_where(|s| s.role.as_deref() == Some("admin"), announce)
+                .await
+                .unwrap();
+            assert_eq!(report.delivered, 2);
+        }
+        _ => {}
+    }
+
+    socket.send(MyPacket::ok()).await.unwrap();
+}
+
+async fn log_role_error(_sources: HandlerSources<RoleSession, MyResource>, error: Error) {
+    println!("Server error: {error}");
+}
+
+#[tokio::test]
+async fn test_broadcast_where_only_reaches_matching_sessions() {
+    let (tx, rx) = oneshot::channel();
+
+    let mut server = AsyncListener::new(
+        ("127.0.0.1", 8213),
+        30,
+        wrap_handler!(role_room),
+        wrap_handler!(log_role_error),
+    )
+    .await
+    .with_pool("room")
+    .await;
+
+    let server_handle = tokio::spawn(async move {
+        tokio::select! {
+            _ = server.run() => {},
+            _ = rx => println!("Server shutting down"),
+        }
+    });
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut clients = Vec::new();
+    for header in ["ADMIN", "ADMIN", "JOIN"] {
+        let mut client = AsyncClient::<MyPacket>::new("127.0.0.1", 8213)
+            .await
+            .unwrap();
+        assert_eq!(client.recv().await.unwrap().header(), "OK");
+
+        let join = MyPacket {
+            header: header.to_string(),
+            body: PacketBody::default(),
+        };
+        assert_eq!(client.send_recv(join).await.unwrap().header(), "OK");
+        clients.push(client);
+    }
+
+    let announce = MyPacket {
+        header: "ANNOUNCE".to_string(),
+        body: PacketBody::default(),
+    };
+    assert_eq!(clients[2].send_recv(announce).await.unwrap().header(), "OK");
+
+    for admin in &mut clients[..2] {
+        let pushed = tokio::time::timeout(Duration::from_secs(2), admin.recv())
+            .await
+            .expect("Admin did not receive the announcement")
+            .unwrap();
+        assert_eq!(pushed.header(), "ANNOUNCE");
+    }
+
+    let untagged = tokio::time::timeout(Duration::from_millis(300), clients[2].recv()).await;
+    assert!(
+        untagged.is_err(),
+        "Untagged session received the announcement"
+    );
+
+    let _ = tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(2), server_handle).await;
+}
+
+async fn notify_all(sources: HandlerSources<MySession, MyResource>, packet: MyPacket) {
+    // Reply first so the sender's request isn't answered by the notice
+    let mut socket = sources.socket.clone();
+    socket.send(MyPacket::ok()).await.unwrap();
+
+    if packet.header() == "NOTIFY" {
+        let notice = MyPacket {
+            header: "NOTICE".to_string(),
+            body: PacketBody::default(),
+        };
+        let report = sources.broadcast_all(notice).await.unwrap();
+        assert_eq!(report.delivered, 3);
+    }
+}
+
+#[tokio::test]
+async fn test_handler_broadcasts_to_all_connections() {
+    let (tx, rx) = oneshot::channel();
+
+    let mut server = AsyncListener::new(
+        ("127.0.0.1", 8214),
+        30,
+        wrap_handler!(notify_all),
+        wrap_handler!(log_error),
+    )
+    .await;
+
+    let server_handle = tokio::spawn(async move {
+        tokio::select! {
+            _ = server.run() => {},
+            _ = rx => println!("Server shutting down"),
+        }
+    });
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // None of these clients send keepalives, so they only join through authentication
+    let mut clients = Vec::new();
+    for _ in 0..3 {
+        let mut client = AsyncClient::<MyPacket>::new("127.0.0.1", 8214)
+            .await
+            .unwrap();
+        assert_eq!(client.recv().await.unwrap().header(), "OK");
+        clients.push(client);
+    }
+
+    let notify = MyPacket {
+        header: "NOTIFY".to_string(),
+        body: PacketBody::default(),
+    };
+    assert_eq!(clients[0].send_recv(notify).await.unwrap().header(), "OK");
+
+    for client in &mut clients {
+        let pushed = tokio::time::timeout(Duration::from_secs(2), client.recv())
+            .await
+            .expect("Client did not receive the notice")
+            .unwrap();
+        assert_eq!(pushed.header(), "NOTICE");
+    }
+
+    let _ = tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(2), server_handle).await;
+}
+
+#[tokio::test]
+async fn test_spawned_listener_reports_assigned_port() {
+    let server = AsyncListener::new(
+        ("127.0.0.1", 0),
+        30,
+        wrap_handler!(echo_ok),
+        wrap_handler!(log_error),
+    )
+    .await
+    .spawn();
+
+    let addr = server.local_addr().unwrap();
+    assert_ne!(addr.port(), 0);
+
+    let mut client = AsyncClient::<MyPacket>::new("127.0.0.1", addr.port())
+        .await
+        .unwrap();
+    assert_eq!(client.recv().await.unwrap().header(), "OK");
+    assert_eq!(
+        client.send_recv(MyPacket::ok()).await.unwrap().header(),
+        "OK"
+    );
+
+    // Once stopped, the port no longer accepts connections
+    server.stop().await;
+    assert!(TcpStream::connect(addr).await.is_err());
+}
+
+#[tokio::test]
+async fn test_listener_on_port_zero_reports_local_addr() {
+    let (tx, rx) = oneshot::channel();
+
+    let mut server = AsyncListener::new(
+        ("127.0.0.1", 0),
+        30,
+        wrap_handler!(echo_ok),
+        wrap_handler!(log_error),
+    )
+    .await;
+
+    // The address is known as soon as the listener is bound
+    let addr = server.local_addr().unwrap();
+    assert!(addr.ip().is_loopback());
+    assert_ne!(addr.port(), 0);
+
+    let server_handle = tokio::spawn(async move {
+        tokio::select! {
+            _ = server.run() => {},
+            _ = rx => println!("Server shutting down"),
+        }
+    });
+
+    let mut client = AsyncClient::<MyPacket>::new("127.0.0.1", addr.port())
+        .await
+        .unwrap();
+    assert_eq!(client.recv().await.unwrap().header(), "OK");
+    assert_eq!(
+        client.send_recv(MyPacket::ok()).await.unwrap().header(),
+        "OK"
+    );
+
+    let _ = tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(2), server_handle).await;
+}
+
+#[tokio::test]
+async fn test_try_new_reports_port_in_use() {
+    let first = AsyncListener::<MyPacket, MySession, MyResource>::try_new(
+        ("127.0.0.1", 0),
+        30,
+        wrap_handler!(echo_ok),
+        wrap_handler!(log_error),
+    )
+    .await
+    .unwrap();
+    let port = first.local_addr().unwrap().port();
+
+    let second = AsyncListener::<MyPacket, MySession, MyResource>::try_new(
+        ("127.0.0.1", port),
+        30,
+        wrap_handler!(echo_ok),
+        wrap_handler!(log_error),
+    )
+    .await;
+    assert!(matches!(second, Err(Error::BindFailed(_))));
+}
+
+#[tokio::test]
+async fn test_listener_survives_peers_closing_during_setup() {
+    let server = AsyncListener::new(
+        ("127.0.0.1", 0),
+        30,
+        wrap_handler!(echo_ok),
+        wrap_handler!(log_error),
+    )
+    .await
+    .spawn();
+    let addr = server.local_addr().unwrap();
+
+    // Peers that hang up before the listener gets to set them up
+    for _ in 0..20 {
+        drop(TcpStream::connect(addr).await.unwrap());
+    }
+
+    let mut client = AsyncClient::<MyPacket>::new("127.0.0.1", addr.port())
+        .await
+        .unwrap();
+    assert_eq!(client.recv().await.unwrap().header(), "OK");
+    assert_eq!(
+        client.send_recv(MyPacket::ok()).await.unwrap().header(),
+        "OK"
+    );
+
+    server.stop().await;
+}
+
+static OVERLAP_ACTIVE: AtomicUsize = AtomicUsize::new(0);
+static OVERLAP_PEAK: AtomicUsize = AtomicUsize::new(0);
+
+// Stays busy for a while, recording how many overlap handlers run at once
+async fn overlap_work() {
+    let active = OVERLAP_ACTIVE.fetch_add(1, Ordering::SeqCst) + 1;
+    OVERLAP_PEAK.fetch_max(active, Ordering::SeqCst);
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    OVERLAP_ACTIVE.fetch_sub(1, Ordering::SeqCst);
+}
+
+async fn overlap_reply(sources: HandlerSources<MySession, MyResource>, _packet: MyPacket) {
+    overlap_work().await;
+    let mut socket = sources.socket;
+    socket.send(MyPacket::ok()).await.unwrap();
+}
+
+async fn overlap_audit(_sources: HandlerSources<MySession, MyResource>, _packet: MyPacket) {
+    overlap_work().await;
+}
+
+#[tokio::test]
+async fn test_parallel_dispatch_overlaps_handlers_and_packets() {
+    handler_registry::register_handler::<MyPacket, MySession, MyResource>(
+        "OVERLAP",
+        |sources, packet| Box::pin(overlap_reply(sources, packet)),
+    );
+    handler_registry::register_handler::<MyPacket, MySession, MyResource>(
+        "OVERLAP",
+        |sources, packet| Box::pin(overlap_audit(sources, packet)),
+    );
+
+    let server = AsyncListener::new(
+        ("127.0.0.1", 0),
+        30,
+        wrap_handler!(echo_ok),
+        wrap_handler!(log_error),
+    )
+    .await
+    .with_dispatch_mode(DispatchMode::Parallel { max_in_flight: 2 })
+    .spawn();
+    let port = server.local_addr().unwrap().port();
+
+    let mut client = AsyncClient::<MyPacket>::new("127.0.0.1", port)
+        .await
+        .unwrap();
+    assert_eq!(client.recv().await.unwrap().header(), "OK");
+
+    let overlap = MyPacket {
+        header: "OVERLAP".to_string(),
+        body: PacketBody::default(),
+    };
+    client.send(overlap.clone()).await.unwrap();
+    client.send(overlap).await.unwrap();
+    for _ in 0..2 {
+        assert_eq!(client.recv().await.unwrap().header(), "OK");
+    }
+
+    // Both handlers of both packets were running at the same time
+    assert_eq!(OVERLAP_PEAK.load(Ordering::SeqCst), 4);
+
+    server.stop().await;
+}
+
+// A resource that loads its data asynchronously before the listener starts
+#[derive(Debug, Clone)]
+struct PreloadedResource {
+    greeting: String,
+}
+
+impl crate::resources::Resource for PreloadedResource {
+    fn new() -> Self {
+        Self {
+            greeting: String::new(),
+        }
+    }
+
+    async fn new_async() -> Self {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        Self {
+            greeting: "loaded at startup".to_string(),
+        }
+    }
+}
+
+async fn greet(sources: HandlerSources<MySession, PreloadedResource>, _packet: MyPacket) {
+    let greeting = sources.resources.read().await.greeting.clone();
+    let mut socket = sources.socket;
+    let mut response = MyPacket::ok();
+    response.body_mut().token = Some(greeting);
+    let _ = socket.send(response).await;
+}
+
+async fn log_preloaded_error(_sources: HandlerSources<MySession, PreloadedResource>, error: Error) {
+    eprintln!("Server error: {error}");
+}
+
+#[tokio::test]
+async fn test_handlers_read_asynchronously_initialized_resource() {
+    let server = AsyncListener::new(
+        ("127.0.0.1", 0),
+        30,
+        wrap_handler!(greet),
+        wrap_handler!(log_preloaded_error),
+    )
+    .await
+    .with_resource_async()
+    .await
+    .spawn();
+    let port = server.local_addr().unwrap().port();
+
+    let mut client = AsyncClient::<MyPacket>::new("127.0.0.1", port)
+        .await
+        .unwrap();
+    assert_eq!(client.recv().await.unwrap().header(), "OK");
+
+    let response = client.send_recv(MyPacket::ok()).await.unwrap();
+    assert_eq!(response.body().token.as_deref(), Some("loaded at startup"));
+
+    server.stop().await;
+}
+
+static CONNECTIONS_CREATED: AtomicUsize = AtomicUsize::new(0);
+
+// Per-connection state: which connection this is and how many packets it sent
+#[derive(Debug, Clone)]
+struct ConnectionCounter {
+    connection: usize,
+    packets: std::sync::Arc<AtomicUsize>,
+}
+
+impl crate::resources::Resource for ConnectionCounter {
+    fn new() -> Self {
+        Self {
+            connection: CONNECTIONS_CREATED.fetch_add(1, Ordering::SeqCst),
+            packets: std::sync::Arc::new(AtomicUsize::new(0)),
+        }
+    }
+}
+
+async fn count_packets(
+    sources: HandlerSources<MySession, MyResource, ConnectionCounter>,
+    _packet: MyPacket,
+) {
+    let counter = &sources.connection_resources;
+    let packets = counter.packets.fetch_add(1, Ordering::SeqCst) + 1;
+    let mut response = MyPacket::ok();
+    response.body_mut().token = Some(format!("{}:{packets}", counter.connection));
+    let mut socket = sources.socket;
+    let _ = socket.send(response).await;
+}
+
+async fn log_counter_error(
+    _sources: HandlerSources<MySession, MyResource, ConnectionCounter>,
+    error: Error,
+) {
+    eprintln!("Server error: {error}");
+}
+
+#[tokio::test]
+async fn test_connections_get_their_own_connection_resources() {
+    let server = AsyncListener::new(
+        ("127.0.0.1", 0),
+        30,
+        wrap_handler!(count_packets),
+        wrap_handler!(log_counter_error),
+    )
+    .await
+    .spawn();
+    let port = server.local_addr().unwrap().port();
+
+    let mut first = AsyncClient::<MyPacket>::new("127.0.0.1", port)
+        .await
+        .unwrap();
+    assert_eq!(first.recv().await.unwrap().header(), "OK");
+    let mut second = AsyncClient::<MyPacket>::new("127.0.0.1", port)
+        .await
+        .unwrap();
+    assert_eq!(second.recv().await.unwrap().header(), "OK");
+
+    let mut seen = Vec::new();
+    for _ in 0..2 {
+        for client in [&mut first, &mut second] {
+            let response = client.send_recv(MyPacket::ok()).await.unwrap();
+            seen.push(response.body().token.unwrap());
+        }
+    }
+
+    let split = |token: &str| {
+        let (connection, packets) = token.split_once(':').unwrap();
+        (connection.to_string(), packets.to_string())
+    };
+    let seen: Vec<(String, String)> = seen.iter().map(|token| split(token)).collect();
+    assert_eq!(
+        seen.iter()
+            .map(|(_, packets)| packets.as_str())
+            .collect::<Vec<_>>(),
+        ["1", "1", "2", "2"]
+    );
+    assert_eq!(seen[0].0, seen[2].0);
+    assert_eq!(seen[1].0, seen[3].0);
+    assert_ne!(seen[0].0, seen[1].0);
+
+    server.stop().await;
+}
+
+static TRY_HANDLER_ERRORS: AtomicUsize = AtomicUsize::new(0);
+
+async fn reject_missing_username(
+    sources: HandlerSources<MySession, MyResource>,
+    packet: MyPacket,
+) -> Result<(), Error> {
+    let username = packet
+        .body()
+        .username
+        .ok_or_else(|| Error::InvalidPacket("missing username".to_string()))?;
+    let mut response = MyPacket::ok();
+    response.body_mut().username = Some(username);
+    let mut socket = sources.socket;
+    socket.send(response).await
+}
+
+async fn report_try_error(sources: HandlerSources<MySession, MyResource>, error: Error) {
+    TRY_HANDLER_ERRORS.fetch_add(1, Ordering::SeqCst);
+    let mut socket = sources.socket;
+    let _ = socket.send(MyPacket::error(error)).await;
+}
+
+#[tokio::test]
+async fn test_try_handler_routes_errors_to_error_handler() {
+    let server = AsyncListener::new(
+        ("127.0.0.1", 0),
+        30,
+        wrap_try_handler!(reject_missing_username, report_try_error),
+        wrap_handler!(report_try_error),
+    )
+    .await
+    .spawn();
+    let port = server.local_addr().unwrap().port();
+
+    let mut client = AsyncClient::<MyPacket>::new("127.0.0.1", port)
+        .await
+        .unwrap();
+    assert_eq!(client.recv().await.unwrap().header(), "OK");
+
+    let mut packet = MyPacket::ok();
+    packet.body_mut().username = Some("present".to_string());
+    let response = client.send_recv(packet).await.unwrap();
+    assert_eq!(response.body().username.as_deref(), Some("present"));
+    assert_eq!(TRY_HANDLER_ERRORS.load(Ordering::SeqCst), 0);
+
+    let response = client.send_recv(MyPacket::ok()).await.unwrap();
+    assert_eq!(response.header(), "ERROR");
+    assert!(
+        response
+            .body()
+            .error_string
+            .as_deref()
+            .is_some_and(|e| e.contains("missing username"))
+    );
+    assert_eq!(TRY_HANDLER_ERRORS.load(Ordering::SeqCst), 1);
+
+    server.stop().await;
+}
+
+static PLAINTEXT_HANDLED: AtomicUsize = AtomicUsize::new(0);
+static PLAINTEXT_ERRORS: std::sync::Mutex<Vec<Error>> = std::sync::Mutex::new(Vec::new());
+
+async fn count_plaintext(_sources: HandlerSources<MySession, MyResource>, _packet: MyPacket) {
+    PLAINTEXT_HANDLED.fetch_add(1, Ordering::SeqCst);
+}
+
+async fn record_plaintext_error(_sources: HandlerSources<MySession, MyResource>, error: Error) {
+    PLAINTEXT_ERRORS.lock().unwrap().push(error);
+}
+
+#[tokio::test]
+async fn test_plaintext_client_is_refused_when_encryption_required() {
+    let server = AsyncListener::new(
+        ("127.0.0.1", 0),
+        30,
+        wrap_handler!(count_plaintext),
+        wrap_handler!(record_plaintext_error),
+    )
+    .await
+    .with_encryption_config(EncryptionConfig::default_on())
+    .spawn();
+    let port = server.local_addr().unwrap().port();
+
+    // Skip the key exchange and start talking straight away
+    let mut client = AsyncClient::<MyPacket>::new("127.0.0.1", port)
+        .await
+        .unwrap();
+    client.send(MyPacket::ok()).await.unwrap();
+
+    let refusal = client.recv().await.unwrap();
+    assert_eq!(refusal.header(), "ERROR");
+    assert_eq!(
+        refusal.body().error_string,
+        Some(Error::EncryptionRequired.to_string())
+    );
+    assert!(client.recv().await.is_err());
+
+    // The error handler runs once the connection has been refused
+    for _ in 0..50 {
+        if !PLAINTEXT_ERRORS.lock().unwrap().is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(
+        *PLAINTEXT_ERRORS.lock().unwrap(),
+        vec![Error::EncryptionRequired]
+    );
+    assert_eq!(PLAINTEXT_HANDLED.load(Ordering::SeqCst), 0);
+
+    server.stop().await;
+}
+
+static RATE_HANDLED: AtomicUsize = AtomicUsize::new(0);
+static RATE_LIMITED: AtomicUsize = AtomicUsize::new(0);
+
+async fn count_rate_handled(_sources: HandlerSources<MySession, MyResource>, _packet: MyPacket) {
+    RATE_HANDLED.fetch_add(1, Ordering::SeqCst);
+}
+
+async fn count_rate_limited(_sources: HandlerSources<MySession, MyResource>, error: Error) {
+    if error == Error::RateLimited {
+        RATE_LIMITED.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+#[tokio::test]
+async fn test_per_connection_rate_drops_excess_packets() {
+    let mut server = AsyncListener::new_in_memory(
+        30,
+        wrap_handler!(count_rate_handled),
+        wrap_handler!(count_rate_limited),
+    )
+    .with_per_connection_rate(1, 3)
+    .with_rate_limit_errors(true);
+
+    let (client_end, server_end) = tokio::io::duplex(64 * 1024);
+    let mut client = AsyncClient::<MyPacket>::from_duplex(client_end);
+    let ((), greeting) = tokio::join!(server.accept_in_memory(server_end), client.recv());
+    assert_eq!(greeting.unwrap().header(), "OK");
+
+    for _ in 0..10 {
+        client.send(MyPacket::ok()).await.unwrap();
+    }
+
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(2);
+    while RATE_HANDLED.load(Ordering::SeqCst) + RATE_LIMITED.load(Ordering::SeqCst) < 10
+        && tokio::time::Instant::now() < deadline
+    {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    // The burst lets three packets through, the rest arrive before a token is refilled
+    assert_eq!(RATE_HANDLED.load(Ordering::SeqCst), 3);
+    assert_eq!(RATE_LIMITED.load(Ordering::SeqCst), 7);
+}
+
+static ISSUED_SESSIONS: AtomicUsize = AtomicUsize::new(0);
+
+#[tokio::test]
+async fn test_session_id_fn_names_new_sessions() {
+    let mut server =
+        AsyncListener::new_in_memory(30, wrap_handler!(echo_ok), wrap_handler!(log_error))
+            .with_authenticator(
+                Authenticator::new(AuthType::UserPassword)
+                    .with_auth_fn(|_username, _password| Box::pin(async { Ok(()) }))
+                    .with_session_id_fn(|context| {
+                        let n = ISSUED_SESSIONS.fetch_add(1, Ordering::SeqCst) + 1;
+                        format!("{}-{n}", context.username.unwrap_or("guest"))
+                    }),
+            );
+
+    let mut issued = Vec::new();
+    for user in ["alice", "bob"] {
+        let (client_end, server_end) = tokio::io::duplex(64 * 1024);
+        let mut client =
+            AsyncClient::<MyPacket>::from_duplex(client_end).with_credentials(user, "password");
+        let ((), accepted) = tokio::join!(
+            server.accept_in_memory(server_end),
+            client.send_recv(MyPacket::ok())
+        );
+        issued.push(accepted.unwrap().session_id(None).unwrap());
+    }
+
+    assert_eq!(issued, ["alice-1", "bob-2"]);
+    let mut stored: Vec<_> = server
+        .active_sessions()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|session| session.id)
+        .collect();
+    stored.sort();
+    assert_eq!(stored, issued);
+}
+
+#[tokio::test]
+async fn test_reuseaddr_allows_rapid_rebinding() {
+    let options = BindOptions::default().with_reuseaddr(true).with_backlog(16);
+
+    let mut port = 0;
+    for _ in 0..5 {
+        let server = AsyncListener::try_new_with_options(
+            ("127.0.0.1", port),
+            options,
+            30,
+            wrap_handler!(echo_ok),
+            wrap_handler!(log_error),
+        )
+        .await
+        .unwrap()
+        .with_idle_timeout(Duration::from_millis(20))
+        .spawn();
+        port = server.local_addr().unwrap().port();
+
+        // The idle timeout makes the listener close first, leaving the port in TIME_WAIT
+        let mut client = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        let mut received = Vec::new();
+        tokio::time::timeout(Duration::from_secs(2), client.read_to_end(&mut received))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(!received.is_empty());
+        drop(client);
+
+        server.stop().await;
+    }
+}
+
+async fn reply_ok(mut sources: HandlerSources<MySession, MyResource>, _packet: MyPacket) {
+    sources.reply(MyPacket::ok()).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_reply_carries_session_and_request_ids() {
+    let mut server =
+        AsyncListener::new_in_memory(30, wrap_handler!(reply_ok), wrap_handler!(log_error))
+            .with_authenticator(
+                Authenticator::new(AuthType::UserPassword)
+                    .with_auth_fn(|_username, _password| Box::pin(async { Ok(()) })),
+            );
+
+    let (client_end, server_end) = tokio::io::duplex(64 * 1024);
+    let mut client =
+        AsyncClient::<MyPacket>::from_duplex(client_end).with_credentials("admin", "password");
+    let ((), accepted) = tokio::join!(
+        server.accept_in_memory(server_end),
+        client.send_recv(MyPacket::ok())
+    );
+    let session_id = accepted.unwrap().session_id(None).unwrap();
+
+    let mut request = MyPacket::ok();
+    request.body_mut().request_id = Some(42);
+    client.send(request).await.unwrap();
+
+    let mut response = client.recv().await.unwrap();
+    assert_eq!(response.session_id(None), Some(session_id));
+    assert_eq!(response.body().request_id, Some(42));
+}
+
+async fn echo_username(
+    _sources: HandlerSources<MySession, MyResource>,
+    packet: MyPacket,
+) -> Option<MyPacket> {
+    let mut response = MyPacket::ok();
+    response.body_mut().username = Some(packet.body().username?);
+    Some(response)
+}
+
+#[tokio::test]
+async fn test_reply_handler_sends_returned_packet() {
+    let mut server = AsyncListener::new_in_memory(
+        30,
+        wrap_reply_handler!(echo_username, log_error),
+        wrap_handler!(log_error),
+    );
+
+    let (client_end, server_end) = tokio::io::duplex(64 * 1024);
+    let mut client = AsyncClient::<MyPacket>::from_duplex(client_end);
+    let ((), greeting) = tokio::join!(server.accept_in_memory(server_end), client.recv());
+    assert_eq!(greeting.unwrap().header(), "OK");
+
+    let mut request = MyPacket::ok();
+    request.body_mut().username = Some("returned".to_string());
+    let response = client.send_recv(request).await.unwrap();
+    assert_eq!(response.body().username.as_deref(), Some("returned"));
+
+    // Returning None sends nothing back
+    client.send(MyPacket::ok()).await.unwrap();
+    assert!(
+        tokio::time::timeout(Duration::from_millis(200), client.recv())
+            .await
+            .is_err()
+    );
+}
+
+async fn ignore_packet(_sources: HandlerSources<MySession, MyResource>, _packet: MyPacket) {}
+
+#[tokio::test]
+async fn test_default_reply_answers_silent_handlers() {
+    let mut default_reply = MyPacket::ok();
+    default_reply.body_mut().username = Some("default".to_string());
+    let mut server =
+        AsyncListener::new_in_memory(30, wrap_handler!(ignore_packet), wrap_handler!(log_error))
+            .with_default_reply(default_reply);
+
+    let (client_end, server_end) = tokio::io::duplex(64 * 1024);
+    let mut client = AsyncClient::<MyPacket>::from_duplex(client_end);
+    let ((), greeting) = tokio::join!(server.accept_in_memory(server_end), client.recv());
+    assert_eq!(greeting.unwrap().header(), "OK");
+
+    let mut request = MyPacket::ok();
+    request.body_mut().request_id = Some(7);
+    client.send(request).await.unwrap();
+    let response = tokio::time::timeout(Duration::from_secs(2), client.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(response.body().username.as_deref(), Some("default"));
+    assert_eq!(response.body().request_id, Some(7));
+
+    // Handlers that do reply are left alone
+    let mut server =
+        AsyncListener::new_in_memory(30, wrap_handler!(reply_ok), wrap_handler!(log_error))
+            .with_default_reply(MyPacket::error(Error::InvalidPacket("unused".to_string())));
+    let (client_end, server_end) = tokio::io::duplex(64 * 1024);
+    let mut client = AsyncClient::<MyPacket>::from_duplex(client_end);
+    let ((), _) = tokio::join!(server.accept_in_memory(server_end), client.recv());
+    let response = client.send_recv(MyPacket::ok()).await.unwrap();
+    assert_eq!(response.header(), "OK");
+    assert!(
+        tokio::time::timeout(Duration::from_millis(200), client.recv())
+            .await
+            .is_err()
+    );
+}
+
+static UNREADABLE_ERRORS: std::sync::Mutex<Vec<Error>> = std::sync::Mutex::new(Vec::new());
+
+async fn record_unreadable(_sources: HandlerSources<MySession, MyResource>, error: Error) {
+    UNREADABLE_ERRORS.lock().unwrap().push(error);
+}
+
+#[tokio::test]
+async fn test_undecryptable_frame_keeps_connection_open() {
+    let server = AsyncListener::new(
+        ("127.0.0.1", 0),
+        30,
+        wrap_handler!(echo_ok),
+        wrap_handler!(record_unreadable),
+    )
+    .await
+    .with_encryption_config(EncryptionConfig::default_on())
+    .spawn();
+    let port = server.local_addr().unwrap().port();
+
+    let mut client = AsyncClient::<MyPacket>::new("127.0.0.1", port)
+        .await
+        .unwrap()
+        .with_encryption_config(EncryptionConfig::default_on())
+        .await
+        .unwrap();
+    assert_eq!(client.recv().await.unwrap().header(), "OK");
+
+    // A frame that isn't a ciphertext only loses that packet
+    client
+        .connection
+        .writer_tx
+        .send(ClientMessage::Data(
+            Bytes::from_static(b"not a ciphertext"),
+            None,
+        ))
+        .await
+        .unwrap();
+    let response = client.send_recv(MyPacket::ok()).await.unwrap();
+    assert_eq!(response.header(), "OK");
+    assert_eq!(UNREADABLE_ERRORS.lock().unwrap().len(), 1);
+
+    server.stop().await;
+}
+
+static DECOMPRESS_ERRORS: std::sync::Mutex<Vec<Error>> = std::sync::Mutex::new(Vec::new());
+
+async fn record_decompress_error(_sources: HandlerSources<MySession, MyResource>, error: Error) {
+    DECOMPRESS_ERRORS.lock().unwrap().push(error);
+}
+
+#[tokio::test]
+async fn test_corrupt_compressed_frame_keeps_connection_open() {
+    let server = AsyncListener::new(
+        ("127.0.0.1", 0),
+        30,
+        wrap_handler!(echo_ok),
+        wrap_handler!(record_decompress_error),
+    )
+    .await
+    .with_compression(CompressionConfig::default_on())
+    .spawn();
+    let port = server.local_addr().unwrap().port();
+
+    let mut client = AsyncClient::<MyPacket>::new("127.0.0.1", port)
+        .await
+        .unwrap()
+        .with_compression(CompressionConfig::default_on());
+    assert_eq!(client.recv().await.unwrap().header(), "OK");
+
+    // Flagged as gzip but not a gzip stream
+    client
+        .connection
+        .writer_tx
+        .send(ClientMessage::Data(
+            Bytes::from_static(b"\x01not gzip"),
+            None,
+        ))
+        .await
+        .unwrap();
+    let response = client.send_recv(MyPacket::ok()).await.unwrap();
+    assert_eq!(response.header(), "OK");
+    assert!(matches!(
+        DECOMPRESS_ERRORS.lock().unwrap().as_slice(),
+        [Error::Compression(_)]
+    ));
+
+    server.stop().await;
+}
+
+#[cfg(feature = "websocket")]
+#[tokio::test]
+async fn test_websocket_client_send_recv() {
+    let (tx, rx) = oneshot::channel();
+
+    let mut server = AsyncListener::new_ws(
+        ("127.0.0.1", 8236),
+        30,
+        wrap_handler!(echo_ok),
+        wrap_handler!(log_error),
+    )
+    .await
+    .with_encryption_config(EncryptionConfig::default_on())
+    .with_authenticator(Authenticator::new(AuthType::UserPassword).with_auth_fn(
+        |username, password| {
+            Box::pin(async move {
+                if username == "admin" && password == "password" {
+                    Ok(())
+                } else {
+                    Err(Error::InvalidCredentials)
+                }
+            })
+        },
+    ));
+
+    let server_handle = tokio::spawn(async move {
+        tokio::select! {
+            _ = server.run() => {},
+            _ = rx => println!("Server shutting down"),
+        }
+    });
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut client = AsyncClient::<MyPacket>::new_ws("ws://127.0.0.1:8236")
+        .await
+        .unwrap()
+        .with_credentials("admin", "password")
+        .with_encryption_config(EncryptionConfig::default_on())
+        .await
+        .unwrap();
+    assert!(client.is_encrypted());
+
+    // The first request carries the credentials, a rejection would be an ERROR
+    let login = client.send_recv(MyPacket::ok()).await.unwrap();
+    assert_eq!(login.header(), "OK");
+
+    let response = client.send_recv(MyPacket::ok()).await.unwrap();
+    assert_eq!(response.header(), "OK");
+
+    let _ = tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(2), server_handle).await;
+}
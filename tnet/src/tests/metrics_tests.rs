@@ -0,0 +1,117 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use tokio::sync::oneshot;
+
+use super::{MyPacket, MyResource, MySession};
+use crate::{
+    asynch::{
+        authenticator::{AuthType, Authenticator},
+        client::AsyncClient,
+        listener::{AsyncListener, HandlerSources},
+    },
+    errors::Error,
+    metrics::AtomicMetrics,
+    packet::{Packet, PacketBody},
+    wrap_handler,
+};
+
+async fn pong(sources: HandlerSources<MySession, MyResource>, packet: MyPacket) {
+    if packet.header() != "PING" {
+        return;
+    }
+
+    let mut socket = sources.socket;
+    let reply = MyPacket {
+        header: "PONG".to_string(),
+        body: PacketBody::default(),
+    };
+    if let Err(e) = socket.send(reply).await {
+        eprintln!("Failed to send response: {e}");
+    }
+}
+
+async fn log_error(_sources: HandlerSources<MySession, MyResource>, error: Error) {
+    println!("Server error: {error}");
+}
+
+#[tokio::test]
+async fn test_metrics_count_round_trips() {
+    let (tx, rx) = oneshot::channel();
+    let server_metrics = Arc::new(AtomicMetrics::new());
+
+    let mut server = AsyncListener::new(
+        ("127.0.0.1", 8240),
+        30,
+        wrap_handler!(pong),
+        wrap_handler!(log_error),
+    )
+    .await
+    .with_authenticator(
+        Authenticator::new(AuthType::PreSharedKey)
+            .with_key_fn(|_key| Box::pin(async move { Ok(()) })),
+    )
+    .with_metrics(server_metrics.clone());
+
+    let server_handle = tokio::spawn(async move {
+        tokio::select! {
+            _ = server.run() => {},
+            _ = rx => println!("Server shutting down"),
+        }
+    });
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let client_metrics = Arc::new(AtomicMetrics::new());
+    let mut client = AsyncClient::<MyPacket>::new("127.0.0.1", 8240)
+        .await
+        .unwrap()
+        .with_token("secret-key")
+        .with_metrics(client_metrics.clone());
+
+    // Authenticates and counts as the client's connection
+    client.finalize().await;
+
+    for _ in 0..3 {
+        let ping = MyPacket {
+            header: "PING".to_string(),
+            body: PacketBody::default(),
+        };
+        assert_eq!(client.send_recv(ping).await.unwrap().header(), "PONG");
+    }
+
+    let client_stats = client_metrics.snapshot();
+    let server_stats = server_metrics.snapshot();
+
+    assert_eq!(client_stats.packets_sent, 4);
+    assert_eq!(client_stats.packets_received, 4);
+    assert_eq!(client_stats.connections_opened, 1);
+    assert_eq!(client_stats.active_connections, 1);
+    assert_eq!(client_stats.reconnect_attempts, 0);
+
+    assert_eq!(server_stats.packets_received, 4);
+    assert_eq!(server_stats.packets_sent, 4);
+    assert_eq!(server_stats.auth_successes, 1);
+    assert_eq!(server_stats.auth_failures, 0);
+    assert_eq!(server_stats.active_connections, 1);
+
+    // Both ends see the same frames
+    assert_eq!(client_stats.bytes_sent, server_stats.bytes_received);
+    assert_eq!(client_stats.bytes_received, server_stats.bytes_sent);
+
+    client.close().await.unwrap();
+    assert_eq!(client_metrics.snapshot().active_connections, 0);
+
+    let deadline = Instant::now() + Duration::from_secs(2);
+    while server_metrics.snapshot().active_connections != 0 && Instant::now() < deadline {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    let server_stats = server_metrics.snapshot();
+    assert_eq!(server_stats.connections_opened, 1);
+    assert_eq!(server_stats.active_connections, 0);
+
+    let _ = tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(2), server_handle).await;
+}
@@ -13,9 +13,21 @@ use crate::{
 };
 use serde::{Deserialize, Serialize};
 
+pub mod blocking_tests;
+pub mod client_tests;
+pub mod compression_tests;
+pub mod enum_string_tests;
+pub mod listener_tests;
+pub mod metrics_tests;
+pub mod packet_tests;
 pub mod reconnection_tests;
+pub mod registry_tests;
 pub mod relay_test;
+pub mod session_tests;
+pub mod socket_tests;
 pub mod tlisten_tests;
+#[cfg(unix)]
+pub mod uds_tests;
 
 // Define packet type exactly as in README
 #[derive(Debug, Clone, Serialize, Deserialize)]
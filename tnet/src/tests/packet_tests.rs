@@ -0,0 +1,411 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    compression::CompressionConfig,
+    encrypt::{CipherSuite, EncryptError, Encryptor, HandshakeHello, REPLAY_WINDOW, Role},
+    errors::Error,
+    packet::{Packet, PacketBody, SerializationFormat},
+};
+
+// Declares a test packet type that uses the given serialization format
+macro_rules! format_packet {
+    ($name:ident, $format:expr) => {
+        #[derive(Debug, Clone, Serialize, Deserialize)]
+        struct $name {
+            header: String,
+            body: PacketBody,
+            data: Option<String>,
+            numbers: Vec<u32>,
+        }
+
+        impl Packet for $name {
+            fn format() -> SerializationFormat {
+                $format
+            }
+
+            fn header(&self) -> String {
+                self.header.clone()
+            }
+
+            fn body(&self) -> PacketBody {
+                self.body.clone()
+            }
+
+            fn body_mut(&mut self) -> &mut PacketBody {
+                &mut self.body
+            }
+
+            fn ok() -> Self {
+                Self {
+                    header: "OK".to_string(),
+                    body: PacketBody::default(),
+                    data: None,
+                    numbers: Vec::new(),
+                }
+            }
+
+            fn error(error: Error) -> Self {
+                Self {
+                    header: "ERROR".to_string(),
+                    body: PacketBody::with_error_string(error.to_string()),
+                    data: None,
+                    numbers: Vec::new(),
+                }
+            }
+
+            fn keep_alive() -> Self {
+                Self {
+                    header: "KEEPALIVE".to_string(),
+                    body: PacketBody::default(),
+                    data: None,
+                    numbers: Vec::new(),
+                }
+            }
+        }
+
+        impl $name {
+            fn sample() -> Self {
+                let mut body = PacketBody::broadcasting();
+                body.session_id = Some("session-123".to_string());
+                Self {
+                    header: "DATA".to_string(),
+                    body,
+                    data: Some("hello".to_string()),
+                    numbers: vec![1, 2, 3],
+                }
+            }
+
+            fn assert_matches_sample(&self) {
+                assert_eq!(self.header, "DATA");
+                assert_eq!(self.data.as_deref(), Some("hello"));
+                assert_eq!(self.numbers, vec![1, 2, 3]);
+                assert_eq!(self.body.session_id.as_deref(), Some("session-123"));
+                assert!(self.is_broadcast());
+            }
+        }
+    };
+}
+
+format_packet!(BincodePacket, SerializationFormat::Bincode);
+format_packet!(JsonPacket, SerializationFormat::Json);
+format_packet!(MsgPackPacket, SerializationFormat::MessagePack);
+
+#[test]
+fn test_default_format_is_json() {
+    assert_eq!(SerializationFormat::default(), SerializationFormat::Json);
+    assert_eq!(super::MyPacket::format(), SerializationFormat::Json);
+}
+
+#[test]
+fn test_bincode_round_trip() {
+    let bytes = BincodePacket::sample().ser();
+    assert!(serde_json::from_slice::<serde_json::Value>(&bytes).is_err());
+    BincodePacket::de(&bytes).assert_matches_sample();
+}
+
+#[test]
+fn test_json_round_trip() {
+    let bytes = JsonPacket::sample().ser();
+    assert!(serde_json::from_slice::<serde_json::Value>(&bytes).is_ok());
+    JsonPacket::de(&bytes).assert_matches_sample();
+}
+
+#[test]
+fn test_message_pack_round_trip() {
+    let bytes = MsgPackPacket::sample().ser();
+    assert!(serde_json::from_slice::<serde_json::Value>(&bytes).is_err());
+    MsgPackPacket::de(&bytes).assert_matches_sample();
+}
+
+#[test]
+fn test_encrypted_round_trip_for_each_format() {
+    let encryptor = Encryptor::new(&Encryptor::generate_key()).unwrap();
+
+    let bytes = BincodePacket::sample().encrypted_ser(&encryptor);
+    BincodePacket::encrypted_de(&bytes, &encryptor).assert_matches_sample();
+
+    let bytes = JsonPacket::sample().encrypted_ser(&encryptor);
+    JsonPacket::encrypted_de(&bytes, &encryptor).assert_matches_sample();
+
+    let bytes = MsgPackPacket::sample().encrypted_ser(&encryptor);
+    MsgPackPacket::encrypted_de(&bytes, &encryptor).assert_matches_sample();
+}
+
+#[test]
+fn test_broadcast_flag_survives_round_trip() {
+    let encryptor = Encryptor::new(&Encryptor::generate_key()).unwrap();
+    let compression = CompressionConfig::default();
+
+    let broadcast = super::MyPacket::ok().set_broadcasting();
+    let frame = compression.encode(&broadcast, None);
+    let received: super::MyPacket = compression.decode(&frame, None).unwrap();
+    assert!(received.is_broadcast());
+
+    let frame = compression.encode(&broadcast, Some(&encryptor));
+    let received: super::MyPacket = compression.decode(&frame, Some(&encryptor)).unwrap();
+    assert!(received.is_broadcast());
+
+    let reply = super::MyPacket::de(&super::MyPacket::ok().ser());
+    assert!(!reply.is_broadcast());
+}
+
+#[test]
+fn test_format_rejects_foreign_encoding() {
+    let json = SerializationFormat::Json
+        .serialize(&JsonPacket::sample())
+        .unwrap();
+    let result = SerializationFormat::MessagePack.deserialize::<MsgPackPacket>(&json);
+    assert!(matches!(result, Err(Error::Serialization(_))));
+}
+
+#[test]
+fn test_replayed_encrypted_frame_is_rejected() {
+    let encryptor = Encryptor::new(&Encryptor::generate_key()).unwrap();
+    let compression = CompressionConfig::default();
+
+    let frame = JsonPacket::sample().encrypted_ser(&encryptor);
+    let packet: JsonPacket = compression.decode(&frame, Some(&encryptor)).unwrap();
+    packet.assert_matches_sample();
+
+    let replayed = compression.decode::<JsonPacket>(&frame, Some(&encryptor));
+    assert!(matches!(replayed, Err(Error::ReplayDetected)));
+}
+
+#[test]
+fn test_reflected_encrypted_frame_is_rejected() {
+    let key = Encryptor::generate_key();
+    let compression = CompressionConfig::default();
+
+    for suite in CipherSuite::ALL {
+        let client = Encryptor::new_with_suite(&key, suite)
+            .unwrap()
+            .with_role(Role::Client);
+        let server = Encryptor::new_with_suite(&key, suite)
+            .unwrap()
+            .with_role(Role::Server);
+
+        // Each side's frames are accepted by the other side only
+        let from_client = JsonPacket::sample().encrypted_ser(&client);
+        let from_server = JsonPacket::sample().encrypted_ser(&server);
+        let reflected = compression.decode::<JsonPacket>(&from_client, Some(&client));
+        assert!(matches!(reflected, Err(Error::ReplayDetected)));
+        let reflected = compression.decode::<JsonPacket>(&from_server, Some(&server));
+        assert!(matches!(reflected, Err(Error::ReplayDetected)));
+
+        let packet: JsonPacket = compression.decode(&from_client, Some(&server)).unwrap();
+        packet.assert_matches_sample();
+        let packet: JsonPacket = compression.decode(&from_server, Some(&client)).unwrap();
+        packet.assert_matches_sample();
+    }
+}
+
+#[test]
+fn test_replay_window_tolerates_reordering() {
+    let encryptor = Encryptor::new(&Encryptor::generate_key()).unwrap();
+    let frames = (0..=REPLAY_WINDOW + 1)
+        .map(|i| encryptor.encrypt(&i.to_be_bytes()).unwrap())
+        .collect::<Vec<_>>();
+
+    // Late frames inside the window are still accepted, once each
+    assert!(encryptor.decrypt(&frames[5]).is_ok());
+    assert!(encryptor.decrypt(&frames[2]).is_ok());
+    assert_eq!(encryptor.decrypt(&frames[2]), Err(EncryptError::Replayed));
+
+    // Once the window has moved past a frame it can no longer be accepted
+    let newest = &frames[REPLAY_WINDOW as usize + 1];
+    assert!(encryptor.decrypt(newest).is_ok());
+    assert_eq!(encryptor.decrypt(&frames[0]), Err(EncryptError::Replayed));
+    assert!(encryptor.decrypt(&frames[3]).is_ok());
+}
+
+#[test]
+fn test_pong_is_not_a_ping() {
+    let ping = JsonPacket::de(&JsonPacket::ping().ser());
+    assert!(ping.is_ping());
+    assert!(!ping.is_pong());
+
+    let pong = JsonPacket::de(&JsonPacket::pong().ser());
+    assert!(pong.is_pong());
+    assert!(!pong.is_ping());
+}
+
+#[test]
+fn test_attachment_round_trip_through_encrypted_path() {
+    let encryptor = Encryptor::new(&Encryptor::generate_key()).unwrap();
+    let data = (0..256 * 1024).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+
+    let mut packet = BincodePacket::sample();
+    packet.body = PacketBody::with_attachment(data.clone());
+
+    // Bincode stores the bytes as-is rather than as a text encoding
+    let plain = packet.ser();
+    assert!(plain.len() < data.len() + 1024);
+
+    for compression in [
+        CompressionConfig::default(),
+        CompressionConfig::default_on(),
+    ] {
+        let frame = compression.encode(&packet, Some(&encryptor));
+        let received: BincodePacket = compression.decode(&frame, Some(&encryptor)).unwrap();
+        assert_eq!(received.body().attachment(), Some(data.as_slice()));
+    }
+}
+
+#[test]
+fn test_attachment_round_trip_in_text_and_binary_formats() {
+    let data = (0..=255).collect::<Vec<u8>>();
+
+    // The default JSON format carries the bytes as base64 rather than a list of numbers
+    let mut packet = JsonPacket::sample();
+    packet.body = PacketBody::with_attachment(data.clone());
+    let json = packet.ser();
+    assert!(String::from_utf8_lossy(&json).contains(&BASE64.encode(&data)));
+    assert_eq!(
+        JsonPacket::de(&json).body().attachment(),
+        Some(data.as_slice())
+    );
+
+    // Older peers sent the list of numbers, which still decodes
+    let mut legacy = serde_json::to_value(&packet).unwrap();
+    legacy["body"]["attachment"] = serde_json::json!(data);
+    let legacy = serde_json::to_vec(&legacy).unwrap();
+    assert_eq!(
+        JsonPacket::de(&legacy).body().attachment(),
+        Some(data.as_slice())
+    );
+
+    let mut packet = MsgPackPacket::sample();
+    packet.body = PacketBody::with_attachment(data.clone());
+    let msgpack = packet.ser();
+    assert!(msgpack.len() < MsgPackPacket::sample().ser().len() + data.len() + 16);
+    assert_eq!(
+        MsgPackPacket::de(&msgpack).body().attachment(),
+        Some(data.as_slice())
+    );
+}
+
+#[test]
+fn test_encrypted_round_trip_for_each_cipher_suite() {
+    for suite in CipherSuite::ALL {
+        let encryptor = Encryptor::new_with_suite(&Encryptor::generate_key(), suite).unwrap();
+        assert_eq!(encryptor.suite(), suite);
+
+        let bytes = JsonPacket::sample().encrypted_ser(&encryptor);
+        JsonPacket::encrypted_de(&bytes, &encryptor).assert_matches_sample();
+    }
+}
+
+#[test]
+fn test_cipher_suites_do_not_decrypt_each_other() {
+    let key = Encryptor::generate_key();
+    let aes = Encryptor::new_with_suite(&key, CipherSuite::Aes256Gcm).unwrap();
+    let chacha = Encryptor::new_with_suite(&key, CipherSuite::ChaCha20Poly1305).unwrap();
+
+    let encrypted = aes.encrypt(b"secret").unwrap();
+    assert_eq!(chacha.decrypt(&encrypted), Err(EncryptError::DecryptFailed));
+}
+
+#[test]
+fn test_encryptor_failure_modes_are_distinct() {
+    for suite in CipherSuite::ALL {
+        assert!(matches!(
+            Encryptor::new_with_suite(&[0; 5], suite),
+            Err(EncryptError::InvalidKey(_))
+        ));
+
+        let encryptor = Encryptor::new_with_suite(&Encryptor::generate_key(), suite).unwrap();
+        let other = Encryptor::new_with_suite(&Encryptor::generate_key(), suite).unwrap();
+        let encrypted = encryptor.encrypt(b"secret").unwrap();
+
+        // Wrong key and a flipped ciphertext bit both fail authentication
+        assert_eq!(other.decrypt(&encrypted), Err(EncryptError::DecryptFailed));
+        let mut corrupt = BASE64.decode(&encrypted).unwrap();
+        *corrupt.last_mut().unwrap() ^= 1;
+        assert_eq!(
+            encryptor.decrypt(&BASE64.encode(&corrupt)),
+            Err(EncryptError::DecryptFailed)
+        );
+
+        let truncated = BASE64.encode(&BASE64.decode(&encrypted).unwrap()[..10]);
+        assert_eq!(encryptor.decrypt(&truncated), Err(EncryptError::Truncated));
+
+        assert!(matches!(
+            encryptor.decrypt("not base64!"),
+            Err(EncryptError::Malformed(_))
+        ));
+    }
+
+    // Each failure keeps its identity once converted into the crate error
+    assert!(matches!(
+        Error::from(EncryptError::InvalidKey(String::new())),
+        Error::InvalidKey(_)
+    ));
+    assert_eq!(
+        Error::from(EncryptError::DecryptFailed),
+        Error::DecryptFailed
+    );
+    assert_eq!(Error::from(EncryptError::Truncated), Error::TruncatedFrame);
+    assert_eq!(Error::from(EncryptError::Replayed), Error::ReplayDetected);
+}
+
+#[test]
+fn test_handshake_hello_negotiation() {
+    use CipherSuite::{Aes256Gcm, ChaCha20Poly1305};
+
+    let hello = HandshakeHello {
+        public_key: [7; 32],
+        suites: vec![ChaCha20Poly1305, Aes256Gcm],
+    };
+    let decoded = HandshakeHello::decode(&hello.encode()).unwrap();
+    assert_eq!(decoded, hello);
+
+    // The server's preference wins among the suites the client offered
+    assert_eq!(
+        CipherSuite::negotiate(&[Aes256Gcm, ChaCha20Poly1305], &decoded.suites),
+        Some(Aes256Gcm)
+    );
+    assert_eq!(
+        CipherSuite::negotiate(&[ChaCha20Poly1305], &[Aes256Gcm]),
+        None
+    );
+
+    // A bare public key comes from a peer that only knows AES-256-GCM
+    let legacy = HandshakeHello::decode(&[7; 32]).unwrap();
+    assert_eq!(legacy.suites, [Aes256Gcm]);
+    assert!(HandshakeHello::decode(&[7; 31]).is_none());
+}
+
+// Already derives `Clone`; `tpacket` adds only the remaining default derives
+#[tnet_macros::tpacket(name = "already_clone")]
+#[derive(Clone, PartialEq)]
+struct AlreadyClone {
+    value: u32,
+}
+
+// Holds a field that is neither `Clone` nor `Serialize`
+#[tnet_macros::tpacket(derive(Debug))]
+struct LocalOnly {
+    _guard: std::sync::Mutex<u32>,
+}
+
+#[test]
+fn test_tpacket_skips_existing_and_unwanted_derives() {
+    let packet = AlreadyClone { value: 7 };
+    let copy = packet.clone();
+    assert_eq!(copy, packet);
+
+    let json = serde_json::to_string(&packet).unwrap();
+    let decoded: AlreadyClone = serde_json::from_str(&json).unwrap();
+    assert_eq!(decoded, packet);
+    assert_eq!(
+        TNET_PACKET_MARKER_ALREADYCLONE,
+        "already_clone=AlreadyClone"
+    );
+
+    let local = LocalOnly {
+        _guard: std::sync::Mutex::new(1),
+    };
+    assert!(format!("{local:?}").starts_with("LocalOnly"));
+}
@@ -1,9 +1,13 @@
-use std::time::{Duration, Instant};
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 use tokio::time::sleep;
 
 use crate::{
     asynch::{
-        client::{AsyncClient, ReconnectionConfig},
+        client::{AsyncClient, ConnectionStatus, Endpoint, KeepAliveConfig, ReconnectionConfig},
+        framing,
         listener::{AsyncListener, HandlerSources},
     },
     errors::Error,
@@ -107,10 +111,7 @@ impl ImplResource for TestResource {
 }
 
 // Handler functions for the server
-async fn handle_ok(
-    sources: HandlerSources<TestSession, TestResource>,
-    packet: TestPacket,
-) {
+async fn handle_ok(sources: HandlerSources<TestSession, TestResource>, packet: TestPacket) {
     let mut socket = sources.socket;
     println!("Server received packet: {:?}", packet);
 
@@ -130,10 +131,7 @@ async fn handle_ok(
     });
 }
 
-async fn handle_error(
-    sources: HandlerSources<TestSession, TestResource>,
-    error: Error,
-) {
+async fn handle_error(sources: HandlerSources<TestSession, TestResource>, error: Error) {
     println!("Server received error: {:?}", error);
     let mut socket = sources.socket;
     let _ = socket.send(TestPacket::error(error)).await;
@@ -196,6 +194,7 @@ async fn test_basic_reconnection() {
             backoff_factor: 1.5,
             jitter: 0.1,
             reinitialize: true,
+            max_total_retry_duration: None,
         });
 
     // Initialize the connection
@@ -302,6 +301,7 @@ async fn test_fallback_endpoints() {
                     backoff_factor: 1.5,
                     jitter: 0.1,
                     reinitialize: true,
+                    max_total_retry_duration: None,
                 }),
                 Err(_) => {
                     // If we can't connect to the fallback either, skip the test
@@ -339,6 +339,59 @@ async fn test_fallback_endpoints() {
         .ok();
 }
 
+// Failover: the client moves to a fallback endpoint once the primary is gone
+#[tokio::test]
+async fn test_reconnects_to_fallback_endpoint() {
+    let fallback_port = 9097;
+    let (fallback_stop_tx, fallback_stop_rx) = oneshot::channel();
+    let fallback_handle = start_test_server(fallback_port, fallback_stop_rx).await;
+    sleep(Duration::from_millis(100)).await;
+
+    // The primary accepts a single connection and then goes away for good
+    let primary = tokio::net::TcpListener::bind(("127.0.0.1", 0))
+        .await
+        .unwrap();
+    let primary_port = primary.local_addr().unwrap().port();
+    let accept = tokio::spawn(async move { primary.accept().await.unwrap() });
+
+    let reconnected_to = Arc::new(Mutex::new(None));
+    let mut client = AsyncClient::<TestPacket>::new("127.0.0.1", primary_port)
+        .await
+        .unwrap()
+        .with_reconnection(ReconnectionConfig {
+            endpoints: vec![("127.0.0.1".to_string(), fallback_port)],
+            auto_reconnect: true,
+            max_attempts: Some(3),
+            initial_retry_delay: 0.05,
+            reinitialize: false,
+            ..ReconnectionConfig::default()
+        })
+        .with_on_reconnect({
+            let reconnected_to = reconnected_to.clone();
+            move |_, endpoint: &Endpoint| {
+                *reconnected_to.lock().unwrap() = Some(endpoint.clone());
+            }
+        });
+
+    drop(accept.await.unwrap());
+    let deadline = Instant::now() + Duration::from_secs(2);
+    while client.status() != ConnectionStatus::Closed && Instant::now() < deadline {
+        sleep(Duration::from_millis(10)).await;
+    }
+
+    let response = client.send_recv(TestPacket::ok()).await.unwrap();
+    assert_eq!(response.header(), "OK");
+    assert_eq!(
+        *reconnected_to.lock().unwrap(),
+        Some(Endpoint::Tcp("127.0.0.1".to_string(), fallback_port))
+    );
+
+    fallback_stop_tx.send(()).unwrap();
+    tokio::time::timeout(Duration::from_secs(2), fallback_handle)
+        .await
+        .ok();
+}
+
 // Test 3: Exponential backoff - modified to be more robust
 #[tokio::test]
 async fn test_exponential_backoff() {
@@ -405,6 +458,69 @@ async fn test_exponential_backoff() {
     let _ = tokio::time::timeout(Duration::from_secs(6), server_handle).await;
 }
 
+// Jitter spreads delays on both sides of the exponential backoff
+#[test]
+fn test_backoff_jitter_is_symmetric() {
+    let config = ReconnectionConfig {
+        initial_retry_delay: 1.0,
+        max_retry_delay: 60.0,
+        backoff_factor: 2.0,
+        jitter: 0.5,
+        ..ReconnectionConfig::default()
+    };
+
+    let base = 8.0; // 1.0 * 2.0^3
+    let delays: Vec<f64> = (0..1000).map(|_| config.backoff_delay(3)).collect();
+
+    assert!(delays.iter().any(|&d| d < base));
+    assert!(delays.iter().any(|&d| d > base));
+    assert!(
+        delays
+            .iter()
+            .all(|&d| (base * 0.5..=base * 1.5).contains(&d))
+    );
+
+    // Clamped to the configured bounds
+    for attempt in 0..20 {
+        let delay = config.backoff_delay(attempt);
+        assert!((config.initial_retry_delay..=config.max_retry_delay).contains(&delay));
+    }
+}
+
+// The total retry duration bounds reconnection even without an attempt limit
+#[tokio::test]
+async fn test_reconnection_gives_up_after_total_duration() {
+    let listener = tokio::net::TcpListener::bind(("127.0.0.1", 0))
+        .await
+        .unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let accept = tokio::spawn(async move { listener.accept().await.unwrap() });
+
+    let mut client = AsyncClient::<TestPacket>::new("127.0.0.1", port)
+        .await
+        .unwrap()
+        .with_reconnection(ReconnectionConfig {
+            auto_reconnect: true,
+            max_attempts: None,
+            initial_retry_delay: 0.05,
+            max_retry_delay: 0.1,
+            reinitialize: false,
+            max_total_retry_duration: Some(Duration::from_millis(300)),
+            ..ReconnectionConfig::default()
+        });
+
+    // Drop the connection and stop listening, so every attempt fails
+    drop(accept.await.unwrap());
+    sleep(Duration::from_millis(100)).await;
+
+    let started = Instant::now();
+    let result = client.send_recv(TestPacket::ok()).await;
+
+    let elapsed = started.elapsed();
+    assert_eq!(result.unwrap_err(), Error::ReconnectTimedOut);
+    assert!(elapsed < Duration::from_secs(1), "{elapsed:?}");
+}
+
 // Test 4: Session restoration after reconnection
 #[tokio::test]
 async fn test_session_restoration() {
@@ -436,6 +552,7 @@ async fn test_session_restoration() {
             backoff_factor: 1.5,
             jitter: 0.1,
             reinitialize: true,
+            max_total_retry_duration: None,
         });
 
     // Initialize the connection
@@ -528,9 +645,6 @@ async fn test_max_retries_exceeded() {
     // For this test, we still need a client struct to configure reconnection parameters
     // but the initial connection attempt will fail
 
-
-
-
     // This test is mainly to ensure the client handles max retry limits gracefully
     // We'll make an attempt to connect to a non-existent server
 
@@ -582,6 +696,7 @@ async fn test_reconnection_after_downtime() {
             backoff_factor: 1.5,
             jitter: 0.1,
             reinitialize: true,
+            max_total_retry_duration: None,
         });
 
     // Initialize the connection
@@ -592,7 +707,10 @@ async fn test_reconnection_after_downtime() {
     let initial_response = match client.send_recv(initial_packet).await {
         Ok(response) => response,
         Err(e) => {
-            println!("Skipping test as we could not establish initial session: {:?}", e);
+            println!(
+                "Skipping test as we could not establish initial session: {:?}",
+                e
+            );
             let _ = server_stop_tx.send(());
             return;
         }
@@ -600,7 +718,10 @@ async fn test_reconnection_after_downtime() {
 
     // Verify we have a session
     let initial_session_id = initial_response.body().session_id.clone();
-    assert!(initial_session_id.is_some(), "No session ID in initial response");
+    assert!(
+        initial_session_id.is_some(),
+        "No session ID in initial response"
+    );
     println!("Initial session ID: {:?}", initial_session_id);
 
     // Stop the server
@@ -664,4 +785,68 @@ async fn test_reconnection_after_downtime() {
     tokio::time::timeout(Duration::from_secs(2), new_server_handle)
         .await
         .ok();
-}
\ No newline at end of file
+}
+
+// A shared client reconnects on its own once the keep-alive notices the server is gone
+#[tokio::test]
+async fn test_keepalive_triggers_reconnection() {
+    let port = 9098;
+
+    // The first server answers the finalize request and then goes away
+    let listener = tokio::net::TcpListener::bind(("127.0.0.1", port))
+        .await
+        .unwrap();
+    let accept = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        framing::write_frame(&mut stream, &TestPacket::ok().ser())
+            .await
+            .unwrap();
+        stream
+    });
+
+    let reconnects = Arc::new(Mutex::new(0));
+    let mut client = AsyncClient::<TestPacket>::new("127.0.0.1", port)
+        .await
+        .unwrap()
+        .with_keep_alive(KeepAliveConfig {
+            interval: 1,
+            ..KeepAliveConfig::default_on()
+        })
+        .with_reconnection(ReconnectionConfig {
+            auto_reconnect: true,
+            max_attempts: Some(20),
+            initial_retry_delay: 0.1,
+            max_retry_delay: 0.2,
+            reinitialize: true,
+            ..ReconnectionConfig::default()
+        })
+        .with_on_reconnect({
+            let reconnects = reconnects.clone();
+            move |_, _| *reconnects.lock().unwrap() += 1
+        });
+    client.finalize().await;
+    let client = client.convert_to_ref();
+
+    drop(accept.await.unwrap());
+    sleep(Duration::from_millis(1500)).await;
+
+    // Bring a server back without touching the client
+    let (server_stop_tx, server_stop_rx) = oneshot::channel();
+    let server_handle = start_test_server(port, server_stop_rx).await;
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while *reconnects.lock().unwrap() == 0 && Instant::now() < deadline {
+        sleep(Duration::from_millis(50)).await;
+    }
+    assert_eq!(*reconnects.lock().unwrap(), 1);
+
+    let client = client.read().await;
+    assert_eq!(client.status(), ConnectionStatus::Connected);
+    assert!(client.is_keepalive_running());
+    drop(client);
+
+    server_stop_tx.send(()).unwrap();
+    tokio::time::timeout(Duration::from_secs(2), server_handle)
+        .await
+        .ok();
+}
@@ -0,0 +1,208 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::RwLock,
+};
+
+use super::{MyResource, MySession};
+use crate::{
+    asynch::{
+        listener::{HandlerSources, PoolRef, ResourceRef},
+        socket::{TSocket, TSockets},
+    },
+    errors::Error,
+    handler_registry::{self, HandlerFlow},
+    packet::{Packet, PacketBody},
+    resources::Resource,
+    session::Sessions,
+};
+
+// Packet type only used by this module, so other tests can't touch its registry slots
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RegistryPacket {
+    header: String,
+    body: PacketBody,
+}
+
+impl Packet for RegistryPacket {
+    fn header(&self) -> String {
+        self.header.clone()
+    }
+
+    fn body(&self) -> PacketBody {
+        self.body.clone()
+    }
+
+    fn body_mut(&mut self) -> &mut PacketBody {
+        &mut self.body
+    }
+
+    fn ok() -> Self {
+        Self {
+            header: "OK".to_string(),
+            body: PacketBody::default(),
+        }
+    }
+
+    fn error(error: Error) -> Self {
+        Self {
+            header: "ERROR".to_string(),
+            body: PacketBody::with_error_string(error.to_string()),
+        }
+    }
+
+    fn keep_alive() -> Self {
+        Self {
+            header: "KEEPALIVE".to_string(),
+            body: PacketBody::default(),
+        }
+    }
+}
+
+// Handler sources backed by a real loopback connection
+async fn test_sources() -> (HandlerSources<MySession, MyResource>, TcpStream) {
+    let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (client, accepted) = tokio::join!(TcpStream::connect(addr), listener.accept());
+
+    let sources = HandlerSources {
+        socket: TSocket::new(accepted.unwrap().0, Arc::new(RwLock::new(Sessions::new()))),
+        pools: PoolRef(Arc::new(RwLock::new(HashMap::new()))),
+        resources: ResourceRef::new(MyResource::new()),
+        all_connections: TSockets::new(),
+        connection_resources: Arc::new(()),
+    };
+    (sources, client.unwrap())
+}
+
+async fn noop(_sources: HandlerSources<MySession, MyResource>, _packet: RegistryPacket) {}
+
+fn register(header: &str) {
+    handler_registry::register_handler::<RegistryPacket, MySession, MyResource>(
+        header,
+        |sources, packet| Box::pin(noop(sources, packet)),
+    );
+}
+
+fn handler_count(header: &str) -> usize {
+    handler_registry::get_handlers::<RegistryPacket, MySession, MyResource>(header).len()
+}
+
+#[test]
+fn test_deregister_handlers_for_header() {
+    register("DEREG_FOO");
+    register("DEREG_FOO");
+    register("DEREG_BAR");
+
+    assert_eq!(handler_count("DEREG_FOO"), 2);
+
+    let removed =
+        handler_registry::deregister_handlers::<RegistryPacket, MySession, MyResource>("DEREG_FOO");
+    assert_eq!(removed, 2);
+    assert_eq!(handler_count("DEREG_FOO"), 0);
+    assert_eq!(handler_count("DEREG_BAR"), 1);
+
+    // Nothing left to remove
+    assert_eq!(
+        handler_registry::deregister_handlers::<RegistryPacket, MySession, MyResource>("DEREG_FOO"),
+        0
+    );
+}
+
+#[test]
+fn test_deregister_all_for_header() {
+    register("DEREG_ALL");
+    register("DEREG_ALL_OTHER");
+    handler_registry::register_handler::<super::MyPacket, MySession, MyResource>(
+        "DEREG_ALL",
+        |_sources, _packet| Box::pin(async {}),
+    );
+
+    assert_eq!(handler_registry::deregister_all_for_header("DEREG_ALL"), 2);
+    assert_eq!(handler_count("DEREG_ALL"), 0);
+    assert!(
+        handler_registry::get_handlers::<super::MyPacket, MySession, MyResource>("DEREG_ALL")
+            .is_empty()
+    );
+
+    // Headers sharing a prefix are not affected
+    assert_eq!(handler_count("DEREG_ALL_OTHER"), 1);
+}
+
+#[tokio::test]
+async fn test_handlers_run_in_priority_order() {
+    let order = Arc::new(Mutex::new(Vec::new()));
+
+    for priority in [0, 10, -5, 10] {
+        let order = order.clone();
+        handler_registry::register_handler_with_priority::<RegistryPacket, MySession, MyResource>(
+            "PRIORITY",
+            priority,
+            move |_sources, _packet| {
+                order.lock().unwrap().push(priority);
+                Box::pin(async {})
+            },
+        );
+    }
+
+    let (sources, _client) = test_sources().await;
+    let packet = RegistryPacket {
+        header: "PRIORITY".to_string(),
+        body: PacketBody::default(),
+    };
+
+    let handlers =
+        handler_registry::get_handlers::<RegistryPacket, MySession, MyResource>("PRIORITY");
+    for handler in handlers {
+        handler(sources.clone(), packet.clone()).await;
+    }
+
+    assert_eq!(*order.lock().unwrap(), vec![10, 10, 0, -5]);
+}
+
+#[tokio::test]
+async fn test_stop_halts_handler_chain() {
+    let second_ran = Arc::new(AtomicBool::new(false));
+    let second_ran_clone = second_ran.clone();
+
+    handler_registry::register_flow_handler::<RegistryPacket, MySession, MyResource>(
+        "FLOW_STOP",
+        |_sources, _packet| Box::pin(async { HandlerFlow::Stop }),
+    );
+    handler_registry::register_handler::<RegistryPacket, MySession, MyResource>(
+        "FLOW_STOP",
+        move |_sources, _packet| {
+            second_ran_clone.store(true, Ordering::SeqCst);
+            Box::pin(async {})
+        },
+    );
+
+    let (sources, _client) = test_sources().await;
+    let packet = RegistryPacket {
+        header: "FLOW_STOP".to_string(),
+        body: PacketBody::default(),
+    };
+
+    let handlers =
+        handler_registry::get_flow_handlers::<RegistryPacket, MySession, MyResource>("FLOW_STOP");
+    assert_eq!(handlers.len(), 2);
+
+    let mut ran = 0;
+    for handler in handlers {
+        ran += 1;
+        if handler(sources.clone(), packet.clone()).await == HandlerFlow::Stop {
+            break;
+        }
+    }
+
+    assert_eq!(ran, 1);
+    assert!(!second_ran.load(Ordering::SeqCst));
+}
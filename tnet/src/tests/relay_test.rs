@@ -140,6 +140,9 @@ async fn test_phantom_relay_no_auth() {
         server_addr: "127.0.0.1",
         server_port: endpoint_port,
         enc_conf: EncryptionConfig::default(),
+        hops: &[],
+        endpoints: &[],
+        load_balance: LoadBalanceStrategy::RoundRobin,
     };
 
     // 4. Create test packet to relay
@@ -233,6 +236,9 @@ async fn test_phantom_relay_with_auth() {
         server_addr: "127.0.0.1",
         server_port: endpoint_port,
         enc_conf: EncryptionConfig::default(),
+        hops: &[],
+        endpoints: &[],
+        load_balance: LoadBalanceStrategy::RoundRobin,
     };
 
     // 4. Create test packet to relay
@@ -287,6 +293,7 @@ async fn test_phantom_relay_with_auth_and_encryption() {
         enabled: true,
         key: None,
         auto_key_exchange: true,
+        cipher_suites: Vec::new(),
     })
     .with_authenticator(
         Authenticator::new(AuthType::UserPassword).with_auth_fn(|user, pass| {
@@ -329,6 +336,7 @@ async fn test_phantom_relay_with_auth_and_encryption() {
         enabled: true,
         key: None,
         auto_key_exchange: true,
+        cipher_suites: Vec::new(),
     };
 
     let phantom_conf = PhantomConf {
@@ -338,6 +346,9 @@ async fn test_phantom_relay_with_auth_and_encryption() {
         server_addr: "127.0.0.1",
         server_port: endpoint_port,
         enc_conf: encryption_config,
+        hops: &[],
+        endpoints: &[],
+        load_balance: LoadBalanceStrategy::RoundRobin,
     };
 
     // 4. Create test packet to relay
@@ -438,6 +449,9 @@ async fn test_phantom_relay_auth_failure() {
         server_addr: "127.0.0.1",
         server_port: endpoint_port,
         enc_conf: EncryptionConfig::default(),
+        hops: &[],
+        endpoints: &[],
+        load_balance: LoadBalanceStrategy::RoundRobin,
     };
 
     // 4. Create test packet to relay
@@ -551,3 +565,716 @@ async fn test_direct_phantom_client() {
     let _ = endpoint_tx.send(());
     let _ = tokio::time::timeout(Duration::from_secs(2), endpoint_handle).await;
 }
+
+// Round trips should only be bounded by the network, not by fixed delays
+#[tokio::test]
+async fn test_phantom_client_round_trip_throughput() {
+    const ROUND_TRIPS: u32 = 200;
+
+    let (endpoint_tx, endpoint_rx) = oneshot::channel();
+    let endpoint_port = 8099;
+
+    let mut endpoint_server = AsyncListener::new(
+        ("127.0.0.1", endpoint_port),
+        30,
+        wrap_handler!(handle_ok),
+        wrap_handler!(handle_error),
+    )
+    .await;
+
+    let endpoint_handle = tokio::spawn(async move {
+        tokio::select! {
+            _ = endpoint_server.run() => {},
+            _ = endpoint_rx => println!("Endpoint server shutting down"),
+        }
+    });
+
+    let mut phantom_client = AsyncPhantomClient::new("127.0.0.1", endpoint_port)
+        .await
+        .expect("Failed to create phantom client");
+
+    // Consume the session OK sent on connect
+    phantom_client
+        .recv_raw()
+        .await
+        .expect("Failed to receive session");
+
+    let start = std::time::Instant::now();
+    for i in 0..ROUND_TRIPS {
+        let test_packet = TestPacket {
+            header: "TEST".to_string(),
+            body: PacketBody::default(),
+            data: Some(i.to_string()),
+        };
+        let bytes = serde_json::to_vec(&test_packet).expect("Failed to serialize test packet");
+
+        let response_bytes = phantom_client
+            .send_recv_raw(bytes)
+            .await
+            .expect("Failed to get response");
+        let response: TestPacket =
+            serde_json::from_slice(&response_bytes).expect("Failed to deserialize response");
+        assert_eq!(response.data, Some(format!("Processed: {i}")));
+    }
+    let elapsed = start.elapsed();
+
+    assert!(
+        elapsed < Duration::from_micros(250) * ROUND_TRIPS,
+        "{ROUND_TRIPS} round trips took {elapsed:?}"
+    );
+
+    let _ = endpoint_tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(2), endpoint_handle).await;
+}
+
+// A dropped connection should be reported instead of writes being queued forever
+#[tokio::test]
+async fn test_phantom_client_reports_closed_connection() {
+    let listener = tokio::net::TcpListener::bind(("127.0.0.1", 0))
+        .await
+        .expect("Failed to bind listener");
+    let port = listener.local_addr().unwrap().port();
+
+    let (mut phantom_client, accepted) = tokio::join!(
+        AsyncPhantomClient::new("127.0.0.1", port),
+        listener.accept()
+    );
+    let phantom_client = phantom_client
+        .as_mut()
+        .expect("Failed to create phantom client");
+
+    // The server goes away without sending anything
+    drop(accepted.expect("Failed to accept connection"));
+
+    let deadline = std::time::Instant::now() + Duration::from_secs(2);
+    let error = loop {
+        match phantom_client.send(PhantomPacket::ok()).await {
+            Err(e) => break e,
+            Ok(()) => {
+                assert!(
+                    std::time::Instant::now() < deadline,
+                    "Send kept succeeding after the server closed"
+                );
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        }
+    };
+    assert!(matches!(error, Error::ConnectionClosed), "{error:?}");
+    assert!(matches!(
+        phantom_client.recv().await,
+        Err(Error::ConnectionClosed)
+    ));
+}
+
+// A receive that is already waiting should fail as soon as the server closes
+#[tokio::test]
+async fn test_phantom_client_pending_recv_fails_on_close() {
+    let listener = tokio::net::TcpListener::bind(("127.0.0.1", 0))
+        .await
+        .expect("Failed to bind listener");
+    let port = listener.local_addr().unwrap().port();
+
+    let (phantom_client, accepted) = tokio::join!(
+        AsyncPhantomClient::new("127.0.0.1", port),
+        listener.accept()
+    );
+    let mut phantom_client = phantom_client.expect("Failed to create phantom client");
+    let (stream, _) = accepted.expect("Failed to accept connection");
+
+    let pending = tokio::spawn(async move {
+        let started = std::time::Instant::now();
+        (phantom_client.recv().await, started.elapsed())
+    });
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    drop(stream);
+
+    let (result, elapsed) = pending.await.unwrap();
+    assert!(matches!(result, Err(Error::ConnectionClosed)), "{result:?}");
+    assert!(elapsed < Duration::from_secs(1), "recv took {elapsed:?}");
+}
+
+static CHAINED_PAYLOADS: std::sync::Mutex<Vec<String>> = std::sync::Mutex::new(Vec::new());
+
+async fn record_chained_payload(
+    sources: HandlerSources<PhantomSession, PhantomResources>,
+    packet: TestPacket,
+) {
+    if let Some(data) = packet.data.clone() {
+        CHAINED_PAYLOADS.lock().unwrap().push(data);
+    }
+    handle_ok(sources, packet).await;
+}
+
+// Phantom A forwards to phantom B, which delivers to the endpoint
+#[tokio::test]
+async fn test_phantom_relay_chain() {
+    let endpoint_port = 8250;
+    let first_relay_port = 8251;
+    let second_relay_port = 8252;
+
+    let (endpoint_tx, endpoint_rx) = oneshot::channel();
+    let mut endpoint_server = AsyncListener::new(
+        ("127.0.0.1", endpoint_port),
+        30,
+        wrap_handler!(record_chained_payload),
+        wrap_handler!(handle_error),
+    )
+    .await;
+    let endpoint_handle = tokio::spawn(async move {
+        tokio::select! {
+            _ = endpoint_server.run() => {},
+            _ = endpoint_rx => println!("Endpoint server shutting down"),
+        }
+    });
+
+    let mut relay_shutdowns = Vec::new();
+    let mut relay_handles = Vec::new();
+    for port in [first_relay_port, second_relay_port] {
+        let (tx, rx) = oneshot::channel::<()>();
+        let mut relay = PhantomListener::new(Some(("127.0.0.1".to_string(), port))).await;
+        relay_shutdowns.push(tx);
+        relay_handles.push(tokio::spawn(async move {
+            tokio::select! {
+                _ = relay.server.run() => {},
+                _ = rx => println!("Phantom server shutting down"),
+            }
+        }));
+    }
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let second_relay = PhantomConf {
+        header: "relay",
+        username: None,
+        password: None,
+        server_addr: "127.0.0.1",
+        server_port: second_relay_port,
+        enc_conf: EncryptionConfig::default(),
+        hops: &[],
+        endpoints: &[],
+        load_balance: LoadBalanceStrategy::RoundRobin,
+    };
+    let phantom_conf = PhantomConf {
+        header: "relay",
+        username: None,
+        password: None,
+        server_addr: "127.0.0.1",
+        server_port: endpoint_port,
+        enc_conf: EncryptionConfig::default(),
+        hops: &[second_relay],
+        endpoints: &[],
+        load_balance: LoadBalanceStrategy::RoundRobin,
+    };
+
+    let test_packet = TestPacket {
+        header: "TEST".to_string(),
+        body: PacketBody::default(),
+        data: Some("chained payload".to_string()),
+    };
+    let phantom_packet = PhantomPacket::produce_from_conf(&phantom_conf, &test_packet);
+    assert_eq!(phantom_packet.hops.len(), 1);
+
+    let mut client = AsyncPhantomClient::new("127.0.0.1", first_relay_port)
+        .await
+        .expect("Failed to connect to phantom server");
+    client
+        .send(phantom_packet)
+        .await
+        .expect("Failed to send relay request");
+
+    let response = loop {
+        let packet = client.recv().await.expect("Failed to get response");
+        if packet.header != "OK" {
+            break packet;
+        }
+    };
+    assert_eq!(response.header, "relay-response", "{response:?}");
+    assert!(response.recv_packet.is_some());
+    assert_eq!(
+        *CHAINED_PAYLOADS.lock().unwrap(),
+        vec!["chained payload".to_string()]
+    );
+
+    for tx in relay_shutdowns {
+        let _ = tx.send(());
+    }
+    let _ = endpoint_tx.send(());
+    for handle in relay_handles {
+        let _ = tokio::time::timeout(Duration::from_secs(2), handle).await;
+    }
+    let _ = tokio::time::timeout(Duration::from_secs(2), endpoint_handle).await;
+}
+
+static BALANCED_PAYLOADS: std::sync::Mutex<Vec<(u16, String)>> = std::sync::Mutex::new(Vec::new());
+
+async fn record_on_first_backend(
+    sources: HandlerSources<PhantomSession, PhantomResources>,
+    packet: TestPacket,
+) {
+    if let Some(data) = packet.data.clone() {
+        BALANCED_PAYLOADS.lock().unwrap().push((8253, data));
+    }
+    handle_ok(sources, packet).await;
+}
+
+async fn record_on_second_backend(
+    sources: HandlerSources<PhantomSession, PhantomResources>,
+    packet: TestPacket,
+) {
+    if let Some(data) = packet.data.clone() {
+        BALANCED_PAYLOADS.lock().unwrap().push((8254, data));
+    }
+    handle_ok(sources, packet).await;
+}
+
+// Clients are spread across the pool and keep hitting the backend they got first
+#[tokio::test]
+async fn test_phantom_relay_load_balancing() {
+    let relay_port = 8255;
+
+    let mut shutdowns = Vec::new();
+    let mut handles = Vec::new();
+    let backends: [(
+        u16,
+        AsyncListenerOkHandler<TestPacket, PhantomSession, PhantomResources>,
+    ); 2] = [
+        (8253, wrap_handler!(record_on_first_backend)),
+        (8254, wrap_handler!(record_on_second_backend)),
+    ];
+    for (port, ok_handler) in backends {
+        let (tx, rx) = oneshot::channel::<()>();
+        let mut backend = AsyncListener::new(
+            ("127.0.0.1", port),
+            30,
+            ok_handler,
+            wrap_handler!(handle_error),
+        )
+        .await;
+        shutdowns.push(tx);
+        handles.push(tokio::spawn(async move {
+            tokio::select! {
+                _ = backend.run() => {},
+                _ = rx => println!("Endpoint server shutting down"),
+            }
+        }));
+    }
+
+    let (tx, rx) = oneshot::channel::<()>();
+    let mut relay = PhantomListener::new(Some(("127.0.0.1".to_string(), relay_port))).await;
+    shutdowns.push(tx);
+    handles.push(tokio::spawn(async move {
+        tokio::select! {
+            _ = relay.server.run() => {},
+            _ = rx => println!("Phantom server shutting down"),
+        }
+    }));
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let second_backend = PhantomConf {
+        header: "relay",
+        username: None,
+        password: None,
+        server_addr: "127.0.0.1",
+        server_port: 8254,
+        enc_conf: EncryptionConfig::default(),
+        hops: &[],
+        endpoints: &[],
+        load_balance: LoadBalanceStrategy::RoundRobin,
+    };
+    let phantom_conf = PhantomConf {
+        header: "relay",
+        username: None,
+        password: None,
+        server_addr: "127.0.0.1",
+        server_port: 8253,
+        enc_conf: EncryptionConfig::default(),
+        hops: &[],
+        endpoints: &[second_backend],
+        load_balance: LoadBalanceStrategy::RoundRobin,
+    };
+
+    let mut clients = Vec::new();
+    for _ in 0..3 {
+        let client = AsyncPhantomClient::new("127.0.0.1", relay_port)
+            .await
+            .expect("Failed to connect to phantom server");
+        clients.push(client);
+    }
+
+    // Every client relays twice, taking turns
+    for round in 0..2 {
+        for (i, client) in clients.iter_mut().enumerate() {
+            let test_packet = TestPacket {
+                header: "TEST".to_string(),
+                body: PacketBody::default(),
+                data: Some(format!("client-{i}-{round}")),
+            };
+            let phantom_packet = PhantomPacket::produce_from_conf(&phantom_conf, &test_packet);
+            client
+                .send(phantom_packet)
+                .await
+                .expect("Failed to send relay request");
+
+            let response = loop {
+                let packet = client.recv().await.expect("Failed to get response");
+                if packet.header != "OK" {
+                    break packet;
+                }
+            };
+            assert_eq!(response.header, "relay-response", "{response:?}");
+        }
+    }
+
+    let payloads = BALANCED_PAYLOADS.lock().unwrap().clone();
+    assert_eq!(payloads.len(), 6);
+
+    let backend_of = |client: usize| {
+        let prefix = format!("client-{client}-");
+        let backends: Vec<u16> = payloads
+            .iter()
+            .filter(|(_, data)| data.starts_with(&prefix))
+            .map(|(port, _)| *port)
+            .collect();
+        assert_eq!(backends.len(), 2);
+        assert_eq!(backends[0], backends[1], "client {client} moved backends");
+        backends[0]
+    };
+    assert_eq!(
+        [backend_of(0), backend_of(1), backend_of(2)],
+        [8253, 8254, 8253]
+    );
+
+    for tx in shutdowns {
+        let _ = tx.send(());
+    }
+    for handle in handles {
+        let _ = tokio::time::timeout(Duration::from_secs(2), handle).await;
+    }
+}
+
+static FAILOVER_HITS: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+async fn count_failover_payload(
+    sources: HandlerSources<PhantomSession, PhantomResources>,
+    packet: TestPacket,
+) {
+    if packet.header == "TEST" {
+        FAILOVER_HITS.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+    }
+    handle_ok(sources, packet).await;
+}
+
+// A pool member that can't be reached is skipped instead of failing the relay
+#[tokio::test]
+async fn test_phantom_relay_load_balancing_skips_failed_server() {
+    let endpoint = AsyncListener::new(
+        ("127.0.0.1", 0),
+        30,
+        wrap_handler!(count_failover_payload),
+        wrap_handler!(handle_error),
+    )
+    .await
+    .spawn();
+    let endpoint_port = endpoint.local_addr().unwrap().port();
+
+    // Nothing listens on this port once the listener is dropped
+    let closed_port = tokio::net::TcpListener::bind(("127.0.0.1", 0))
+        .await
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+
+    let relay = PhantomListener::new(Some(("127.0.0.1".to_string(), 0)))
+        .await
+        .server
+        .spawn();
+    let relay_port = relay.local_addr().unwrap().port();
+
+    let live_endpoint = PhantomConf {
+        header: "relay",
+        username: None,
+        password: None,
+        server_addr: "127.0.0.1",
+        server_port: endpoint_port,
+        enc_conf: EncryptionConfig::default(),
+        hops: &[],
+        endpoints: &[],
+        load_balance: LoadBalanceStrategy::RoundRobin,
+    };
+    // Round robin hands the first request to the closed port
+    let phantom_conf = PhantomConf {
+        header: "relay",
+        username: None,
+        password: None,
+        server_addr: "127.0.0.1",
+        server_port: closed_port,
+        enc_conf: EncryptionConfig::default(),
+        hops: &[],
+        endpoints: &[live_endpoint],
+        load_balance: LoadBalanceStrategy::RoundRobin,
+    };
+    let test_packet = TestPacket {
+        header: "TEST".to_string(),
+        body: PacketBody::default(),
+        data: Some("failover".to_string()),
+    };
+
+    let mut client = AsyncPhantomClient::new("127.0.0.1", relay_port)
+        .await
+        .expect("Failed to connect to phantom server");
+    for _ in 0..2 {
+        let phantom_packet = PhantomPacket::produce_from_conf(&phantom_conf, &test_packet);
+        let response = relay_once(&mut client, phantom_packet).await;
+        assert_eq!(response.header, "relay-response", "{response:?}");
+    }
+    assert_eq!(FAILOVER_HITS.load(std::sync::atomic::Ordering::SeqCst), 2);
+
+    relay.stop().await;
+    endpoint.stop().await;
+}
+
+// The relay tags every response it sends back through the interceptor
+#[tokio::test]
+async fn test_phantom_relay_interceptor_rewrites_responses() {
+    let endpoint = AsyncListener::new(
+        ("127.0.0.1", 0),
+        30,
+        wrap_handler!(handle_ok),
+        wrap_handler!(handle_error),
+    )
+    .await
+    .spawn();
+    let endpoint_port = endpoint.local_addr().unwrap().port();
+
+    let relay = PhantomListener::new(Some(("127.0.0.1".to_string(), 0)))
+        .await
+        .with_relay_interceptor(|request, mut response| {
+            assert_eq!(request.header, "relay");
+            let mut relayed: TestPacket = response.cast_recv_packet().unwrap();
+            relayed.data = Some("tagged by relay".to_string());
+            response.recv_packet = Some(serde_json::to_string(&relayed).unwrap());
+            response
+        })
+        .server
+        .spawn();
+    let relay_port = relay.local_addr().unwrap().port();
+
+    let phantom_conf = PhantomConf {
+        header: "relay",
+        username: None,
+        password: None,
+        server_addr: "127.0.0.1",
+        server_port: endpoint_port,
+        enc_conf: EncryptionConfig::default(),
+        hops: &[],
+        endpoints: &[],
+        load_balance: LoadBalanceStrategy::RoundRobin,
+    };
+    let test_packet = TestPacket {
+        header: "TEST".to_string(),
+        body: PacketBody::default(),
+        data: Some("intercepted".to_string()),
+    };
+    let phantom_packet = PhantomPacket::produce_from_conf(&phantom_conf, &test_packet);
+
+    let mut client = AsyncPhantomClient::new("127.0.0.1", relay_port)
+        .await
+        .expect("Failed to connect to phantom server");
+    client
+        .send(phantom_packet)
+        .await
+        .expect("Failed to send relay request");
+
+    let response = loop {
+        let packet = client.recv().await.expect("Failed to get response");
+        if packet.header != "OK" {
+            break packet;
+        }
+    };
+    assert_eq!(response.header, "relay-response", "{response:?}");
+    let relayed: TestPacket = response.cast_recv_packet().unwrap();
+    assert_eq!(relayed.header, "OK");
+    assert_eq!(relayed.data.as_deref(), Some("tagged by relay"));
+
+    relay.stop().await;
+    endpoint.stop().await;
+}
+
+static CACHED_HITS: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+async fn count_cached_payload(
+    sources: HandlerSources<PhantomSession, PhantomResources>,
+    packet: TestPacket,
+) {
+    if packet.header == "TEST" {
+        CACHED_HITS.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+    }
+    handle_ok(sources, packet).await;
+}
+
+async fn relay_once(client: &mut AsyncPhantomClient, packet: PhantomPacket) -> PhantomPacket {
+    client
+        .send(packet)
+        .await
+        .expect("Failed to send relay request");
+    loop {
+        let packet = client.recv().await.expect("Failed to get response");
+        if packet.header != "OK" {
+            break packet;
+        }
+    }
+}
+
+// Repeated relays from one client are answered from the cache, other clients are not
+#[tokio::test]
+async fn test_phantom_relay_caches_responses_per_client() {
+    let endpoint = AsyncListener::new(
+        ("127.0.0.1", 0),
+        30,
+        wrap_handler!(count_cached_payload),
+        wrap_handler!(handle_error),
+    )
+    .await
+    .spawn();
+    let endpoint_port = endpoint.local_addr().unwrap().port();
+
+    let relay = PhantomListener::new(Some(("127.0.0.1".to_string(), 0)))
+        .await
+        .with_response_cache(RelayCacheConfig::default().with_ttl(Duration::from_secs(60)))
+        .server
+        .spawn();
+    let relay_port = relay.local_addr().unwrap().port();
+
+    let phantom_conf = PhantomConf {
+        header: "relay",
+        username: None,
+        password: None,
+        server_addr: "127.0.0.1",
+        server_port: endpoint_port,
+        enc_conf: EncryptionConfig::default(),
+        hops: &[],
+        endpoints: &[],
+        load_balance: LoadBalanceStrategy::RoundRobin,
+    };
+    let test_packet = TestPacket {
+        header: "TEST".to_string(),
+        body: PacketBody::default(),
+        data: Some("cached payload".to_string()),
+    };
+    let phantom_packet = PhantomPacket::produce_from_conf(&phantom_conf, &test_packet);
+
+    let mut client = AsyncPhantomClient::new("127.0.0.1", relay_port)
+        .await
+        .expect("Failed to connect to phantom server");
+    let first = relay_once(&mut client, phantom_packet.clone()).await;
+    let second = relay_once(&mut client, phantom_packet.clone()).await;
+    assert_eq!(first.header, "relay-response", "{first:?}");
+    assert_eq!(second.recv_packet, first.recv_packet);
+    assert_eq!(CACHED_HITS.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+    let mut other = AsyncPhantomClient::new("127.0.0.1", relay_port)
+        .await
+        .expect("Failed to connect to phantom server");
+    let response = relay_once(&mut other, phantom_packet).await;
+    assert_eq!(response.header, "relay-response", "{response:?}");
+    assert_eq!(CACHED_HITS.load(std::sync::atomic::Ordering::SeqCst), 2);
+
+    relay.stop().await;
+    endpoint.stop().await;
+}
+
+static GUARDED_HITS: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+async fn count_guarded_payload(
+    sources: HandlerSources<PhantomSession, PhantomResources>,
+    packet: TestPacket,
+) {
+    if packet.header == "TEST" {
+        GUARDED_HITS.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+    }
+    handle_ok(sources, packet).await;
+}
+
+// The relay turns away clients that do not authenticate with it
+#[tokio::test]
+async fn test_phantom_relay_requires_its_own_credentials() {
+    let endpoint = AsyncListener::new(
+        ("127.0.0.1", 0),
+        30,
+        wrap_handler!(count_guarded_payload),
+        wrap_handler!(handle_error),
+    )
+    .await
+    .spawn();
+    let endpoint_port = endpoint.local_addr().unwrap().port();
+
+    let relay = PhantomListener::new(Some(("127.0.0.1".to_string(), 0)))
+        .await
+        .with_authenticator(Authenticator::new(AuthType::UserPassword).with_auth_fn(
+            |user, pass| {
+                Box::pin(async move {
+                    if user == "relay-user" && pass == "relay-pass" {
+                        Ok(())
+                    } else {
+                        Err(Error::InvalidCredentials)
+                    }
+                })
+            },
+        ))
+        .server
+        .spawn();
+    let relay_port = relay.local_addr().unwrap().port();
+
+    let phantom_conf = PhantomConf {
+        header: "relay",
+        username: None,
+        password: None,
+        server_addr: "127.0.0.1",
+        server_port: endpoint_port,
+        enc_conf: EncryptionConfig::default(),
+        hops: &[],
+        endpoints: &[],
+        load_balance: LoadBalanceStrategy::RoundRobin,
+    };
+    let test_packet = TestPacket {
+        header: "TEST".to_string(),
+        body: PacketBody::default(),
+        data: Some("guarded".to_string()),
+    };
+    let phantom_packet = PhantomPacket::produce_from_conf(&phantom_conf, &test_packet);
+
+    // Without credentials the relay rejects the request before relaying it
+    let mut stranger = AsyncPhantomClient::new("127.0.0.1", relay_port)
+        .await
+        .expect("Failed to connect to phantom server");
+    stranger
+        .send(phantom_packet.clone())
+        .await
+        .expect("Failed to send relay request");
+    let rejection = tokio::time::timeout(Duration::from_secs(2), stranger.recv())
+        .await
+        .expect("Relay never answered");
+    assert!(
+        rejection
+            .as_ref()
+            .is_ok_and(|packet| packet.header == "ERROR")
+            || rejection.is_err(),
+        "{rejection:?}"
+    );
+    assert_eq!(GUARDED_HITS.load(std::sync::atomic::Ordering::SeqCst), 0);
+
+    let mut member = AsyncPhantomClient::new("127.0.0.1", relay_port)
+        .await
+        .expect("Failed to connect to phantom server")
+        .with_credentials("relay-user", "relay-pass");
+    member.finalize().await;
+    let response = relay_once(&mut member, phantom_packet).await;
+    assert_eq!(response.header, "relay-response", "{response:?}");
+    assert_eq!(GUARDED_HITS.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+    relay.stop().await;
+    endpoint.stop().await;
+}
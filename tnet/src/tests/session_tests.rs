@@ -0,0 +1,555 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use futures::future::BoxFuture;
+
+use serde::{Deserialize, Serialize};
+
+use tokio::{
+    io::AsyncWriteExt,
+    sync::{RwLock, oneshot},
+};
+
+use super::{MyPacket, MyResource, MySession};
+use crate::{
+    asynch::{
+        authenticator::{AuthType, Authenticator},
+        client::{AsyncClient, ConnectionStatus, ReconnectionConfig},
+        listener::{AsyncListener, HandlerSources},
+    },
+    errors::Error,
+    packet::{Packet, PacketBody},
+    session::{
+        JsonFileSessionStore, Session, SessionExpiryPolicy, SessionStore, SessionSummary, Sessions,
+    },
+    wrap_handler,
+};
+
+#[tokio::test]
+async fn test_json_store_resolves_sessions_after_reopen() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("sessions.json");
+
+    let store = JsonFileSessionStore::<MySession>::open(&path)
+        .await
+        .unwrap();
+    store
+        .save(MySession::empty("kept".to_string()))
+        .await
+        .unwrap();
+    store
+        .save(MySession::empty("removed".to_string()))
+        .await
+        .unwrap();
+    store.remove("removed").await.unwrap();
+    drop(store);
+
+    // A fresh store over the same file sees what the old one saved
+    let store = JsonFileSessionStore::<MySession>::open(&path)
+        .await
+        .unwrap();
+    let session = store.load("kept").await.unwrap();
+    assert_eq!(
+        session.map(|s| s.id().to_string()),
+        Some("kept".to_string())
+    );
+    assert!(store.load("removed").await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_json_store_clears_expired_sessions() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("sessions.json");
+
+    let mut expired = MySession::empty("expired".to_string());
+    expired.duration = Duration::ZERO;
+
+    let store = JsonFileSessionStore::<MySession>::open(&path)
+        .await
+        .unwrap();
+    store.save(expired).await.unwrap();
+    store
+        .save(MySession::empty("live".to_string()))
+        .await
+        .unwrap();
+    store
+        .clear_expired(SessionExpiryPolicy::Fixed)
+        .await
+        .unwrap();
+
+    let store = JsonFileSessionStore::<MySession>::open(&path)
+        .await
+        .unwrap();
+    assert!(store.load("expired").await.unwrap().is_none());
+    assert!(store.load("live").await.unwrap().is_some());
+}
+
+// Touches are kept in memory until they are flushed, not written one by one
+#[tokio::test]
+async fn test_json_store_batches_touches() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("sessions.json");
+
+    let store = JsonFileSessionStore::<MySession>::open(&path)
+        .await
+        .unwrap();
+    store
+        .save(MySession::empty("active".to_string()))
+        .await
+        .unwrap();
+    store.touch("active").await.unwrap();
+    assert!(store.last_active("active").await.unwrap().is_some());
+
+    let reopened = JsonFileSessionStore::<MySession>::open(&path)
+        .await
+        .unwrap();
+    assert!(reopened.last_active("active").await.unwrap().is_none());
+
+    store.flush().await.unwrap();
+    let reopened = JsonFileSessionStore::<MySession>::open(&path)
+        .await
+        .unwrap();
+    assert!(reopened.last_active("active").await.unwrap().is_some());
+}
+
+#[tokio::test]
+async fn test_sliding_expiry_keeps_active_session_alive() {
+    let store = RwLock::new(Sessions::<MySession>::new());
+
+    let mut session = MySession::empty("active".to_string());
+    session.duration = Duration::from_secs(2);
+    store.save(session).await.unwrap();
+
+    // Activity every second carries the session past its original lifespan
+    for _ in 0..3 {
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        store.touch("active").await.unwrap();
+        store
+            .clear_expired(SessionExpiryPolicy::Sliding)
+            .await
+            .unwrap();
+        assert!(store.load("active").await.unwrap().is_some());
+    }
+
+    // Measured from creation, the same session has aged out
+    store
+        .clear_expired(SessionExpiryPolicy::Fixed)
+        .await
+        .unwrap();
+    assert!(store.load("active").await.unwrap().is_none());
+}
+
+async fn room_handler(sources: HandlerSources<MySession, MyResource>, packet: MyPacket) {
+    let mut socket = sources.socket;
+    let response = match packet.header.as_str() {
+        "JOIN" => {
+            socket.set_meta("room", "lobby").await.unwrap();
+            MyPacket::ok()
+        }
+        _ => MyPacket {
+            header: socket.get_meta("room").await.unwrap_or_default(),
+            body: PacketBody::default(),
+        },
+    };
+    socket.send(response).await.unwrap();
+}
+
+async fn log_error(_sources: HandlerSources<MySession, MyResource>, error: Error) {
+    println!("Server error: {error}");
+}
+
+#[tokio::test]
+async fn test_session_metadata_persists_across_packets() {
+    let (tx, rx) = oneshot::channel();
+
+    let mut server = AsyncListener::new(
+        ("127.0.0.1", 8230),
+        30,
+        wrap_handler!(room_handler),
+        wrap_handler!(log_error),
+    )
+    .await;
+
+    let server_handle = tokio::spawn(async move {
+        tokio::select! {
+            _ = server.run() => {},
+            _ = rx => println!("Server shutting down"),
+        }
+    });
+
+    let mut client = AsyncClient::<MyPacket>::new("127.0.0.1", 8230)
+        .await
+        .unwrap();
+    assert_eq!(client.recv().await.unwrap().header(), "OK");
+
+    let join = MyPacket {
+        header: "JOIN".to_string(),
+        body: PacketBody::default(),
+    };
+    assert_eq!(client.send_recv(join).await.unwrap().header(), "OK");
+
+    let where_am_i = MyPacket {
+        header: "WHERE".to_string(),
+        body: PacketBody::default(),
+    };
+    assert_eq!(
+        client.send_recv(where_am_i).await.unwrap().header(),
+        "lobby"
+    );
+
+    let _ = tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(2), server_handle).await;
+}
+
+async fn whoami(sources: HandlerSources<MySession, MyResource>, _packet: MyPacket) {
+    let response = MyPacket {
+        header: sources.username().await.unwrap_or_default(),
+        body: PacketBody::default(),
+    };
+    let mut socket = sources.socket;
+    socket.send(response).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_handler_reads_authenticated_username() {
+    let (tx, rx) = oneshot::channel();
+
+    let mut server = AsyncListener::new(
+        ("127.0.0.1", 8231),
+        30,
+        wrap_handler!(whoami),
+        wrap_handler!(log_error),
+    )
+    .await
+    .with_authenticator(
+        Authenticator::new(AuthType::UserPassword).with_auth_fn(|user, pass| {
+            Box::pin(async move {
+                if user == "alice" && pass == "wonderland" {
+                    Ok(())
+                } else {
+                    Err(Error::InvalidCredentials)
+                }
+            })
+        }),
+    );
+
+    let server_handle = tokio::spawn(async move {
+        tokio::select! {
+            _ = server.run() => {},
+            _ = rx => println!("Server shutting down"),
+        }
+    });
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut client = AsyncClient::<MyPacket>::new("127.0.0.1", 8231)
+        .await
+        .unwrap()
+        .with_credentials("alice", "wonderland");
+    client.finalize().await;
+
+    let who = MyPacket {
+        header: "WHOAMI".to_string(),
+        body: PacketBody::default(),
+    };
+    assert_eq!(client.send_recv(who).await.unwrap().header(), "alice");
+
+    let _ = tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(2), server_handle).await;
+}
+
+// Hangs up on DROP, otherwise reports the connection's session
+async fn session_reporter(sources: HandlerSources<MySession, MyResource>, packet: MyPacket) {
+    let mut socket = sources.socket;
+    if packet.header() == "DROP" {
+        let _ = socket.write_part.lock().await.shutdown().await;
+        return;
+    }
+
+    let mut reply = MyPacket::ok();
+    reply.body_mut().session_id = socket.session_id.clone();
+    socket.send(reply).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_reconnect_without_credentials_reuses_session() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("sessions.json");
+    let (tx, rx) = oneshot::channel();
+
+    let mut server = AsyncListener::new(
+        ("127.0.0.1", 8232),
+        30,
+        wrap_handler!(session_reporter),
+        wrap_handler!(log_error),
+    )
+    .await
+    .with_session_store(
+        JsonFileSessionStore::<MySession>::open(&path)
+            .await
+            .unwrap(),
+    );
+
+    let server_handle = tokio::spawn(async move {
+        tokio::select! {
+            _ = server.run() => {},
+            _ = rx => println!("Server shutting down"),
+        }
+    });
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut client = AsyncClient::<MyPacket>::new("127.0.0.1", 8232)
+        .await
+        .unwrap()
+        .with_reconnection(ReconnectionConfig {
+            auto_reconnect: true,
+            initial_retry_delay: 0.05,
+            reinitialize: false,
+            ..ReconnectionConfig::default()
+        });
+    client.finalize().await;
+    let session_id = client.session_id().map(str::to_string);
+    assert!(session_id.is_some());
+
+    let drop_packet = MyPacket {
+        header: "DROP".to_string(),
+        body: PacketBody::default(),
+    };
+    let who = MyPacket {
+        header: "WHOAMI".to_string(),
+        body: PacketBody::default(),
+    };
+    for _ in 0..3 {
+        client.send(drop_packet.clone()).await.unwrap();
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while client.status() != ConnectionStatus::Closed && Instant::now() < deadline {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        assert_eq!(client.status(), ConnectionStatus::Closed);
+
+        // The retried request lands on the original session
+        let mut reply = client.send_recv(who.clone()).await.unwrap();
+        assert_eq!(reply.session_id(None), session_id);
+        assert_eq!(client.session_id().map(str::to_string), session_id);
+    }
+
+    let _ = tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(2), server_handle).await;
+
+    // The sessions minted for each reconnect were dropped again
+    let saved: serde_json::Value = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+    let ids = saved["sessions"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|s| s["id"].as_str().map(str::to_string))
+        .collect::<Vec<_>>();
+    assert_eq!(ids, vec![session_id]);
+}
+
+// A session that expires two seconds after it is created
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ShortSession {
+    id: String,
+    created_at: u64,
+}
+
+impl Session for ShortSession {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn created_at(&self) -> u64 {
+        self.created_at
+    }
+
+    fn lifespan(&self) -> Duration {
+        Duration::from_secs(2)
+    }
+
+    fn empty(id: String) -> Self {
+        Self {
+            id,
+            created_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+        }
+    }
+}
+
+async fn short_ok(sources: HandlerSources<ShortSession, MyResource>, _packet: MyPacket) {
+    let mut socket = sources.socket;
+    socket.send(MyPacket::ok()).await.unwrap();
+}
+
+async fn log_short_error(_sources: HandlerSources<ShortSession, MyResource>, error: Error) {
+    println!("Server error: {error}");
+}
+
+#[tokio::test]
+async fn test_session_count_tracks_connections_until_cleared() {
+    let (tx, rx) = oneshot::channel();
+
+    let mut server = AsyncListener::<MyPacket, ShortSession, MyResource>::new(
+        ("127.0.0.1", 8233),
+        30,
+        wrap_handler!(short_ok),
+        wrap_handler!(log_short_error),
+    )
+    .await;
+    assert_eq!(server.session_count().await.unwrap(), 0);
+
+    // Hand the listener back once it stops so its sessions can be inspected
+    let server_handle = tokio::spawn(async move {
+        tokio::select! {
+            _ = server.run() => {},
+            _ = rx => println!("Server shutting down"),
+        }
+        server
+    });
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut ids = Vec::new();
+    for _ in 0..3 {
+        let mut client = AsyncClient::<MyPacket>::new("127.0.0.1", 8233)
+            .await
+            .unwrap();
+        let mut greeting = client.recv().await.unwrap();
+        ids.push(greeting.session_id(None).unwrap());
+    }
+
+    let _ = tx.send(());
+    let server = server_handle.await.unwrap();
+
+    assert_eq!(server.session_count().await.unwrap(), 3);
+    let sessions = server.active_sessions().await.unwrap();
+    assert_eq!(
+        sessions.iter().map(|s| s.id.clone()).collect::<Vec<_>>(),
+        ids
+    );
+    assert!(sessions.iter().all(|s| s.expires_at == s.created_at + 2));
+
+    tokio::time::sleep(Duration::from_secs(3)).await;
+    server.clear_expired_sessions().await.unwrap();
+    assert_eq!(server.session_count().await.unwrap(), 0);
+    assert!(server.active_sessions().await.unwrap().is_empty());
+}
+
+// An in-memory store that counts how often expired sessions are cleared
+struct CountingStore {
+    inner: RwLock<Sessions<MySession>>,
+    clears: Arc<AtomicUsize>,
+}
+
+impl SessionStore<MySession> for CountingStore {
+    fn load<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<Option<MySession>, Error>> {
+        self.inner.load(id)
+    }
+
+    fn save(&self, session: MySession) -> BoxFuture<'_, Result<(), Error>> {
+        self.inner.save(session)
+    }
+
+    fn remove<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<(), Error>> {
+        self.inner.remove(id)
+    }
+
+    fn touch<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<(), Error>> {
+        self.inner.touch(id)
+    }
+
+    fn last_active<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<Option<u64>, Error>> {
+        self.inner.last_active(id)
+    }
+
+    fn get_meta<'a>(
+        &'a self,
+        id: &'a str,
+        key: &'a str,
+    ) -> BoxFuture<'a, Result<Option<String>, Error>> {
+        self.inner.get_meta(id, key)
+    }
+
+    fn set_meta<'a>(
+        &'a self,
+        id: &'a str,
+        key: &'a str,
+        value: String,
+    ) -> BoxFuture<'a, Result<(), Error>> {
+        self.inner.set_meta(id, key, value)
+    }
+
+    fn clear_expired(&self, policy: SessionExpiryPolicy) -> BoxFuture<'_, Result<(), Error>> {
+        self.clears.fetch_add(1, Ordering::SeqCst);
+        self.inner.clear_expired(policy)
+    }
+
+    fn count(&self) -> BoxFuture<'_, Result<usize, Error>> {
+        self.inner.count()
+    }
+
+    fn summaries(
+        &self,
+        policy: SessionExpiryPolicy,
+    ) -> BoxFuture<'_, Result<Vec<SessionSummary>, Error>> {
+        self.inner.summaries(policy)
+    }
+}
+
+#[tokio::test]
+async fn test_cleanup_interval_changes_on_running_listener() {
+    let clears = Arc::new(AtomicUsize::new(0));
+    let (tx, rx) = oneshot::channel();
+
+    let mut server = AsyncListener::new(
+        ("127.0.0.1", 8234),
+        30,
+        wrap_handler!(room_handler),
+        wrap_handler!(log_error),
+    )
+    .await
+    .with_session_store(CountingStore {
+        inner: RwLock::new(Sessions::new()),
+        clears: clears.clone(),
+    });
+    let schedule = server.cleanup_schedule();
+    assert_eq!(schedule.interval(), 30);
+
+    let server_handle = tokio::spawn(async move {
+        tokio::select! {
+            _ = server.run() => {},
+            _ = rx => println!("Server shutting down"),
+        }
+    });
+
+    // Nothing is due for another 30 seconds
+    tokio::time::sleep(Duration::from_millis(1200)).await;
+    assert_eq!(clears.load(Ordering::SeqCst), 0);
+
+    // The shorter interval applies to the wait already in progress
+    schedule.set_interval(1);
+    tokio::time::sleep(Duration::from_millis(2500)).await;
+    assert!(clears.load(Ordering::SeqCst) >= 2);
+
+    schedule.pause();
+    assert!(schedule.is_paused());
+    let paused_at = clears.load(Ordering::SeqCst);
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    assert_eq!(clears.load(Ordering::SeqCst), paused_at);
+
+    schedule.resume();
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    assert!(clears.load(Ordering::SeqCst) > paused_at);
+
+    let _ = tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(2), server_handle).await;
+}
@@ -0,0 +1,434 @@
+use std::{
+    pin::Pin,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::RwLock,
+};
+
+use super::{MyPacket, MySession};
+use crate::{
+    asynch::{
+        framing::{self, FRAME_HEADER_LEN, FrameReader},
+        socket::{BatchConfig, PreparedBroadcast, SocketWriter, TSocket, TSockets},
+    },
+    compression::{CompressionAlgorithm, CompressionConfig},
+    encrypt::Encryptor,
+    packet::{Packet, PacketBody},
+    session::Sessions,
+};
+
+// Builds a connected (server-side TSocket, raw client stream) pair on an ephemeral port
+async fn socket_pair() -> (TSocket<MySession>, TcpStream) {
+    let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let (client, accepted) = tokio::join!(TcpStream::connect(addr), listener.accept());
+    let (stream, _) = accepted.unwrap();
+
+    let sessions = Arc::new(RwLock::new(Sessions::new()));
+    (TSocket::new(stream, sessions), client.unwrap())
+}
+
+fn test_packet(header: &str) -> MyPacket {
+    MyPacket {
+        header: header.to_string(),
+        body: PacketBody::default(),
+    }
+}
+
+#[tokio::test]
+async fn test_concurrent_send_on_cloned_socket() {
+    let (socket, mut client) = socket_pair().await;
+
+    let expected: usize = [test_packet("first"), test_packet("second")]
+        .iter()
+        .map(|p| FRAME_HEADER_LEN + p.ser().len())
+        .sum::<usize>()
+        * 50;
+
+    let mut first = socket.clone();
+    let mut second = socket.clone();
+
+    let a = tokio::spawn(async move {
+        for _ in 0..50 {
+            first.send(test_packet("first")).await?;
+        }
+        Ok::<_, crate::errors::Error>(())
+    });
+    let b = tokio::spawn(async move {
+        for _ in 0..50 {
+            second.send(test_packet("second")).await?;
+        }
+        Ok::<_, crate::errors::Error>(())
+    });
+
+    let mut received = Vec::new();
+    let mut buf = vec![0; 4096];
+    while received.len() < expected {
+        let n = tokio::time::timeout(Duration::from_secs(5), client.read(&mut buf))
+            .await
+            .expect("Timed out waiting for data")
+            .unwrap();
+        assert!(n > 0, "Connection closed early");
+        received.extend_from_slice(&buf[..n]);
+    }
+
+    assert!(a.await.expect("First sender panicked").is_ok());
+    assert!(b.await.expect("Second sender panicked").is_ok());
+    assert_eq!(received.len(), expected);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_concurrent_encrypted_sends_are_not_replayed() {
+    let key = Encryptor::generate_key();
+    let (socket, client) = socket_pair().await;
+    let socket = socket.with_encryptor(Encryptor::new(&key).unwrap());
+
+    // Far more senders than the replay window, all racing for the writer
+    let senders: Vec<_> = (0..256)
+        .map(|i| {
+            let mut socket = socket.clone();
+            tokio::spawn(async move { socket.send(test_packet(&format!("packet-{i}"))).await })
+        })
+        .collect();
+
+    let decryptor = Encryptor::new(&key).unwrap();
+    let mut frames = FrameReader::new(client);
+    for _ in 0..senders.len() {
+        let frame = tokio::time::timeout(Duration::from_secs(5), frames.read_frame())
+            .await
+            .expect("Timed out waiting for packet")
+            .unwrap()
+            .unwrap();
+        let received: MyPacket = CompressionConfig::default()
+            .decode(&frame, Some(&decryptor))
+            .expect("Frame rejected by the replay window");
+        assert!(received.header.starts_with("packet-"));
+    }
+
+    for sender in senders {
+        assert!(sender.await.expect("Sender panicked").is_ok());
+    }
+}
+
+#[tokio::test]
+async fn test_packets_written_together_are_received_separately() {
+    let (mut socket, mut client) = socket_pair().await;
+
+    // Both packets land in the same read on the server side
+    let mut wire = framing::encode_frame(&test_packet("first").ser());
+    wire.extend(framing::encode_frame(&test_packet("second").ser()));
+    client.write_all(&wire).await.unwrap();
+
+    let first: MyPacket = socket.recv().await.unwrap();
+    let second: MyPacket = socket.recv().await.unwrap();
+    assert_eq!(first.header, "first");
+    assert_eq!(second.header, "second");
+}
+
+#[tokio::test]
+async fn test_broadcast_prunes_disconnected_sockets() {
+    let mut pool = TSockets::new();
+    let mut clients = Vec::new();
+    for i in 0..3 {
+        let (socket, client) = socket_pair().await;
+        pool.add(socket.with_session_id(format!("client-{i}")))
+            .await;
+        clients.push(client);
+    }
+
+    drop(clients.remove(1));
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    // The first write after the peer hangs up can still succeed, so keep
+    // broadcasting until the reset is noticed
+    let mut pruned = 0;
+    for _ in 0..5 {
+        let report = pool.broadcast(test_packet("ping")).await.unwrap();
+        pruned += report.pruned;
+        if pruned > 0 {
+            assert_eq!(report.delivered, 2);
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    assert_eq!(pruned, 1);
+    let remaining = pool
+        .iter()
+        .await
+        .filter_map(|s| s.session_id)
+        .collect::<Vec<_>>();
+    assert_eq!(remaining, ["client-0", "client-2"]);
+}
+
+#[tokio::test]
+async fn test_broadcast_prunes_only_the_dead_anonymous_socket() {
+    let mut pool = TSockets::new();
+    let (alive, mut alive_client) = socket_pair().await;
+    let (dead, dead_client) = socket_pair().await;
+    pool.add(alive).await;
+    pool.add(dead).await;
+
+    drop(dead_client);
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let mut pruned = 0;
+    for _ in 0..5 {
+        pruned += pool.broadcast(test_packet("ping")).await.unwrap().pruned;
+        if pruned > 0 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    assert_eq!(pruned, 1);
+    assert_eq!(pool.len().await, 1);
+
+    let mut reader = FrameReader::new(&mut alive_client);
+    let frame = tokio::time::timeout(Duration::from_secs(5), reader.read_frame())
+        .await
+        .expect("Timed out waiting for broadcast")
+        .unwrap()
+        .unwrap();
+    assert_eq!(MyPacket::de(&frame).header, "ping");
+}
+
+#[tokio::test]
+async fn test_broadcast_reaches_sockets_with_different_encodings() {
+    let key = Encryptor::generate_key();
+    let compressed = CompressionConfig {
+        algorithm: CompressionAlgorithm::Zstd,
+        min_size: 0,
+    };
+    let configs = [
+        (CompressionConfig::default(), None),
+        (CompressionConfig::default(), None),
+        (compressed, None),
+        (compressed, None),
+        (CompressionConfig::default(), Some(key)),
+    ];
+
+    let mut pool = TSockets::new();
+    let mut clients = Vec::new();
+    for (i, (compression, key)) in configs.into_iter().enumerate() {
+        let (mut socket, client) = socket_pair().await;
+        socket = socket
+            .with_session_id(format!("client-{i}"))
+            .with_compression(compression);
+        if let Some(key) = key {
+            socket = socket.with_encryptor(Encryptor::new(&key).unwrap());
+        }
+        pool.add(socket).await;
+        clients.push((framing::FrameReader::new(client), compression, key));
+    }
+
+    let mut packet = test_packet("news");
+    packet.body.error_string = Some("headline ".repeat(200));
+    for _ in 0..2 {
+        let report = pool.broadcast(packet.clone()).await.unwrap();
+        assert_eq!(report.delivered, 5);
+    }
+
+    // Every client decodes both copies with its own settings
+    for (frames, compression, key) in &mut clients {
+        let encryptor = key.map(|key| Encryptor::new(&key).unwrap());
+        for _ in 0..2 {
+            let frame = tokio::time::timeout(Duration::from_secs(5), frames.read_frame())
+                .await
+                .expect("Timed out waiting for broadcast")
+                .unwrap()
+                .unwrap();
+            let received: MyPacket = compression.decode(&frame, encryptor.as_ref()).unwrap();
+            assert_eq!(received.header, "news");
+            assert_eq!(received.body.error_string, packet.body.error_string);
+            assert!(received.is_broadcast());
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_preserialized_broadcast_sends_identical_bytes() {
+    let shared = Encryptor::new(&Encryptor::generate_key()).unwrap();
+
+    // One pool without encryption and one whose sockets share an encryptor
+    let mut plain_pool = TSockets::new();
+    let mut encrypted_pool = TSockets::new();
+    let mut plain_clients = Vec::new();
+    let mut encrypted_clients = Vec::new();
+    for i in 0..3 {
+        let (socket, client) = socket_pair().await;
+        plain_pool
+            .add(socket.with_session_id(format!("plain-{i}")))
+            .await;
+        plain_clients.push(framing::FrameReader::new(client));
+
+        let (socket, client) = socket_pair().await;
+        encrypted_pool
+            .add(
+                socket
+                    .with_session_id(format!("encrypted-{i}"))
+                    .with_encryptor(shared.clone()),
+            )
+            .await;
+        encrypted_clients.push(framing::FrameReader::new(client));
+    }
+
+    let prepared = PreparedBroadcast::new(test_packet("news"));
+    assert_eq!(prepared.header(), "news");
+    plain_pool.broadcast_preserialized(&prepared).await.unwrap();
+    encrypted_pool
+        .broadcast_preserialized(&prepared)
+        .await
+        .unwrap();
+
+    let mut plain_frames = Vec::new();
+    for frames in &mut plain_clients {
+        plain_frames.push(frames.read_frame().await.unwrap().unwrap());
+    }
+    let mut encrypted_frames = Vec::new();
+    for frames in &mut encrypted_clients {
+        encrypted_frames.push(frames.read_frame().await.unwrap().unwrap());
+    }
+
+    assert!(plain_frames.iter().all(|frame| *frame == plain_frames[0]));
+    assert_eq!(plain_frames[0], prepared.serialized());
+    assert!(
+        encrypted_frames
+            .iter()
+            .all(|frame| *frame == encrypted_frames[0])
+    );
+
+    let received: MyPacket = CompressionConfig::default()
+        .decode(&encrypted_frames[0], Some(&shared))
+        .unwrap();
+    assert_eq!(received.header, "news");
+    assert!(received.is_broadcast());
+}
+
+#[tokio::test]
+async fn test_pool_len_and_contains_track_membership() {
+    let mut pool = TSockets::new();
+    assert!(pool.is_empty().await);
+
+    let mut sockets = Vec::new();
+    for i in 0..3 {
+        let (socket, _client) = socket_pair().await;
+        let socket = socket.with_session_id(format!("client-{i}"));
+        pool.add(socket.clone()).await;
+        sockets.push(socket);
+    }
+
+    assert_eq!(pool.len().await, 3);
+    assert!(!pool.is_empty().await);
+    assert!(pool.contains("client-1").await);
+
+    pool.remove(&sockets[1]).await;
+    assert_eq!(pool.len().await, 2);
+    assert!(!pool.contains("client-1").await);
+    assert!(pool.contains("client-2").await);
+
+    pool.remove_batch(vec![&sockets[0], &sockets[2]]).await;
+    assert!(pool.is_empty().await);
+}
+
+#[tokio::test]
+async fn test_remove_by_session_id() {
+    let mut pool = TSockets::new();
+    for id in ["alice", "bob"] {
+        let (socket, _client) = socket_pair().await;
+        pool.add(socket.with_session_id(id.to_string())).await;
+    }
+
+    assert!(pool.remove_by_session_id("alice").await);
+    assert!(!pool.remove_by_session_id("alice").await);
+    assert_eq!(pool.len().await, 1);
+    assert!(pool.contains("bob").await);
+}
+
+// Passes writes through, counting how often the writer is flushed
+struct FlushCounter {
+    inner: SocketWriter,
+    flushes: Arc<AtomicUsize>,
+}
+
+impl AsyncWrite for FlushCounter {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        self.flushes.fetch_add(1, Ordering::SeqCst);
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+// Sends 1000 small packets and returns how many flushes it took to deliver them
+async fn flushes_for_small_packets(batching: Option<BatchConfig>) -> usize {
+    let (client_end, server_end) = tokio::io::duplex(64 * 1024);
+    let socket =
+        TSocket::<MySession>::new_in_memory(server_end, Arc::new(RwLock::new(Sessions::new())));
+
+    let flushes = Arc::new(AtomicUsize::new(0));
+    {
+        let mut writer = socket.write_part.lock().await;
+        let inner = std::mem::replace(&mut *writer, Box::new(tokio::io::sink()));
+        *writer = Box::new(FlushCounter {
+            inner,
+            flushes: flushes.clone(),
+        });
+    }
+    let mut socket = match batching {
+        Some(config) => socket.with_batching(config),
+        None => socket,
+    };
+
+    let reader = tokio::spawn(async move {
+        let mut frames = FrameReader::new(client_end);
+        for _ in 0..1000 {
+            frames.read_frame().await.unwrap().unwrap();
+        }
+    });
+
+    for _ in 0..1000 {
+        socket.send(test_packet("tick")).await.unwrap();
+    }
+    tokio::time::timeout(Duration::from_secs(5), reader)
+        .await
+        .expect("Timed out waiting for the packets")
+        .unwrap();
+
+    flushes.load(Ordering::SeqCst)
+}
+
+#[tokio::test]
+async fn test_batching_coalesces_flushes() {
+    let unbatched = flushes_for_small_packets(None).await;
+    let batched = flushes_for_small_packets(Some(BatchConfig {
+        max_batch: 50,
+        max_delay: Duration::from_millis(50),
+    }))
+    .await;
+
+    assert_eq!(unbatched, 1000);
+    // Full batches flush on their own; a slow batch may add a delayed flush
+    assert!(batched >= 20, "Only {batched} flushes for 1000 packets");
+    assert!(batched < 100, "Batching still took {batched} flushes");
+}
@@ -892,7 +892,6 @@ async fn test_error_handling_in_multiple_handlers() {
         }
     }
 
-    
     #[allow(clippy::significant_drop_tightening)]
     async fn fault_handler3(
         sources: HandlerSources<MacroTestSession, MacroTestResource>,
@@ -0,0 +1,115 @@
+use std::time::Duration;
+
+use tokio::sync::oneshot;
+
+use super::{MyPacket, MyResource, MySession};
+use crate::{
+    asynch::{
+        authenticator::{AuthType, Authenticator},
+        client::{AsyncClient, EncryptionConfig},
+        listener::{AsyncListener, HandlerSources},
+    },
+    errors::Error,
+    packet::{Packet, PacketBody},
+    wrap_handler,
+};
+
+async fn echo_header(sources: HandlerSources<MySession, MyResource>, packet: MyPacket) {
+    let mut socket = sources.socket;
+    let response = MyPacket {
+        header: format!("ECHO_{}", packet.header()),
+        body: PacketBody::default(),
+    };
+    socket.send(response).await.unwrap();
+}
+
+async fn log_error(_sources: HandlerSources<MySession, MyResource>, error: Error) {
+    println!("Server error: {error}");
+}
+
+#[tokio::test]
+async fn test_uds_send_recv() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("tnet.sock");
+    let (tx, rx) = oneshot::channel();
+
+    let mut server = AsyncListener::new_uds(
+        &path,
+        30,
+        wrap_handler!(echo_header),
+        wrap_handler!(log_error),
+    )
+    .await;
+
+    let server_handle = tokio::spawn(async move {
+        tokio::select! {
+            _ = server.run() => {},
+            _ = rx => println!("Server shutting down"),
+        }
+    });
+
+    let mut client = AsyncClient::<MyPacket>::new_uds(&path).await.unwrap();
+    assert_eq!(client.recv().await.unwrap().header(), "OK");
+
+    let ping = MyPacket {
+        header: "PING".to_string(),
+        body: PacketBody::default(),
+    };
+    let response = client.send_recv(ping).await.unwrap();
+    assert_eq!(response.header(), "ECHO_PING");
+
+    let _ = tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(2), server_handle).await;
+}
+
+#[tokio::test]
+async fn test_uds_with_encryption_and_auth() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("tnet-secure.sock");
+    let (tx, rx) = oneshot::channel();
+
+    let mut server = AsyncListener::new_uds(
+        &path,
+        30,
+        wrap_handler!(echo_header),
+        wrap_handler!(log_error),
+    )
+    .await
+    .with_encryption_config(EncryptionConfig::default_on())
+    .with_authenticator(
+        Authenticator::new(AuthType::UserPassword).with_auth_fn(|user, pass| {
+            Box::pin(async move {
+                if user == "admin" && pass == "password" {
+                    Ok(())
+                } else {
+                    Err(Error::InvalidCredentials)
+                }
+            })
+        }),
+    );
+
+    let server_handle = tokio::spawn(async move {
+        tokio::select! {
+            _ = server.run() => {},
+            _ = rx => println!("Server shutting down"),
+        }
+    });
+
+    let mut client = AsyncClient::<MyPacket>::new_uds(&path)
+        .await
+        .unwrap()
+        .with_credentials("admin", "password")
+        .with_encryption_config(EncryptionConfig::default_on())
+        .await
+        .unwrap();
+
+    let ping = MyPacket {
+        header: "SECRET".to_string(),
+        body: PacketBody::default(),
+    };
+    let response = client.send_recv(ping).await.unwrap();
+    assert_eq!(response.header(), "ECHO_SECRET");
+
+    let _ = tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(2), server_handle).await;
+}
@@ -0,0 +1,6 @@
+#[test]
+fn enum_str_rejects_unknown_keys() {
+    let cases = trybuild::TestCases::new();
+    cases.compile_fail("tests/ui/enum_str_misspelled_key.rs");
+    cases.compile_fail("tests/ui/enum_str_misplaced_key.rs");
+}
@@ -0,0 +1,13 @@
+#[test]
+fn tlisten_for_checks_header_variants() {
+    let cases = trybuild::TestCases::new();
+    cases.pass("tests/ui/tlisten_for_variant.rs");
+    cases.compile_fail("tests/ui/tlisten_for_misspelled_variant.rs");
+}
+
+#[test]
+fn tlisten_for_rejects_mismatched_signatures() {
+    let cases = trybuild::TestCases::new();
+    cases.compile_fail("tests/ui/tlisten_for_sync_fn.rs");
+    cases.compile_fail("tests/ui/tlisten_for_extra_param.rs");
+}
@@ -0,0 +1,10 @@
+use tnet::prelude::*;
+
+#[derive(Debug, Clone, PartialEq, ParseEnumString)]
+enum Header {
+    Ok,
+    #[enum_str(separator = "|")]
+    Error(String),
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use tnet::prelude::*;
+
+#[derive(Debug, Clone, PartialEq, ParseEnumString)]
+#[enum_str(seperator = "|")]
+enum Header {
+    Ok,
+    Error(String),
+}
+
+fn main() {}
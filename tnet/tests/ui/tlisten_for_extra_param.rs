@@ -0,0 +1,11 @@
+include!("tlisten_types.rs");
+
+#[tlisten_for("LOGIN")]
+async fn handle_login(
+    _sources: HandlerSources<UiSession, UiResource>,
+    _packet: UiPacket,
+    _retries: usize,
+) {
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+include!("tlisten_types.rs");
+
+#[tlisten_for(Header::Lgoin)]
+async fn handle_login(sources: HandlerSources<UiSession, UiResource>, _packet: UiPacket) {
+    let mut socket = sources.socket;
+    let _ = socket.send(UiPacket::ok()).await;
+}
+
+fn main() {}
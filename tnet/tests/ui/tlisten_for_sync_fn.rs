@@ -0,0 +1,6 @@
+include!("tlisten_types.rs");
+
+#[tlisten_for("LOGIN")]
+fn handle_login(_sources: HandlerSources<UiSession, UiResource>, _packet: UiPacket) {}
+
+fn main() {}
@@ -0,0 +1,18 @@
+include!("tlisten_types.rs");
+
+#[tlisten_for(Header::Login)]
+async fn handle_login(sources: HandlerSources<UiSession, UiResource>, _packet: UiPacket) {
+    let mut socket = sources.socket;
+    let _ = socket.send(UiPacket::ok()).await;
+}
+
+#[tlisten_for("LOGOUT")]
+async fn handle_logout(sources: HandlerSources<UiSession, UiResource>, _packet: UiPacket) {
+    let mut socket = sources.socket;
+    let _ = socket.send(UiPacket::ok()).await;
+}
+
+fn main() {
+    assert!(get_handler::<UiPacket, UiSession, UiResource>("Login").is_some());
+    assert!(get_handler::<UiPacket, UiSession, UiResource>("LOGOUT").is_some());
+}
@@ -0,0 +1,82 @@
+use std::time::Duration;
+
+use tnet::prelude::*;
+
+#[derive(Debug, Clone, PartialEq, ParseEnumString)]
+pub enum Header {
+    Login,
+    Logout,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UiPacket {
+    header: String,
+    body: PacketBody,
+}
+
+impl ImplPacket for UiPacket {
+    fn header(&self) -> String {
+        self.header.clone()
+    }
+
+    fn body(&self) -> PacketBody {
+        self.body.clone()
+    }
+
+    fn body_mut(&mut self) -> &mut PacketBody {
+        &mut self.body
+    }
+
+    fn ok() -> Self {
+        Self {
+            header: "OK".to_string(),
+            body: PacketBody::default(),
+        }
+    }
+
+    fn error(error: Error) -> Self {
+        Self {
+            header: "ERROR".to_string(),
+            body: PacketBody::with_error_string(error.to_string()),
+        }
+    }
+
+    fn keep_alive() -> Self {
+        Self {
+            header: "KEEPALIVE".to_string(),
+            body: PacketBody::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UiSession {
+    id: String,
+}
+
+impl ImplSession for UiSession {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn created_at(&self) -> u64 {
+        0
+    }
+
+    fn lifespan(&self) -> Duration {
+        Duration::from_secs(60)
+    }
+
+    fn empty(id: String) -> Self {
+        Self { id }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct UiResource;
+
+impl ImplResource for UiResource {
+    fn new() -> Self {
+        Self
+    }
+}